@@ -17,6 +17,7 @@ import type {
   HubConfig,
   NewTrade,
   OfferResponse,
+  OffersPage,
   PatchOffer,
   PostOffer,
   Profile,
@@ -228,9 +229,9 @@ export class CosmosChain implements Chain {
       const response = (await this.cwClient!.queryContractSmart(
         this.hubInfo.hubConfig.offer_addr,
         queryMsg
-      )) as OfferResponse[]
+      )) as OffersPage
       console.log('response >>> ', response)
-      return response
+      return response.offers
     } catch (e) {
       throw DefaultError.fromError(e)
     }
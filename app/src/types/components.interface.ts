@@ -36,6 +36,11 @@ export interface OfferResponse {
   profile: Profile
 }
 
+export interface OffersPage {
+  offers: OfferResponse[]
+  next_cursor?: number
+}
+
 export interface Profile {
   addr: string
   created_at: number
@@ -105,6 +110,7 @@ export enum OfferState {
   active = 'active',
   paused = 'paused',
   archived = 'archive',
+  filled = 'filled',
 }
 
 export interface FetchOffersArgs {
@@ -1,18 +1,24 @@
-use cosmwasm_std::{entry_point, Addr, Binary, Decimal, Deps, StdResult, Storage, Uint64};
+use cosmwasm_std::{entry_point, Addr, Binary, Decimal, Deps, Order, StdResult, Storage, Uint64};
 use cosmwasm_std::{
     to_json_binary, CosmosMsg, DepsMut, Env, MessageInfo, Response, SubMsg, WasmMsg,
 };
 use cw2::{get_contract_version, set_contract_version};
+use cw_storage_plus::Bound;
 use localmoney_protocol::constants::{
-    MAX_PLATFORM_FEE, MAX_TRADE_DISPUTE_TIMER, MAX_TRADE_EXPIRATION_TIMER,
+    MAX_ARBITRATION_FEE, MAX_CANCELLATION_FEE_BPS, MAX_PLATFORM_FEE, MAX_TRADE_DISPUTE_TIMER,
+    MAX_TRADE_EXPIRATION_TIMER,
 };
 
-use crate::state::{ADMIN, CONFIG};
+use crate::state::{ADMIN, ALLOWED_PAIRS, CONFIG, PAIR_WHITELIST_ENABLED};
+use cw20::Denom;
+use localmoney_protocol::currencies::FiatCurrency;
+use localmoney_protocol::denom_utils::{denom_to_string, normalize_denom};
 use localmoney_protocol::errors::ContractError;
 use localmoney_protocol::errors::ContractError::Unauthorized;
-use localmoney_protocol::guards::assert_migration_parameters;
+use localmoney_protocol::guards::{assert_migration_parameters, validate_min_max_items_per_page};
 use localmoney_protocol::hub::{
-    Admin, ExecuteMsg, HubConfig, InstantiateMsg, MigrateMsg, QueryMsg,
+    Admin, AllowedPairsResponse, CurrencyPair, ExecuteMsg, HubConfig, InstantiateMsg, MigrateMsg,
+    QueryMsg,
 };
 use localmoney_protocol::offer::ExecuteMsg::RegisterHub as OfferRegisterHub;
 use localmoney_protocol::price::ExecuteMsg::RegisterHub as PriceRegisterHub;
@@ -52,6 +58,15 @@ pub fn execute(
     match msg {
         ExecuteMsg::UpdateConfig(config) => update_config(deps, info, config),
         ExecuteMsg::UpdateAdmin { admin_addr } => update_admin(deps, info, admin_addr),
+        ExecuteMsg::SetPaused { paused } => set_paused(deps, info, paused),
+        ExecuteMsg::SetPairAllowed {
+            fiat_currency,
+            denom,
+            allowed,
+        } => set_pair_allowed(deps, info, fiat_currency, denom, allowed),
+        ExecuteMsg::SetPairWhitelistEnabled { enabled } => {
+            set_pair_whitelist_enabled(deps, info, enabled)
+        }
     }
 }
 
@@ -118,6 +133,52 @@ fn save_config(storage: &mut dyn Storage, config: &HubConfig) -> Result<(), Cont
         });
     }
 
+    if config.arbitration_fee_pct > Decimal::percent(MAX_ARBITRATION_FEE) {
+        return Err(ContractError::InvalidParameter {
+            parameter: "arbitration_fee_pct".to_string(),
+            message: Some(format!(
+                "The arbitration fee cannot be greater than {}%.",
+                MAX_ARBITRATION_FEE
+            )),
+        });
+    }
+
+    if config.cancellation_fee_bps > MAX_CANCELLATION_FEE_BPS {
+        return Err(ContractError::InvalidParameter {
+            parameter: "cancellation_fee_bps".to_string(),
+            message: Some(format!(
+                "The cancellation fee cannot be greater than {} bps.",
+                MAX_CANCELLATION_FEE_BPS
+            )),
+        });
+    }
+
+    if config.local_denom_fee_discount_pct > Decimal::one() {
+        return Err(ContractError::InvalidParameter {
+            parameter: "local_denom_fee_discount_pct".to_string(),
+            message: Some("The discount cannot be greater than 100%.".to_string()),
+        });
+    }
+
+    if !config.fee_split.is_valid() {
+        return Err(ContractError::InvalidParameter {
+            parameter: "fee_split".to_string(),
+            message: Some("maker_pct and taker_pct must add up to 100.".to_string()),
+        });
+    }
+
+    let limits = &config.text_limits;
+    if limits.description == 0
+        || limits.contact == 0
+        || limits.dispute_reason == 0
+        || limits.evidence_uri == 0
+    {
+        return Err(ContractError::InvalidParameter {
+            parameter: "text_limits".to_string(),
+            message: Some("Text limits cannot be 0.".to_string()),
+        });
+    }
+
     check_timer_parameter(
         "trade_expiration_timer",
         config.trade_expiration_timer,
@@ -130,6 +191,21 @@ fn save_config(storage: &mut dyn Storage, config: &HubConfig) -> Result<(), Cont
         MAX_TRADE_DISPUTE_TIMER,
     )?;
 
+    for (parameter, timer) in [
+        ("trade_payment_timer", config.trade_payment_timer),
+        ("funding_deadline_seconds", config.funding_deadline_seconds),
+    ] {
+        if timer > MAX_TRADE_EXPIRATION_TIMER {
+            return Err(ContractError::InvalidParameter {
+                parameter: parameter.to_string(),
+                message: Some(format!(
+                    "This value should be smaller than {0}.",
+                    MAX_TRADE_EXPIRATION_TIMER
+                )),
+            });
+        }
+    }
+
     CONFIG.save(storage, config).unwrap();
 
     Ok(())
@@ -147,6 +223,86 @@ fn check_timer_parameter(param_name: &str, param: u64, max: u64) -> Result<(), C
     Ok(())
 }
 
+/// Toggles the protocol pause without touching the rest of the config or
+/// re-registering the hub on every contract.
+fn set_paused(deps: DepsMut, info: MessageInfo, paused: bool) -> Result<Response, ContractError> {
+    let admin = ADMIN.load(deps.storage).unwrap();
+    if !info.sender.eq(&admin.addr) {
+        return Err(Unauthorized {
+            owner: admin.addr.clone(),
+            caller: info.sender.clone(),
+        });
+    }
+
+    let mut config = CONFIG.load(deps.storage)?;
+    config.paused = paused;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_paused")
+        .add_attribute("paused", paused.to_string()))
+}
+
+/// Lists or delists a fiat currency and denom pair for new offers.
+fn set_pair_allowed(
+    deps: DepsMut,
+    info: MessageInfo,
+    fiat_currency: FiatCurrency,
+    denom: Denom,
+    allowed: bool,
+) -> Result<Response, ContractError> {
+    let admin = ADMIN.load(deps.storage).unwrap();
+    if !info.sender.eq(&admin.addr) {
+        return Err(Unauthorized {
+            owner: admin.addr.clone(),
+            caller: info.sender.clone(),
+        });
+    }
+
+    let pair = CurrencyPair {
+        fiat_currency,
+        denom: normalize_denom(&denom)?,
+    };
+    let (fiat_key, denom_key) = pair_key(&pair.fiat_currency, &pair.denom);
+    if allowed {
+        ALLOWED_PAIRS.save(deps.storage, (&fiat_key, &denom_key), &pair)?;
+    } else {
+        ALLOWED_PAIRS.remove(deps.storage, (&fiat_key, &denom_key));
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_pair_allowed")
+        .add_attribute("fiat_currency", pair.fiat_currency.to_string())
+        .add_attribute("denom", denom_to_string(&pair.denom))
+        .add_attribute("allowed", allowed.to_string()))
+}
+
+/// Turns the pair whitelist on or off. While on, an empty whitelist allows
+/// no pair at all.
+fn set_pair_whitelist_enabled(
+    deps: DepsMut,
+    info: MessageInfo,
+    enabled: bool,
+) -> Result<Response, ContractError> {
+    let admin = ADMIN.load(deps.storage).unwrap();
+    if !info.sender.eq(&admin.addr) {
+        return Err(Unauthorized {
+            owner: admin.addr.clone(),
+            caller: info.sender.clone(),
+        });
+    }
+
+    PAIR_WHITELIST_ENABLED.save(deps.storage, &enabled)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_pair_whitelist_enabled")
+        .add_attribute("enabled", enabled.to_string()))
+}
+
+fn pair_key(fiat_currency: &FiatCurrency, denom: &Denom) -> (String, String) {
+    (fiat_currency.to_string(), denom_to_string(denom))
+}
+
 fn update_admin(
     deps: DepsMut,
     info: MessageInfo,
@@ -176,7 +332,52 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_json_binary(&CONFIG.load(deps.storage).unwrap()),
         QueryMsg::Admin {} => to_json_binary(&ADMIN.load(deps.storage).unwrap()),
+        QueryMsg::IsPairAllowed {
+            fiat_currency,
+            denom,
+        } => to_json_binary(&query_is_pair_allowed(deps, fiat_currency, denom)?),
+        QueryMsg::AllowedPairs { limit, start_after } => {
+            to_json_binary(&query_allowed_pairs(deps, limit, start_after)?)
+        }
+    }
+}
+
+fn query_is_pair_allowed(deps: Deps, fiat_currency: FiatCurrency, denom: Denom) -> StdResult<bool> {
+    if !PAIR_WHITELIST_ENABLED
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+    {
+        return Ok(true);
     }
+    let (fiat_key, denom_key) = pair_key(&fiat_currency, &denom);
+    Ok(ALLOWED_PAIRS.has(deps.storage, (&fiat_key, &denom_key)))
+}
+
+fn query_allowed_pairs(
+    deps: Deps,
+    limit: u32,
+    start_after: Option<CurrencyPair>,
+) -> StdResult<AllowedPairsResponse> {
+    let limit = validate_min_max_items_per_page(limit) as usize;
+    let start_after = start_after.map(|pair| pair_key(&pair.fiat_currency, &pair.denom));
+    let pairs = ALLOWED_PAIRS
+        .range(
+            deps.storage,
+            start_after
+                .as_ref()
+                .map(|(fiat, denom)| Bound::exclusive((fiat.as_str(), denom.as_str()))),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, pair)| pair))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllowedPairsResponse {
+        enabled: PAIR_WHITELIST_ENABLED
+            .may_load(deps.storage)?
+            .unwrap_or_default(),
+        pairs,
+    })
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
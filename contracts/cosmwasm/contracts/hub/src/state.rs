@@ -1,6 +1,12 @@
-use cw_storage_plus::Item;
+use cw_storage_plus::{Item, Map};
 
-use localmoney_protocol::hub::{Admin, HubConfig};
+use localmoney_protocol::hub::{Admin, CurrencyPair, HubConfig};
 
 pub const CONFIG: Item<HubConfig> = Item::new("config");
 pub const ADMIN: Item<Admin> = Item::new("admin");
+/// Fiat currency and denom pairs listed for new offers, keyed by the fiat
+/// currency and `denom_to_string` of the denom.
+pub const ALLOWED_PAIRS: Map<(&str, &str), CurrencyPair> = Map::new("allowed_pairs");
+/// Restricts new offers to `ALLOWED_PAIRS` while set. Kept out of the config
+/// so `UpdateConfig` cannot lift it by accident.
+pub const PAIR_WHITELIST_ENABLED: Item<bool> = Item::new("pair_whitelist_enabled");
@@ -0,0 +1,407 @@
+use cosmwasm_std::testing::{message_info, mock_dependencies, mock_env};
+use cosmwasm_std::{from_json, Addr, Decimal, Deps, DepsMut, Response, Uint128};
+use cw20::Denom;
+use localmoney_protocol::constants::{MAX_ARBITRATION_FEE, MAX_CANCELLATION_FEE_BPS};
+use localmoney_protocol::currencies::FiatCurrency;
+use localmoney_protocol::errors::ContractError;
+use localmoney_protocol::hub::{
+    AllowedPairsResponse, CurrencyPair, ExecuteMsg, FeeSplit, HubConfig, InstantiateMsg, QueryMsg,
+    TextLimits,
+};
+
+use crate::contract::{execute, instantiate, query};
+
+const ADMIN: &str = "admin";
+
+fn hub_config() -> HubConfig {
+    HubConfig {
+        offer_addr: Addr::unchecked("offer"),
+        trade_addr: Addr::unchecked("trade"),
+        profile_addr: Addr::unchecked("profile"),
+        price_addr: Addr::unchecked("price"),
+        price_provider_addr: Addr::unchecked("price_provider"),
+        local_market_addr: Addr::unchecked("local_market"),
+        local_denom: Denom::Native("local".to_string()),
+        chain_fee_collector_addr: Addr::unchecked("chain_fee_collector"),
+        warchest_addr: Addr::unchecked("warchest"),
+        active_offers_limit: 3,
+        active_trades_limit: 10,
+        arbitration_fee_pct: Decimal::percent(1),
+        burn_fee_pct: Decimal::permille(2),
+        chain_fee_pct: Decimal::permille(3),
+        warchest_fee_pct: Decimal::permille(5),
+        trade_expiration_timer: 1200,
+        trade_dispute_timer: 3600,
+        trade_limit_min: Uint128::new(1),
+        trade_limit_max: Uint128::new(100),
+        local_denom_fee_discount_pct: Decimal::zero(),
+        offer_update_delay: 0,
+        text_limits: TextLimits::default(),
+        trade_payment_timer: 0,
+        funding_deadline_seconds: 0,
+        paused: false,
+        fee_split: FeeSplit::default(),
+        fee_denom: None,
+        cancellation_fee_bps: 0,
+    }
+}
+
+fn update_config(deps: DepsMut, config: HubConfig) -> Result<Response, ContractError> {
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    execute(deps, mock_env(), admin, ExecuteMsg::UpdateConfig(config))
+}
+
+#[test]
+fn config_query_returns_text_limits() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin,
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+
+    let text_limits = TextLimits {
+        description: 280,
+        contact: 2048,
+        dispute_reason: 1000,
+        evidence_uri: 512,
+    };
+    update_config(
+        deps.as_mut(),
+        HubConfig {
+            text_limits: text_limits.clone(),
+            ..hub_config()
+        },
+    )
+    .unwrap();
+
+    let config: HubConfig =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(config.text_limits, text_limits);
+
+    let err = update_config(
+        deps.as_mut(),
+        HubConfig {
+            text_limits: TextLimits {
+                description: 0,
+                ..text_limits
+            },
+            ..hub_config()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "text_limits")
+    );
+}
+
+#[test]
+fn only_the_admin_can_pause_the_protocol() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin.clone(),
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+    update_config(deps.as_mut(), hub_config()).unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked("stranger"), &[]),
+        ExecuteMsg::SetPaused { paused: true },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        admin,
+        ExecuteMsg::SetPaused { paused: true },
+    )
+    .unwrap();
+    let config: HubConfig =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        config,
+        HubConfig {
+            paused: true,
+            ..hub_config()
+        }
+    );
+}
+
+#[test]
+fn fee_split_must_add_up_to_100() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin,
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+
+    for (maker_pct, taker_pct) in [(50, 40), (60, 60), (255, 101)] {
+        let err = update_config(
+            deps.as_mut(),
+            HubConfig {
+                fee_split: FeeSplit {
+                    maker_pct,
+                    taker_pct,
+                },
+                ..hub_config()
+            },
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "fee_split")
+        );
+    }
+
+    let fee_split = FeeSplit {
+        maker_pct: 30,
+        taker_pct: 70,
+    };
+    update_config(
+        deps.as_mut(),
+        HubConfig {
+            fee_split: fee_split.clone(),
+            ..hub_config()
+        },
+    )
+    .unwrap();
+    let config: HubConfig =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(config.fee_split, fee_split);
+}
+
+#[test]
+fn arbitration_fee_is_capped() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin,
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+
+    let err = update_config(
+        deps.as_mut(),
+        HubConfig {
+            arbitration_fee_pct: Decimal::percent(MAX_ARBITRATION_FEE + 1),
+            ..hub_config()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "arbitration_fee_pct")
+    );
+    update_config(
+        deps.as_mut(),
+        HubConfig {
+            arbitration_fee_pct: Decimal::percent(MAX_ARBITRATION_FEE),
+            ..hub_config()
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn cancellation_fee_is_capped() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin,
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+
+    let err = update_config(
+        deps.as_mut(),
+        HubConfig {
+            cancellation_fee_bps: MAX_CANCELLATION_FEE_BPS + 1,
+            ..hub_config()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "cancellation_fee_bps")
+    );
+    update_config(
+        deps.as_mut(),
+        HubConfig {
+            cancellation_fee_bps: MAX_CANCELLATION_FEE_BPS,
+            ..hub_config()
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn admins_list_and_delist_offer_pairs() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin.clone(),
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+    update_config(deps.as_mut(), hub_config()).unwrap();
+
+    let set_pair =
+        |fiat_currency: FiatCurrency, denom: &str, allowed: bool| ExecuteMsg::SetPairAllowed {
+            fiat_currency,
+            denom: Denom::Native(denom.to_string()),
+            allowed,
+        };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked("stranger"), &[]),
+        set_pair(FiatCurrency::USD, "uatom", true),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    let uatom = Denom::Native("uatom".to_string());
+    let is_allowed = |deps: Deps, fiat_currency: FiatCurrency| -> bool {
+        let msg = QueryMsg::IsPairAllowed {
+            fiat_currency,
+            denom: uatom.clone(),
+        };
+        from_json(query(deps, mock_env(), msg).unwrap()).unwrap()
+    };
+    let allowed_pairs = |deps: Deps| -> AllowedPairsResponse {
+        let msg = QueryMsg::AllowedPairs {
+            limit: 10,
+            start_after: None,
+        };
+        from_json(query(deps, mock_env(), msg).unwrap()).unwrap()
+    };
+    assert!(is_allowed(deps.as_ref(), FiatCurrency::EUR));
+
+    for msg in [
+        set_pair(FiatCurrency::USD, "uatom", true),
+        set_pair(FiatCurrency::USD, " uatom ", true),
+        set_pair(FiatCurrency::EUR, "uatom", true),
+        set_pair(FiatCurrency::EUR, "uatom", false),
+    ] {
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+    }
+    let usd_uatom = CurrencyPair {
+        fiat_currency: FiatCurrency::USD,
+        denom: uatom.clone(),
+    };
+    assert_eq!(
+        allowed_pairs(deps.as_ref()),
+        AllowedPairsResponse {
+            enabled: false,
+            pairs: vec![usd_uatom.clone()],
+        }
+    );
+    // Listing pairs does nothing until the whitelist is enabled
+    assert!(is_allowed(deps.as_ref(), FiatCurrency::EUR));
+
+    let enable = |enabled: bool| ExecuteMsg::SetPairWhitelistEnabled { enabled };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked("stranger"), &[]),
+        enable(true),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+    execute(deps.as_mut(), mock_env(), admin.clone(), enable(true)).unwrap();
+    assert!(is_allowed(deps.as_ref(), FiatCurrency::USD));
+    assert!(!is_allowed(deps.as_ref(), FiatCurrency::EUR));
+
+    // Updating the config keeps both the pairs and the flag
+    update_config(deps.as_mut(), hub_config()).unwrap();
+    assert!(allowed_pairs(deps.as_ref()).enabled);
+    assert!(!is_allowed(deps.as_ref(), FiatCurrency::EUR));
+
+    // An enabled whitelist with no pairs left allows nothing
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        admin.clone(),
+        set_pair(FiatCurrency::USD, "uatom", false),
+    )
+    .unwrap();
+    assert!(allowed_pairs(deps.as_ref()).pairs.is_empty());
+    assert!(!is_allowed(deps.as_ref(), FiatCurrency::USD));
+
+    execute(deps.as_mut(), mock_env(), admin, enable(false)).unwrap();
+    assert!(is_allowed(deps.as_ref(), FiatCurrency::USD));
+}
+
+#[test]
+fn allowed_pairs_are_paginated() {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        admin.clone(),
+        InstantiateMsg {
+            admin_addr: Addr::unchecked(ADMIN),
+        },
+    )
+    .unwrap();
+
+    let pair = |fiat_currency: FiatCurrency| CurrencyPair {
+        fiat_currency,
+        denom: Denom::Native("uatom".to_string()),
+    };
+    for fiat_currency in [FiatCurrency::USD, FiatCurrency::EUR, FiatCurrency::BRL] {
+        let msg = ExecuteMsg::SetPairAllowed {
+            fiat_currency,
+            denom: Denom::Native("uatom".to_string()),
+            allowed: true,
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+    }
+
+    let page = |start_after: Option<CurrencyPair>| -> Vec<CurrencyPair> {
+        let msg = QueryMsg::AllowedPairs {
+            limit: 2,
+            start_after,
+        };
+        let response: AllowedPairsResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        response.pairs
+    };
+    let first = page(None);
+    assert_eq!(
+        first,
+        vec![pair(FiatCurrency::BRL), pair(FiatCurrency::EUR)]
+    );
+    assert_eq!(page(first.last().cloned()), vec![pair(FiatCurrency::USD)]);
+}
@@ -1,22 +1,40 @@
-use crate::state::OFFERS_COUNT;
+use crate::state::{
+    BLOCKED_TAKERS, FAVORITES, OFFERS_COUNT, OFFER_STATS, PENDING_UPDATES, STATS_REBUILD_CURSOR,
+};
 use cosmwasm_std::{
-    entry_point, to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError,
-    StdResult, SubMsg,
+    coins, entry_point, to_json_binary, Addr, BankMsg, Binary, Deps, DepsMut, Empty, Env,
+    MessageInfo, Order, QuerierWrapper, Response, StdError, StdResult, Storage, SubMsg, Uint128,
 };
 use cw2::{get_contract_version, set_contract_version};
+use cw20::{BalanceResponse, Cw20QueryMsg, Denom};
+use cw_storage_plus::Bound;
+use localmoney_protocol::constants::{MAX_OFFER_BATCH_SIZE, MAX_OFFER_SCAN};
+use localmoney_protocol::currencies::FiatCurrency;
+use localmoney_protocol::denom_utils::{denom_to_string, normalize_denom};
 use localmoney_protocol::errors::ContractError;
 use localmoney_protocol::errors::ContractError::HubAlreadyRegistered;
 use localmoney_protocol::guards::{
-    assert_migration_parameters, assert_min_g_max, assert_offer_description_valid, assert_ownership,
+    assert_contact_valid, assert_max_active_trades_valid, assert_migration_parameters,
+    assert_min_g_max, assert_not_paused, assert_offer_description_valid,
+    assert_offer_notional_bounds, assert_offer_trade_capacity, assert_ownership,
+    assert_sent_funds, validate_min_max_items_per_page,
+};
+use localmoney_protocol::hub::HubConfig;
+use localmoney_protocol::hub_utils::{
+    get_hub_admin, get_hub_config, is_pair_allowed, query_version, register_hub_internal, HUB_ADDR,
 };
-use localmoney_protocol::hub_utils::{get_hub_config, register_hub_internal};
 use localmoney_protocol::offer::{
-    offers, ExecuteMsg, InstantiateMsg, MigrateMsg, Offer, OfferModel, OfferMsg, OfferResponse,
-    OfferState, OfferUpdateMsg, OffersCount, QueryMsg,
+    filter_prefix, load_solvency, offers, ExecuteMsg, FiatOfferStats, InstantiateMsg, MigrateMsg,
+    Offer, OfferModel, OfferMsg, OfferResponse, OfferState, OfferStateCounts, OfferStats,
+    OfferType, OfferUpdateMsg, OffersCount, PendingOfferUpdate, QueryMsg, SolvencyAttestation,
+    SOLVENCY_ATTESTATIONS,
 };
+use localmoney_protocol::price::query_fiat_price_for_denom;
 use localmoney_protocol::profile::{
     load_profile, update_profile_active_offers_msg, update_profile_contact_msg,
 };
+use localmoney_protocol::storage_utils::{reindex_limit, reindex_page};
+use localmoney_protocol::trade::{calc_denom_fiat_price, TradeState};
 
 const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -47,15 +65,36 @@ pub fn execute(
     match msg {
         ExecuteMsg::RegisterHub {} => register_hub(deps, info),
         ExecuteMsg::Create { offer } => create_offer(deps, env, info, offer),
+        ExecuteMsg::CreateBatch { offers } => create_offer_batch(deps, env, info, offers),
         ExecuteMsg::UpdateOffer { offer_update } => update_offer(deps, env, info, offer_update),
+        ExecuteMsg::UpdateAllRates {
+            delta_bps,
+            start_after,
+        } => update_all_rates(deps, env, info, delta_bps, start_after),
+        ExecuteMsg::CancelPendingUpdate { id } => cancel_pending_update(deps, info, id),
+        ExecuteMsg::ApplyPendingUpdate { id } => apply_pending_update(deps, env, id),
+        ExecuteMsg::AttestSolvency { id } => attest_solvency(deps, env, info, id),
+        ExecuteMsg::UpdateActiveTrades {
+            id,
+            trade_state,
+            amount,
+        } => update_active_trades(deps, info, id, trade_state, amount),
+        ExecuteMsg::ToggleFavorite { offer_id } => toggle_favorite(deps, info, offer_id),
+        ExecuteMsg::Expire { id } => expire_offer(deps, env, id),
+        ExecuteMsg::BlockTaker { taker } => block_taker(deps, info, taker),
+        ExecuteMsg::UnblockTaker { taker } => unblock_taker(deps, info, taker),
+        ExecuteMsg::Reindex { start_after, limit } => {
+            reindex_offers(deps, info, start_after, limit)
+        }
     }
 }
 
 #[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    let now = env.block.time.seconds();
     match msg {
         QueryMsg::State {} => to_json_binary(&query_state(deps)?),
-        QueryMsg::Offer { id } => to_json_binary(&load_offer_by_id(deps, id)?),
+        QueryMsg::Offer { id } => to_json_binary(&load_offer_by_id(deps, now, id)?),
         QueryMsg::OffersBy {
             offer_type,
             fiat_currency,
@@ -63,18 +102,100 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             order,
             limit,
             last,
+            min_owner_reputation,
         } => to_json_binary(&OfferModel::query_by(
             deps,
+            now,
             offer_type,
             fiat_currency,
             denom,
             order,
             limit,
             last,
+            min_owner_reputation,
+        )?),
+        QueryMsg::OffersById {
+            offer_type,
+            fiat_currency,
+            denom,
+            limit,
+            after,
+        } => to_json_binary(&OfferModel::query_by_id(
+            deps,
+            now,
+            offer_type,
+            fiat_currency,
+            denom,
+            limit,
+            after,
+        )?),
+        QueryMsg::OffersByRate {
+            offer_type,
+            fiat_currency,
+            denom,
+            min_rate,
+            max_rate,
+            limit,
+            last,
+        } => to_json_binary(&OfferModel::query_by_rate(
+            deps,
+            now,
+            offer_type,
+            fiat_currency,
+            denom,
+            min_rate,
+            max_rate,
+            limit,
+            last,
+        )?),
+        QueryMsg::BestOffer {
+            offer_type,
+            fiat_currency,
+            denom,
+            amount,
+        } => to_json_binary(&OfferModel::best_offer(
+            deps,
+            now,
+            offer_type,
+            fiat_currency,
+            denom,
+            amount,
+        )?),
+        QueryMsg::TakeableOffers {
+            offer_type,
+            fiat_currency,
+            denom,
+            taker,
+        } => to_json_binary(&query_takeable_offers(
+            deps,
+            now,
+            offer_type,
+            fiat_currency,
+            denom,
+            taker,
         )?),
         QueryMsg::OffersByOwner { owner, limit, last } => {
-            to_json_binary(&OfferModel::query_by_owner(deps, owner, limit, last)?)
+            to_json_binary(&OfferModel::query_by_owner(deps, now, owner, limit, last)?)
+        }
+        QueryMsg::NextOfferId {} => to_json_binary(&query_next_offer_id(deps)?),
+        QueryMsg::PendingUpdate { id } => {
+            to_json_binary(&PENDING_UPDATES.may_load(deps.storage, id)?)
         }
+        QueryMsg::Favorites {
+            owner,
+            limit,
+            start_after,
+        } => to_json_binary(&query_favorites(deps, now, owner, limit, start_after)?),
+        QueryMsg::Stats {} => to_json_binary(&query_stats(deps)?),
+        QueryMsg::BlockedTakers {
+            maker,
+            limit,
+            start_after,
+        } => to_json_binary(&query_blocked_takers(deps, maker, limit, start_after)?),
+        QueryMsg::IsTakerBlocked { maker, taker } => {
+            to_json_binary(&BLOCKED_TAKERS.has(deps.storage, (&maker, &taker)))
+        }
+        QueryMsg::Version {} => to_json_binary(&query_version(deps.storage)?),
     }
 }
 
@@ -86,29 +207,141 @@ pub fn create_offer(
     msg: OfferMsg,
 ) -> Result<Response, ContractError> {
     let hub_config = get_hub_config(deps.as_ref());
+    let (offer, sub_msgs) =
+        save_new_offer(deps.storage, &deps.querier, &env, &info, &hub_config, msg)?;
+
+    Ok(Response::new()
+        .add_submessages(sub_msgs)
+        .add_attribute("action", "create_offer")
+        .add_attribute("type", offer.offer_type.to_string())
+        .add_attribute("id", offer.id.to_string())
+        .add_attribute("rate", offer.rate.to_string())
+        .add_attribute("min_amount", offer.min_amount.to_string())
+        .add_attribute("max_amount", offer.max_amount.to_string())
+        .add_attribute("backed", offer.is_backed().to_string())
+        .add_attribute("owner", offer.owner.to_string()))
+}
+
+/// Creates up to `MAX_OFFER_BATCH_SIZE` offers at once. Any invalid offer fails the whole batch.
+pub fn create_offer_batch(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    offers: Vec<OfferMsg>,
+) -> Result<Response, ContractError> {
+    if offers.is_empty() || offers.len() > MAX_OFFER_BATCH_SIZE {
+        return Err(ContractError::InvalidParameter {
+            parameter: "offers".to_string(),
+            message: Some(format!(
+                "A batch must hold between 1 and {} offers.",
+                MAX_OFFER_BATCH_SIZE
+            )),
+        });
+    }
+
+    let hub_config = get_hub_config(deps.as_ref());
+    let mut sub_msgs: Vec<SubMsg> = vec![];
+    let mut ids: Vec<String> = vec![];
+    for msg in offers {
+        // A single set of funds can't be split between several deposits.
+        if msg.prefunded {
+            return Err(ContractError::InvalidParameter {
+                parameter: "prefunded".to_string(),
+                message: Some("Prefunded offers must be created one at a time.".to_string()),
+            });
+        }
+        let (offer, mut offer_msgs) =
+            save_new_offer(deps.storage, &deps.querier, &env, &info, &hub_config, msg)?;
+        sub_msgs.append(&mut offer_msgs);
+        ids.push(offer.id.to_string());
+    }
+
+    Ok(Response::new()
+        .add_submessages(sub_msgs)
+        .add_attribute("action", "create_offer_batch")
+        .add_attribute("count", ids.len().to_string())
+        .add_attribute("ids", ids.join(","))
+        .add_attribute("owner", info.sender.to_string()))
+}
+
+/// Validates and stores a new offer with the next sequential id, returning it
+/// along with the profile updates it requires.
+fn save_new_offer(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    info: &MessageInfo,
+    hub_config: &HubConfig,
+    mut msg: OfferMsg,
+) -> Result<(Offer, Vec<SubMsg>), ContractError> {
+    assert_not_paused(hub_config)?;
+    let owner = &info.sender;
+    msg.denom = normalize_denom(&msg.denom)?;
+    let hub_addr = HUB_ADDR.load(storage)?.addr;
+    if !is_pair_allowed(
+        querier,
+        hub_addr.to_string(),
+        msg.fiat_currency.clone(),
+        msg.denom.clone(),
+    )? {
+        return Err(ContractError::UnsupportedPair {
+            fiat_currency: msg.fiat_currency,
+            denom: denom_to_string(&msg.denom),
+        });
+    }
+    let deposit = if msg.prefunded {
+        assert_offer_deposit(info, &msg)?;
+        msg.max_amount
+    } else {
+        Uint128::zero()
+    };
     assert_min_g_max(msg.min_amount, msg.max_amount)?;
-    assert_offer_description_valid(msg.description.clone())?;
+    assert_max_active_trades_valid(msg.max_active_trades)?;
+    let limits = &hub_config.text_limits;
+    assert_offer_description_valid(msg.description.clone(), limits.description)?;
+    assert_contact_valid(
+        &msg.owner_contact,
+        &msg.owner_encryption_key,
+        limits.contact,
+    )?;
+    if matches!(msg.expires_at, Some(expires_at) if expires_at <= env.block.time.seconds()) {
+        return Err(ContractError::InvalidParameter {
+            parameter: "expires_at".to_string(),
+            message: Some("The offer must expire in the future.".to_string()),
+        });
+    }
+    assert_offer_notional_valid(
+        querier,
+        hub_config,
+        &msg.denom,
+        msg.rate,
+        msg.min_amount,
+        msg.max_amount,
+    )?;
 
     // Load offers count to create the next sequential id.
-    let mut offers_count = OFFERS_COUNT
-        .load(deps.storage)
-        .map_err(ContractError::Std)?;
+    let mut offers_count = OFFERS_COUNT.load(storage).map_err(ContractError::Std)?;
     offers_count.count += 1;
     let offer_id = offers_count.count;
 
+    // Record the key version the owner's contact will be sealed with.
+    let owner_key_version =
+        load_profile(querier, hub_config.profile_addr.to_string(), owner.clone())?
+            .key_version_for(&msg.owner_encryption_key);
+
     // Update profile contact info.
     let update_profile_contact_msg = update_profile_contact_msg(
         hub_config.profile_addr.to_string(),
-        info.sender.clone(),
+        owner.clone(),
         msg.owner_contact.clone(),
         msg.owner_encryption_key.clone(),
     );
 
     let offer = OfferModel::create(
-        deps.storage,
+        storage,
         Offer {
             id: offer_id,
-            owner: info.sender.clone(),
+            owner: owner.clone(),
             offer_type: msg.offer_type,
             fiat_currency: msg.fiat_currency.clone(),
             rate: msg.rate,
@@ -118,74 +351,650 @@ pub fn create_offer(
             state: OfferState::Active,
             description: msg.description,
             timestamp: env.block.time.seconds(),
+            max_active_trades: msg.max_active_trades,
+            active_trades_count: 0,
+            owner_key_version,
+            expires_at: msg.expires_at,
+            filled_amount: Uint128::zero(),
+            auto_repost: msg.auto_repost,
+            deposit,
         },
     )
     .offer;
 
     // Update offers count.
     OFFERS_COUNT
-        .save(deps.storage, &offers_count)
+        .save(storage, &offers_count)
         .map_err(ContractError::Std)?;
+    record_state_change(storage, &offer, None, &offer.state)?;
 
     // Update profile active offers.
     let update_profile_offers_msg = update_profile_active_offers_msg(
         hub_config.profile_addr.to_string(),
-        info.sender.clone(),
-        offer.state,
+        owner.clone(),
+        offer.state.clone(),
     );
 
-    Ok(Response::new()
-        .add_submessage(update_profile_contact_msg)
-        .add_submessage(update_profile_offers_msg)
-        .add_attribute("action", "create_offer")
-        .add_attribute("type", offer.offer_type.to_string())
-        .add_attribute("id", offer.id.to_string())
-        .add_attribute("rate", offer.rate.to_string())
-        .add_attribute("min_amount", offer.min_amount.to_string())
-        .add_attribute("max_amount", offer.max_amount.to_string())
-        .add_attribute("owner", offer.owner.to_string()))
+    Ok((
+        offer,
+        vec![update_profile_contact_msg, update_profile_offers_msg],
+    ))
+}
+
+/// Requires a prefunded offer to be a native sell offer sent along with
+/// exactly its `max_amount`.
+fn assert_offer_deposit(info: &MessageInfo, msg: &OfferMsg) -> Result<(), ContractError> {
+    match (&msg.offer_type, &msg.denom) {
+        (OfferType::Sell, Denom::Native(denom)) => assert_sent_funds(info, msg.max_amount, denom),
+        _ => Err(ContractError::InvalidParameter {
+            parameter: "prefunded".to_string(),
+            message: Some("Only sell offers in a native denom can be prefunded.".to_string()),
+        }),
+    }
+}
+
+/// Sends an archived offer's deposit back to its owner and clears it.
+fn refund_deposit(offer: &mut Offer) -> Option<SubMsg> {
+    let denom = match &offer.denom {
+        Denom::Native(denom) if offer.is_backed() => denom.clone(),
+        _ => return None,
+    };
+    let amount = std::mem::take(&mut offer.deposit);
+    Some(SubMsg::new(BankMsg::Send {
+        to_address: offer.owner.to_string(),
+        amount: coins(amount.u128(), denom),
+    }))
 }
 
+/// Prices an offer's amount range in USD with the hub's price contract and
+/// checks it against the hub trade limits.
+fn assert_offer_notional_valid(
+    querier: &QuerierWrapper,
+    hub_config: &HubConfig,
+    denom: &Denom,
+    rate: Uint128,
+    min_amount: Uint128,
+    max_amount: Uint128,
+) -> Result<(), ContractError> {
+    let denom_usd_price = query_fiat_price_for_denom(
+        querier,
+        denom.clone(),
+        FiatCurrency::USD,
+        hub_config.price_addr.to_string(),
+    )
+    .map_err(|_| ContractError::InvalidPriceForDenom {})?;
+    let offer_usd_price = calc_denom_fiat_price(rate, denom_usd_price.price);
+    assert_offer_notional_bounds(min_amount, max_amount, offer_usd_price, hub_config)
+}
+
+/// Updates an offer, or queues the update when the hub sets an `offer_update_delay`.
+/// Contact changes are never delayed.
 pub fn update_offer(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: OfferUpdateMsg,
 ) -> Result<Response, ContractError> {
     assert_min_g_max(msg.min_amount, msg.max_amount)?;
+    assert_max_active_trades_valid(msg.max_active_trades)?;
 
     let hub_config = get_hub_config(deps.as_ref());
-    let mut offer_model = OfferModel::may_load(deps.storage, msg.id);
+    assert_not_paused(&hub_config)?;
+    let offer = OfferModel::from_store(deps.storage, msg.id);
 
-    assert_ownership(info.sender.clone(), offer_model.offer.owner.clone())?;
-    assert_offer_description_valid(msg.description.clone())?;
+    assert_ownership(info.sender.clone(), offer.owner.clone())?;
+    let limits = &hub_config.text_limits;
+    assert_offer_description_valid(msg.description.clone(), limits.description)?;
+    assert_offer_notional_valid(
+        &deps.querier,
+        &hub_config,
+        &offer.denom,
+        msg.rate,
+        msg.min_amount,
+        msg.max_amount,
+    )?;
 
     let mut sub_msgs: Vec<SubMsg> = Vec::new();
-    if msg.owner_contact.is_some() && msg.owner_encryption_key.is_some() {
+    if let (Some(owner_contact), Some(owner_encryption_key)) =
+        (&msg.owner_contact, &msg.owner_encryption_key)
+    {
+        assert_contact_valid(owner_contact, owner_encryption_key, limits.contact)?;
         sub_msgs.push(update_profile_contact_msg(
             hub_config.profile_addr.to_string(),
             info.sender.clone(),
-            msg.owner_contact.clone().unwrap(),
-            msg.owner_encryption_key.clone().unwrap(),
+            owner_contact.clone(),
+            owner_encryption_key.clone(),
         ));
     }
-    if msg.state != offer_model.offer.state {
-        sub_msgs.push(update_profile_active_offers_msg(
-            hub_config.profile_addr.to_string(),
-            info.sender.clone(),
-            msg.state.clone(),
-        ))
+
+    if hub_config.offer_update_delay > 0 {
+        let apply_at = env.block.time.seconds() + hub_config.offer_update_delay;
+        PENDING_UPDATES.save(
+            deps.storage,
+            msg.id,
+            &PendingOfferUpdate {
+                update: msg,
+                apply_at,
+            },
+        )?;
+        return Ok(Response::new()
+            .add_submessages(sub_msgs)
+            .add_attribute("action", "schedule_offer_update")
+            .add_attribute("id", offer.id.to_string())
+            .add_attribute("owner", offer.owner.to_string())
+            .add_attribute("apply_at", apply_at.to_string()));
     }
 
-    let offer = offer_model.update(msg);
+    // An immediate update supersedes anything still queued.
+    PENDING_UPDATES.remove(deps.storage, msg.id);
+    let (offer, update_msgs) = apply_offer_update(deps, &hub_config, msg)?;
 
     Ok(Response::new()
         .add_submessages(sub_msgs)
+        .add_submessages(update_msgs)
         .add_attribute("action", "update_offer")
         .add_attribute("id", offer.id.to_string())
         .add_attribute("owner", offer.owner.to_string()))
 }
 
+/// Shifts the rate of up to `MAX_OFFER_BATCH_SIZE` of the sender's active
+/// offers by `delta_bps`. Each new rate is validated like an `UpdateOffer`,
+/// and any invalid one fails the whole message.
+pub fn update_all_rates(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    delta_bps: i32,
+    start_after: Option<u64>,
+) -> Result<Response, ContractError> {
+    if delta_bps == 0 || delta_bps <= -10_000 {
+        return Err(ContractError::InvalidParameter {
+            parameter: "delta_bps".to_string(),
+            message: Some("Must be non-zero and above -10000.".to_string()),
+        });
+    }
+    let hub_config = get_hub_config(deps.as_ref());
+    assert_not_paused(&hub_config)?;
+
+    // Read one extra offer to know whether another call is needed.
+    let mut page: Vec<Offer> = offers()
+        .idx
+        .owner
+        .prefix(info.sender.clone())
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .filter(|item| !matches!(item, Ok((_, offer)) if offer.state != OfferState::Active))
+        .take(MAX_OFFER_BATCH_SIZE + 1)
+        .map(|item| item.map(|(_, offer)| offer))
+        .collect::<StdResult<_>>()?;
+    let next_start_after = if page.len() > MAX_OFFER_BATCH_SIZE {
+        page.truncate(MAX_OFFER_BATCH_SIZE);
+        page.last().map(|offer| offer.id)
+    } else {
+        None
+    };
+
+    let scale = Uint128::new((10_000 + delta_bps) as u128);
+    let apply_at = env.block.time.seconds() + hub_config.offer_update_delay;
+    let mut ids: Vec<String> = vec![];
+    for offer in page {
+        let rate = offer.rate.multiply_ratio(scale, 10_000u128);
+        if rate.is_zero() {
+            return Err(ContractError::InvalidParameter {
+                parameter: "delta_bps".to_string(),
+                message: Some(format!("Offer {} would be left without a rate.", offer.id)),
+            });
+        }
+        assert_offer_notional_valid(
+            &deps.querier,
+            &hub_config,
+            &offer.denom,
+            rate,
+            offer.min_amount,
+            offer.max_amount,
+        )?;
+
+        let update = OfferUpdateMsg {
+            id: offer.id,
+            owner_contact: None,
+            owner_encryption_key: None,
+            rate,
+            min_amount: offer.min_amount,
+            max_amount: offer.max_amount,
+            state: offer.state,
+            description: offer.description,
+            max_active_trades: offer.max_active_trades,
+        };
+        if hub_config.offer_update_delay > 0 {
+            PENDING_UPDATES.save(
+                deps.storage,
+                offer.id,
+                &PendingOfferUpdate { update, apply_at },
+            )?;
+        } else {
+            PENDING_UPDATES.remove(deps.storage, offer.id);
+            // The state is unchanged, so there is no profile update to send.
+            apply_offer_update(deps.branch(), &hub_config, update)?;
+        }
+        ids.push(offer.id.to_string());
+    }
+
+    let mut response = Response::new()
+        .add_attribute("action", "update_all_rates")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("delta_bps", delta_bps.to_string())
+        .add_attribute("count", ids.len().to_string())
+        .add_attribute("ids", ids.join(","));
+    if hub_config.offer_update_delay > 0 {
+        response = response.add_attribute("apply_at", apply_at.to_string());
+    }
+    if let Some(next_start_after) = next_start_after {
+        response = response.add_attribute("next_start_after", next_start_after.to_string());
+    }
+    Ok(response)
+}
+
+pub fn cancel_pending_update(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let offer = OfferModel::from_store(deps.storage, id);
+    assert_ownership(info.sender, offer.owner.clone())?;
+    if !PENDING_UPDATES.has(deps.storage, id) {
+        return Err(ContractError::NoPendingOfferUpdate { offer_id: id });
+    }
+    PENDING_UPDATES.remove(deps.storage, id);
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_pending_update")
+        .add_attribute("id", offer.id.to_string())
+        .add_attribute("owner", offer.owner.to_string()))
+}
+
+pub fn apply_pending_update(deps: DepsMut, env: Env, id: u64) -> Result<Response, ContractError> {
+    let pending = PENDING_UPDATES
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::NoPendingOfferUpdate { offer_id: id })?;
+    if env.block.time.seconds() < pending.apply_at {
+        return Err(ContractError::PendingOfferUpdateNotReady {
+            apply_at: pending.apply_at,
+        });
+    }
+    PENDING_UPDATES.remove(deps.storage, id);
+
+    let hub_config = get_hub_config(deps.as_ref());
+    let (offer, update_msgs) = apply_offer_update(deps, &hub_config, pending.update)?;
+
+    Ok(Response::new()
+        .add_submessages(update_msgs)
+        .add_attribute("action", "update_offer")
+        .add_attribute("id", offer.id.to_string())
+        .add_attribute("owner", offer.owner.to_string()))
+}
+
+/// Records the owner's current balance of the offer denom, read from the bank
+/// module or the cw20 contract, so takers can see the offer was backed.
+pub fn attest_solvency(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let offer = offers()
+        .may_load(deps.storage, id)?
+        .ok_or_else(|| StdError::not_found("Offer"))?;
+    assert_ownership(info.sender, offer.owner.clone())?;
+    if offer.offer_type != OfferType::Sell {
+        return Err(ContractError::InvalidParameter {
+            parameter: "offer_type".to_string(),
+            message: Some("Only sell offers can attest solvency.".to_string()),
+        });
+    }
+
+    let balance = match &offer.denom {
+        Denom::Native(denom) => deps.querier.query_balance(&offer.owner, denom)?.amount,
+        Denom::Cw20(token) => {
+            let response: BalanceResponse = deps.querier.query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: offer.owner.to_string(),
+                },
+            )?;
+            response.balance
+        }
+    };
+    let attestation = SolvencyAttestation {
+        balance,
+        attested_at: env.block.time.seconds(),
+    };
+    SOLVENCY_ATTESTATIONS.save(deps.storage, id, &attestation)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "attest_solvency")
+        .add_attribute("id", id.to_string())
+        .add_attribute("owner", offer.owner.to_string())
+        .add_attribute("balance", balance.to_string()))
+}
+
+/// Keeps the count of open trades drawing from an offer. The trade contract
+/// calls it when a trade is created and once more when that trade closes.
+pub fn update_active_trades(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: u64,
+    trade_state: TradeState,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let hub_config = get_hub_config(deps.as_ref());
+    assert_ownership(info.sender, hub_config.trade_addr.clone())?;
+
+    let mut offer = offers()
+        .may_load(deps.storage, id)?
+        .ok_or_else(|| StdError::not_found("Offer"))?;
+    let mut sub_msgs: Vec<SubMsg> = Vec::new();
+    match trade_state {
+        TradeState::RequestCreated => {
+            assert_offer_trade_capacity(&offer)?;
+            let remaining = offer.remaining_amount();
+            if amount > remaining {
+                return Err(ContractError::InsufficientOfferLiquidity {
+                    requested: amount,
+                    available: remaining,
+                });
+            }
+            offer.filled_amount += amount;
+            offer.active_trades_count += 1;
+            // Whatever is left can't be traded on its own anymore
+            if offer.state == OfferState::Active && offer.remaining_amount() < offer.min_amount {
+                sub_msgs.push(update_profile_active_offers_msg(
+                    hub_config.profile_addr.to_string(),
+                    offer.owner.clone(),
+                    OfferState::Filled,
+                ));
+                record_state_change(
+                    deps.storage,
+                    &offer,
+                    Some(&offer.state),
+                    &OfferState::Filled,
+                )?;
+                offer.state = OfferState::Filled;
+            }
+        }
+        TradeState::RequestCanceled
+        | TradeState::RequestExpired
+        | TradeState::EscrowCanceled
+        | TradeState::EscrowRefunded
+        | TradeState::EscrowReleased
+        | TradeState::SettledForMaker
+        | TradeState::SettledForTaker => {
+            if offer.auto_repost || returns_funds_to_seller(&offer.offer_type, &trade_state) {
+                offer.filled_amount = offer.filled_amount.saturating_sub(amount);
+            }
+            offer.active_trades_count = offer.active_trades_count.saturating_sub(1);
+            if can_repost(deps.as_ref(), &hub_config, &offer)? {
+                sub_msgs.push(update_profile_active_offers_msg(
+                    hub_config.profile_addr.to_string(),
+                    offer.owner.clone(),
+                    OfferState::Active,
+                ));
+                record_state_change(
+                    deps.storage,
+                    &offer,
+                    Some(&offer.state),
+                    &OfferState::Active,
+                )?;
+                offer.state = OfferState::Active;
+            }
+        }
+        _ => {}
+    }
+    OfferModel::store(deps.storage, &offer)?;
+
+    Ok(Response::new()
+        .add_submessages(sub_msgs)
+        .add_attribute("action", "update_offer_active_trades")
+        .add_attribute("id", id.to_string())
+        .add_attribute("trade_state", trade_state.to_string())
+        .add_attribute("active_trades_count", offer.active_trades_count.to_string())
+        .add_attribute("remaining_amount", offer.remaining_amount().to_string()))
+}
+
+/// Whether an auto-repost offer filled by its trades can take new ones again.
+/// Offers the owner paused or archived stay that way, and so does an offer
+/// whose owner has no active offer slot left for it.
+fn can_repost(deps: Deps, hub_config: &HubConfig, offer: &Offer) -> StdResult<bool> {
+    if !offer.auto_repost
+        || offer.state != OfferState::Filled
+        || offer.remaining_amount() < offer.min_amount
+    {
+        return Ok(false);
+    }
+    let profile = load_profile(
+        &deps.querier,
+        hub_config.profile_addr.to_string(),
+        offer.owner.clone(),
+    )?;
+    Ok(profile.active_offers_count < hub_config.active_offers_limit)
+}
+
+/// Whether a trade closing in `trade_state` leaves the escrowed amount with
+/// the seller, so it can be offered again. A filled offer stays filled unless
+/// it auto-reposts, otherwise the owner reactivates it once they are ready to
+/// trade the rest.
+fn returns_funds_to_seller(offer_type: &OfferType, trade_state: &TradeState) -> bool {
+    match trade_state {
+        TradeState::RequestCanceled
+        | TradeState::RequestExpired
+        | TradeState::EscrowCanceled
+        | TradeState::EscrowRefunded => true,
+        // The maker is the seller on sell offers and the taker on buy offers
+        TradeState::SettledForMaker => offer_type == &OfferType::Sell,
+        TradeState::SettledForTaker => offer_type == &OfferType::Buy,
+        _ => false,
+    }
+}
+
+pub fn toggle_favorite(
+    deps: DepsMut,
+    info: MessageInfo,
+    offer_id: u64,
+) -> Result<Response, ContractError> {
+    let res = Response::new()
+        .add_attribute("action", "toggle_favorite")
+        .add_attribute("offer_id", offer_id.to_string());
+    if !offers().has(deps.storage, offer_id) {
+        return Ok(res.add_attribute("favorite", "ignored"));
+    }
+
+    let key = (&info.sender, offer_id);
+    let favorite = !FAVORITES.has(deps.storage, key);
+    if favorite {
+        FAVORITES.save(deps.storage, key, &Empty {})?;
+    } else {
+        FAVORITES.remove(deps.storage, key);
+    }
+
+    Ok(res.add_attribute("favorite", favorite.to_string()))
+}
+
+pub fn block_taker(
+    deps: DepsMut,
+    info: MessageInfo,
+    taker: Addr,
+) -> Result<Response, ContractError> {
+    if taker == info.sender {
+        return Err(ContractError::InvalidParameter {
+            parameter: "taker".to_string(),
+            message: Some("Makers can't block themselves.".to_string()),
+        });
+    }
+    BLOCKED_TAKERS.save(deps.storage, (&info.sender, &taker), &Empty {})?;
+
+    Ok(Response::new()
+        .add_attribute("action", "block_taker")
+        .add_attribute("maker", info.sender)
+        .add_attribute("taker", taker))
+}
+
+pub fn unblock_taker(
+    deps: DepsMut,
+    info: MessageInfo,
+    taker: Addr,
+) -> Result<Response, ContractError> {
+    BLOCKED_TAKERS.remove(deps.storage, (&info.sender, &taker));
+
+    Ok(Response::new()
+        .add_attribute("action", "unblock_taker")
+        .add_attribute("maker", info.sender)
+        .add_attribute("taker", taker))
+}
+
+/// Archives an offer whose expiry has passed, so it stops counting towards
+/// the owner's active offers.
+pub fn expire_offer(deps: DepsMut, env: Env, id: u64) -> Result<Response, ContractError> {
+    let mut offer = OfferModel::from_store(deps.storage, id);
+    if !offer.is_expired(env.block.time.seconds()) {
+        return Err(ContractError::OfferNotExpired {
+            offer_id: id,
+            expires_at: offer.expires_at,
+        });
+    }
+    if offer.state == OfferState::Archive {
+        return Err(ContractError::InvalidOfferStateChange {
+            from: OfferState::Archive,
+            to: OfferState::Archive,
+        });
+    }
+
+    let mut sub_msgs: Vec<SubMsg> = Vec::new();
+    // A paused offer already left the owner's active offers
+    if offer.state == OfferState::Active {
+        let hub_config = get_hub_config(deps.as_ref());
+        sub_msgs.push(update_profile_active_offers_msg(
+            hub_config.profile_addr.to_string(),
+            offer.owner.clone(),
+            OfferState::Archive,
+        ));
+    }
+    record_state_change(
+        deps.storage,
+        &offer,
+        Some(&offer.state),
+        &OfferState::Archive,
+    )?;
+    offer.state = OfferState::Archive;
+    sub_msgs.extend(refund_deposit(&mut offer));
+    OfferModel::store(deps.storage, &offer)?;
+
+    Ok(Response::new()
+        .add_submessages(sub_msgs)
+        .add_attribute("action", "expire_offer")
+        .add_attribute("id", offer.id.to_string())
+        .add_attribute("owner", offer.owner.to_string()))
+}
+
+/// Applies `msg` to its offer, returning the profile update for a state
+/// change and the deposit refund when the offer is archived.
+fn apply_offer_update(
+    deps: DepsMut,
+    hub_config: &HubConfig,
+    msg: OfferUpdateMsg,
+) -> StdResult<(Offer, Vec<SubMsg>)> {
+    let mut offer_model = OfferModel::may_load(deps.storage, msg.id);
+    let previous_state = offer_model.offer.state.clone();
+    let mut sub_msgs = vec![];
+    if msg.state != previous_state {
+        sub_msgs.push(update_profile_active_offers_msg(
+            hub_config.profile_addr.to_string(),
+            offer_model.offer.owner.clone(),
+            msg.state.clone(),
+        ));
+    }
+    let mut offer = offer_model.update(msg).clone();
+    if offer.state != previous_state {
+        record_state_change(deps.storage, &offer, Some(&previous_state), &offer.state)?;
+    }
+    if offer.state == OfferState::Archive {
+        if let Some(refund_msg) = refund_deposit(&mut offer) {
+            OfferModel::store(deps.storage, &offer)?;
+            sub_msgs.push(refund_msg);
+        }
+    }
+    Ok((offer, sub_msgs))
+}
+
+/// Moves `offer` between the state counts behind `Stats`. While a
+/// `Reindex` pass is rebuilding the counts, offers it has not reached yet
+/// are left out, the pass counts them in their state at that point.
+fn record_state_change(
+    storage: &mut dyn Storage,
+    offer: &Offer,
+    from: Option<&OfferState>,
+    to: &OfferState,
+) -> StdResult<()> {
+    if let Some(cursor) = STATS_REBUILD_CURSOR.may_load(storage)? {
+        if offer.id > cursor {
+            return Ok(());
+        }
+    }
+    let key = offer.fiat_currency.to_string();
+    let mut stats = OFFER_STATS
+        .may_load(storage, &key)?
+        .unwrap_or_else(|| FiatOfferStats {
+            fiat_currency: offer.fiat_currency.clone(),
+            counts: OfferStateCounts::default(),
+        });
+    stats.counts.record(from, to);
+    OFFER_STATS.save(storage, &key, &stats)
+}
+
+/// Saves the next page of offers again so the rate index covers offers
+/// stored before it existed, and rebuilds the `Stats` counts along the way.
+/// A pass starts from `start_after: None`, which clears the counts, and each
+/// later call must continue from the previous `next_start_after`.
+fn reindex_offers(
+    deps: DepsMut,
+    info: MessageInfo,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let admin = get_hub_admin(deps.as_ref()).addr;
+    assert_ownership(info.sender, admin)?;
+
+    match start_after {
+        None => OFFER_STATS.clear(deps.storage),
+        Some(id) if STATS_REBUILD_CURSOR.may_load(deps.storage)? != Some(id) => {
+            return Err(ContractError::InvalidParameter {
+                parameter: "start_after".to_string(),
+                message: Some("Must be the next_start_after of the pass in progress.".to_string()),
+            });
+        }
+        Some(_) => {}
+    }
+    let (saved, next_start_after) =
+        reindex_page(deps.storage, &offers(), start_after, reindex_limit(limit))?;
+    match next_start_after {
+        Some(cursor) => STATS_REBUILD_CURSOR.save(deps.storage, &cursor)?,
+        None => STATS_REBUILD_CURSOR.remove(deps.storage),
+    }
+    saved
+        .iter()
+        .try_for_each(|offer| record_state_change(deps.storage, offer, None, &offer.state))?;
+
+    let mut response = Response::new()
+        .add_attribute("action", "reindex_offers")
+        .add_attribute("count", saved.len().to_string());
+    if let Some(next_start_after) = next_start_after {
+        response = response.add_attribute("next_start_after", next_start_after.to_string());
+    }
+    Ok(response)
+}
+
 fn register_hub(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
     // Supply two generic arguments to register_hub_internal.
     register_hub_internal::<ContractError, ContractError>(
@@ -200,7 +1009,25 @@ fn query_state(deps: Deps) -> StdResult<OffersCount> {
     OFFERS_COUNT.load(deps.storage)
 }
 
-pub fn load_offer_by_id(deps: Deps, id: u64) -> StdResult<OfferResponse> {
+fn query_stats(deps: Deps) -> StdResult<OfferStats> {
+    let by_fiat = OFFER_STATS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, stats)| stats))
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut totals = OfferStateCounts::default();
+    by_fiat.iter().for_each(|stats| totals.merge(&stats.counts));
+    Ok(OfferStats {
+        total_created: OFFERS_COUNT.load(deps.storage)?.count,
+        totals,
+        by_fiat,
+    })
+}
+
+fn query_next_offer_id(deps: Deps) -> StdResult<u64> {
+    Ok(OFFERS_COUNT.load(deps.storage)?.count + 1)
+}
+
+pub fn load_offer_by_id(deps: Deps, now: u64, id: u64) -> StdResult<OfferResponse> {
     let hub_config = get_hub_config(deps);
     let offer = offers()
         .may_load(deps.storage, id)?
@@ -210,7 +1037,90 @@ pub fn load_offer_by_id(deps: Deps, id: u64) -> StdResult<OfferResponse> {
         hub_config.profile_addr.to_string(),
         offer.owner.clone(),
     )?;
-    Ok(OfferResponse { offer, profile })
+    let solvency = load_solvency(deps.storage, offer.id, now)?;
+    Ok(OfferResponse {
+        remaining_amount: offer.remaining_amount(),
+        backed: offer.is_backed(),
+        offer: offer.at(now),
+        profile,
+        solvency,
+    })
+}
+
+fn query_takeable_offers(
+    deps: Deps,
+    now: u64,
+    offer_type: OfferType,
+    fiat_currency: FiatCurrency,
+    denom: Denom,
+    taker: Addr,
+) -> StdResult<Vec<Offer>> {
+    // Takers buy from sell offers, so the lowest rate is best there.
+    let order = match offer_type {
+        OfferType::Sell => Order::Ascending,
+        OfferType::Buy => Order::Descending,
+    };
+    let prefix = filter_prefix(&offer_type, &fiat_currency, &denom, &OfferState::Active);
+    offers()
+        .idx
+        .rate
+        .sub_prefix(prefix)
+        .range(deps.storage, None, None, order)
+        .take(MAX_OFFER_SCAN as usize)
+        .map(|item| item.map(|(_, offer)| offer))
+        .filter(|item| match item {
+            Ok(offer) => {
+                !offer.is_expired(now)
+                    && offer.owner.ne(&taker)
+                    && !BLOCKED_TAKERS.has(deps.storage, (&offer.owner, &taker))
+                    && assert_offer_trade_capacity(offer).is_ok()
+            }
+            Err(_) => true,
+        })
+        .collect()
+}
+
+fn query_blocked_takers(
+    deps: Deps,
+    maker: Addr,
+    limit: u32,
+    start_after: Option<Addr>,
+) -> StdResult<Vec<Addr>> {
+    let limit = validate_min_max_items_per_page(limit) as usize;
+    BLOCKED_TAKERS
+        .prefix(&maker)
+        .keys(
+            deps.storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .collect()
+}
+
+fn query_favorites(
+    deps: Deps,
+    now: u64,
+    owner: Addr,
+    limit: u32,
+    start_after: Option<u64>,
+) -> StdResult<Vec<OfferResponse>> {
+    let limit = validate_min_max_items_per_page(limit) as usize;
+    let offer_ids = FAVORITES
+        .prefix(&owner)
+        .keys(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<Vec<u64>>>()?;
+    offer_ids
+        .into_iter()
+        .map(|id| load_offer_by_id(deps, now, id))
+        .collect()
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
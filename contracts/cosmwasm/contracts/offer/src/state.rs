@@ -1,11 +1,28 @@
-use cosmwasm_std::Addr;
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, MultiIndex};
+use cosmwasm_std::{Addr, Empty};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 
-use localmoney_protocol::offer::{OffersCount, TradeAddr};
+use localmoney_protocol::offer::{FiatOfferStats, OffersCount, PendingOfferUpdate, TradeAddr};
 
 /// The storage key for offers count; note that cw-storage-plus items use &str keys.
 pub const OFFERS_COUNT: Item<OffersCount> = Item::new("offers_count_v0_4_1");
 
+/// Offer state counts per fiat currency, updated on every state change.
+pub const OFFER_STATS: Map<&str, FiatOfferStats> = Map::new("offer_stats");
+
+/// Last offer id a `Reindex` pass has counted into `OFFER_STATS`, present
+/// only while the pass is in progress.
+pub const STATS_REBUILD_CURSOR: Item<u64> = Item::new("offer_stats_rebuild_cursor");
+
+/// At most one queued update per offer id, a newer update replaces it.
+pub const PENDING_UPDATES: Map<u64, PendingOfferUpdate> = Map::new("pending_offer_updates");
+
+/// Offers a taker has saved, keyed by `(taker, offer_id)`.
+pub const FAVORITES: Map<(&Addr, u64), Empty> = Map::new("offer_favorites");
+
+/// Takers a maker refuses to trade with on any of their offers, keyed by
+/// `(maker, taker)`.
+pub const BLOCKED_TAKERS: Map<(&Addr, &Addr), Empty> = Map::new("blocked_takers");
+
 /// TradeIndexes now uses the new generic ordering:
 ///   MultiIndex<'a, T, K, P> where:
 ///   - T is the main data type (TradeAddr),
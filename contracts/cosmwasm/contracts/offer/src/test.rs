@@ -0,0 +1,1875 @@
+use cosmwasm_std::testing::{
+    message_info, mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{
+    coins, from_json, to_json_binary, Addr, BankMsg, ContractResult, Decimal, Env, OwnedDeps,
+    Response, SubMsg, SystemResult, Uint128, Uint256, WasmQuery,
+};
+use cw20::Denom;
+use localmoney_protocol::constants::{
+    CONTACT_LIMIT, MAX_OFFER_BATCH_SIZE, MAX_OFFER_SCAN, MAX_REINDEX_BATCH_SIZE,
+    SOLVENCY_ATTESTATION_TTL,
+};
+use localmoney_protocol::currencies::FiatCurrency;
+use localmoney_protocol::errors::ContractError;
+use localmoney_protocol::hub::{
+    Admin, CurrencyPair, FeeSplit, HubConfig, QueryMsg as HubQueryMsg, TextLimits,
+};
+use localmoney_protocol::hub_utils::VersionResponse;
+use localmoney_protocol::offer::{
+    offers, ExecuteMsg, FiatOfferStats, InstantiateMsg, Offer, OfferMsg, OfferOrder, OfferResponse,
+    OfferState, OfferStateCounts, OfferStats, OfferType, OfferUpdateMsg, OffersCount, OffersPage,
+    PendingOfferUpdate, QueryMsg, SolvencyStatus,
+};
+use localmoney_protocol::price::{DenomFiatPrice, QueryMsg as PriceQueryMsg};
+use localmoney_protocol::profile::{
+    update_profile_active_offers_msg, Profile, QueryMsg as ProfileQueryMsg,
+};
+use localmoney_protocol::trade::TradeState;
+
+use crate::contract::{execute, instantiate, query};
+use crate::state::OFFER_STATS;
+
+const HUB: &str = "hub";
+const ADMIN: &str = "admin";
+const MAKER: &str = "maker";
+
+const UPDATE_DELAY: u64 = 600;
+
+fn hub_config() -> HubConfig {
+    HubConfig {
+        offer_addr: Addr::unchecked("offer"),
+        trade_addr: Addr::unchecked("trade"),
+        profile_addr: Addr::unchecked("profile"),
+        price_addr: Addr::unchecked("price"),
+        price_provider_addr: Addr::unchecked("price_provider"),
+        local_market_addr: Addr::unchecked("local_market"),
+        local_denom: Denom::Native("local".to_string()),
+        chain_fee_collector_addr: Addr::unchecked("chain_fee_collector"),
+        warchest_addr: Addr::unchecked("warchest"),
+        active_offers_limit: 3,
+        active_trades_limit: 10,
+        arbitration_fee_pct: Decimal::percent(1),
+        burn_fee_pct: Decimal::permille(2),
+        chain_fee_pct: Decimal::permille(3),
+        warchest_fee_pct: Decimal::permille(5),
+        trade_expiration_timer: 1200,
+        trade_dispute_timer: 3600,
+        trade_limit_min: Uint128::new(1),
+        trade_limit_max: Uint128::new(1_000_000),
+        local_denom_fee_discount_pct: Decimal::zero(),
+        offer_update_delay: 0,
+        text_limits: TextLimits::default(),
+        trade_payment_timer: 0,
+        funding_deadline_seconds: 0,
+        paused: false,
+        fee_split: FeeSplit::default(),
+        fee_denom: None,
+        cancellation_fee_bps: 0,
+    }
+}
+
+fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    setup_with_config(hub_config())
+}
+
+fn setup_with_config(config: HubConfig) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = mock_dependencies();
+    let info = message_info(&Addr::unchecked(HUB), &[]);
+    instantiate(deps.as_mut(), mock_env(), info.clone(), InstantiateMsg {}).unwrap();
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::RegisterHub {}).unwrap();
+    mock_queries(&mut deps, config);
+    deps
+}
+
+/// Answers the hub, profile and price queries the offer contract makes,
+/// serving `config` from the hub.
+fn mock_queries(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, config: HubConfig) {
+    mock_queries_with_profile(deps, config, Profile::new(Addr::unchecked(MAKER), 0))
+}
+
+/// Like `mock_queries`, with every profile answered from `profile`.
+fn mock_queries_with_profile(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    config: HubConfig,
+    profile: Profile,
+) {
+    mock_queries_with_pairs(deps, config, profile, None)
+}
+
+/// Like `mock_queries_with_profile`, with the hub's pair whitelist enabled
+/// and listing `allowed_pairs`, or disabled for `None`.
+fn mock_queries_with_pairs(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    config: HubConfig,
+    profile: Profile,
+    allowed_pairs: Option<Vec<CurrencyPair>>,
+) {
+    deps.querier.update_wasm(move |query| match query {
+        WasmQuery::Smart { contract_addr, msg } if contract_addr == HUB => {
+            let response = match from_json(msg).unwrap() {
+                HubQueryMsg::Config {} => to_json_binary(&config),
+                HubQueryMsg::Admin {} => to_json_binary(&Admin {
+                    addr: Addr::unchecked(ADMIN),
+                }),
+                HubQueryMsg::IsPairAllowed {
+                    fiat_currency,
+                    denom,
+                } => to_json_binary(&match &allowed_pairs {
+                    Some(pairs) => pairs.contains(&CurrencyPair {
+                        fiat_currency,
+                        denom,
+                    }),
+                    None => true,
+                }),
+                msg => panic!("unexpected hub query: {:?}", msg),
+            };
+            SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+        }
+        WasmQuery::Smart { contract_addr, msg }
+            if contract_addr == config.profile_addr.as_str() =>
+        {
+            let response = match from_json(msg).unwrap() {
+                ProfileQueryMsg::Profile { addr } => to_json_binary(&Profile {
+                    addr,
+                    ..profile.clone()
+                }),
+                ProfileQueryMsg::Profiles { .. } => to_json_binary(&vec![profile.clone()]),
+                msg => panic!("unexpected profile query: {:?}", msg),
+            };
+            SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+        }
+        WasmQuery::Smart { contract_addr, msg } if contract_addr == config.price_addr.as_str() => {
+            let PriceQueryMsg::Price { fiat, denom } = from_json(msg).unwrap();
+            let price = DenomFiatPrice {
+                denom,
+                fiat,
+                price: Uint256::from(100u128),
+            };
+            SystemResult::Ok(ContractResult::Ok(to_json_binary(&price).unwrap()))
+        }
+        _ => panic!("unexpected query: {:?}", query),
+    });
+}
+
+fn offer_msg() -> OfferMsg {
+    OfferMsg {
+        offer_type: OfferType::Sell,
+        owner_contact: "maker_contact".to_string(),
+        owner_encryption_key: "bWFrZXJfa2V5".to_string(),
+        fiat_currency: FiatCurrency::USD,
+        rate: Uint128::new(100),
+        denom: Denom::Native("uatom".to_string()),
+        min_amount: Uint128::new(1_000_000),
+        max_amount: Uint128::new(50_000_000),
+        description: None,
+        max_active_trades: None,
+        expires_at: None,
+        auto_repost: false,
+        prefunded: false,
+    }
+}
+
+fn create_offer(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, msg: OfferMsg) -> u64 {
+    let info = message_info(&Addr::unchecked(MAKER), &[]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Create { offer: msg },
+    )
+    .unwrap();
+    res.attributes
+        .iter()
+        .find(|attr| attr.key == "id")
+        .unwrap()
+        .value
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn next_offer_id_matches_created_offer() {
+    let mut deps = setup();
+
+    for _ in 0..2 {
+        let next_id: u64 =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::NextOfferId {}).unwrap()).unwrap();
+        let offer_id = create_offer(&mut deps, offer_msg());
+        assert_eq!(next_id, offer_id);
+    }
+}
+
+#[test]
+fn version_reports_the_package_and_hub_registration() {
+    let mut deps = mock_dependencies();
+    let hub = message_info(&Addr::unchecked(HUB), &[]);
+    instantiate(deps.as_mut(), mock_env(), hub.clone(), InstantiateMsg {}).unwrap();
+    let version = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| -> VersionResponse {
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Version {}).unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        version(&deps),
+        VersionResponse {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            hub_registered: false,
+        }
+    );
+    execute(deps.as_mut(), mock_env(), hub, ExecuteMsg::RegisterHub {}).unwrap();
+    assert!(version(&deps).hub_registered);
+}
+
+fn delayed_setup() -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, u64) {
+    let mut deps = setup_with_config(HubConfig {
+        offer_update_delay: UPDATE_DELAY,
+        ..hub_config()
+    });
+    let id = create_offer(&mut deps, offer_msg());
+    (deps, id)
+}
+
+fn rate_update(id: u64, rate: u128) -> OfferUpdateMsg {
+    OfferUpdateMsg {
+        id,
+        owner_contact: None,
+        owner_encryption_key: None,
+        rate: Uint128::new(rate),
+        min_amount: Uint128::new(1_000_000),
+        max_amount: Uint128::new(50_000_000),
+        state: OfferState::Active,
+        description: None,
+        max_active_trades: None,
+    }
+}
+
+fn maker_execute(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    env: Env,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        env,
+        message_info(&Addr::unchecked(MAKER), &[]),
+        msg,
+    )
+}
+
+fn pending_update(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    id: u64,
+) -> Option<PendingOfferUpdate> {
+    from_json(query(deps.as_ref(), mock_env(), QueryMsg::PendingUpdate { id }).unwrap()).unwrap()
+}
+
+fn env_at(seconds: u64) -> Env {
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(seconds);
+    env
+}
+
+#[test]
+fn update_is_queued_when_delay_is_set() {
+    let (mut deps, id) = delayed_setup();
+
+    let update = rate_update(id, 120);
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: update.clone(),
+        },
+    )
+    .unwrap();
+
+    let offer = offers().load(&deps.storage, id).unwrap();
+    assert_eq!(offer.rate, Uint128::new(100));
+    assert_eq!(
+        pending_update(&deps, id),
+        Some(PendingOfferUpdate {
+            update,
+            apply_at: mock_env().block.time.seconds() + UPDATE_DELAY,
+        })
+    );
+}
+
+#[test]
+fn cancelled_update_is_never_applied() {
+    let (mut deps, id) = delayed_setup();
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: rate_update(id, 120),
+        },
+    )
+    .unwrap();
+
+    let other = message_info(&Addr::unchecked("other"), &[]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        other,
+        ExecuteMsg::CancelPendingUpdate { id },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::CancelPendingUpdate { id },
+    )
+    .unwrap();
+    assert_eq!(pending_update(&deps, id), None);
+
+    let err = maker_execute(
+        &mut deps,
+        env_at(UPDATE_DELAY),
+        ExecuteMsg::ApplyPendingUpdate { id },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::NoPendingOfferUpdate { offer_id } if offer_id == id));
+    assert_eq!(
+        offers().load(&deps.storage, id).unwrap().rate,
+        Uint128::new(100)
+    );
+}
+
+#[test]
+fn pending_update_applies_after_delay() {
+    let (mut deps, id) = delayed_setup();
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: rate_update(id, 120),
+        },
+    )
+    .unwrap();
+
+    let err = maker_execute(
+        &mut deps,
+        env_at(UPDATE_DELAY - 1),
+        ExecuteMsg::ApplyPendingUpdate { id },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::PendingOfferUpdateNotReady { .. }
+    ));
+
+    // Anyone can apply a matured update.
+    let keeper = message_info(&Addr::unchecked("keeper"), &[]);
+    execute(
+        deps.as_mut(),
+        env_at(UPDATE_DELAY),
+        keeper,
+        ExecuteMsg::ApplyPendingUpdate { id },
+    )
+    .unwrap();
+
+    assert_eq!(
+        offers().load(&deps.storage, id).unwrap().rate,
+        Uint128::new(120)
+    );
+    assert_eq!(pending_update(&deps, id), None);
+}
+
+#[test]
+fn update_all_rates_shifts_every_active_offer() {
+    let mut deps = setup();
+    let ids: Vec<u64> = [200, 400, 1000]
+        .iter()
+        .map(|rate| {
+            create_offer(
+                &mut deps,
+                OfferMsg {
+                    rate: Uint128::new(*rate),
+                    max_amount: Uint128::new(4_000_000),
+                    ..offer_msg()
+                },
+            )
+        })
+        .collect();
+    let paused = create_offer(&mut deps, offer_msg());
+    let mut pause = rate_update(paused, 100);
+    pause.state = OfferState::Paused;
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: pause,
+        },
+    )
+    .unwrap();
+
+    let res = maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateAllRates {
+            delta_bps: 50,
+            start_after: None,
+        },
+    )
+    .unwrap();
+    assert!(res
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "count" && attr.value == "3"));
+    assert!(!res
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "next_start_after"));
+
+    let rate = |id: u64| offers().load(&deps.storage, id).unwrap().rate.u128();
+    assert_eq!(
+        ids.iter().map(|id| rate(*id)).collect::<Vec<_>>(),
+        vec![201, 402, 1005]
+    );
+    assert_eq!(rate(paused), 100);
+
+    let err = maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateAllRates {
+            delta_bps: -10_000,
+            start_after: None,
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidParameter { .. }));
+}
+
+fn reindex(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    start_after: Option<u64>,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(ADMIN), &[]),
+        ExecuteMsg::Reindex {
+            start_after,
+            limit: None,
+        },
+    )
+}
+
+fn attr(res: &Response, key: &str) -> Option<String> {
+    res.attributes
+        .iter()
+        .find(|attr| attr.key == key)
+        .map(|attr| attr.value.clone())
+}
+
+#[test]
+fn reindex_is_paginated_and_admin_only() {
+    let mut deps = setup();
+    for _ in 0..=MAX_REINDEX_BATCH_SIZE {
+        create_offer(&mut deps, offer_msg());
+    }
+
+    let err = maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::Reindex {
+            start_after: None,
+            limit: None,
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    let first = reindex(&mut deps, None).unwrap();
+    assert_eq!(
+        attr(&first, "count"),
+        Some(MAX_REINDEX_BATCH_SIZE.to_string())
+    );
+    let next_start_after = attr(&first, "next_start_after").unwrap();
+    assert_eq!(next_start_after, MAX_REINDEX_BATCH_SIZE.to_string());
+
+    let last = reindex(&mut deps, Some(next_start_after.parse().unwrap())).unwrap();
+    assert_eq!(attr(&last, "count"), Some("1".to_string()));
+    assert_eq!(attr(&last, "next_start_after"), None);
+
+    let smaller = execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(ADMIN), &[]),
+        ExecuteMsg::Reindex {
+            start_after: None,
+            limit: Some(10),
+        },
+    )
+    .unwrap();
+    assert_eq!(attr(&smaller, "count"), Some("10".to_string()));
+    assert_eq!(attr(&smaller, "next_start_after"), Some("10".to_string()));
+}
+
+fn offers_by_id(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    limit: u32,
+    after: Option<u64>,
+) -> OffersPage {
+    let msg = QueryMsg::OffersById {
+        offer_type: OfferType::Sell,
+        fiat_currency: FiatCurrency::USD,
+        denom: Denom::Native("uatom".to_string()),
+        limit,
+        after,
+    };
+    from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+}
+
+fn page_ids(page: &OffersPage) -> Vec<u64> {
+    page.offers
+        .iter()
+        .map(|response| response.offer.id)
+        .collect()
+}
+
+#[test]
+fn offers_by_id_pages_are_stable_across_updates() {
+    let mut deps = setup();
+    for _ in 0..4 {
+        create_offer(&mut deps, offer_msg());
+    }
+
+    let first = offers_by_id(&deps, 2, None);
+    assert_eq!(page_ids(&first), vec![1, 2]);
+    assert_eq!(first.next_cursor, Some(2));
+
+    // Pausing an offer already served must not shift the next page.
+    let mut pause = rate_update(2, 100);
+    pause.state = OfferState::Paused;
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: pause,
+        },
+    )
+    .unwrap();
+
+    let second = offers_by_id(&deps, 2, first.next_cursor);
+    assert_eq!(page_ids(&second), vec![3, 4]);
+    assert_eq!(second.next_cursor, None);
+
+    assert_eq!(page_ids(&offers_by_id(&deps, 10, None)), vec![1, 3, 4]);
+}
+
+fn offers_by_reputation(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    min_owner_reputation: u64,
+    last: Option<u64>,
+) -> OffersPage {
+    let msg = offer_msg();
+    let query_msg = QueryMsg::OffersBy {
+        offer_type: msg.offer_type,
+        fiat_currency: msg.fiat_currency,
+        denom: msg.denom,
+        order: OfferOrder::TradesCount,
+        limit: 10,
+        last,
+        min_owner_reputation: Some(min_owner_reputation),
+    };
+    from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+}
+
+#[test]
+fn offers_by_skips_makers_below_the_reputation_bar() {
+    const VETERAN: &str = "veteran";
+    let mut deps = setup();
+    let veteran_offer = ExecuteMsg::Create { offer: offer_msg() };
+    let veteran_info = message_info(&Addr::unchecked(VETERAN), &[]);
+    execute(deps.as_mut(), mock_env(), veteran_info, veteran_offer).unwrap();
+    for _ in 0..MAX_OFFER_SCAN {
+        create_offer(&mut deps, offer_msg());
+    }
+
+    // Only the veteran has released trades.
+    let config = hub_config();
+    deps.querier.update_wasm(move |query| match query {
+        WasmQuery::Smart { contract_addr, .. } if contract_addr == HUB => {
+            SystemResult::Ok(ContractResult::Ok(to_json_binary(&config).unwrap()))
+        }
+        WasmQuery::Smart { msg, .. } => {
+            let response = match from_json(msg).unwrap() {
+                ProfileQueryMsg::Profile { addr } => to_json_binary(&Profile {
+                    released_trades_count: if addr.as_str() == VETERAN { 12 } else { 0 },
+                    ..Profile::new(addr, 0)
+                }),
+                ProfileQueryMsg::Profiles { .. } => to_json_binary(&Vec::<Profile>::new()),
+            };
+            SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+        }
+        _ => panic!("unexpected query: {:?}", query),
+    });
+
+    // The newest offers fill the whole scan window without a match.
+    let first = offers_by_reputation(&deps, 5, None);
+    assert_eq!(page_ids(&first), Vec::<u64>::new());
+    assert_eq!(first.next_cursor, Some(2));
+
+    let second = offers_by_reputation(&deps, 5, first.next_cursor);
+    assert_eq!(page_ids(&second), vec![1]);
+    assert_eq!(second.next_cursor, None);
+
+    let unfiltered = offers_by_reputation(&deps, 0, None);
+    assert_eq!(unfiltered.offers.len(), 10);
+    assert_eq!(unfiltered.next_cursor, Some(92));
+}
+
+fn create_batch(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    offers: Vec<OfferMsg>,
+) -> Result<Response, ContractError> {
+    maker_execute(deps, mock_env(), ExecuteMsg::CreateBatch { offers })
+}
+
+fn offers_count(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> u64 {
+    let state: OffersCount =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    state.count
+}
+
+#[test]
+fn batch_creates_offers_with_sequential_ids() {
+    let mut deps = setup();
+    create_offer(&mut deps, offer_msg());
+
+    let mut buy = offer_msg();
+    buy.offer_type = OfferType::Buy;
+    let res = create_batch(&mut deps, vec![offer_msg(), buy, offer_msg()]).unwrap();
+
+    let ids = res
+        .attributes
+        .iter()
+        .find(|attr| attr.key == "ids")
+        .unwrap();
+    assert_eq!(ids.value, "2,3,4");
+    assert_eq!(offers_count(&deps), 4);
+    assert_eq!(
+        offers().load(&deps.storage, 3).unwrap().offer_type,
+        OfferType::Buy
+    );
+}
+
+#[test]
+fn batch_is_rejected_as_a_whole() {
+    let mut deps = setup();
+
+    let oversized = vec![offer_msg(); MAX_OFFER_BATCH_SIZE + 1];
+    let err = create_batch(&mut deps, oversized).unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "offers")
+    );
+
+    let mut invalid = offer_msg();
+    invalid.min_amount = invalid.max_amount;
+    let err = create_batch(&mut deps, vec![offer_msg(), invalid]).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidMinMax { .. }));
+}
+
+fn offer_solvency(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    env: Env,
+    id: u64,
+) -> Option<SolvencyStatus> {
+    let response: OfferResponse =
+        from_json(query(deps.as_ref(), env, QueryMsg::Offer { id }).unwrap()).unwrap();
+    response.solvency
+}
+
+#[test]
+fn fresh_solvency_attestation_is_shown() {
+    let mut deps = setup();
+    let id = create_offer(&mut deps, offer_msg());
+    assert_eq!(offer_solvency(&deps, mock_env(), id), None);
+
+    deps.querier
+        .bank
+        .update_balance(MAKER, coins(70_000_000, "uatom"));
+    maker_execute(&mut deps, mock_env(), ExecuteMsg::AttestSolvency { id }).unwrap();
+
+    assert_eq!(
+        offer_solvency(&deps, env_at(60), id),
+        Some(SolvencyStatus {
+            balance: Uint128::new(70_000_000),
+            attested_at: mock_env().block.time.seconds(),
+            stale: false,
+        })
+    );
+}
+
+#[test]
+fn stale_solvency_attestation_is_flagged() {
+    let mut deps = setup();
+    let id = create_offer(&mut deps, offer_msg());
+    deps.querier
+        .bank
+        .update_balance(MAKER, coins(70_000_000, "uatom"));
+    maker_execute(&mut deps, mock_env(), ExecuteMsg::AttestSolvency { id }).unwrap();
+
+    let solvency = offer_solvency(&deps, env_at(SOLVENCY_ATTESTATION_TTL + 1), id).unwrap();
+    assert!(solvency.stale);
+
+    // Attesting again refreshes it.
+    let later = env_at(SOLVENCY_ATTESTATION_TTL + 1);
+    maker_execute(&mut deps, later.clone(), ExecuteMsg::AttestSolvency { id }).unwrap();
+    assert!(!offer_solvency(&deps, later, id).unwrap().stale);
+}
+
+#[test]
+fn only_the_owner_of_a_sell_offer_can_attest_solvency() {
+    let mut deps = setup();
+    let id = create_offer(&mut deps, offer_msg());
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked("someone"), &[]),
+        ExecuteMsg::AttestSolvency { id },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    let mut buy = offer_msg();
+    buy.offer_type = OfferType::Buy;
+    let buy_id = create_offer(&mut deps, buy);
+    let err = maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::AttestSolvency { id: buy_id },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "offer_type")
+    );
+}
+
+const TRADE_AMOUNT: u128 = 1_000_000;
+
+fn update_active_trades(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    sender: &str,
+    trade_state: TradeState,
+    amount: u128,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::UpdateActiveTrades {
+            id: 1,
+            trade_state,
+            amount: Uint128::new(amount),
+        },
+    )
+}
+
+#[test]
+fn offer_caps_its_active_trades() {
+    let mut deps = setup();
+    let mut msg = offer_msg();
+    msg.max_active_trades = Some(1);
+    create_offer(&mut deps, msg);
+
+    update_active_trades(&mut deps, "trade", TradeState::RequestCreated, TRADE_AMOUNT).unwrap();
+    let err = update_active_trades(&mut deps, "trade", TradeState::RequestCreated, TRADE_AMOUNT)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OfferActiveTradesLimitReached {
+            offer_id: 1,
+            limit: 1
+        }
+    ));
+
+    // A closed trade frees its slot
+    update_active_trades(&mut deps, "trade", TradeState::EscrowReleased, TRADE_AMOUNT).unwrap();
+    update_active_trades(&mut deps, "trade", TradeState::RequestCreated, TRADE_AMOUNT).unwrap();
+    assert_eq!(
+        offers().load(&deps.storage, 1).unwrap().active_trades_count,
+        1
+    );
+
+    let err = update_active_trades(&mut deps, MAKER, TradeState::RequestCanceled, TRADE_AMOUNT)
+        .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+}
+
+#[test]
+fn max_active_trades_must_be_positive() {
+    let mut deps = setup();
+    let mut msg = offer_msg();
+    msg.max_active_trades = Some(0);
+    let err = maker_execute(&mut deps, mock_env(), ExecuteMsg::Create { offer: msg }).unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "max_active_trades")
+    );
+
+    let id = create_offer(&mut deps, offer_msg());
+    let mut update = rate_update(id, 100);
+    update.max_active_trades = Some(0);
+    let err = maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: update,
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "max_active_trades")
+    );
+}
+
+#[test]
+fn offer_amounts_must_stay_inside_the_usd_trading_limits() {
+    let mut deps = setup();
+    let info = message_info(&Addr::unchecked(MAKER), &[]);
+
+    // 6 decimals mistaken for 9, the max is worth 2_000_000 USD
+    let mut msg = offer_msg();
+    msg.max_amount = Uint128::new(200_000_000);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::Create { offer: msg },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OfferMaxAboveTradingLimit { max_amount, trading_limit }
+            if max_amount == Uint256::from(2_000_000u128)
+                && trading_limit == Uint256::from(1_000_000u128)
+    ));
+
+    let mut msg = offer_msg();
+    msg.min_amount = Uint128::new(50);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Create { offer: msg },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OfferMinBelowTradingLimit { min_amount, .. } if min_amount.is_zero()
+    ));
+
+    // A rate update can push the max over the limit as well
+    let id = create_offer(&mut deps, offer_msg());
+    let err = maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: rate_update(id, 250),
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OfferMaxAboveTradingLimit { max_amount, .. }
+            if max_amount == Uint256::from(1_250_000u128)
+    ));
+}
+
+#[test]
+fn offers_can_not_be_created_or_updated_while_paused() {
+    let mut deps = setup();
+    let id = create_offer(&mut deps, offer_msg());
+    mock_queries(
+        &mut deps,
+        HubConfig {
+            paused: true,
+            ..hub_config()
+        },
+    );
+
+    let create = ExecuteMsg::Create { offer: offer_msg() };
+    let err = maker_execute(&mut deps, mock_env(), create).unwrap_err();
+    assert!(matches!(err, ContractError::ContractPaused {}));
+
+    let err = create_batch(&mut deps, vec![offer_msg()]).unwrap_err();
+    assert!(matches!(err, ContractError::ContractPaused {}));
+
+    let update = ExecuteMsg::UpdateOffer {
+        offer_update: rate_update(id, 110),
+    };
+    let err = maker_execute(&mut deps, mock_env(), update).unwrap_err();
+    assert!(matches!(err, ContractError::ContractPaused {}));
+    assert_eq!(offers_count(&deps), 1);
+}
+
+fn offers_by_rate(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    min_rate: Option<u128>,
+    max_rate: Option<u128>,
+    limit: u32,
+    last: Option<u64>,
+) -> Vec<(u64, u128)> {
+    let msg = offer_msg();
+    let offers: Vec<OfferResponse> = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::OffersByRate {
+                offer_type: msg.offer_type,
+                fiat_currency: msg.fiat_currency,
+                denom: msg.denom,
+                min_rate: min_rate.map(Uint128::new),
+                max_rate: max_rate.map(Uint128::new),
+                limit,
+                last,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    offers
+        .iter()
+        .map(|response| (response.offer.id, response.offer.rate.u128()))
+        .collect()
+}
+
+#[test]
+fn offers_are_filtered_by_an_inclusive_rate_band() {
+    let mut deps = setup();
+    for rate in [110, 90, 100, 150, 100] {
+        let mut msg = offer_msg();
+        msg.rate = Uint128::new(rate);
+        create_offer(&mut deps, msg);
+    }
+    let mut buy = offer_msg();
+    buy.offer_type = OfferType::Buy;
+    create_offer(&mut deps, buy);
+
+    assert_eq!(
+        offers_by_rate(&deps, Some(100), Some(110), 10, None),
+        vec![(3, 100), (5, 100), (1, 110)]
+    );
+    assert_eq!(
+        offers_by_rate(&deps, None, None, 10, None),
+        vec![(2, 90), (3, 100), (5, 100), (1, 110), (4, 150)]
+    );
+
+    // Pages continue after the last offer, also between offers of equal rate
+    assert_eq!(
+        offers_by_rate(&deps, Some(100), None, 2, None),
+        vec![(3, 100), (5, 100)]
+    );
+    assert_eq!(
+        offers_by_rate(&deps, Some(100), None, 2, Some(3)),
+        vec![(5, 100), (1, 110)]
+    );
+    assert_eq!(
+        offers_by_rate(&deps, Some(100), None, 2, Some(1)),
+        vec![(4, 150)]
+    );
+
+    assert!(offers_by_rate(&deps, Some(120), Some(140), 10, None).is_empty());
+    assert!(offers_by_rate(&deps, Some(110), Some(100), 10, None).is_empty());
+}
+
+fn best_offer(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    offer_type: OfferType,
+    amount: u128,
+) -> Option<u64> {
+    let msg = offer_msg();
+    let best: Option<OfferResponse> = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::BestOffer {
+                offer_type,
+                fiat_currency: msg.fiat_currency,
+                denom: msg.denom,
+                amount: Uint128::new(amount),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    best.map(|response| response.offer.id)
+}
+
+#[test]
+fn best_offer_is_the_best_rate_that_takes_the_amount() {
+    let mut deps = setup();
+    // Sell offers 1 to 3, the cheapest only takes up to 5_000_000
+    for (rate, max_amount) in [(90, 5_000_000), (100, 50_000_000), (110, 50_000_000)] {
+        create_offer(
+            &mut deps,
+            OfferMsg {
+                rate: Uint128::new(rate),
+                max_amount: Uint128::new(max_amount),
+                ..offer_msg()
+            },
+        );
+    }
+    // Buy offers 4 and 5, the highest paying needs at least 20_000_000
+    for (rate, min_amount) in [(120, 20_000_000), (110, 1_000_000)] {
+        create_offer(
+            &mut deps,
+            OfferMsg {
+                offer_type: OfferType::Buy,
+                rate: Uint128::new(rate),
+                min_amount: Uint128::new(min_amount),
+                ..offer_msg()
+            },
+        );
+    }
+
+    assert_eq!(best_offer(&deps, OfferType::Sell, 2_000_000), Some(1));
+    assert_eq!(best_offer(&deps, OfferType::Sell, 10_000_000), Some(2));
+    assert_eq!(best_offer(&deps, OfferType::Buy, 30_000_000), Some(4));
+    assert_eq!(best_offer(&deps, OfferType::Buy, 10_000_000), Some(5));
+    assert_eq!(best_offer(&deps, OfferType::Sell, 60_000_000), None);
+}
+
+fn toggle_favorite(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    offer_id: u64,
+) -> String {
+    let info = message_info(&Addr::unchecked("taker"), &[]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::ToggleFavorite { offer_id },
+    )
+    .unwrap();
+    res.attributes
+        .iter()
+        .find(|attr| attr.key == "favorite")
+        .unwrap()
+        .value
+        .clone()
+}
+
+fn favorites(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    limit: u32,
+    start_after: Option<u64>,
+) -> Vec<(u64, OfferState)> {
+    let offers: Vec<OfferResponse> = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Favorites {
+                owner: Addr::unchecked("taker"),
+                limit,
+                start_after,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    offers
+        .into_iter()
+        .map(|response| (response.offer.id, response.offer.state))
+        .collect()
+}
+
+#[test]
+fn takers_keep_favorites_across_offer_state_changes() {
+    let mut deps = setup();
+    let ids: Vec<u64> = (0..3)
+        .map(|_| create_offer(&mut deps, offer_msg()))
+        .collect();
+
+    assert_eq!(toggle_favorite(&mut deps, ids[2]), "true");
+    assert_eq!(toggle_favorite(&mut deps, ids[0]), "true");
+    assert_eq!(toggle_favorite(&mut deps, 99), "ignored");
+    assert_eq!(
+        favorites(&deps, 10, None),
+        vec![(ids[0], OfferState::Active), (ids[2], OfferState::Active)]
+    );
+
+    // A paused offer stays saved and reports its new state
+    let mut pause = rate_update(ids[2], 100);
+    pause.state = OfferState::Paused;
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: pause,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        favorites(&deps, 1, Some(ids[0])),
+        vec![(ids[2], OfferState::Paused)]
+    );
+
+    // Toggling again removes it, other takers' favorites are separate
+    assert_eq!(toggle_favorite(&mut deps, ids[0]), "false");
+    assert_eq!(
+        favorites(&deps, 10, None),
+        vec![(ids[2], OfferState::Paused)]
+    );
+    let other: Vec<OfferResponse> = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Favorites {
+                owner: Addr::unchecked(MAKER),
+                limit: 10,
+                start_after: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(other.is_empty());
+}
+
+#[test]
+fn offer_denoms_are_stored_in_canonical_form() {
+    let mut deps = setup();
+    let mut msg = offer_msg();
+    msg.denom = Denom::Native(" uatom ".to_string());
+    let id = create_offer(&mut deps, msg);
+    let stored = offers().load(&deps.storage, id).unwrap();
+    assert_eq!(stored.denom, Denom::Native("uatom".to_string()));
+
+    // Lands in the same order book as an offer created with the canonical denom
+    create_offer(&mut deps, offer_msg());
+    assert_eq!(offers_by_rate(&deps, None, None, 10, None).len(), 2);
+
+    let mut msg = offer_msg();
+    msg.denom = Denom::Native("u atom".to_string());
+    let err = maker_execute(&mut deps, mock_env(), ExecuteMsg::Create { offer: msg }).unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "denom")
+    );
+}
+
+#[test]
+fn offers_keep_the_key_version_they_were_created_under() {
+    let mut deps = setup();
+    let old_offer = create_offer(&mut deps, offer_msg());
+
+    let rotated = Profile {
+        encryption_key: Some("cm90YXRlZF9rZXk=".to_string()),
+        key_version: 2,
+        ..Profile::new(Addr::unchecked(MAKER), 0)
+    };
+    mock_queries_with_profile(&mut deps, hub_config(), rotated);
+    let new_offer = create_offer(
+        &mut deps,
+        OfferMsg {
+            owner_encryption_key: "cm90YXRlZF9rZXk=".to_string(),
+            ..offer_msg()
+        },
+    );
+
+    let load = |id: u64| -> OfferResponse {
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Offer { id }).unwrap()).unwrap()
+    };
+    let old = load(old_offer);
+    assert_eq!(old.offer.owner_key_version, 1);
+    assert_eq!(old.profile.key_version, 2);
+    assert_eq!(load(new_offer).offer.owner_key_version, 2);
+}
+
+#[test]
+fn offers_are_only_created_for_listed_pairs() {
+    let uatom = Denom::Native("uatom".to_string());
+    let mut pairs = vec![CurrencyPair {
+        fiat_currency: FiatCurrency::USD,
+        denom: uatom.clone(),
+    }];
+    let profile = Profile::new(Addr::unchecked(MAKER), 0);
+    let mut deps = setup();
+    mock_queries_with_pairs(
+        &mut deps,
+        hub_config(),
+        profile.clone(),
+        Some(pairs.clone()),
+    );
+    create_offer(&mut deps, offer_msg());
+
+    let eur_offer = || ExecuteMsg::Create {
+        offer: OfferMsg {
+            fiat_currency: FiatCurrency::EUR,
+            ..offer_msg()
+        },
+    };
+    let err = maker_execute(&mut deps, mock_env(), eur_offer()).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::UnsupportedPair { fiat_currency: FiatCurrency::EUR, denom } if denom == "uatom"
+    ));
+
+    pairs.push(CurrencyPair {
+        fiat_currency: FiatCurrency::EUR,
+        denom: uatom,
+    });
+    mock_queries_with_pairs(&mut deps, hub_config(), profile, Some(pairs));
+    maker_execute(&mut deps, mock_env(), eur_offer()).unwrap();
+}
+
+fn assert_invalid_contact(result: Result<Response, ContractError>) {
+    assert!(matches!(
+        result.unwrap_err(),
+        ContractError::InvalidContact { .. }
+    ));
+}
+
+#[test]
+fn offers_reject_oversized_contacts_and_malformed_keys() {
+    let mut deps = setup();
+    let oversized = "a".repeat(CONTACT_LIMIT as usize + 1);
+
+    let create = |msg: OfferMsg| ExecuteMsg::Create { offer: msg };
+    assert_invalid_contact(maker_execute(
+        &mut deps,
+        mock_env(),
+        create(OfferMsg {
+            owner_contact: oversized.clone(),
+            ..offer_msg()
+        }),
+    ));
+    for key in ["maker_key", "abc", "bWFrZXJfa2V5===", ""] {
+        assert_invalid_contact(maker_execute(
+            &mut deps,
+            mock_env(),
+            create(OfferMsg {
+                owner_encryption_key: key.to_string(),
+                ..offer_msg()
+            }),
+        ));
+    }
+
+    let id = create_offer(&mut deps, offer_msg());
+    let update = |contact: &str, key: &str| ExecuteMsg::UpdateOffer {
+        offer_update: OfferUpdateMsg {
+            owner_contact: Some(contact.to_string()),
+            owner_encryption_key: Some(key.to_string()),
+            ..rate_update(id, 120)
+        },
+    };
+    assert_invalid_contact(maker_execute(
+        &mut deps,
+        mock_env(),
+        update(&oversized, "bWFrZXJfa2V5"),
+    ));
+    assert_invalid_contact(maker_execute(
+        &mut deps,
+        mock_env(),
+        update("maker_contact", "not a key"),
+    ));
+    let res = maker_execute(&mut deps, mock_env(), update("maker_contact", "a1b2c3d4")).unwrap();
+    assert_eq!(res.messages.len(), 1);
+}
+
+const OFFER_TTL: u64 = 3600;
+
+/// Creates one offer expiring `OFFER_TTL` seconds from now and one that never
+/// expires.
+fn expiring_setup() -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, u64, u64) {
+    let mut deps = setup();
+    let expires_at = mock_env().block.time.seconds() + OFFER_TTL;
+    let expiring = create_offer(
+        &mut deps,
+        OfferMsg {
+            expires_at: Some(expires_at),
+            ..offer_msg()
+        },
+    );
+    let open_ended = create_offer(&mut deps, offer_msg());
+    (deps, expiring, open_ended)
+}
+
+fn listed_ids(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, env: Env) -> Vec<u64> {
+    let msg = offer_msg();
+    let by_id: OffersPage = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersById {
+                offer_type: msg.offer_type.clone(),
+                fiat_currency: msg.fiat_currency.clone(),
+                denom: msg.denom.clone(),
+                limit: 10,
+                after: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let by_rate: Vec<OfferResponse> = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersByRate {
+                offer_type: msg.offer_type.clone(),
+                fiat_currency: msg.fiat_currency.clone(),
+                denom: msg.denom.clone(),
+                min_rate: None,
+                max_rate: None,
+                limit: 10,
+                last: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let by_trades: OffersPage = from_json(
+        query(
+            deps.as_ref(),
+            env,
+            QueryMsg::OffersBy {
+                offer_type: msg.offer_type,
+                fiat_currency: msg.fiat_currency,
+                denom: msg.denom,
+                order: OfferOrder::TradesCount,
+                limit: 10,
+                last: None,
+                min_owner_reputation: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let mut ids = page_ids(&by_id);
+    ids.sort_unstable();
+    for responses in [by_rate, by_trades.offers] {
+        let mut other: Vec<u64> = responses.iter().map(|r| r.offer.id).collect();
+        other.sort_unstable();
+        assert_eq!(other, ids);
+    }
+    ids
+}
+
+fn offer_state(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    env: Env,
+    id: u64,
+) -> OfferState {
+    let response: OfferResponse =
+        from_json(query(deps.as_ref(), env, QueryMsg::Offer { id }).unwrap()).unwrap();
+    response.offer.state
+}
+
+#[test]
+fn expired_offers_are_hidden_from_listings() {
+    let (deps, expiring, open_ended) = expiring_setup();
+
+    assert_eq!(
+        listed_ids(&deps, env_at(OFFER_TTL - 1)),
+        vec![expiring, open_ended]
+    );
+    assert_eq!(
+        offer_state(&deps, env_at(OFFER_TTL - 1), expiring),
+        OfferState::Active
+    );
+
+    assert_eq!(listed_ids(&deps, env_at(OFFER_TTL)), vec![open_ended]);
+    assert_eq!(
+        offer_state(&deps, env_at(OFFER_TTL), expiring),
+        OfferState::Archive
+    );
+}
+
+#[test]
+fn offers_can_not_be_created_already_expired() {
+    let mut deps = setup();
+    let msg = OfferMsg {
+        expires_at: Some(mock_env().block.time.seconds()),
+        ..offer_msg()
+    };
+    let err = maker_execute(&mut deps, mock_env(), ExecuteMsg::Create { offer: msg }).unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "expires_at")
+    );
+}
+
+#[test]
+fn anyone_can_archive_an_expired_offer() {
+    let (mut deps, expiring, open_ended) = expiring_setup();
+    let anyone = message_info(&Addr::unchecked("anyone"), &[]);
+    let expire = |id| ExecuteMsg::Expire { id };
+
+    let err = execute(
+        deps.as_mut(),
+        env_at(OFFER_TTL - 1),
+        anyone.clone(),
+        expire(expiring),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::OfferNotExpired { .. }));
+    let err = execute(
+        deps.as_mut(),
+        env_at(OFFER_TTL),
+        anyone.clone(),
+        expire(open_ended),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OfferNotExpired {
+            expires_at: None,
+            ..
+        }
+    ));
+
+    let res = execute(
+        deps.as_mut(),
+        env_at(OFFER_TTL),
+        anyone.clone(),
+        expire(expiring),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    let stored = offers().load(&deps.storage, expiring).unwrap();
+    assert_eq!(stored.state, OfferState::Archive);
+    assert_eq!(stats(&deps).totals, state_counts(1, 0, 1));
+
+    let err = execute(deps.as_mut(), env_at(OFFER_TTL), anyone, expire(expiring)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidOfferStateChange { .. }));
+}
+
+fn stats(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> OfferStats {
+    from_json(query(deps.as_ref(), mock_env(), QueryMsg::Stats {}).unwrap()).unwrap()
+}
+
+fn state_counts(active: u64, paused: u64, archived: u64) -> OfferStateCounts {
+    OfferStateCounts {
+        active,
+        paused,
+        archived,
+        filled: 0,
+    }
+}
+
+#[test]
+fn pausing_an_offer_moves_it_from_active_to_paused() {
+    let mut deps = setup();
+    let usd = create_offer(&mut deps, offer_msg());
+    create_offer(&mut deps, offer_msg());
+    create_offer(
+        &mut deps,
+        OfferMsg {
+            fiat_currency: FiatCurrency::EUR,
+            ..offer_msg()
+        },
+    );
+    assert_eq!(stats(&deps).totals, state_counts(3, 0, 0));
+
+    let pause = OfferUpdateMsg {
+        state: OfferState::Paused,
+        ..rate_update(usd, 100)
+    };
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: pause,
+        },
+    )
+    .unwrap();
+
+    let stats = stats(&deps);
+    assert_eq!(stats.total_created, 3);
+    assert_eq!(stats.totals, state_counts(2, 1, 0));
+    assert_eq!(
+        stats.by_fiat,
+        vec![
+            FiatOfferStats {
+                fiat_currency: FiatCurrency::EUR,
+                counts: state_counts(1, 0, 0),
+            },
+            FiatOfferStats {
+                fiat_currency: FiatCurrency::USD,
+                counts: state_counts(1, 1, 0),
+            },
+        ]
+    );
+}
+
+#[test]
+fn reindex_rebuilds_the_stats_across_pages() {
+    let mut deps = setup();
+    for _ in 0..=MAX_REINDEX_BATCH_SIZE {
+        create_offer(&mut deps, offer_msg());
+    }
+    // Offers stored before the counts existed
+    OFFER_STATS.clear(&mut deps.storage);
+
+    reindex(&mut deps, None).unwrap();
+    let err = reindex(&mut deps, Some(1)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidParameter { .. }));
+
+    // One offer the pass has counted and one it has not reached yet
+    for id in [1, MAX_REINDEX_BATCH_SIZE as u64 + 1] {
+        let pause = OfferUpdateMsg {
+            state: OfferState::Paused,
+            ..rate_update(id, 100)
+        };
+        maker_execute(
+            &mut deps,
+            mock_env(),
+            ExecuteMsg::UpdateOffer {
+                offer_update: pause,
+            },
+        )
+        .unwrap();
+    }
+    let active = MAX_REINDEX_BATCH_SIZE as u64 - 1;
+    assert_eq!(stats(&deps).totals, state_counts(active, 1, 0));
+
+    reindex(&mut deps, Some(MAX_REINDEX_BATCH_SIZE as u64)).unwrap();
+    assert_eq!(stats(&deps).totals, state_counts(active, 2, 0));
+}
+
+fn takeable_offers(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    env: Env,
+    taker: &str,
+) -> Vec<u64> {
+    let msg = QueryMsg::TakeableOffers {
+        offer_type: OfferType::Sell,
+        fiat_currency: FiatCurrency::USD,
+        denom: Denom::Native("uatom".to_string()),
+        taker: Addr::unchecked(taker),
+    };
+    let offers: Vec<Offer> = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+    offers.iter().map(|offer| offer.id).collect()
+}
+
+#[test]
+fn takeable_offers_are_best_rate_first_and_skip_what_the_taker_cant_take() {
+    let mut deps = setup();
+    let ids: Vec<u64> = [105, 98, 99]
+        .iter()
+        .map(|rate| {
+            create_offer(
+                &mut deps,
+                OfferMsg {
+                    rate: Uint128::new(*rate),
+                    ..offer_msg()
+                },
+            )
+        })
+        .collect();
+    let expiring = create_offer(
+        &mut deps,
+        OfferMsg {
+            expires_at: Some(mock_env().block.time.seconds() + 1),
+            ..offer_msg()
+        },
+    );
+    assert_eq!(
+        takeable_offers(&deps, mock_env(), "taker"),
+        vec![ids[1], ids[2], expiring, ids[0]]
+    );
+
+    let mut later = mock_env();
+    later.block.time = later.block.time.plus_seconds(1);
+    assert_eq!(
+        takeable_offers(&deps, later, "taker"),
+        vec![ids[1], ids[2], ids[0]]
+    );
+    assert!(takeable_offers(&deps, mock_env(), MAKER).is_empty());
+
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::BlockTaker {
+            taker: Addr::unchecked("taker"),
+        },
+    )
+    .unwrap();
+    assert!(takeable_offers(&deps, mock_env(), "taker").is_empty());
+}
+
+fn blocked_takers(
+    deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    start_after: Option<&str>,
+) -> Vec<Addr> {
+    let msg = QueryMsg::BlockedTakers {
+        maker: Addr::unchecked(MAKER),
+        limit: 10,
+        start_after: start_after.map(Addr::unchecked),
+    };
+    from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+}
+
+fn is_taker_blocked(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, taker: &str) -> bool {
+    let msg = QueryMsg::IsTakerBlocked {
+        maker: Addr::unchecked(MAKER),
+        taker: Addr::unchecked(taker),
+    };
+    from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+}
+
+#[test]
+fn makers_block_and_unblock_takers() {
+    let mut deps = setup();
+    for taker in ["taker_b", "taker_a"] {
+        let msg = ExecuteMsg::BlockTaker {
+            taker: Addr::unchecked(taker),
+        };
+        maker_execute(&mut deps, mock_env(), msg).unwrap();
+    }
+    assert_eq!(
+        blocked_takers(&deps, None),
+        vec![Addr::unchecked("taker_a"), Addr::unchecked("taker_b")]
+    );
+    assert_eq!(
+        blocked_takers(&deps, Some("taker_a")),
+        vec![Addr::unchecked("taker_b")]
+    );
+    assert!(is_taker_blocked(&deps, "taker_a"));
+
+    let msg = ExecuteMsg::UnblockTaker {
+        taker: Addr::unchecked("taker_a"),
+    };
+    maker_execute(&mut deps, mock_env(), msg).unwrap();
+    assert!(!is_taker_blocked(&deps, "taker_a"));
+    assert_eq!(
+        blocked_takers(&deps, None),
+        vec![Addr::unchecked("taker_b")]
+    );
+
+    let msg = ExecuteMsg::BlockTaker {
+        taker: Addr::unchecked(MAKER),
+    };
+    let err = maker_execute(&mut deps, mock_env(), msg).unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "taker")
+    );
+}
+
+fn remaining_amount(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> Uint128 {
+    let response: OfferResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Offer { id: 1 }).unwrap()).unwrap();
+    response.remaining_amount
+}
+
+#[test]
+fn partial_fills_keep_the_offer_active_while_the_rest_covers_min_amount() {
+    let mut deps = setup();
+    create_offer(&mut deps, offer_msg());
+
+    update_active_trades(&mut deps, "trade", TradeState::RequestCreated, 20_000_000).unwrap();
+    assert_eq!(remaining_amount(&deps), Uint128::new(30_000_000));
+    assert_eq!(offer_state(&deps, mock_env(), 1), OfferState::Active);
+    assert_eq!(listed_ids(&deps, mock_env()), vec![1]);
+
+    let err = update_active_trades(&mut deps, "trade", TradeState::RequestCreated, 30_000_001)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InsufficientOfferLiquidity { available, .. } if available == Uint128::new(30_000_000)
+    ));
+
+    // Released funds stay filled, canceled ones go back to the offer
+    update_active_trades(&mut deps, "trade", TradeState::RequestCreated, 5_000_000).unwrap();
+    update_active_trades(&mut deps, "trade", TradeState::EscrowReleased, 20_000_000).unwrap();
+    update_active_trades(&mut deps, "trade", TradeState::RequestCanceled, 5_000_000).unwrap();
+    assert_eq!(remaining_amount(&deps), Uint128::new(30_000_000));
+}
+
+#[test]
+fn offers_filled_below_min_amount_are_marked_filled() {
+    let mut deps = setup();
+    create_offer(&mut deps, offer_msg());
+
+    let res =
+        update_active_trades(&mut deps, "trade", TradeState::RequestCreated, 49_500_000).unwrap();
+    assert_eq!(remaining_amount(&deps), Uint128::new(500_000));
+    assert_eq!(offer_state(&deps, mock_env(), 1), OfferState::Filled);
+    assert_eq!(
+        res.messages,
+        vec![update_profile_active_offers_msg(
+            hub_config().profile_addr.to_string(),
+            Addr::unchecked(MAKER),
+            OfferState::Filled,
+        )]
+    );
+    assert!(listed_ids(&deps, mock_env()).is_empty());
+    assert_eq!(
+        stats(&deps).totals,
+        OfferStateCounts {
+            filled: 1,
+            ..state_counts(0, 0, 0)
+        }
+    );
+
+    let err = update_active_trades(&mut deps, "trade", TradeState::RequestCreated, TRADE_AMOUNT)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InsufficientOfferLiquidity { .. }
+    ));
+}
+
+/// Creates an offer with `auto_repost` and fills it past its min amount.
+fn filled_offer(auto_repost: bool) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = setup();
+    create_offer(
+        &mut deps,
+        OfferMsg {
+            auto_repost,
+            ..offer_msg()
+        },
+    );
+    update_active_trades(&mut deps, "trade", TradeState::RequestCreated, 49_500_000).unwrap();
+    assert_eq!(offer_state(&deps, mock_env(), 1), OfferState::Filled);
+    deps
+}
+
+#[test]
+fn auto_repost_offers_are_active_again_once_their_trade_settles() {
+    let mut deps = filled_offer(true);
+    let res =
+        update_active_trades(&mut deps, "trade", TradeState::EscrowReleased, 49_500_000).unwrap();
+
+    assert_eq!(offer_state(&deps, mock_env(), 1), OfferState::Active);
+    assert_eq!(remaining_amount(&deps), Uint128::new(50_000_000));
+    assert_eq!(
+        res.messages,
+        vec![update_profile_active_offers_msg(
+            hub_config().profile_addr.to_string(),
+            Addr::unchecked(MAKER),
+            OfferState::Active,
+        )]
+    );
+    assert_eq!(listed_ids(&deps, mock_env()), vec![1]);
+    assert_eq!(stats(&deps).totals, state_counts(1, 0, 0));
+}
+
+#[test]
+fn offers_without_auto_repost_stay_filled() {
+    let mut deps = filled_offer(false);
+    let res =
+        update_active_trades(&mut deps, "trade", TradeState::EscrowReleased, 49_500_000).unwrap();
+
+    assert_eq!(offer_state(&deps, mock_env(), 1), OfferState::Filled);
+    assert_eq!(remaining_amount(&deps), Uint128::new(500_000));
+    assert!(res.messages.is_empty());
+}
+
+#[test]
+fn offers_archived_by_their_owner_are_not_reposted() {
+    let mut deps = filled_offer(true);
+    let archive = OfferUpdateMsg {
+        state: OfferState::Archive,
+        ..rate_update(1, 100)
+    };
+    maker_execute(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::UpdateOffer {
+            offer_update: archive,
+        },
+    )
+    .unwrap();
+
+    update_active_trades(&mut deps, "trade", TradeState::EscrowReleased, 49_500_000).unwrap();
+    assert_eq!(offer_state(&deps, mock_env(), 1), OfferState::Archive);
+}
+
+fn prefunded_msg() -> OfferMsg {
+    OfferMsg {
+        prefunded: true,
+        ..offer_msg()
+    }
+}
+
+fn create_funded(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    funds: u128,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(MAKER), &coins(funds, "uatom")),
+        msg,
+    )
+}
+
+fn load_offer(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, id: u64) -> OfferResponse {
+    from_json(query(deps.as_ref(), mock_env(), QueryMsg::Offer { id }).unwrap()).unwrap()
+}
+
+#[test]
+fn prefunded_sell_offers_hold_their_max_amount() {
+    let mut deps = setup();
+    let create = |offer| ExecuteMsg::Create { offer };
+
+    let err = create_funded(&mut deps, 1_000_000, create(prefunded_msg())).unwrap_err();
+    assert!(matches!(err, ContractError::FundEscrowError { .. }));
+    let buy_offer = OfferMsg {
+        offer_type: OfferType::Buy,
+        ..prefunded_msg()
+    };
+    let err = create_funded(&mut deps, 50_000_000, create(buy_offer)).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InvalidParameter { ref parameter, .. } if parameter == "prefunded"
+    ));
+    let batch = ExecuteMsg::CreateBatch {
+        offers: vec![prefunded_msg()],
+    };
+    let err = create_funded(&mut deps, 50_000_000, batch).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidParameter { .. }));
+
+    create_funded(&mut deps, 50_000_000, create(prefunded_msg())).unwrap();
+    let prefunded = load_offer(&deps, 1);
+    assert!(prefunded.backed);
+    assert_eq!(prefunded.offer.deposit, Uint128::new(50_000_000));
+    let unfunded = create_offer(&mut deps, offer_msg());
+    assert!(!load_offer(&deps, unfunded).backed);
+}
+
+#[test]
+fn closing_a_prefunded_offer_refunds_its_deposit() {
+    let mut deps = setup();
+    let refund = SubMsg::new(BankMsg::Send {
+        to_address: MAKER.to_string(),
+        amount: coins(50_000_000, "uatom"),
+    });
+    let create = ExecuteMsg::Create {
+        offer: prefunded_msg(),
+    };
+    create_funded(&mut deps, 50_000_000, create).unwrap();
+    let archive = |id| ExecuteMsg::UpdateOffer {
+        offer_update: OfferUpdateMsg {
+            state: OfferState::Archive,
+            ..rate_update(id, 100)
+        },
+    };
+
+    let res = maker_execute(&mut deps, mock_env(), archive(1)).unwrap();
+    assert!(res.messages.contains(&refund));
+    assert!(!load_offer(&deps, 1).backed);
+    // The deposit is only sent back once.
+    let res = maker_execute(&mut deps, mock_env(), archive(1)).unwrap();
+    assert!(!res.messages.contains(&refund));
+
+    let create = ExecuteMsg::Create {
+        offer: OfferMsg {
+            expires_at: Some(mock_env().block.time.seconds() + OFFER_TTL),
+            ..prefunded_msg()
+        },
+    };
+    create_funded(&mut deps, 50_000_000, create).unwrap();
+    let anyone = message_info(&Addr::unchecked("anyone"), &[]);
+    let expire = ExecuteMsg::Expire { id: 2 };
+    let res = execute(deps.as_mut(), env_at(OFFER_TTL), anyone, expire).unwrap();
+    assert!(res.messages.contains(&refund));
+    assert!(!load_offer(&deps, 2).backed);
+}
@@ -45,6 +45,9 @@ pub fn execute(
             contact,
             encryption_key,
         } => update_profile_contact(deps, env, info, profile_addr, contact, encryption_key),
+        ExecuteMsg::RotateEncryptionKey { new_key } => {
+            rotate_encryption_key(deps, env, info, new_key)
+        }
         ExecuteMsg::UpdateTradesCount {
             profile_addr,
             trade_state,
@@ -81,6 +84,7 @@ fn update_profile_contact(
         profile.created_at = created_at
     }
     profile.contact = Some(contact.clone());
+    profile.key_version = profile.key_version_for(&encryption_key);
     profile.encryption_key = Some(encryption_key.clone());
     ProfileModel::store(storage, &profile);
 
@@ -92,6 +96,34 @@ fn update_profile_contact(
     Ok(res)
 }
 
+fn rotate_encryption_key(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_key: String,
+) -> Result<Response, ContractError> {
+    let mut profile = ProfileModel::query_profile(deps.storage, info.sender.clone());
+    if new_key.trim().is_empty() || profile.encryption_key.as_deref() == Some(new_key.as_str()) {
+        return Err(ContractError::InvalidParameter {
+            parameter: "new_key".to_string(),
+            message: Some("The new key must be set and differ from the current one.".to_string()),
+        });
+    }
+    if profile.created_at.eq(&0) {
+        profile.created_at = env.block.time.seconds();
+    }
+    profile.key_version = profile.key_version_for(&new_key);
+    profile.encryption_key = Some(new_key.clone());
+    ProfileModel::store(deps.storage, &profile);
+
+    let res = Response::new()
+        .add_attribute("action", "rotate_encryption_key")
+        .add_attribute("profile_addr", info.sender.to_string())
+        .add_attribute("encryption_pk", new_key)
+        .add_attribute("key_version", profile.key_version.to_string());
+    Ok(res)
+}
+
 pub fn update_trades_count(
     deps: DepsMut,
     env: Env,
@@ -187,7 +219,7 @@ pub fn update_active_offers(
                 profile.active_offers_count -= 1;
             }
         }
-        OfferState::Archive => {
+        OfferState::Archive | OfferState::Filled => {
             if profile.active_offers_count > 0 {
                 profile.active_offers_count -= 1;
             }
@@ -1 +1,4 @@
 pub mod contract;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,57 @@
+use cosmwasm_std::testing::{
+    message_info, mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{from_json, Addr, OwnedDeps, Response};
+use localmoney_protocol::errors::ContractError;
+use localmoney_protocol::profile::{ExecuteMsg, Profile, ProfileModel, QueryMsg};
+
+use crate::contract::{execute, query};
+
+const OWNER: &str = "owner";
+
+fn rotate(
+    deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    new_key: &str,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(OWNER), &[]),
+        ExecuteMsg::RotateEncryptionKey {
+            new_key: new_key.to_string(),
+        },
+    )
+}
+
+fn profile(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> Profile {
+    let msg = QueryMsg::Profile {
+        addr: Addr::unchecked(OWNER),
+    };
+    from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+}
+
+#[test]
+fn rotating_the_key_bumps_its_version_and_keeps_the_contact() {
+    let mut deps = mock_dependencies();
+    ProfileModel::store(
+        &mut deps.storage,
+        &Profile {
+            contact: Some("sealed_contact".to_string()),
+            encryption_key: Some("first_key".to_string()),
+            key_version: 1,
+            ..Profile::new(Addr::unchecked(OWNER), 1)
+        },
+    );
+
+    rotate(&mut deps, "second_key").unwrap();
+    let rotated = profile(&deps);
+    assert_eq!(rotated.key_version, 2);
+    assert_eq!(rotated.encryption_key, Some("second_key".to_string()));
+    assert_eq!(rotated.contact, Some("sealed_contact".to_string()));
+
+    let err = rotate(&mut deps, "second_key").unwrap_err();
+    assert!(matches!(err, ContractError::InvalidParameter { .. }));
+    let err = rotate(&mut deps, " ").unwrap_err();
+    assert!(matches!(err, ContractError::InvalidParameter { .. }));
+    assert_eq!(profile(&deps).key_version, 2);
+}
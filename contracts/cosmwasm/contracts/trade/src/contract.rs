@@ -1,38 +1,50 @@
 use cosmwasm_std::{
-    coin, entry_point, to_json_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, CustomQuery,
-    Decimal, Deps, DepsMut, Env, MessageInfo, Reply, ReplyOn, Response, StdResult, SubMsg, Uint128,
-    Uint256, WasmMsg,
+    attr, coin, entry_point, to_json_binary, Addr, Attribute, BankMsg, Binary, Coin, CosmosMsg,
+    CustomQuery, Deps, DepsMut, Env, MessageInfo, Reply, ReplyOn, Response, StdResult, Storage,
+    SubMsg, Uint128, Uint256, WasmMsg,
 };
 use cw2::{get_contract_version, set_contract_version};
 use std::ops::Sub;
 
 use cw20::Denom;
+use localmoney_protocol::constants::MAX_ARBITRATOR_RATING;
 use localmoney_protocol::currencies::FiatCurrency;
 use localmoney_protocol::denom_utils::denom_to_string;
 use localmoney_protocol::errors::ContractError;
 use localmoney_protocol::errors::ContractError::{
-    FundEscrowError, HubAlreadyRegistered, InvalidDenom, InvalidParameter, InvalidTradeState,
-    OfferNotFound, RefundErrorNotExpired, TradeExpired,
+    HubAlreadyRegistered, InvalidParameter, InvalidTradeState, OfferNotFound,
+    RefundErrorNotExpired, TradeExpired,
 };
 use localmoney_protocol::guards::{
-    assert_migration_parameters, assert_ownership, assert_sender_is_buyer_or_seller,
-    assert_trade_state_and_type, assert_trade_state_change, assert_trade_state_change_is_valid,
-    assert_value_in_range, validate_min_max_items_per_page,
+    assert_dispute_evidence_valid, assert_migration_parameters, assert_not_paused,
+    assert_offer_trade_capacity, assert_ownership, assert_sender_is_buyer_or_seller,
+    assert_sent_funds, assert_text_length, assert_trade_state_and_type, assert_trade_state_change,
+    assert_trade_state_change_is_valid, assert_valid_arbitrator, assert_value_in_range,
+    validate_min_max_items_per_page,
 };
 use localmoney_protocol::hub::HubConfig;
-use localmoney_protocol::hub_utils::{get_hub_admin, get_hub_config, register_hub_internal};
-use localmoney_protocol::offer::{load_offer, Arbitrator, OfferType, TradeInfo};
+use localmoney_protocol::hub_utils::{
+    get_hub_admin, get_hub_config, query_version, register_hub_internal,
+};
+use localmoney_protocol::offer::{
+    is_taker_blocked, load_offer, load_takeable_offers, update_offer_active_trades_msg, Arbitrator,
+    Offer, OfferType, TradeInfo,
+};
 use localmoney_protocol::price::{query_fiat_price_for_denom, DenomFiatPrice};
 use localmoney_protocol::profile::{
     load_profile, update_profile_contact_msg, update_profile_trades_count_msg,
 };
+use localmoney_protocol::storage_utils::{reindex_limit, reindex_page};
 use localmoney_protocol::trade::{
-    arbitrators, calc_denom_fiat_price, ArbitratorModel, ConversionRoute, ConversionStep,
-    ExecuteMsg, FeeInfo, InstantiateMsg, MigrateMsg, NewTrade, QueryMsg, Swap, SwapMsg, Trade,
-    TradeModel, TradeResponse, TradeState, TradeStateItem, TraderRole, DENOM_CONVERSION_ROUTE,
-    DENOM_CONVERSION_STEP,
+    arbitrators, calc_arbitration_fee, calc_denom_fiat_price, calc_fiat_amount, calculate_fees,
+    trades, ArbitratorModel, ConversionRoute, ConversionStep, DisputeEvidence, DisputeInfoResponse,
+    ExecuteMsg, FeeConversion, FeeInfo, InstantiateMsg, MigrateMsg, NewTrade, QueryMsg, Swap,
+    SwapMsg, TakeBestTrade, Trade, TradeEvent, TradeModel, TradeResponse, TradeState,
+    TradeStateItem, TraderRole, ARBITRATOR_RATINGS, ARBITRATOR_STATS, DENOM_CONVERSION_ROUTE,
+    DENOM_CONVERSION_STEP, DISPUTE_EVIDENCE, FEE_CONVERSION,
 };
 pub const SWAP_REPLY_ID: u64 = 1u64;
+pub const FEE_SWAP_REPLY_ID: u64 = 2u64;
 
 const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -60,6 +72,7 @@ pub fn execute(
     match msg {
         ExecuteMsg::RegisterHub {} => register_hub(deps, info),
         ExecuteMsg::Create(new_trade) => create_trade(deps, env, info, new_trade),
+        ExecuteMsg::TakeBest(take) => take_best(deps, env, info, take),
         ExecuteMsg::AcceptRequest {
             trade_id,
             maker_contact,
@@ -69,14 +82,38 @@ pub fn execute(
             maker_contact,
         } => fund_escrow(deps, env, info, trade_id, maker_contact),
         ExecuteMsg::ReleaseEscrow { trade_id } => release_escrow(deps, env, info, trade_id),
+        ExecuteMsg::ReleasePartial { trade_id, amount } => {
+            release_partial(deps, env, info, trade_id, amount)
+        }
         ExecuteMsg::FiatDeposited { trade_id } => fiat_deposited(deps, env, info, trade_id),
         ExecuteMsg::CancelRequest { trade_id } => cancel_request(deps, env, info, trade_id),
+        ExecuteMsg::CancelUnfunded { trade_id } => cancel_unfunded(deps, env, info, trade_id),
         ExecuteMsg::RefundEscrow { trade_id } => refund_escrow(deps, env, info, trade_id),
+        ExecuteMsg::AdminCancel { trade_id } => admin_cancel(deps, env, info, trade_id),
+        ExecuteMsg::Reindex { start_after, limit } => {
+            reindex_trades(deps, info, start_after, limit)
+        }
         ExecuteMsg::DisputeEscrow {
             trade_id,
             buyer_contact,
             seller_contact,
-        } => dispute_escrow(deps, env, info, trade_id, buyer_contact, seller_contact),
+            reason,
+            evidence_uri,
+        } => dispute_escrow(
+            deps,
+            env,
+            info,
+            trade_id,
+            buyer_contact,
+            seller_contact,
+            reason,
+            evidence_uri,
+        ),
+        ExecuteMsg::SubmitDisputeEvidence {
+            trade_id,
+            reason,
+            evidence_uri,
+        } => submit_dispute_evidence(deps, env, info, trade_id, reason, evidence_uri),
         ExecuteMsg::NewArbitrator {
             arbitrator,
             fiat,
@@ -85,9 +122,18 @@ pub fn execute(
         ExecuteMsg::DeleteArbitrator { arbitrator, fiat } => {
             delete_arbitrator(deps, info, arbitrator, fiat)
         }
+        ExecuteMsg::CrankOverdue { trade_id } => crank_overdue(deps, env, info, trade_id),
         ExecuteMsg::SettleDispute { trade_id, winner } => {
             settle_dispute(deps, env, info, trade_id, winner)
         }
+        ExecuteMsg::ResolveSplit {
+            trade_id,
+            buyer_amount,
+            seller_amount,
+        } => resolve_split(deps, env, info, trade_id, buyer_amount, seller_amount),
+        ExecuteMsg::RateArbitrator { trade_id, rating } => {
+            rate_arbitrator(deps, info, trade_id, rating)
+        }
         ExecuteMsg::RegisterConversionRouteForDenom { denom, route } => {
             register_conversion_route_for_denom(deps, info, denom, route)
         }
@@ -122,6 +168,14 @@ fn create_trade(
 ) -> Result<Response, ContractError> {
     // Load Hub Cfg
     let hub_cfg = get_hub_config(deps.as_ref());
+    assert_not_paused(&hub_cfg)?;
+    let contact_limit = hub_cfg.text_limits.contact;
+    assert_text_length("taker_contact", &new_trade.taker_contact, contact_limit)?;
+    assert_text_length(
+        "profile_taker_contact",
+        &new_trade.profile_taker_contact,
+        contact_limit,
+    )?;
 
     // Load Offer
     let offer_id = new_trade.offer_id.clone();
@@ -137,7 +191,18 @@ fn create_trade(
     }
     let offer_result = offer_result.unwrap();
     let offer = offer_result.offer;
+    let maker_key_version = offer_result.profile.key_version;
     assert_value_in_range(offer.min_amount, offer.max_amount, new_trade.amount.clone())?;
+    assert_offer_trade_capacity(&offer)?;
+    match offer.expires_at {
+        Some(expires_at) if offer.is_expired(env.block.time.seconds()) => {
+            return Err(ContractError::OfferExpired {
+                offer_id: offer.id,
+                expires_at,
+            });
+        }
+        _ => {}
+    }
 
     // Can't create Trade with the same wallet
     if info.sender.eq(&offer.owner) {
@@ -146,6 +211,17 @@ fn create_trade(
             caller: info.sender,
         });
     }
+    if is_taker_blocked(
+        &deps.querier,
+        hub_cfg.offer_addr.to_string(),
+        offer.owner.clone(),
+        new_trade.taker.clone(),
+    )? {
+        return Err(ContractError::TakerBlocked {
+            maker: offer.owner,
+            taker: new_trade.taker,
+        });
+    }
 
     // Check if new_trade.amount in fiat is lower than the trade limit at hub_cfg
     let offer_denom_usd_price = query_fiat_price_for_denom(
@@ -160,13 +236,7 @@ fn create_trade(
         price: Uint256::from_u128(0),
     });
     let offer_usd_price = calc_denom_fiat_price(offer.rate, offer_denom_usd_price.price);
-    let new_trade_amount = Uint256::from_u128(new_trade.amount.u128());
-    let usd_trade_amount = (new_trade_amount * offer_usd_price)
-        .checked_div(Uint256::from_u128(100u128))
-        .unwrap_or(Uint256::zero());
-    let usd_trade_amount = usd_trade_amount
-        .checked_div(Uint256::from_u128(1_000_000u128))
-        .unwrap_or(Uint256::zero());
+    let usd_trade_amount = calc_fiat_amount(new_trade.amount, offer_usd_price);
 
     // The min amount
     let min_amount = Uint256::from_u128(hub_cfg.trade_limit_min.u128());
@@ -216,8 +286,20 @@ fn create_trade(
         seller_contact = None // maker
     }
 
-    let trades_count = TradeModel::size(deps.storage) as u64;
-    let trade_id = trades_count + 1;
+    // The taker's contact update below may rotate their key
+    let taker_key_version = load_profile(
+        &deps.querier,
+        hub_cfg.profile_addr.to_string(),
+        new_trade.taker.clone(),
+    )?
+    .key_version_for(&new_trade.profile_taker_encryption_key);
+    let (buyer_key_version, seller_key_version) = if offer.offer_type == OfferType::Buy {
+        (maker_key_version, taker_key_version)
+    } else {
+        (taker_key_version, maker_key_version)
+    };
+
+    let trade_id = next_trade_id(deps.storage);
 
     let new_trade_state = TradeStateItem {
         actor: new_trade.taker.clone(),
@@ -239,32 +321,34 @@ fn create_trade(
         deps.as_ref(),
         random_seed as usize,
         offer.fiat_currency.clone(),
-    );
+        &[buyer.clone(), seller.clone()],
+    )?;
+    assert_valid_arbitrator(&arbitrator.arbitrator, &buyer, &seller)?;
 
     let expires_at = env.block.time.seconds() + hub_cfg.trade_expiration_timer;
     //Instantiate Trade state
-    let trade = TradeModel::create(
-        deps.storage,
-        Trade::new(
-            trade_id.clone(),
-            env.contract.address.clone(),
-            buyer.clone(),
-            seller.clone(),
-            seller_contact,
-            buyer_contact,
-            arbitrator.arbitrator,
-            hub_cfg.offer_addr.clone(),
-            offer_id,
-            env.block.time.seconds(),
-            expires_at,
-            offer.denom.clone(),
-            new_trade.amount.clone(),
-            offer.fiat_currency,
-            denom_final_price,
-            trade_state_history,
-        ),
-    )
-    .trade;
+    let mut trade = Trade::new(
+        trade_id.clone(),
+        env.contract.address.clone(),
+        buyer.clone(),
+        seller.clone(),
+        seller_contact,
+        buyer_contact,
+        arbitrator.arbitrator,
+        hub_cfg.offer_addr.clone(),
+        offer_id,
+        env.block.time.seconds(),
+        expires_at,
+        offer.denom.clone(),
+        new_trade.amount.clone(),
+        offer.fiat_currency,
+        denom_final_price,
+        trade_state_history,
+    );
+    trade.buyer_key_version = buyer_key_version;
+    trade.seller_key_version = seller_key_version;
+    trade.fee_split = hub_cfg.fee_split.clone();
+    let trade = TradeModel::create(deps.storage, trade).trade;
 
     let mut profile_submsgs = create_update_trades_count_msgs(
         hub_cfg.profile_addr.to_string(),
@@ -273,6 +357,12 @@ fn create_trade(
         TradeState::RequestCreated,
     );
     sub_msgs.append(&mut profile_submsgs);
+    sub_msgs.push(update_offer_active_trades_msg(
+        hub_cfg.offer_addr.to_string(),
+        offer.id,
+        TradeState::RequestCreated,
+        trade.amount,
+    ));
 
     let denom_str = denom_to_string(&trade.denom);
     let res = Response::new()
@@ -312,9 +402,40 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::ArbitratorsFiat { fiat } => to_json_binary(
             &ArbitratorModel::query_arbitrators_fiat(deps.storage, fiat)?,
         ),
+        QueryMsg::ArbitratorStats { arbitrator } => to_json_binary(
+            &ARBITRATOR_STATS
+                .may_load(deps.storage, &arbitrator)?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::NextTradeId {} => to_json_binary(&next_trade_id(deps.storage)),
+        QueryMsg::TradesByState {
+            state,
+            limit,
+            start_after,
+        } => to_json_binary(&TradeModel::trades_by_state(
+            deps.storage,
+            state,
+            validate_min_max_items_per_page(limit) as usize,
+            start_after,
+        )?),
+        QueryMsg::DisputeInfo { trade_id } => to_json_binary(&query_dispute_info(deps, trade_id)?),
+        QueryMsg::Version {} => to_json_binary(&query_version(deps.storage)?),
     }
 }
 
+fn next_trade_id(storage: &dyn Storage) -> u64 {
+    TradeModel::size(storage) as u64 + 1
+}
+
+fn query_dispute_info(deps: Deps, trade_id: u64) -> StdResult<DisputeInfoResponse> {
+    let trade = TradeModel::from_store(deps.storage, trade_id);
+    Ok(DisputeInfoResponse {
+        trade_id,
+        buyer: DISPUTE_EVIDENCE.may_load(deps.storage, (trade_id, &trade.buyer))?,
+        seller: DISPUTE_EVIDENCE.may_load(deps.storage, (trade_id, &trade.seller))?,
+    })
+}
+
 fn register_hub<T: CustomQuery>(
     deps: DepsMut<T>,
     info: MessageInfo,
@@ -419,6 +540,134 @@ pub fn query_trades<T: CustomQuery>(
     Ok(trades_infos)
 }
 
+/// Splits a large order across the best priced offers. Each trade goes through
+/// `create_trade`, so a single failing trade reverts the whole message.
+fn take_best(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    take: TakeBestTrade,
+) -> Result<Response, ContractError> {
+    if take.amount.is_zero() {
+        return Err(InvalidParameter {
+            parameter: "amount".to_string(),
+            message: Some("Amount must be greater than zero.".to_string()),
+        });
+    }
+    let hub_cfg = get_hub_config(deps.as_ref());
+
+    // The offer contract already leaves out offers the taker can't trade on.
+    let candidates = load_takeable_offers(
+        &deps.querier,
+        hub_cfg.offer_addr.to_string(),
+        take.offer_type.clone(),
+        take.fiat_currency.clone(),
+        take.denom.clone(),
+        info.sender.clone(),
+    )?;
+    let denom_usd_price = query_fiat_price_for_denom(
+        &deps.querier,
+        take.denom.clone(),
+        FiatCurrency::USD,
+        hub_cfg.price_addr.to_string(),
+    )
+    .map_or(Uint256::zero(), |price| price.price);
+    let allocations = allocate_best(
+        candidates,
+        &take.offer_type,
+        &info.sender,
+        take.amount,
+        |offer, fill| within_trade_limits(&hub_cfg, denom_usd_price, offer, fill),
+    )?;
+
+    let mut res = Response::new();
+    let mut trade_ids: Vec<String> = vec![];
+    for (offer_id, amount) in allocations {
+        let trade_res = create_trade(
+            deps.branch(),
+            env.clone(),
+            info.clone(),
+            NewTrade {
+                offer_id,
+                amount,
+                taker: info.sender.clone(),
+                profile_taker_contact: take.profile_taker_contact.clone(),
+                profile_taker_encryption_key: take.profile_taker_encryption_key.clone(),
+                taker_contact: take.taker_contact.clone(),
+            },
+        )?;
+        if let Some(trade_id) = trade_res.attributes.iter().find(|a| a.key == "trade_id") {
+            trade_ids.push(trade_id.value.clone());
+        }
+        res = res.add_submessages(trade_res.messages);
+    }
+
+    Ok(res
+        .add_attribute("action", "take_best")
+        .add_attribute("amount", take.amount.to_string())
+        .add_attribute("trade_ids", trade_ids.join(",")))
+}
+
+/// Greedily fills `amount` from the best priced offers, taking as much of each
+/// offer as its remaining amount allows and skipping offers whose `min_amount` is
+/// above what is left, that have no room for another trade or whose fill
+/// `fits` rejects. Returns `(offer_id, amount)` pairs.
+fn allocate_best(
+    mut offers: Vec<Offer>,
+    offer_type: &OfferType,
+    taker: &Addr,
+    amount: Uint128,
+    fits: impl Fn(&Offer, Uint128) -> bool,
+) -> Result<Vec<(u64, Uint128)>, ContractError> {
+    // Takers buy from sell offers, so the lowest rate is best there, and the highest on buy offers.
+    offers.sort_by(|a, b| {
+        let by_rate = match offer_type {
+            OfferType::Sell => a.rate.cmp(&b.rate),
+            OfferType::Buy => b.rate.cmp(&a.rate),
+        };
+        by_rate.then(a.id.cmp(&b.id))
+    });
+
+    let mut remaining = amount;
+    let mut allocations = vec![];
+    let takeable = offers
+        .iter()
+        .filter(|offer| offer.owner.ne(taker) && assert_offer_trade_capacity(offer).is_ok());
+    for offer in takeable {
+        if remaining.is_zero() {
+            break;
+        }
+        let fill = remaining.min(offer.remaining_amount());
+        if fill < offer.min_amount || !fits(offer, fill) {
+            continue;
+        }
+        allocations.push((offer.id, fill));
+        remaining -= fill;
+    }
+
+    if !remaining.is_zero() {
+        return Err(ContractError::InsufficientOfferLiquidity {
+            requested: amount,
+            available: amount - remaining,
+        });
+    }
+    Ok(allocations)
+}
+
+/// Whether a `fill` trade on `offer` stays inside the hub's USD trade
+/// limits, the same check `create_trade` applies.
+fn within_trade_limits(
+    hub_cfg: &HubConfig,
+    denom_usd_price: Uint256,
+    offer: &Offer,
+    fill: Uint128,
+) -> bool {
+    let offer_usd_price = calc_denom_fiat_price(offer.rate, denom_usd_price);
+    let usd_trade_amount = calc_fiat_amount(fill, offer_usd_price);
+    usd_trade_amount >= Uint256::from_u128(hub_cfg.trade_limit_min.u128())
+        && usd_trade_amount <= Uint256::from_u128(hub_cfg.trade_limit_max.u128())
+}
+
 fn fund_escrow(
     deps: DepsMut,
     env: Env,
@@ -428,6 +677,13 @@ fn fund_escrow(
 ) -> Result<Response, ContractError> {
     // Load HubConfig, Trade & Offer
     let hub_config = get_hub_config(deps.as_ref());
+    if let Some(maker_contact) = &maker_contact {
+        assert_text_length(
+            "maker_contact",
+            maker_contact,
+            hub_config.text_limits.contact,
+        )?;
+    }
     let mut trade = TradeModel::from_store(deps.storage, trade_id);
     let offer = load_offer(
         &deps.querier.clone(),
@@ -437,23 +693,11 @@ fn fund_escrow(
     .unwrap()
     .offer;
 
-    // Ensure the message has the correct funds
-    let trade_denom = &denom_to_string(&trade.denom);
-    let balance = match info.funds.first().unwrap() {
-        coin if coin.denom.eq(trade_denom) => coin.clone(),
-        _ => {
-            let received = info.funds.first().unwrap_or(&Coin::default()).denom.clone();
-            return Err(InvalidDenom {
-                expected: trade_denom.clone(),
-                received,
-            });
-        }
-    };
-    let fee_info = calculate_fees(&hub_config, trade.amount.clone());
+    let fee_info = calculate_fees(&hub_config, trade.amount.clone(), &trade.denom);
 
     // Everybody can set the state to RequestExpired, if it is expired (they are doing as a favor).
     if trade.request_expired(env.block.time.seconds()) {
-        trade.set_state(TradeState::RequestExpired, &env, &info);
+        trade.set_state(TradeState::RequestExpired, &env, &info)?;
         TradeModel::store(deps.storage, &trade).unwrap();
 
         return Err(TradeExpired {
@@ -481,23 +725,19 @@ fn fund_escrow(
     // Ensure TradeState::Created for Sell and TradeState::Accepted for Buy orders
     assert_trade_state_and_type(&trade, &offer.offer_type)?;
 
-    // If the sender funding the escrow is the maker, the fee must be added on top of the trade amount
-    let total_fees = if offer.owner.eq(&info.sender) {
-        fee_info.total_fees()
-    } else {
-        Uint128::new(0u128)
-    };
+    // The seller's share of the fees is added on top of the trade amount
+    let (seller_fees, _) = trade.fee_shares(fee_info.total_fees(), &offer.owner);
 
-    // Ensure the amount sent is equal to the trade amount + fees
-    if balance.amount != trade.amount + total_fees {
-        return Err(FundEscrowError {
-            required_amount: trade.amount + total_fees,
-            sent_amount: balance.amount,
-        });
-    }
+    // Ensure exactly the trade amount + fees was sent in the trade denom
+    let required_amount = trade.amount + seller_fees;
+    assert_sent_funds(&info, required_amount, &denom_to_string(&trade.denom))?;
 
     // Set the state to EscrowFunded and store the trade
-    trade.set_state(TradeState::EscrowFunded, &env, &info);
+    let from_state = trade.get_state();
+    trade.set_state(TradeState::EscrowFunded, &env, &info)?;
+    if hub_config.trade_payment_timer > 0 {
+        trade.payment_deadline = Some(env.block.time.seconds() + hub_config.trade_payment_timer);
+    }
     TradeModel::store(deps.storage, &trade).unwrap();
 
     let mut sub_msgs: Vec<SubMsg> = vec![];
@@ -512,14 +752,17 @@ fn fund_escrow(
         sub_msgs.append(&mut profile_submsgs);
     }
 
+    let trade_event =
+        TradeEvent::new(&trade, from_state, &info.sender, trade.amount, &env).to_attribute()?;
     let res = Response::new()
         .add_submessages(sub_msgs)
         .add_attribute("action", "fund_escrow")
         .add_attribute("trade_id", trade_id.to_string())
         .add_attribute("trade.amount", trade.amount.clone().to_string())
-        .add_attribute("sent_amount", balance.amount.to_string())
+        .add_attribute("sent_amount", required_amount.to_string())
         .add_attribute("seller", info.sender)
-        .add_attribute("state", trade.get_state().to_string());
+        .add_attribute("state", trade.get_state().to_string())
+        .add_attributes(vec![trade_event]);
     Ok(res)
 }
 
@@ -531,6 +774,14 @@ fn accept_request(
     trade_id: u64,
     maker_contact: String,
 ) -> Result<Response, ContractError> {
+    // Load Hub Cfg
+    let hub_config = get_hub_config(deps.as_ref());
+    assert_text_length(
+        "maker_contact",
+        &maker_contact,
+        hub_config.text_limits.contact,
+    )?;
+
     let mut trade = TradeModel::from_store(deps.storage, trade_id);
     // Only the buyer can accept the request
     assert_ownership(info.sender.clone(), trade.buyer.clone()).unwrap();
@@ -544,16 +795,13 @@ fn accept_request(
     .unwrap();
 
     // Change trade state
-    trade.set_state(TradeState::RequestAccepted, &env, &info);
+    trade.set_state(TradeState::RequestAccepted, &env, &info)?;
 
     // Set maker contact as buyer
     trade.buyer_contact = Some(maker_contact);
 
     TradeModel::store(deps.storage, &trade).unwrap();
 
-    // Load Hub Cfg
-    let hub_config = get_hub_config(deps.as_ref());
-
     let sub_msgs = create_update_trades_count_msgs(
         hub_config.profile_addr.to_string(),
         trade.buyer.clone(),
@@ -589,7 +837,7 @@ fn fiat_deposited(
     .unwrap();
 
     // Update trade State to TradeState::FiatDeposited
-    trade.set_state(TradeState::FiatDeposited, &env, &info);
+    trade.set_state(TradeState::FiatDeposited, &env, &info)?;
     // Sets the time that will enable the dispute
     let enables_dispute_at = env.block.time.seconds() + hub_config.trade_dispute_timer;
     trade.enables_dispute_at = Some(enables_dispute_at);
@@ -634,11 +882,10 @@ fn cancel_request(
     )
     .unwrap();
 
+    let hub_config = get_hub_config(deps.as_ref());
     let mut sub_msgs: Vec<SubMsg> = vec![];
     // Should not be called when the current state is TradeState::RequestCreated
     if vec![TradeState::EscrowFunded, TradeState::RequestAccepted].contains(&trade.get_state()) {
-        // Load hub config
-        let hub_config = get_hub_config(deps.as_ref());
         let mut profile_submsgs = create_update_trades_count_msgs(
             hub_config.profile_addr.to_string(),
             trade.buyer.clone(),
@@ -650,12 +897,18 @@ fn cancel_request(
 
     if trade.get_state().eq(&TradeState::EscrowFunded) {
         // Update trade State to TradeState::EscrowCanceled
-        trade.set_state(TradeState::EscrowCanceled, &env, &info);
+        trade.set_state(TradeState::EscrowCanceled, &env, &info)?;
     } else {
         // Update trade State to TradeState::RequestCanceled
-        trade.set_state(TradeState::RequestCanceled, &env, &info);
+        trade.set_state(TradeState::RequestCanceled, &env, &info)?;
     }
     TradeModel::store(deps.storage, &trade).unwrap();
+    sub_msgs.push(update_offer_active_trades_msg(
+        hub_config.offer_addr.to_string(),
+        trade.offer_id,
+        trade.get_state(),
+        trade.amount,
+    ));
 
     let res = Response::new()
         .add_attribute("action", "cancel_request")
@@ -663,6 +916,58 @@ fn cancel_request(
     Ok(res)
 }
 
+/// Lets the seller drop a trade whose escrow was never funded once the hub's
+/// funding deadline has passed, so it stops holding a slot on the offer.
+fn cancel_unfunded(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+) -> Result<Response, ContractError> {
+    let mut trade = TradeModel::from_store(deps.storage, trade_id);
+    assert_ownership(info.sender.clone(), trade.seller.clone())?;
+    assert_trade_state_change(
+        trade.get_state(),
+        vec![TradeState::RequestCreated, TradeState::RequestAccepted],
+        TradeState::RequestCanceled,
+    )?;
+
+    let hub_config = get_hub_config(deps.as_ref());
+    let funding_deadline = Some(hub_config.funding_deadline_seconds)
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| trade.created_at + seconds);
+    match funding_deadline {
+        Some(deadline) if env.block.time.seconds() >= deadline => {}
+        funding_deadline => {
+            return Err(ContractError::FundingDeadlineNotPassed { funding_deadline })
+        }
+    }
+
+    let mut sub_msgs: Vec<SubMsg> = vec![];
+    if trade.get_state() == TradeState::RequestAccepted {
+        sub_msgs.append(&mut create_update_trades_count_msgs(
+            hub_config.profile_addr.to_string(),
+            trade.buyer.clone(),
+            trade.seller.clone(),
+            TradeState::EscrowCanceled,
+        ));
+    }
+    trade.set_state(TradeState::RequestCanceled, &env, &info)?;
+    TradeModel::store(deps.storage, &trade).unwrap();
+    sub_msgs.push(update_offer_active_trades_msg(
+        hub_config.offer_addr.to_string(),
+        trade.offer_id,
+        trade.get_state(),
+        trade.amount,
+    ));
+
+    Ok(Response::new()
+        .add_submessages(sub_msgs)
+        .add_attribute("action", "cancel_unfunded")
+        .add_attribute("trade_id", trade_id.to_string())
+        .add_attribute("state", trade.get_state().to_string()))
+}
+
 fn release_escrow(
     deps: DepsMut,
     env: Env,
@@ -690,7 +995,8 @@ fn release_escrow(
     let hub_config = get_hub_config(deps.as_ref());
 
     // Update trade State to TradeState::EscrowReleased
-    trade.set_state(TradeState::EscrowReleased, &env, &info);
+    let from_state = trade.get_state();
+    trade.set_state(TradeState::EscrowReleased, &env, &info)?;
     TradeModel::store(deps.storage, &trade).unwrap();
 
     // Load the offer related to this trade
@@ -702,20 +1008,23 @@ fn release_escrow(
     .unwrap();
 
     let mut send_msgs: Vec<SubMsg> = Vec::new();
-    // Calculate and add protocol fees
-    let mut release_amount = trade.amount.clone();
+    let mut fee_attributes: Vec<Attribute> = Vec::new();
+    // Calculate and add protocol fees, partial releases already paid theirs
+    let mut release_amount = trade.remaining_amount();
+    let trade_event =
+        TradeEvent::new(&trade, from_state, &info.sender, release_amount, &env).to_attribute()?;
     let fee_info = add_protocol_fees_msgs(
         deps,
         &mut send_msgs,
+        &mut fee_attributes,
         &release_amount,
         trade_denom.clone(),
         &hub_config,
     );
 
-    // Only deducts fees from the release_amount if the maker (offer owner) is the buyer
-    if trade.buyer.eq(&offer_response.offer.owner) {
-        release_amount = release_amount.sub(fee_info.total_fees());
-    }
+    // The buyer's share of the fees comes out of the release_amount
+    let (_, buyer_fees) = trade.fee_shares(fee_info.total_fees(), &offer_response.offer.owner);
+    release_amount = release_amount.sub(buyer_fees);
 
     let mut profile_submsgs = create_update_trades_count_msgs(
         hub_config.profile_addr.to_string(),
@@ -724,6 +1033,12 @@ fn release_escrow(
         TradeState::EscrowReleased,
     );
     send_msgs.append(&mut profile_submsgs);
+    send_msgs.push(update_offer_active_trades_msg(
+        hub_config.offer_addr.to_string(),
+        trade.offer_id,
+        TradeState::EscrowReleased,
+        trade.amount,
+    ));
 
     // Send tokens to buyer
     send_msgs.push(SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
@@ -737,42 +1052,169 @@ fn release_escrow(
         .add_attribute("trade_id", trade_id.to_string())
         .add_attribute("state", trade.get_state().clone().to_string())
         .add_attribute("trade_denom", denom_to_string(&trade.denom))
-        .add_attribute("total_amount", trade.amount.u128().to_string());
+        .add_attribute("total_amount", trade.amount.u128().to_string())
+        .add_attributes(vec![trade_event])
+        .add_attributes(fee_attributes);
     Ok(res)
 }
 
-fn refund_escrow(
+/// Releases a tranche of the escrow to the buyer. The seller can do it once
+/// the fiat is deposited and the arbitrator while the trade is disputed. Each
+/// tranche pays its share of the fees, and the trade only settles when the
+/// last of the escrow is released.
+fn release_partial(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     trade_id: u64,
+    amount: Uint128,
 ) -> Result<Response, ContractError> {
-    // Refund can only happen if trade state is TradeState::EscrowFunded and FundingTimeout is expired
-    let trade = TradeModel::from_store(deps.storage, trade_id);
+    let mut trade = TradeModel::from_store(deps.storage, trade_id);
+    let hub_config = get_hub_config(deps.as_ref());
+    let offer = load_offer(
+        &deps.querier,
+        trade.offer_id,
+        hub_config.offer_addr.to_string(),
+    )
+    .unwrap()
+    .offer;
+
+    let by_arbitrator = trade.arbitrator.eq(&info.sender);
+    let (expected_state, final_state) = if trade.seller.eq(&info.sender) {
+        (TradeState::FiatDeposited, TradeState::EscrowReleased)
+    } else if by_arbitrator {
+        let settled_state = if trade.buyer.eq(&offer.owner) {
+            TradeState::SettledForMaker
+        } else {
+            TradeState::SettledForTaker
+        };
+        (TradeState::EscrowDisputed, settled_state)
+    } else {
+        return Err(ContractError::Unauthorized {
+            owner: trade.seller.clone(),
+            caller: info.sender,
+        });
+    };
+    if trade.get_state().ne(&expected_state) {
+        return Err(InvalidTradeState {
+            current: trade.get_state(),
+            expected: expected_state,
+        });
+    }
+
+    if amount.is_zero() {
+        return Err(InvalidParameter {
+            parameter: "amount".to_string(),
+            message: Some("Release amount must be greater than zero.".to_string()),
+        });
+    }
+    let remaining = trade.remaining_amount();
+    if amount > remaining {
+        return Err(ContractError::ReleaseExceedsRemaining { amount, remaining });
+    }
+
+    let from_state = trade.get_state();
+    trade.released_amount += amount;
+    let fully_released = trade.remaining_amount().is_zero();
+    if fully_released {
+        trade.set_state(final_state.clone(), &env, &info)?;
+    }
+    TradeModel::store(deps.storage, &trade).unwrap();
+    if fully_released && by_arbitrator {
+        record_resolution(deps.storage, &trade.arbitrator)?;
+    }
+
+    let trade_denom = denom_to_string(&trade.denom);
+    let mut send_msgs: Vec<SubMsg> = Vec::new();
+    let mut fee_attributes: Vec<Attribute> = Vec::new();
+    let fee_info = add_protocol_fees_msgs(
+        deps,
+        &mut send_msgs,
+        &mut fee_attributes,
+        &amount,
+        trade_denom.clone(),
+        &hub_config,
+    );
+
+    // The buyer's share of the fees comes out of the release_amount
+    let (_, buyer_fees) = trade.fee_shares(fee_info.total_fees(), &offer.owner);
+    let mut release_amount = amount.sub(buyer_fees);
+    if by_arbitrator {
+        let arbitration_fee_amount = calc_arbitration_fee(&hub_config, amount);
+        release_amount = release_amount.sub(arbitration_fee_amount);
+        send_msgs.push(SubMsg::new(create_send_msg(
+            trade.arbitrator.clone(),
+            vec![Coin::new(
+                arbitration_fee_amount.u128(),
+                trade_denom.clone(),
+            )],
+        )));
+    }
+    send_msgs.push(SubMsg::new(create_send_msg(
+        trade.buyer.clone(),
+        vec![Coin::new(release_amount.u128(), trade_denom.clone())],
+    )));
 
-    //
+    if fully_released {
+        send_msgs.append(&mut create_update_trades_count_msgs(
+            hub_config.profile_addr.to_string(),
+            trade.buyer.clone(),
+            trade.seller.clone(),
+            final_state.clone(),
+        ));
+        send_msgs.push(update_offer_active_trades_msg(
+            hub_config.offer_addr.to_string(),
+            trade.offer_id,
+            final_state,
+            trade.amount,
+        ));
+    }
+
+    let trade_event =
+        TradeEvent::new(&trade, from_state, &info.sender, amount, &env).to_attribute()?;
+    let res = Response::new()
+        .add_submessages(send_msgs)
+        .add_attribute("action", "release_partial")
+        .add_attribute("trade_id", trade_id.to_string())
+        .add_attribute("state", trade.get_state().to_string())
+        .add_attribute("released_amount", amount.to_string())
+        .add_attribute("remaining_amount", trade.remaining_amount().to_string())
+        .add_attributes(vec![trade_event])
+        .add_attributes(fee_attributes);
+    Ok(res)
+}
+
+fn refund_escrow(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+) -> Result<Response, ContractError> {
+    // Refund can only happen if trade state is TradeState::EscrowFunded or TradeState::EscrowCanceled
+    let mut trade: Trade = TradeModel::from_store(deps.storage, trade_id);
     assert_trade_state_change(
         trade.get_state(),
         vec![TradeState::EscrowFunded, TradeState::EscrowCanceled],
-        TradeState::EscrowFunded,
-    )
-    .unwrap();
+        TradeState::EscrowRefunded,
+    )?;
 
-    // anyone can try to refund, as long as the trade is funded and expired or escrow canceled
+    // anyone can refund a funded trade once it expired, before that only the seller can
     let block_time = env.block.time.seconds();
-    if trade.get_state().eq(&TradeState::EscrowFunded) && !trade.request_expired(block_time) {
+    let expired = trade.request_expired(block_time);
+    if trade.get_state().eq(&TradeState::EscrowFunded) && !expired && info.sender.ne(&trade.seller)
+    {
         return Err(RefundErrorNotExpired {
-            message:
-                "Only expired trades that are not disputed can be refunded by non-arbitrators."
-                    .to_string(),
+            message: "Only the seller can refund a funded trade before it expires.".to_string(),
             trade: trade.get_state().to_string(),
         });
     }
-
-    let mut trade: Trade = TradeModel::from_store(deps.storage, trade_id);
+    let refund_type = if expired { "expired" } else { "manual" };
+    // A canceled escrow already gave its slot back to the offer
+    let was_canceled = trade.get_state().eq(&TradeState::EscrowCanceled);
 
     //Update trade state to TradeState::EscrowRefunded
-    trade.set_state(TradeState::EscrowRefunded, &env, &info);
+    let from_state = trade.get_state();
+    trade.set_state(TradeState::EscrowRefunded, &env, &info)?;
     TradeModel::store(deps.storage, &trade).unwrap();
 
     let hub_config = get_hub_config(deps.as_ref());
@@ -783,17 +1225,123 @@ fn refund_escrow(
         trade.seller.clone(),
         TradeState::EscrowRefunded,
     );
+    if !was_canceled {
+        sub_msgs.push(update_offer_active_trades_msg(
+            hub_config.offer_addr.to_string(),
+            trade.offer_id,
+            TradeState::EscrowRefunded,
+            trade.amount,
+        ));
+    }
 
     let amount = trade.amount.clone();
     let denom = denom_to_string(&trade.denom);
-    let refund_amount = vec![Coin::new(amount.u128(), denom.clone())];
+    // Only a canceled escrow pays the cancellation fee, expiries and manual
+    // refunds of a funded trade are returned in full
+    let cancellation_fee = if was_canceled {
+        hub_config.cancellation_fee(amount)
+    } else {
+        Uint128::zero()
+    };
+    if !cancellation_fee.is_zero() {
+        sub_msgs.push(SubMsg::new(create_send_msg(
+            hub_config.chain_fee_collector_addr.clone(),
+            vec![Coin::new(cancellation_fee.u128(), denom.clone())],
+        )));
+    }
+    let trade_event =
+        TradeEvent::new(&trade, from_state, &info.sender, amount, &env).to_attribute()?;
+    let refund_amount = vec![Coin::new((amount - cancellation_fee).u128(), denom.clone())];
     sub_msgs.push(SubMsg::new(create_send_msg(trade.seller, refund_amount)));
     let res = Response::new()
         .add_attribute("action", "refund_escrow")
+        .add_attribute("trade_id", trade_id.to_string())
+        .add_attribute("refund_type", refund_type)
+        .add_attribute("cancellation_fee", cancellation_fee.to_string())
+        .add_attributes(vec![trade_event])
         .add_submessages(sub_msgs);
     Ok(res)
 }
 
+/// Unwinds a funded trade that is stuck, returning whatever is left in escrow
+/// to the seller.
+fn admin_cancel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+) -> Result<Response, ContractError> {
+    let admin = get_hub_admin(deps.as_ref()).addr;
+    assert_ownership(info.sender.clone(), admin)?;
+
+    let mut trade: Trade = TradeModel::from_store(deps.storage, trade_id);
+    let prior_state = trade.get_state();
+    // Refunded rather than canceled, an escrow canceled trade could still be refunded again
+    assert_trade_state_change(
+        prior_state.clone(),
+        vec![
+            TradeState::EscrowFunded,
+            TradeState::FiatDeposited,
+            TradeState::EscrowDisputed,
+        ],
+        TradeState::EscrowRefunded,
+    )?;
+    trade.set_state(TradeState::EscrowRefunded, &env, &info)?;
+    TradeModel::store(deps.storage, &trade).unwrap();
+
+    let hub_config = get_hub_config(deps.as_ref());
+    let refund_amount = trade.remaining_amount();
+    let mut sub_msgs: Vec<SubMsg> = create_update_trades_count_msgs(
+        hub_config.profile_addr.to_string(),
+        trade.buyer.clone(),
+        trade.seller.clone(),
+        TradeState::EscrowRefunded,
+    );
+    sub_msgs.push(update_offer_active_trades_msg(
+        hub_config.offer_addr.to_string(),
+        trade.offer_id,
+        TradeState::EscrowRefunded,
+        refund_amount,
+    ));
+    if !refund_amount.is_zero() {
+        let refund = vec![Coin::new(
+            refund_amount.u128(),
+            denom_to_string(&trade.denom),
+        )];
+        sub_msgs.push(SubMsg::new(create_send_msg(trade.seller.clone(), refund)));
+    }
+
+    Ok(Response::new()
+        .add_submessages(sub_msgs)
+        .add_attribute("action", "admin_cancel")
+        .add_attribute("trade_id", trade_id.to_string())
+        .add_attribute("admin", info.sender.to_string())
+        .add_attribute("prior_state", prior_state.to_string())
+        .add_attribute("refund_amount", refund_amount.to_string()))
+}
+
+/// Saves the next page of trades again so the state index covers trades
+/// stored before it existed.
+fn reindex_trades(
+    deps: DepsMut,
+    info: MessageInfo,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let admin = get_hub_admin(deps.as_ref()).addr;
+    assert_ownership(info.sender, admin)?;
+
+    let (saved, next_start_after) =
+        reindex_page(deps.storage, &trades(), start_after, reindex_limit(limit))?;
+    let mut response = Response::new()
+        .add_attribute("action", "reindex_trades")
+        .add_attribute("count", saved.len().to_string());
+    if let Some(next_start_after) = next_start_after {
+        response = response.add_attribute("next_start_after", next_start_after.to_string());
+    }
+    Ok(response)
+}
+
 //region arbitration
 pub fn create_arbitrator(
     deps: DepsMut,
@@ -852,6 +1400,7 @@ pub fn delete_arbitrator(
     Ok(res)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn dispute_escrow(
     deps: DepsMut,
     env: Env,
@@ -859,7 +1408,14 @@ fn dispute_escrow(
     trade_id: u64,
     buyer_contact: String,
     seller_contact: String,
+    reason: String,
+    evidence_uri: Option<String>,
 ) -> Result<Response, ContractError> {
+    let text_limits = get_hub_config(deps.as_ref()).text_limits;
+    assert_text_length("buyer_contact", &buyer_contact, text_limits.contact)?;
+    assert_text_length("seller_contact", &seller_contact, text_limits.contact)?;
+    assert_dispute_evidence_valid(&reason, &evidence_uri, &text_limits)?;
+
     let mut trade = TradeModel::from_store(deps.storage, trade_id);
     // TODO: check escrow funding timer*
     // Only the buyer or seller can start a dispute
@@ -888,26 +1444,205 @@ fn dispute_escrow(
     }
 
     // Update trade State to TradeState::Disputed and sets arbitrator
-    trade.set_state(TradeState::EscrowDisputed, &env, &info);
+    let from_state = trade.get_state();
+    trade.set_state(TradeState::EscrowDisputed, &env, &info)?;
     trade.arbitrator_buyer_contact = Some(buyer_contact);
     trade.arbitrator_seller_contact = Some(seller_contact);
     TradeModel::store(deps.storage, &trade).unwrap();
-
+    DISPUTE_EVIDENCE.save(
+        deps.storage,
+        (trade.id, &info.sender),
+        &DisputeEvidence {
+            reason,
+            evidence_uri,
+            submitted_at: env.block.time.seconds(),
+        },
+    )?;
+
+    let trade_event = TradeEvent::new(
+        &trade,
+        from_state,
+        &info.sender,
+        trade.remaining_amount(),
+        &env,
+    )
+    .to_attribute()?;
     let res = Response::new()
         .add_attribute("action", "dispute_escrow")
         .add_attribute("trade_id", trade.id.to_string())
         .add_attribute("state", trade.get_state().to_string())
-        .add_attribute("arbitrator", trade.arbitrator.to_string());
+        .add_attribute("arbitrator", trade.arbitrator.to_string())
+        .add_attributes(vec![trade_event]);
 
     Ok(res)
 }
 
+/// Either party of a disputed trade can submit evidence once, a later
+/// submission replaces their previous one until the dispute is settled.
+fn submit_dispute_evidence(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+    reason: String,
+    evidence_uri: Option<String>,
+) -> Result<Response, ContractError> {
+    let trade = TradeModel::from_store(deps.storage, trade_id);
+    assert_sender_is_buyer_or_seller(
+        info.sender.clone(),
+        trade.buyer.clone(),
+        trade.seller.clone(),
+    )?;
+    if TradeState::EscrowDisputed.ne(&trade.get_state()) {
+        return Err(InvalidTradeState {
+            current: trade.get_state(),
+            expected: TradeState::EscrowDisputed,
+        });
+    }
+    let text_limits = get_hub_config(deps.as_ref()).text_limits;
+    assert_dispute_evidence_valid(&reason, &evidence_uri, &text_limits)?;
+
+    DISPUTE_EVIDENCE.save(
+        deps.storage,
+        (trade.id, &info.sender),
+        &DisputeEvidence {
+            reason,
+            evidence_uri,
+            submitted_at: env.block.time.seconds(),
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "submit_dispute_evidence")
+        .add_attribute("trade_id", trade.id.to_string())
+        .add_attribute("party", info.sender.to_string()))
+}
+
+/// Pushes a funded trade that is still unsettled after its payment deadline
+/// into dispute, so the arbitrator picks it up without either party acting.
+fn crank_overdue(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+) -> Result<Response, ContractError> {
+    let mut trade = TradeModel::from_store(deps.storage, trade_id);
+    assert_trade_state_change(
+        trade.get_state(),
+        vec![TradeState::EscrowFunded, TradeState::FiatDeposited],
+        TradeState::EscrowDisputed,
+    )?;
+
+    match trade.payment_deadline {
+        Some(deadline) if env.block.time.seconds() > deadline => {}
+        payment_deadline => return Err(ContractError::TradeNotOverdue { payment_deadline }),
+    }
+
+    let from_state = trade.get_state();
+    trade.set_state(TradeState::EscrowDisputed, &env, &info)?;
+    TradeModel::store(deps.storage, &trade).unwrap();
+
+    let trade_event = TradeEvent::new(
+        &trade,
+        from_state,
+        &info.sender,
+        trade.remaining_amount(),
+        &env,
+    )
+    .to_attribute()?;
+    Ok(Response::new()
+        .add_attribute("action", "crank_overdue")
+        .add_attribute("trade_id", trade.id.to_string())
+        .add_attribute("state", trade.get_state().to_string())
+        .add_attribute("arbitrator", trade.arbitrator.to_string())
+        .add_attributes(vec![trade_event]))
+}
+
 fn settle_dispute(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     trade_id: u64,
     winner: Addr,
+) -> Result<Response, ContractError> {
+    let res = settle_disputed_trade(deps, env, info, trade_id, |trade, maker, payout| {
+        // Check if winner is eligible, it must be either maker or taker
+        let final_state = if winner.eq(maker) {
+            TradeState::SettledForMaker
+        } else if winner.eq(&trade.taker(maker)) {
+            TradeState::SettledForTaker
+        } else {
+            return Err(ContractError::InvalidSender {
+                sender: winner.clone(),
+                buyer: trade.buyer.clone(),
+                seller: trade.seller.clone(),
+            });
+        };
+        Ok((final_state, vec![(winner.clone(), payout)]))
+    })?;
+    Ok(res.add_attribute("winner", winner.to_string()))
+}
+
+fn resolve_split(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+    buyer_amount: Uint128,
+    seller_amount: Uint128,
+) -> Result<Response, ContractError> {
+    let res = settle_disputed_trade(deps, env, info, trade_id, |trade, maker, payout| {
+        // The shares must cover exactly what settling for a single winner pays out
+        if buyer_amount.checked_add(seller_amount).ok() != Some(payout) {
+            return Err(InvalidParameter {
+                parameter: "buyer_amount".to_string(),
+                message: Some(format!(
+                    "buyer_amount and seller_amount must add up to {}.",
+                    payout
+                )),
+            });
+        }
+
+        // The trade is settled for whoever was awarded the larger share
+        let (maker_amount, taker_amount) = if trade.seller.eq(maker) {
+            (seller_amount, buyer_amount)
+        } else {
+            (buyer_amount, seller_amount)
+        };
+        let final_state = if maker_amount >= taker_amount {
+            TradeState::SettledForMaker
+        } else {
+            TradeState::SettledForTaker
+        };
+        Ok((
+            final_state,
+            vec![
+                (trade.buyer.clone(), buyer_amount),
+                (trade.seller.clone(), seller_amount),
+            ],
+        ))
+    })?;
+    Ok(res
+        .add_attribute("action", "resolve_split")
+        .add_attribute("trade_id", trade_id.to_string())
+        .add_attribute("buyer_amount", buyer_amount.to_string())
+        .add_attribute("seller_amount", seller_amount.to_string()))
+}
+
+/// Closes a disputed trade for its arbitrator. `decide` is given the trade,
+/// the offer's maker and the escrow left once the arbitration fee and the
+/// buyer's share of the protocol fees are taken out. It returns the final
+/// state and who gets how much of that escrow.
+fn settle_disputed_trade(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade_id: u64,
+    decide: impl FnOnce(
+        &Trade,
+        &Addr,
+        Uint128,
+    ) -> Result<(TradeState, Vec<(Addr, Uint128)>), ContractError>,
 ) -> Result<Response, ContractError> {
     let hub_config = get_hub_config(deps.as_ref());
     let mut trade = TradeModel::from_store(deps.storage, trade_id);
@@ -928,69 +1663,51 @@ fn settle_dispute(
         });
     }
 
-    // Load Offer
     let offer = load_offer(
         &deps.querier,
-        trade.offer_id.clone(),
+        trade.offer_id,
         trade.offer_contract.to_string(),
-    )
-    .unwrap()
+    )?
     .offer;
-
-    // Define maker and taker
-    let maker = offer.owner.clone();
-    let taker = if trade.seller.eq(&maker) {
-        trade.buyer.clone()
-    } else {
-        trade.seller.clone()
-    };
-
-    // Check if winner is eligible, it must be either maker or taker
-    if winner.eq(&maker) {
-        trade.set_state(TradeState::SettledForMaker, &env, &info);
-    } else if winner.eq(&taker) {
-        trade.set_state(TradeState::SettledForTaker, &env, &info)
-    } else {
-        return Err(ContractError::InvalidSender {
-            sender: winner,
-            buyer: trade.buyer,
-            seller: trade.seller,
-        });
-    }
+    let maker = offer.owner;
+
+    // Only the escrow left after partial releases is settled here
+    let settle_amount = trade.remaining_amount();
+    let arbitration_fee_amount = calc_arbitration_fee(&hub_config, settle_amount);
+    // The buyer's share of the fees comes out of what is paid out
+    let fee_info = calculate_fees(&hub_config, settle_amount, &trade.denom);
+    let (_, buyer_fees) = trade.fee_shares(fee_info.total_fees(), &maker);
+    let payout = settle_amount - arbitration_fee_amount - buyer_fees;
+    let (final_state, payouts) = decide(&trade, &maker, payout)?;
+
+    trade.set_state(final_state, &env, &info)?;
     TradeModel::store(deps.storage, &trade).unwrap();
+    record_resolution(deps.storage, &trade.arbitrator)?;
 
     // Collect Protocol Fees
     let trade_denom = denom_to_string(&trade.denom);
     let mut send_msgs: Vec<SubMsg> = vec![];
-    let fee_info = add_protocol_fees_msgs(
+    let mut fee_attributes: Vec<Attribute> = vec![];
+    add_protocol_fees_msgs(
         deps,
         &mut send_msgs,
-        &trade.amount,
+        &mut fee_attributes,
+        &settle_amount,
         trade_denom.clone(),
         &hub_config,
     );
 
-    // Pay arbitration fee
-    let arbitration_fee_amount = (hub_config.arbitration_fee_pct
-        * Decimal::from_ratio(trade.amount.u128(), 1u128))
-    .atomics();
-    let mut release_amount = trade.amount.sub(Uint128::from(arbitration_fee_amount));
-
-    // Only deducts fees from the release_amount if the maker (offer owner) is the buyer
-    if trade.buyer.eq(&offer.owner) {
-        release_amount = release_amount.sub(fee_info.total_fees());
+    // Pay out the escrow and the arbitration fee
+    let arbitrator_payout = (trade.arbitrator.clone(), arbitration_fee_amount);
+    for (recipient, amount) in payouts.into_iter().chain([arbitrator_payout]) {
+        if !amount.is_zero() {
+            send_msgs.push(SubMsg::new(create_send_msg(
+                recipient,
+                vec![Coin::new(amount.u128(), trade_denom.clone())],
+            )));
+        }
     }
 
-    // Send funds to winner and arbitrator
-    let denom = denom_to_string(&trade.denom);
-    let arbitration_fee = vec![Coin::new(arbitration_fee_amount.u128(), denom.clone())];
-    let winner_amount = vec![Coin::new(release_amount.u128(), denom.clone())];
-    send_msgs.push(SubMsg::new(create_send_msg(winner.clone(), winner_amount)));
-    send_msgs.push(SubMsg::new(create_send_msg(
-        trade.arbitrator.clone(),
-        arbitration_fee,
-    )));
-
     // Create Update Profile SubMsgs
     let profile_submsgs = create_update_trades_count_msgs(
         hub_config.profile_addr.to_string(),
@@ -998,15 +1715,81 @@ fn settle_dispute(
         trade.seller.clone(),
         trade.get_state(),
     );
+    let offer_submsg = update_offer_active_trades_msg(
+        hub_config.offer_addr.to_string(),
+        trade.offer_id,
+        trade.get_state(),
+        trade.amount,
+    );
 
-    let res = Response::new()
+    Ok(Response::new()
         .add_attribute("arbitrator", trade.arbitrator.to_string())
-        .add_attribute("winner", winner.to_string())
         .add_attribute("maker", maker.to_string())
-        .add_attribute("taker", taker.to_string())
+        .add_attribute("taker", trade.taker(&maker).to_string())
+        .add_attribute("state", trade.get_state().to_string())
+        .add_attributes(fee_attributes)
         .add_submessages(profile_submsgs)
-        .add_submessages(send_msgs);
-    Ok(res)
+        .add_submessage(offer_submsg)
+        .add_submessages(send_msgs))
+}
+
+/// Counts a dispute the arbitrator settled towards their selection weight.
+fn record_resolution(storage: &mut dyn Storage, arbitrator: &Addr) -> StdResult<()> {
+    ARBITRATOR_STATS.update(storage, arbitrator, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.resolved_disputes += 1;
+        Ok(stats)
+    })?;
+    Ok(())
+}
+
+fn rate_arbitrator(
+    deps: DepsMut,
+    info: MessageInfo,
+    trade_id: u64,
+    rating: u8,
+) -> Result<Response, ContractError> {
+    let trade = TradeModel::from_store(deps.storage, trade_id);
+    assert_sender_is_buyer_or_seller(
+        info.sender.clone(),
+        trade.buyer.clone(),
+        trade.seller.clone(),
+    )?;
+    let state = trade.get_state();
+    if !matches!(
+        state,
+        TradeState::SettledForMaker | TradeState::SettledForTaker
+    ) {
+        return Err(InvalidTradeState {
+            current: state,
+            expected: TradeState::SettledForMaker,
+        });
+    }
+    if rating == 0 || rating > MAX_ARBITRATOR_RATING {
+        return Err(InvalidParameter {
+            parameter: "rating".to_string(),
+            message: Some(format!(
+                "Rating must be between 1 and {}.",
+                MAX_ARBITRATOR_RATING
+            )),
+        });
+    }
+    if ARBITRATOR_RATINGS.has(deps.storage, (trade_id, &info.sender)) {
+        return Err(ContractError::ArbitratorAlreadyRated { trade_id });
+    }
+    ARBITRATOR_RATINGS.save(deps.storage, (trade_id, &info.sender), &rating)?;
+    ARBITRATOR_STATS.update(deps.storage, &trade.arbitrator, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.ratings_count += 1;
+        stats.ratings_total += u64::from(rating);
+        Ok(stats)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "rate_arbitrator")
+        .add_attribute("trade_id", trade_id.to_string())
+        .add_attribute("arbitrator", trade.arbitrator.to_string())
+        .add_attribute("rating", rating.to_string()))
 }
 
 /// Registers a conversion route for a given denom.
@@ -1036,6 +1819,7 @@ fn register_conversion_route_for_denom(
 pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
     match msg.id {
         SWAP_REPLY_ID => handle_swap_reply(deps, msg),
+        FEE_SWAP_REPLY_ID => handle_fee_swap_reply(deps),
         id => Err(ContractError::UnknownReplyId { reply_id: id }),
     }
 }
@@ -1152,6 +1936,49 @@ fn handle_swap_reply(deps: DepsMut, _msg: Reply) -> Result<Response, ContractErr
     };
 }
 
+/// Shares what the fee swap returned between the chain fee collector and the
+/// warchest, in proportion to the fees each was due in the trade denom.
+fn handle_fee_swap_reply(deps: DepsMut) -> Result<Response, ContractError> {
+    let hub_config = get_hub_config(deps.as_ref());
+    let conversion = FEE_CONVERSION.load(deps.storage)?;
+    FEE_CONVERSION.remove(deps.storage);
+
+    let fee_denom = conversion.previous_balance.denom.clone();
+    let balance = deps
+        .querier
+        .query_balance(hub_config.trade_addr.to_string(), fee_denom.clone())?;
+    let received = balance.amount.sub(conversion.previous_balance.amount);
+    if received.is_zero() {
+        return Err(ContractError::SwapErrorInvalidAmount {});
+    }
+
+    let chain_share = received.multiply_ratio(
+        conversion.chain_amount,
+        conversion.chain_amount + conversion.warchest_amount,
+    );
+    let warchest_share = received.sub(chain_share);
+    let mut send_msgs: Vec<SubMsg> = vec![];
+    if !chain_share.is_zero() {
+        send_msgs.push(SubMsg::new(create_send_msg(
+            hub_config.chain_fee_collector_addr,
+            vec![Coin::new(chain_share, fee_denom.clone())],
+        )));
+    }
+    if !warchest_share.is_zero() {
+        send_msgs.push(SubMsg::new(create_send_msg(
+            hub_config.warchest_addr,
+            vec![Coin::new(warchest_share, fee_denom.clone())],
+        )));
+    }
+
+    Ok(Response::new()
+        .add_submessages(send_msgs)
+        .add_attribute("event", "fee_swap_reply")
+        .add_attribute("received_denom", fee_denom)
+        .add_attribute("chain_fee_amount", chain_share.to_string())
+        .add_attribute("warchest_fee_amount", warchest_share.to_string()))
+}
+
 // Create sub messages for updating trades count fields on maker and taker profiles
 fn create_update_trades_count_msgs(
     profile_addr: String,
@@ -1175,32 +2002,21 @@ fn create_send_msg(to_address: Addr, amount: Vec<Coin>) -> CosmosMsg {
     })
 }
 
-/// Returns a FeeInfo struct containing the calculated fees and the final release amount.
-fn calculate_fees(hub_config: &HubConfig, amount: Uint128) -> FeeInfo {
-    let amount_u128 = amount.u128();
-    let burn_amount = (hub_config.burn_fee_pct * Decimal::from_ratio(amount_u128, 1u128)).atomics();
-    let chain_amount =
-        (hub_config.chain_fee_pct * Decimal::from_ratio(amount_u128, 1u128)).atomics();
-    let warchest_amount =
-        (hub_config.warchest_fee_pct * Decimal::from_ratio(amount_u128, 1u128)).atomics();
-
-    FeeInfo {
-        burn_amount: Uint128::from(burn_amount),
-        chain_amount: Uint128::from(chain_amount),
-        warchest_amount: Uint128::from(warchest_amount),
-    }
-}
-
 // Adds protocol fees to the given send_msgs.
 fn add_protocol_fees_msgs(
     deps: DepsMut,
     send_msgs: &mut Vec<SubMsg>,
+    attributes: &mut Vec<Attribute>,
     release_amount: &Uint128,
     trade_denom: String,
     hub_cfg: &HubConfig,
 ) -> FeeInfo {
     // Calculate fees
-    let fee_info = calculate_fees(hub_cfg, release_amount.clone());
+    let fee_info = calculate_fees(
+        hub_cfg,
+        release_amount.clone(),
+        &Denom::Native(trade_denom.clone()),
+    );
 
     // Protocol Fee (Burn)
     if !fee_info.burn_amount.is_zero() {
@@ -1259,6 +2075,63 @@ fn add_protocol_fees_msgs(
         }
     }
 
+    // Chain and warchest fees are swapped into the hub's fee denom, if it has
+    // one, when a pool converts the trade denom directly into it.
+    let collector_fees = fee_info.chain_amount + fee_info.warchest_amount;
+    let fee_denom = hub_cfg
+        .fee_denom
+        .as_ref()
+        .map(denom_to_string)
+        .filter(|fee_denom| fee_denom.ne(&trade_denom) && !collector_fees.is_zero());
+    if let Some(fee_denom) = fee_denom {
+        let direct_route = DENOM_CONVERSION_ROUTE
+            .may_load(deps.storage, &trade_denom)
+            .unwrap()
+            .and_then(|route| route.first().cloned())
+            .filter(|step| denom_to_string(&step.ask_asset).eq(&fee_denom));
+        if let Some(step) = direct_route {
+            let previous_balance = deps
+                .querier
+                .query_balance(hub_cfg.trade_addr.to_string(), fee_denom.clone())
+                .unwrap_or(Coin::new(0u128, fee_denom.clone()));
+            FEE_CONVERSION
+                .save(
+                    deps.storage,
+                    &FeeConversion {
+                        previous_balance,
+                        chain_amount: fee_info.chain_amount,
+                        warchest_amount: fee_info.warchest_amount,
+                    },
+                )
+                .unwrap();
+            send_msgs.push(SubMsg {
+                id: FEE_SWAP_REPLY_ID,
+                msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: step.pool.to_string(),
+                    msg: to_json_binary(&SwapMsg { swap: Swap {} }).unwrap(),
+                    funds: vec![coin(collector_fees.u128(), trade_denom.clone())],
+                }),
+                gas_limit: None,
+                reply_on: ReplyOn::Success,
+                payload: Binary::default(),
+            });
+            return fee_info;
+        }
+
+        // Without a direct pool the fees are paid in the trade denom, and
+        // what they are worth in the fee denom is recorded for settlement.
+        if let Some(fee_owed) = convert_with_oracle(
+            deps.as_ref(),
+            collector_fees,
+            &trade_denom,
+            &fee_denom,
+            hub_cfg,
+        ) {
+            attributes.push(attr("fee_owed_denom", fee_denom));
+            attributes.push(attr("fee_owed_amount", fee_owed.to_string()));
+        }
+    }
+
     // Chain Fee Sharing
     if !fee_info.chain_amount.is_zero() {
         send_msgs.push(SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
@@ -1276,4 +2149,28 @@ fn add_protocol_fees_msgs(
     }
     fee_info
 }
+
+/// Values `amount` of `from` in `to` through both denoms' USD oracle prices.
+/// None if either price is missing.
+fn convert_with_oracle(
+    deps: Deps,
+    amount: Uint128,
+    from: &str,
+    to: &str,
+    hub_cfg: &HubConfig,
+) -> Option<Uint256> {
+    let usd_price = |denom: &str| {
+        query_fiat_price_for_denom(
+            &deps.querier,
+            Denom::Native(denom.to_string()),
+            FiatCurrency::USD,
+            hub_cfg.price_addr.to_string(),
+        )
+        .ok()
+        .map(|price| price.price)
+        .filter(|price| !price.is_zero())
+    };
+    let (from_price, to_price) = (usd_price(from)?, usd_price(to)?);
+    Some(Uint256::from(amount).multiply_ratio(from_price, to_price))
+}
 //endregion
@@ -0,0 +1,1468 @@
+use cosmwasm_std::testing::{
+    message_info, mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{
+    coins, from_json, to_json_binary, Addr, BankMsg, Binary, Coin, ContractResult, CosmosMsg,
+    Decimal, Env, OwnedDeps, Reply, Response, SubMsgResponse, SubMsgResult, SystemResult,
+    Timestamp, Uint128, Uint256, WasmMsg, WasmQuery,
+};
+use cw20::Denom;
+use localmoney_protocol::constants::{MAX_ARBITRATOR_RATING, MAX_REINDEX_BATCH_SIZE};
+use localmoney_protocol::currencies::FiatCurrency;
+use localmoney_protocol::errors::ContractError;
+use localmoney_protocol::hub::{Admin, FeeSplit, HubConfig, QueryMsg as HubQueryMsg, TextLimits};
+use localmoney_protocol::hub_utils::VersionResponse;
+use localmoney_protocol::offer::{
+    update_offer_active_trades_msg, Offer, OfferResponse, OfferState, OfferType,
+    QueryMsg as OfferQueryMsg,
+};
+use localmoney_protocol::price::DenomFiatPrice;
+use localmoney_protocol::profile::{Profile, QueryMsg as ProfileQueryMsg};
+use localmoney_protocol::trade::{
+    calculate_fees, ArbitratorModel, ArbitratorStats, ConversionRoute, DisputeInfoResponse,
+    ExecuteMsg, InstantiateMsg, NewTrade, QueryMsg, TakeBestTrade, Trade, TradeEvent, TradeModel,
+    TradeState,
+};
+
+use crate::contract;
+use crate::contract::{execute, instantiate, query, FEE_SWAP_REPLY_ID};
+
+type Deps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+const HUB: &str = "hub";
+const ADMIN: &str = "admin";
+const MAKER: &str = "maker";
+const TAKER: &str = "taker";
+const ARBITRATOR: &str = "arbitrator";
+
+fn hub_config() -> HubConfig {
+    HubConfig {
+        offer_addr: Addr::unchecked("offer"),
+        trade_addr: Addr::unchecked("trade"),
+        profile_addr: Addr::unchecked("profile"),
+        price_addr: Addr::unchecked("price"),
+        price_provider_addr: Addr::unchecked("price_provider"),
+        local_market_addr: Addr::unchecked("local_market"),
+        local_denom: Denom::Native("local".to_string()),
+        chain_fee_collector_addr: Addr::unchecked("chain_fee_collector"),
+        warchest_addr: Addr::unchecked("warchest"),
+        active_offers_limit: 3,
+        active_trades_limit: 10,
+        arbitration_fee_pct: Decimal::percent(1),
+        burn_fee_pct: Decimal::permille(2),
+        chain_fee_pct: Decimal::permille(3),
+        warchest_fee_pct: Decimal::permille(5),
+        trade_expiration_timer: 1200,
+        trade_dispute_timer: 3600,
+        trade_limit_min: Uint128::new(1),
+        trade_limit_max: Uint128::new(1_000_000),
+        local_denom_fee_discount_pct: Decimal::zero(),
+        offer_update_delay: 0,
+        text_limits: TextLimits::default(),
+        trade_payment_timer: 0,
+        funding_deadline_seconds: 0,
+        paused: false,
+        fee_split: FeeSplit::default(),
+        fee_denom: None,
+        cancellation_fee_bps: 0,
+    }
+}
+
+fn sell_offer() -> Offer {
+    Offer {
+        id: 1,
+        owner: Addr::unchecked(MAKER),
+        offer_type: OfferType::Sell,
+        fiat_currency: FiatCurrency::USD,
+        rate: Uint128::new(100),
+        min_amount: Uint128::new(1_000_000),
+        max_amount: Uint128::new(50_000_000),
+        description: None,
+        denom: Denom::Native("uatom".to_string()),
+        state: OfferState::Active,
+        timestamp: 0,
+        max_active_trades: None,
+        active_trades_count: 0,
+        owner_key_version: 0,
+        expires_at: None,
+        filled_amount: Uint128::zero(),
+        auto_repost: false,
+        deposit: Uint128::zero(),
+    }
+}
+
+fn reply<T: serde::Serialize>(value: &T) -> cosmwasm_std::QuerierResult {
+    SystemResult::Ok(ContractResult::Ok(to_json_binary(value).unwrap()))
+}
+
+fn offer_response(offer: &Offer) -> OfferResponse {
+    OfferResponse {
+        offer: offer.clone(),
+        profile: Profile::new(offer.owner.clone(), 0),
+        solvency: None,
+        remaining_amount: offer.remaining_amount(),
+        backed: offer.is_backed(),
+    }
+}
+
+/// Answers the hub, offer, price and profile queries the trade contract makes,
+/// serving `config` from the hub and `offers` as the offer contract's only offers.
+fn mock_queries(deps: &mut Deps, config: HubConfig, offers: Vec<Offer>) {
+    mock_queries_with_blocklist(deps, config, offers, vec![])
+}
+
+/// Like `mock_queries`, with the offer contract reporting each `(maker, taker)`
+/// pair in `blocked` as blocked.
+fn mock_queries_with_blocklist(
+    deps: &mut Deps,
+    config: HubConfig,
+    offers: Vec<Offer>,
+    blocked: Vec<(Addr, Addr)>,
+) {
+    let offer = offers[0].clone();
+    deps.querier.update_wasm(move |query| match query {
+        WasmQuery::Smart { contract_addr, msg } => {
+            if contract_addr == HUB {
+                match from_json::<HubQueryMsg>(msg).unwrap() {
+                    HubQueryMsg::Config {} => reply(&config),
+                    HubQueryMsg::Admin {} => reply(&Admin {
+                        addr: Addr::unchecked(ADMIN),
+                    }),
+                    msg => panic!("unexpected hub query: {:?}", msg),
+                }
+            } else if contract_addr == config.offer_addr.as_str() {
+                match from_json::<OfferQueryMsg>(msg).unwrap() {
+                    OfferQueryMsg::Offer { id } => {
+                        reply(&offer_response(offers.iter().find(|o| o.id == id).unwrap()))
+                    }
+                    OfferQueryMsg::TakeableOffers { .. } => reply(&offers),
+                    OfferQueryMsg::IsTakerBlocked { maker, taker } => {
+                        reply(&blocked.contains(&(maker, taker)))
+                    }
+                    msg => panic!("unexpected offer query: {:?}", msg),
+                }
+            } else if contract_addr == config.price_addr.as_str() {
+                reply(&DenomFiatPrice {
+                    denom: offer.denom.clone(),
+                    fiat: offer.fiat_currency.clone(),
+                    price: Uint256::from(100u128),
+                })
+            } else if contract_addr == config.profile_addr.as_str() {
+                match from_json::<ProfileQueryMsg>(msg).unwrap() {
+                    ProfileQueryMsg::Profile { addr } => reply(&Profile::new(addr, 0)),
+                    ProfileQueryMsg::Profiles { .. } => reply(&Vec::<Profile>::new()),
+                }
+            } else {
+                panic!("unexpected query to {}", contract_addr)
+            }
+        }
+        _ => panic!("unexpected query: {:?}", query),
+    });
+}
+
+fn setup(offer: Offer) -> Deps {
+    setup_with_offers(vec![offer])
+}
+
+fn setup_with_offers(offers: Vec<Offer>) -> Deps {
+    setup_with_config(hub_config(), offers)
+}
+
+fn setup_with_config(config: HubConfig, offers: Vec<Offer>) -> Deps {
+    let mut deps = mock_dependencies();
+    let hub = message_info(&Addr::unchecked(HUB), &[]);
+    instantiate(deps.as_mut(), mock_env(), hub.clone(), InstantiateMsg {}).unwrap();
+    execute(deps.as_mut(), mock_env(), hub, ExecuteMsg::RegisterHub {}).unwrap();
+    mock_queries(&mut deps, config, offers);
+
+    let admin = message_info(&Addr::unchecked(ADMIN), &[]);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        admin,
+        ExecuteMsg::NewArbitrator {
+            arbitrator: Addr::unchecked(ARBITRATOR),
+            fiat: FiatCurrency::USD,
+            encryption_key: "arbitrator_key".to_string(),
+        },
+    )
+    .unwrap();
+    deps
+}
+
+fn try_create_trade(deps: &mut Deps, amount: u128) -> Result<Response, ContractError> {
+    let taker = Addr::unchecked(TAKER);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&taker, &[]),
+        ExecuteMsg::Create(NewTrade {
+            offer_id: 1,
+            amount: Uint128::new(amount),
+            taker,
+            profile_taker_contact: "taker_contact".to_string(),
+            profile_taker_encryption_key: "taker_key".to_string(),
+            taker_contact: "taker_contact".to_string(),
+        }),
+    )
+}
+
+fn create_trade(deps: &mut Deps, amount: u128) -> u64 {
+    let res = try_create_trade(deps, amount).unwrap();
+    res.attributes
+        .iter()
+        .find(|attr| attr.key == "trade_id")
+        .unwrap()
+        .value
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn next_trade_id_matches_created_trade() {
+    let mut deps = setup(sell_offer());
+
+    for _ in 0..2 {
+        let next_id: u64 =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::NextTradeId {}).unwrap()).unwrap();
+        let trade_id = create_trade(&mut deps, 10_000_000);
+        assert_eq!(next_id, trade_id);
+    }
+}
+
+#[test]
+fn version_reports_the_package_and_hub_registration() {
+    let mut deps = mock_dependencies();
+    let hub = message_info(&Addr::unchecked(HUB), &[]);
+    instantiate(deps.as_mut(), mock_env(), hub.clone(), InstantiateMsg {}).unwrap();
+    let version = |deps: &Deps| -> VersionResponse {
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Version {}).unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        version(&deps),
+        VersionResponse {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            hub_registered: false,
+        }
+    );
+    execute(deps.as_mut(), mock_env(), hub, ExecuteMsg::RegisterHub {}).unwrap();
+    assert!(version(&deps).hub_registered);
+}
+
+fn register_arbitrator(deps: &mut Deps, arbitrator: &str) {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(ADMIN), &[]),
+        ExecuteMsg::NewArbitrator {
+            arbitrator: Addr::unchecked(arbitrator),
+            fiat: FiatCurrency::USD,
+            encryption_key: "arbitrator_key".to_string(),
+        },
+    )
+    .unwrap();
+}
+
+fn delete_arbitrator(deps: &mut Deps, arbitrator: &str) {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(ADMIN), &[]),
+        ExecuteMsg::DeleteArbitrator {
+            arbitrator: Addr::unchecked(arbitrator),
+            fiat: FiatCurrency::USD,
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn taker_is_never_assigned_as_arbitrator() {
+    let mut deps = setup(sell_offer());
+    register_arbitrator(&mut deps, TAKER);
+
+    for _ in 0..3 {
+        let trade_id = create_trade(&mut deps, 10_000_000);
+        let trade = TradeModel::from_store(&deps.storage, trade_id);
+        assert_eq!(trade.arbitrator, Addr::unchecked(ARBITRATOR));
+    }
+}
+
+#[test]
+fn trade_is_rejected_when_only_a_party_can_arbitrate() {
+    let mut deps = setup(sell_offer());
+    register_arbitrator(&mut deps, TAKER);
+    delete_arbitrator(&mut deps, ARBITRATOR);
+
+    let err = try_create_trade(&mut deps, 10_000_000).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::NoArbitratorAvailable {
+            fiat: FiatCurrency::USD
+        }
+    ));
+}
+
+const TRADE_AMOUNT: u128 = 10_000_000;
+
+/// Creates a trade on the sell offer and has the maker fund its escrow.
+fn funded_trade(deps: &mut Deps) -> u64 {
+    let trade_id = create_trade(deps, TRADE_AMOUNT);
+    let amount = Uint128::new(TRADE_AMOUNT);
+    let fees = calculate_fees(&hub_config(), amount, &sell_offer().denom).total_fees();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(
+            &Addr::unchecked(MAKER),
+            &coins((amount + fees).u128(), "uatom"),
+        ),
+        ExecuteMsg::FundEscrow {
+            trade_id,
+            maker_contact: Some("maker_contact".to_string()),
+        },
+    )
+    .unwrap();
+    trade_id
+}
+
+fn refund(
+    deps: &mut Deps,
+    sender: &str,
+    env: Env,
+    trade_id: u64,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        env,
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::RefundEscrow { trade_id },
+    )
+}
+
+fn attribute<'a>(res: &'a Response, key: &str) -> &'a str {
+    &res.attributes
+        .iter()
+        .find(|attr| attr.key == key)
+        .unwrap()
+        .value
+}
+
+#[test]
+fn anyone_can_refund_once_the_trade_expires() {
+    let mut deps = setup(sell_offer());
+    let trade_id = funded_trade(&mut deps);
+    let expires_at = TradeModel::from_store(&deps.storage, trade_id).expires_at;
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(expires_at);
+    let err = refund(&mut deps, "keeper", env.clone(), trade_id).unwrap_err();
+    assert!(matches!(err, ContractError::RefundErrorNotExpired { .. }));
+
+    env.block.time = Timestamp::from_seconds(expires_at + 1);
+    let res = refund(&mut deps, "keeper", env, trade_id).unwrap();
+    assert_eq!(attribute(&res, "refund_type"), "expired");
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::EscrowRefunded
+    );
+}
+
+#[test]
+fn seller_can_refund_before_expiry() {
+    let mut deps = setup(sell_offer());
+    let trade_id = funded_trade(&mut deps);
+
+    let err = refund(&mut deps, TAKER, mock_env(), trade_id).unwrap_err();
+    assert!(matches!(err, ContractError::RefundErrorNotExpired { .. }));
+
+    let res = refund(&mut deps, MAKER, mock_env(), trade_id).unwrap();
+    assert_eq!(attribute(&res, "refund_type"), "manual");
+}
+
+fn take_best(deps: &mut Deps, amount: u128) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(TAKER), &[]),
+        ExecuteMsg::TakeBest(TakeBestTrade {
+            offer_type: OfferType::Sell,
+            fiat_currency: FiatCurrency::USD,
+            denom: Denom::Native("uatom".to_string()),
+            amount: Uint128::new(amount),
+            profile_taker_contact: "taker_contact".to_string(),
+            profile_taker_encryption_key: "taker_key".to_string(),
+            taker_contact: "taker_contact".to_string(),
+        }),
+    )
+}
+
+/// Three sell offers: the cheapest two can hold 50 and 30 atom, the third is pricier.
+fn split_offers() -> Vec<Offer> {
+    let offer = |id: u64, rate: u128, max_amount: u128| Offer {
+        id,
+        rate: Uint128::new(rate),
+        max_amount: Uint128::new(max_amount),
+        ..sell_offer()
+    };
+    vec![
+        offer(1, 105, 50_000_000),
+        offer(2, 98, 50_000_000),
+        offer(3, 99, 30_000_000),
+    ]
+}
+
+#[test]
+fn take_best_fills_a_large_order_across_offers() {
+    let mut deps = setup_with_offers(split_offers());
+
+    let res = take_best(&mut deps, 70_000_000).unwrap();
+    let trade_ids = res
+        .attributes
+        .iter()
+        .find(|attr| attr.key == "trade_ids")
+        .unwrap();
+    assert_eq!(trade_ids.value, "1,2");
+
+    let first = TradeModel::from_store(&deps.storage, 1);
+    assert_eq!(
+        (first.offer_id, first.amount),
+        (2, Uint128::new(50_000_000))
+    );
+    let second = TradeModel::from_store(&deps.storage, 2);
+    assert_eq!(
+        (second.offer_id, second.amount),
+        (3, Uint128::new(20_000_000))
+    );
+    assert_eq!(second.buyer, Addr::unchecked(TAKER));
+}
+
+#[test]
+fn take_best_rejects_orders_beyond_available_liquidity() {
+    let mut deps = setup_with_offers(split_offers());
+
+    let err = take_best(&mut deps, 140_000_000).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InsufficientOfferLiquidity { requested, available }
+            if requested == Uint128::new(140_000_000) && available == Uint128::new(130_000_000)
+    ));
+    let next_id: u64 =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::NextTradeId {}).unwrap()).unwrap();
+    assert_eq!(next_id, 1);
+}
+
+fn offer_with_trades(max_active_trades: u32, active_trades_count: u32) -> Offer {
+    Offer {
+        max_active_trades: Some(max_active_trades),
+        active_trades_count,
+        ..sell_offer()
+    }
+}
+
+#[test]
+fn trade_is_rejected_when_the_offer_is_at_its_cap() {
+    let mut deps = setup(offer_with_trades(2, 2));
+
+    let err = try_create_trade(&mut deps, TRADE_AMOUNT).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OfferActiveTradesLimitReached {
+            offer_id: 1,
+            limit: 2
+        }
+    ));
+}
+
+#[test]
+fn trades_take_and_give_back_an_offer_slot() {
+    let mut deps = setup(offer_with_trades(2, 1));
+
+    let res = try_create_trade(&mut deps, TRADE_AMOUNT).unwrap();
+    let taken = update_offer_active_trades_msg(
+        "offer".to_string(),
+        1,
+        TradeState::RequestCreated,
+        Uint128::new(TRADE_AMOUNT),
+    );
+    assert!(res.messages.contains(&taken));
+
+    let trade_id = funded_trade(&mut deps);
+    let res = refund(&mut deps, MAKER, mock_env(), trade_id).unwrap();
+    let given_back = update_offer_active_trades_msg(
+        "offer".to_string(),
+        1,
+        TradeState::EscrowRefunded,
+        Uint128::new(TRADE_AMOUNT),
+    );
+    assert!(res.messages.contains(&given_back));
+}
+
+#[test]
+fn take_best_skips_offers_at_their_cap() {
+    let mut offers = split_offers();
+    offers[1].max_active_trades = Some(1);
+    offers[1].active_trades_count = 1;
+    let mut deps = setup_with_offers(offers);
+
+    take_best(&mut deps, 70_000_000).unwrap();
+    let first = TradeModel::from_store(&deps.storage, 1);
+    let second = TradeModel::from_store(&deps.storage, 2);
+    assert_eq!((first.offer_id, second.offer_id), (3, 1));
+}
+
+#[test]
+fn take_best_skips_fills_beyond_the_trade_limit() {
+    // 50 atom at rate 98 is worth 490_000, above the limit
+    let config = HubConfig {
+        trade_limit_max: Uint128::new(400_000),
+        ..hub_config()
+    };
+    let mut deps = setup_with_config(config, split_offers());
+
+    take_best(&mut deps, 60_000_000).unwrap();
+    let first = TradeModel::from_store(&deps.storage, 1);
+    let second = TradeModel::from_store(&deps.storage, 2);
+    assert_eq!(
+        (first.offer_id, first.amount),
+        (3, Uint128::new(30_000_000))
+    );
+    assert_eq!(
+        (second.offer_id, second.amount),
+        (1, Uint128::new(30_000_000))
+    );
+}
+
+const PAYMENT_TIMER: u64 = 600;
+
+fn crank_overdue(deps: &mut Deps, env: Env, trade_id: u64) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        env,
+        message_info(&Addr::unchecked("keeper"), &[]),
+        ExecuteMsg::CrankOverdue { trade_id },
+    )
+}
+
+fn env_at(seconds: u64) -> Env {
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(seconds);
+    env
+}
+
+#[test]
+fn overdue_trade_is_auto_disputed() {
+    let config = HubConfig {
+        trade_payment_timer: PAYMENT_TIMER,
+        ..hub_config()
+    };
+    let mut deps = setup_with_config(config, vec![sell_offer()]);
+    let trade_id = funded_trade(&mut deps);
+    let deadline = mock_env().block.time.seconds() + PAYMENT_TIMER;
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).payment_deadline,
+        Some(deadline)
+    );
+
+    let res = crank_overdue(&mut deps, env_at(deadline + 1), trade_id).unwrap();
+    assert_eq!(attribute(&res, "arbitrator"), ARBITRATOR);
+    let event = trade_event(&res);
+    assert_eq!(event.from_state, TradeState::EscrowFunded);
+    assert_eq!(event.to_state, TradeState::EscrowDisputed);
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::EscrowDisputed
+    );
+}
+
+#[test]
+fn trade_within_its_payment_deadline_stays_put() {
+    let config = HubConfig {
+        trade_payment_timer: PAYMENT_TIMER,
+        ..hub_config()
+    };
+    let mut deps = setup_with_config(config, vec![sell_offer()]);
+    let trade_id = funded_trade(&mut deps);
+    let deadline = mock_env().block.time.seconds() + PAYMENT_TIMER;
+
+    let err = crank_overdue(&mut deps, env_at(deadline), trade_id).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::TradeNotOverdue { payment_deadline } if payment_deadline == Some(deadline)
+    ));
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::EscrowFunded
+    );
+
+    // Without a hub payment timer trades never become overdue.
+    let mut deps = setup(sell_offer());
+    let trade_id = funded_trade(&mut deps);
+    let err = crank_overdue(&mut deps, env_at(deadline * 2), trade_id).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::TradeNotOverdue {
+            payment_deadline: None
+        }
+    ));
+}
+
+fn dispute(
+    deps: &mut Deps,
+    env: Env,
+    trade_id: u64,
+    reason: &str,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        env,
+        message_info(&Addr::unchecked(TAKER), &[]),
+        ExecuteMsg::DisputeEscrow {
+            trade_id,
+            buyer_contact: "buyer_contact".to_string(),
+            seller_contact: "seller_contact".to_string(),
+            reason: reason.to_string(),
+            evidence_uri: Some("ipfs://receipt".to_string()),
+        },
+    )
+}
+
+fn submit_evidence(
+    deps: &mut Deps,
+    sender: &str,
+    trade_id: u64,
+    reason: &str,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::SubmitDisputeEvidence {
+            trade_id,
+            reason: reason.to_string(),
+            evidence_uri: None,
+        },
+    )
+}
+
+fn dispute_info(deps: &Deps, trade_id: u64) -> DisputeInfoResponse {
+    from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::DisputeInfo { trade_id },
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+/// Funds a trade on the sell offer and has the taker mark it paid, the
+/// returned env is the first moment a dispute can be opened.
+fn paid_trade(deps: &mut Deps) -> (u64, Env) {
+    let trade_id = funded_trade(deps);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(TAKER), &[]),
+        ExecuteMsg::FiatDeposited { trade_id },
+    )
+    .unwrap();
+    let enables_dispute_at = mock_env().block.time.seconds() + hub_config().trade_dispute_timer;
+    (trade_id, env_at(enables_dispute_at))
+}
+
+#[test]
+fn both_parties_can_submit_and_replace_dispute_evidence() {
+    let mut deps = setup(sell_offer());
+    let (trade_id, env) = paid_trade(&mut deps);
+    dispute(&mut deps, env.clone(), trade_id, "Seller never released").unwrap();
+
+    let info = dispute_info(&deps, trade_id);
+    let buyer = info.buyer.unwrap();
+    assert_eq!(buyer.reason, "Seller never released");
+    assert_eq!(buyer.evidence_uri, Some("ipfs://receipt".to_string()));
+    assert_eq!(buyer.submitted_at, env.block.time.seconds());
+    assert_eq!(info.seller, None);
+
+    submit_evidence(&mut deps, MAKER, trade_id, "No payment arrived").unwrap();
+    submit_evidence(&mut deps, TAKER, trade_id, "Paid by wire, see bank").unwrap();
+
+    let info = dispute_info(&deps, trade_id);
+    assert_eq!(info.seller.unwrap().reason, "No payment arrived");
+    let buyer = info.buyer.unwrap();
+    assert_eq!(buyer.reason, "Paid by wire, see bank");
+    assert_eq!(buyer.evidence_uri, None);
+
+    let err = submit_evidence(&mut deps, "stranger", trade_id, "Me too").unwrap_err();
+    assert!(matches!(err, ContractError::InvalidSender { .. }));
+}
+
+#[test]
+fn dispute_evidence_is_validated() {
+    let mut deps = setup(sell_offer());
+    let (trade_id, env) = paid_trade(&mut deps);
+
+    let err = submit_evidence(&mut deps, TAKER, trade_id, "Too early").unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTradeState { .. }));
+
+    let err = dispute(&mut deps, env.clone(), trade_id, " ").unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "reason")
+    );
+    let too_long = "a".repeat(TextLimits::default().dispute_reason as usize + 1);
+    let err = dispute(&mut deps, env, trade_id, &too_long).unwrap_err();
+    assert!(
+        matches!(err, ContractError::InvalidParameter { parameter, .. } if parameter == "reason")
+    );
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::FiatDeposited
+    );
+}
+
+fn trades_by_state(deps: &Deps, state: TradeState, start_after: Option<u64>) -> Vec<u64> {
+    let trades: Vec<Trade> = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::TradesByState {
+                state,
+                limit: 10,
+                start_after,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    trades.iter().map(|trade| trade.id).collect()
+}
+
+#[test]
+fn trades_are_listed_by_their_current_state() {
+    let mut deps = setup(sell_offer());
+    let (disputed, env) = paid_trade(&mut deps);
+    dispute(&mut deps, env, disputed, "Seller never released").unwrap();
+    let funded = funded_trade(&mut deps);
+    let refunded = funded_trade(&mut deps);
+    refund(&mut deps, MAKER, mock_env(), refunded).unwrap();
+    let other_funded = funded_trade(&mut deps);
+
+    assert_eq!(
+        trades_by_state(&deps, TradeState::EscrowDisputed, None),
+        vec![disputed]
+    );
+    assert_eq!(
+        trades_by_state(&deps, TradeState::EscrowFunded, None),
+        vec![funded, other_funded]
+    );
+    assert_eq!(
+        trades_by_state(&deps, TradeState::EscrowFunded, Some(funded)),
+        vec![other_funded]
+    );
+    assert_eq!(
+        trades_by_state(&deps, TradeState::EscrowRefunded, None),
+        vec![refunded]
+    );
+    assert!(trades_by_state(&deps, TradeState::FiatDeposited, None).is_empty());
+}
+
+#[test]
+fn open_trades_still_settle_while_the_protocol_is_paused() {
+    let mut deps = setup(sell_offer());
+    let (released, _) = paid_trade(&mut deps);
+    let refunded = funded_trade(&mut deps);
+
+    // Releasing burns through a conversion route these tests don't register
+    let paused = HubConfig {
+        paused: true,
+        burn_fee_pct: Decimal::zero(),
+        ..hub_config()
+    };
+    mock_queries(&mut deps, paused, vec![sell_offer()]);
+
+    let err = try_create_trade(&mut deps, TRADE_AMOUNT).unwrap_err();
+    assert!(matches!(err, ContractError::ContractPaused {}));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(MAKER), &[]),
+        ExecuteMsg::ReleaseEscrow { trade_id: released },
+    )
+    .unwrap();
+    refund(&mut deps, MAKER, mock_env(), refunded).unwrap();
+
+    assert_eq!(
+        trades_by_state(&deps, TradeState::EscrowReleased, None),
+        vec![released]
+    );
+    assert_eq!(
+        trades_by_state(&deps, TradeState::EscrowRefunded, None),
+        vec![refunded]
+    );
+}
+
+fn release_partial(
+    deps: &mut Deps,
+    sender: &str,
+    trade_id: u64,
+    amount: u128,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::ReleasePartial {
+            trade_id,
+            amount: Uint128::new(amount),
+        },
+    )
+}
+
+fn sent_to(res: &Response, addr: &str) -> Vec<Coin> {
+    res.messages
+        .iter()
+        .find_map(|sub_msg| match &sub_msg.msg {
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) if to_address == addr => {
+                Some(amount.clone())
+            }
+            _ => None,
+        })
+        .unwrap()
+}
+
+fn sent_to_taker(res: &Response) -> Vec<Coin> {
+    sent_to(res, TAKER)
+}
+
+#[test]
+fn escrow_is_released_in_tranches() {
+    let mut deps = setup(sell_offer());
+    let (trade_id, _) = paid_trade(&mut deps);
+    // Releasing burns through a conversion route these tests don't register
+    let config = HubConfig {
+        burn_fee_pct: Decimal::zero(),
+        ..hub_config()
+    };
+    mock_queries(&mut deps, config, vec![sell_offer()]);
+
+    let err = release_partial(&mut deps, TAKER, trade_id, 1).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+    let err = release_partial(&mut deps, ARBITRATOR, trade_id, 1).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTradeState { .. }));
+
+    let first = release_partial(&mut deps, MAKER, trade_id, 4_000_000).unwrap();
+    assert_eq!(sent_to_taker(&first), coins(4_000_000, "uatom"));
+    assert_eq!(attribute(&first, "remaining_amount"), "6000000");
+    let trade = TradeModel::from_store(&deps.storage, trade_id);
+    assert_eq!(trade.get_state(), TradeState::FiatDeposited);
+    assert_eq!(trade.released_amount, Uint128::new(4_000_000));
+
+    let err = release_partial(&mut deps, MAKER, trade_id, 6_000_001).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::ReleaseExceedsRemaining { remaining, .. } if remaining.u128() == 6_000_000
+    ));
+
+    let second = release_partial(&mut deps, MAKER, trade_id, 6_000_000).unwrap();
+    assert_eq!(sent_to_taker(&second), coins(6_000_000, "uatom"));
+    let trade = TradeModel::from_store(&deps.storage, trade_id);
+    assert_eq!(trade.get_state(), TradeState::EscrowReleased);
+    assert_eq!(trade.released_amount, Uint128::new(TRADE_AMOUNT));
+    assert!(trade.remaining_amount().is_zero());
+}
+
+const FUNDING_DEADLINE: u64 = 900;
+
+fn cancel_unfunded(
+    deps: &mut Deps,
+    sender: &str,
+    env: Env,
+    trade_id: u64,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        env,
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::CancelUnfunded { trade_id },
+    )
+}
+
+#[test]
+fn seller_cancels_an_unfunded_trade_after_the_funding_deadline() {
+    let config = HubConfig {
+        funding_deadline_seconds: FUNDING_DEADLINE,
+        ..hub_config()
+    };
+    let mut deps = setup_with_config(config, vec![offer_with_trades(2, 1)]);
+    let trade_id = create_trade(&mut deps, TRADE_AMOUNT);
+    let deadline = mock_env().block.time.seconds() + FUNDING_DEADLINE;
+
+    let err = cancel_unfunded(&mut deps, MAKER, env_at(deadline - 1), trade_id).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::FundingDeadlineNotPassed { funding_deadline } if funding_deadline == Some(deadline)
+    ));
+    let err = cancel_unfunded(&mut deps, TAKER, env_at(deadline), trade_id).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    let res = cancel_unfunded(&mut deps, MAKER, env_at(deadline), trade_id).unwrap();
+    let given_back = update_offer_active_trades_msg(
+        "offer".to_string(),
+        1,
+        TradeState::RequestCanceled,
+        Uint128::new(TRADE_AMOUNT),
+    );
+    assert!(res.messages.contains(&given_back));
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::RequestCanceled
+    );
+}
+
+#[test]
+fn funded_trades_can_not_be_cancelled_as_unfunded() {
+    let config = HubConfig {
+        funding_deadline_seconds: FUNDING_DEADLINE,
+        ..hub_config()
+    };
+    let mut deps = setup_with_config(config, vec![sell_offer()]);
+    let trade_id = funded_trade(&mut deps);
+    let after_deadline = env_at(mock_env().block.time.seconds() + FUNDING_DEADLINE);
+
+    let err = cancel_unfunded(&mut deps, MAKER, after_deadline, trade_id).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTradeStateChange { .. }));
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::EscrowFunded
+    );
+}
+
+#[test]
+fn blocked_takers_can_not_open_trades_on_the_makers_offers() {
+    let mut deps = setup(sell_offer());
+    let blocked = vec![(Addr::unchecked(MAKER), Addr::unchecked(TAKER))];
+    mock_queries_with_blocklist(&mut deps, hub_config(), vec![sell_offer()], blocked);
+
+    let err = try_create_trade(&mut deps, TRADE_AMOUNT).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::TakerBlocked { maker, taker } if maker.as_str() == MAKER && taker.as_str() == TAKER
+    ));
+
+    // Once unblocked the taker trades as usual
+    mock_queries(&mut deps, hub_config(), vec![sell_offer()]);
+    try_create_trade(&mut deps, TRADE_AMOUNT).unwrap();
+}
+
+fn admin_cancel(deps: &mut Deps, sender: &str, trade_id: u64) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::AdminCancel { trade_id },
+    )
+}
+
+#[test]
+fn admin_unwinds_a_disputed_trade() {
+    let mut deps = setup(sell_offer());
+    let (trade_id, env) = paid_trade(&mut deps);
+    dispute(&mut deps, env, trade_id, "seller went silent").unwrap();
+
+    for sender in [MAKER, TAKER, ARBITRATOR] {
+        let err = admin_cancel(&mut deps, sender, trade_id).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+    }
+
+    let res = admin_cancel(&mut deps, ADMIN, trade_id).unwrap();
+    assert_eq!(attribute(&res, "admin"), ADMIN);
+    assert_eq!(attribute(&res, "prior_state"), "EscrowDisputed");
+    let refund = CosmosMsg::Bank(BankMsg::Send {
+        to_address: MAKER.to_string(),
+        amount: coins(TRADE_AMOUNT, "uatom"),
+    });
+    assert!(res.messages.iter().any(|sub_msg| sub_msg.msg == refund));
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::EscrowRefunded
+    );
+
+    // The refunded trade can't be unwound a second time
+    let err = admin_cancel(&mut deps, ADMIN, trade_id).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTradeStateChange { .. }));
+}
+
+fn reindex(
+    deps: &mut Deps,
+    sender: &str,
+    start_after: Option<u64>,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::Reindex {
+            start_after,
+            limit: None,
+        },
+    )
+}
+
+#[test]
+fn trades_are_reindexed_in_admin_pages() {
+    let mut deps = setup(sell_offer());
+    let ids: Vec<u64> = (0..=MAX_REINDEX_BATCH_SIZE)
+        .map(|_| create_trade(&mut deps, TRADE_AMOUNT))
+        .collect();
+
+    let err = reindex(&mut deps, MAKER, None).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+    let first = reindex(&mut deps, ADMIN, None).unwrap();
+    assert_eq!(
+        attribute(&first, "count"),
+        MAX_REINDEX_BATCH_SIZE.to_string()
+    );
+    let next_start_after = attribute(&first, "next_start_after");
+    assert_eq!(
+        next_start_after,
+        ids[MAX_REINDEX_BATCH_SIZE - 1].to_string()
+    );
+
+    let last = reindex(&mut deps, ADMIN, Some(next_start_after.parse().unwrap())).unwrap();
+    assert_eq!(attribute(&last, "count"), "1");
+    assert!(!last
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "next_start_after"));
+}
+
+/// Chain and warchest fees on `TRADE_AMOUNT`, 0.3% and 0.5%.
+const TRADE_FEES: u128 = 80_000;
+
+/// Funds a trade on the sell offer with `sent`, under a hub charging
+/// `TRADE_FEES` split by `fee_split`.
+fn fund_under_split(fee_split: FeeSplit, sent: u128) -> Result<(Deps, u64), ContractError> {
+    fund_under_config(
+        HubConfig {
+            fee_split,
+            ..hub_config()
+        },
+        sent,
+    )
+}
+
+/// Funds a trade on the sell offer with `sent` under `config`, without the
+/// burn fee.
+fn fund_under_config(config: HubConfig, sent: u128) -> Result<(Deps, u64), ContractError> {
+    // Releasing burns through a conversion route these tests don't register
+    let config = HubConfig {
+        burn_fee_pct: Decimal::zero(),
+        ..config
+    };
+    let mut deps = setup_with_config(config, vec![sell_offer()]);
+    let trade_id = create_trade(&mut deps, TRADE_AMOUNT);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(MAKER), &coins(sent, "uatom")),
+        ExecuteMsg::FundEscrow {
+            trade_id,
+            maker_contact: Some("maker_contact".to_string()),
+        },
+    )?;
+    Ok((deps, trade_id))
+}
+
+/// Marks the funded trade paid and has the maker release it.
+fn release(deps: &mut Deps, trade_id: u64) -> Response {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(TAKER), &[]),
+        ExecuteMsg::FiatDeposited { trade_id },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(MAKER), &[]),
+        ExecuteMsg::ReleaseEscrow { trade_id },
+    )
+    .unwrap()
+}
+
+/// Checks that the maker, selling, funds `TRADE_AMOUNT` plus `maker_fees`,
+/// the taker receives `TRADE_AMOUNT` less `taker_fees` and the fee collectors
+/// get the whole fee either way.
+fn assert_fee_split(fee_split: FeeSplit, maker_fees: u128, taker_fees: u128) {
+    let err = fund_under_split(fee_split.clone(), TRADE_AMOUNT + maker_fees + 1)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        ContractError::FundEscrowError { required_amount, .. }
+            if required_amount.u128() == TRADE_AMOUNT + maker_fees
+    ));
+
+    let (mut deps, trade_id) =
+        fund_under_split(fee_split.clone(), TRADE_AMOUNT + maker_fees).unwrap();
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).fee_split,
+        fee_split
+    );
+    let res = release(&mut deps, trade_id);
+    assert_eq!(
+        sent_to_taker(&res),
+        coins(TRADE_AMOUNT - taker_fees, "uatom")
+    );
+    assert_eq!(sent_to(&res, "chain_fee_collector"), coins(30_000, "uatom"));
+    assert_eq!(sent_to(&res, "warchest"), coins(50_000, "uatom"));
+}
+
+#[test]
+fn maker_pays_the_fees_by_default() {
+    assert_fee_split(FeeSplit::default(), TRADE_FEES, 0);
+}
+
+#[test]
+fn taker_pays_the_fees() {
+    let fee_split = FeeSplit {
+        maker_pct: 0,
+        taker_pct: 100,
+    };
+    assert_fee_split(fee_split, 0, TRADE_FEES);
+}
+
+#[test]
+fn maker_and_taker_split_the_fees_evenly() {
+    let fee_split = FeeSplit {
+        maker_pct: 50,
+        taker_pct: 50,
+    };
+    assert_fee_split(fee_split, 40_000, 40_000);
+}
+
+fn fee_denom_config(fee_denom: &str) -> HubConfig {
+    HubConfig {
+        fee_denom: Some(Denom::Native(fee_denom.to_string())),
+        ..hub_config()
+    }
+}
+
+#[test]
+fn fees_in_the_fee_denom_are_sent_as_they_are() {
+    let (mut deps, trade_id) =
+        fund_under_config(fee_denom_config("uatom"), TRADE_AMOUNT + TRADE_FEES).unwrap();
+    let res = release(&mut deps, trade_id);
+
+    assert!(res.messages.iter().all(|msg| msg.id == 0));
+    assert_eq!(sent_to(&res, "chain_fee_collector"), coins(30_000, "uatom"));
+    assert_eq!(sent_to(&res, "warchest"), coins(50_000, "uatom"));
+}
+
+#[test]
+fn fees_are_swapped_into_the_fee_denom() {
+    let (mut deps, trade_id) =
+        fund_under_config(fee_denom_config("local"), TRADE_AMOUNT + TRADE_FEES).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(ADMIN), &[]),
+        ExecuteMsg::RegisterConversionRouteForDenom {
+            denom: Denom::Native("uatom".to_string()),
+            route: vec![ConversionRoute {
+                pool: Addr::unchecked("local_pool"),
+                ask_asset: Denom::Native("local".to_string()),
+                offer_asset: Denom::Native("uatom".to_string()),
+            }],
+        },
+    )
+    .unwrap();
+
+    let res = release(&mut deps, trade_id);
+    let swap = res
+        .messages
+        .iter()
+        .find(|msg| msg.id == FEE_SWAP_REPLY_ID)
+        .unwrap();
+    assert!(matches!(
+        &swap.msg,
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, funds, .. })
+            if contract_addr == "local_pool" && *funds == coins(TRADE_FEES, "uatom")
+    ));
+    assert_eq!(sent_to_taker(&res), coins(TRADE_AMOUNT, "uatom"));
+    // The collectors are only paid once the swap replies
+    let bank_sends = res
+        .messages
+        .iter()
+        .filter(|msg| matches!(msg.msg, CosmosMsg::Bank(BankMsg::Send { .. })))
+        .count();
+    assert_eq!(bank_sends, 1);
+
+    // The pool pays out 160 LOCAL, shared 3:5 like the fees
+    deps.querier
+        .bank
+        .update_balance("trade", coins(160, "local"));
+    #[allow(deprecated)]
+    let swapped = Reply {
+        id: FEE_SWAP_REPLY_ID,
+        payload: Binary::default(),
+        gas_used: 0,
+        result: SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: None,
+            msg_responses: vec![],
+        }),
+    };
+    let res = contract::reply(deps.as_mut(), mock_env(), swapped).unwrap();
+    assert_eq!(sent_to(&res, "chain_fee_collector"), coins(60, "local"));
+    assert_eq!(sent_to(&res, "warchest"), coins(100, "local"));
+}
+
+#[test]
+fn fees_owed_in_the_fee_denom_are_recorded_without_a_pool() {
+    let (mut deps, trade_id) =
+        fund_under_config(fee_denom_config("uusd"), TRADE_AMOUNT + TRADE_FEES).unwrap();
+    let res = release(&mut deps, trade_id);
+
+    assert_eq!(sent_to(&res, "chain_fee_collector"), coins(30_000, "uatom"));
+    assert_eq!(sent_to(&res, "warchest"), coins(50_000, "uatom"));
+    // The mocked oracle prices both denoms the same
+    assert_eq!(attribute(&res, "fee_owed_denom"), "uusd");
+    assert_eq!(attribute(&res, "fee_owed_amount"), TRADE_FEES.to_string());
+}
+
+fn trade_event(res: &Response) -> TradeEvent {
+    from_json(attribute(res, "trade_event")).unwrap()
+}
+
+#[test]
+fn escrow_transitions_emit_a_json_trade_event() {
+    let (mut deps, trade_id) = fund_under_config(hub_config(), TRADE_AMOUNT + TRADE_FEES).unwrap();
+    let res = release(&mut deps, trade_id);
+    assert_eq!(
+        trade_event(&res),
+        TradeEvent {
+            trade_id,
+            from_state: TradeState::FiatDeposited,
+            to_state: TradeState::EscrowReleased,
+            actor: Addr::unchecked(MAKER),
+            amount: Uint128::new(TRADE_AMOUNT),
+            denom: "uatom".to_string(),
+            block_height: mock_env().block.height,
+        }
+    );
+
+    let mut deps = setup(sell_offer());
+    let trade_id = funded_trade(&mut deps);
+    let event = trade_event(&refund(&mut deps, MAKER, mock_env(), trade_id).unwrap());
+    assert_eq!(event.from_state, TradeState::EscrowFunded);
+    assert_eq!(event.to_state, TradeState::EscrowRefunded);
+}
+
+fn arbitrator_stats(deps: &Deps) -> ArbitratorStats {
+    let query_msg = QueryMsg::ArbitratorStats {
+        arbitrator: Addr::unchecked(ARBITRATOR),
+    };
+    from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+}
+
+/// Disputes a paid trade and has the arbitrator settle it for the taker.
+fn settled_trade(deps: &mut Deps) -> (u64, Response) {
+    let (trade_id, env) = paid_trade(deps);
+    dispute(deps, env, trade_id, "seller went silent").unwrap();
+    // Settling burns through a conversion route these tests don't register
+    let config = HubConfig {
+        burn_fee_pct: Decimal::zero(),
+        ..hub_config()
+    };
+    mock_queries(deps, config, vec![sell_offer()]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(ARBITRATOR), &[]),
+        ExecuteMsg::SettleDispute {
+            trade_id,
+            winner: Addr::unchecked(TAKER),
+        },
+    )
+    .unwrap();
+    (trade_id, res)
+}
+
+#[test]
+fn arbitrators_are_only_paid_for_settling_a_dispute() {
+    let (mut deps, trade_id) = fund_under_config(hub_config(), TRADE_AMOUNT + TRADE_FEES).unwrap();
+    let res = release(&mut deps, trade_id);
+    assert!(!res.messages.iter().any(|sub_msg| matches!(
+        &sub_msg.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == ARBITRATOR
+    )));
+    assert_eq!(arbitrator_stats(&deps), ArbitratorStats::default());
+
+    let mut deps = setup(sell_offer());
+    let (_, res) = settled_trade(&mut deps);
+    // 1% of the settled escrow
+    assert_eq!(sent_to(&res, ARBITRATOR), coins(100_000, "uatom"));
+    assert_eq!(arbitrator_stats(&deps).resolved_disputes, 1);
+}
+
+#[test]
+fn arbitrators_split_a_disputed_escrow() {
+    let mut deps = setup(sell_offer());
+    let (trade_id, env) = paid_trade(&mut deps);
+    dispute(&mut deps, env, trade_id, "paid half").unwrap();
+    let config = HubConfig {
+        burn_fee_pct: Decimal::zero(),
+        ..hub_config()
+    };
+    mock_queries(&mut deps, config, vec![sell_offer()]);
+    let mut resolve = |sender: &str, buyer_amount: u128, seller_amount: u128| {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            message_info(&Addr::unchecked(sender), &[]),
+            ExecuteMsg::ResolveSplit {
+                trade_id,
+                buyer_amount: Uint128::new(buyer_amount),
+                seller_amount: Uint128::new(seller_amount),
+            },
+        )
+    };
+
+    let err = resolve(TAKER, 6_930_000, 2_970_000).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized { .. }));
+    // The whole escrow, without taking the 1% arbitration fee out
+    let err = resolve(ARBITRATOR, 7_000_000, 3_000_000).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidParameter { .. }));
+
+    let res = resolve(ARBITRATOR, 6_930_000, 2_970_000).unwrap();
+    assert_eq!(sent_to(&res, TAKER), coins(6_930_000, "uatom"));
+    assert_eq!(sent_to(&res, MAKER), coins(2_970_000, "uatom"));
+    assert_eq!(sent_to(&res, ARBITRATOR), coins(100_000, "uatom"));
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::SettledForTaker
+    );
+    assert_eq!(arbitrator_stats(&deps).resolved_disputes, 1);
+}
+
+fn rate_arbitrator(
+    deps: &mut Deps,
+    sender: &str,
+    trade_id: u64,
+    rating: u8,
+) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(sender), &[]),
+        ExecuteMsg::RateArbitrator { trade_id, rating },
+    )
+}
+
+#[test]
+fn parties_rate_the_arbitrator_of_a_settled_trade_once() {
+    let mut deps = setup(sell_offer());
+    let (paid_trade_id, _) = paid_trade(&mut deps);
+    let err = rate_arbitrator(&mut deps, TAKER, paid_trade_id, 5).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTradeState { .. }));
+    let (trade_id, _) = settled_trade(&mut deps);
+
+    let err = rate_arbitrator(&mut deps, "stranger", trade_id, 5).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidSender { .. }));
+    for rating in [0, MAX_ARBITRATOR_RATING + 1] {
+        let err = rate_arbitrator(&mut deps, TAKER, trade_id, rating).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidParameter { .. }));
+    }
+    rate_arbitrator(&mut deps, TAKER, trade_id, 5).unwrap();
+    rate_arbitrator(&mut deps, MAKER, trade_id, 2).unwrap();
+    let err = rate_arbitrator(&mut deps, TAKER, trade_id, 1).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::ArbitratorAlreadyRated { trade_id: id } if id == trade_id
+    ));
+
+    let stats = arbitrator_stats(&deps);
+    assert_eq!(stats.ratings_count, 2);
+    assert_eq!(stats.average_rating(), 3);
+}
+
+#[test]
+fn arbitrators_with_a_better_record_are_picked_more_often() {
+    let mut deps = setup(sell_offer());
+    register_arbitrator(&mut deps, "arbiter");
+    let picks = |deps: &Deps| -> usize {
+        (0..100)
+            .filter(|random_value| {
+                let picked = ArbitratorModel::get_arbitrator_random(
+                    deps.as_ref(),
+                    *random_value,
+                    FiatCurrency::USD,
+                    &[],
+                )
+                .unwrap();
+                picked.arbitrator == Addr::unchecked(ARBITRATOR)
+            })
+            .count()
+    };
+    assert_eq!(picks(&deps), 50);
+
+    let (trade_id, _) = settled_trade(&mut deps);
+    rate_arbitrator(&mut deps, TAKER, trade_id, 5).unwrap();
+    // Weights of 1 + 5 and 1 split the random values 86 to 14
+    assert_eq!(picks(&deps), 86);
+}
+
+/// Funds a trade under a hub keeping `cancellation_fee_bps`, has the taker
+/// cancel it and the maker refund the canceled escrow.
+fn cancel_and_refund(cancellation_fee_bps: u16) -> Response {
+    let (mut deps, trade_id) = fund_under_config(
+        HubConfig {
+            cancellation_fee_bps,
+            ..hub_config()
+        },
+        TRADE_AMOUNT + TRADE_FEES,
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        message_info(&Addr::unchecked(TAKER), &[]),
+        ExecuteMsg::CancelRequest { trade_id },
+    )
+    .unwrap();
+    assert_eq!(
+        TradeModel::from_store(&deps.storage, trade_id).get_state(),
+        TradeState::EscrowCanceled
+    );
+    refund(&mut deps, MAKER, mock_env(), trade_id).unwrap()
+}
+
+#[test]
+fn canceled_escrow_is_refunded_in_full_without_a_cancellation_fee() {
+    let res = cancel_and_refund(0);
+    assert_eq!(sent_to(&res, MAKER), coins(TRADE_AMOUNT, "uatom"));
+    assert_eq!(attribute(&res, "cancellation_fee"), "0");
+    assert!(res.messages.iter().all(|sub_msg| !matches!(
+        &sub_msg.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == "chain_fee_collector"
+    )));
+}
+
+#[test]
+fn canceled_escrow_keeps_the_cancellation_fee() {
+    // 1.5% of TRADE_AMOUNT goes to the collector, the rest back to the maker
+    let res = cancel_and_refund(150);
+    assert_eq!(
+        sent_to(&res, "chain_fee_collector"),
+        coins(150_000, "uatom")
+    );
+    assert_eq!(sent_to(&res, MAKER), coins(9_850_000, "uatom"));
+    assert_eq!(attribute(&res, "cancellation_fee"), "150000");
+}
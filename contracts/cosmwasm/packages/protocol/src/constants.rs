@@ -1,7 +1,18 @@
 pub const BASE_ORACLE_DENOM: &str = "ATOM";
-pub const OFFER_DESCRIPTION_LIMIT: usize = 140;
+pub const OFFER_DESCRIPTION_LIMIT: u32 = 140;
+pub const CONTACT_LIMIT: u32 = 1024;
+pub const ENCRYPTION_KEY_LIMIT: u32 = 1024;
+pub const DISPUTE_REASON_LIMIT: u32 = 500;
+pub const EVIDENCE_URI_LIMIT: u32 = 256;
 pub const MAX_PLATFORM_FEE: u64 = 10; // 10%
+pub const MAX_ARBITRATION_FEE: u64 = 5; // 5%
+pub const MAX_CANCELLATION_FEE_BPS: u16 = 500; // 5%
+pub const MAX_ARBITRATOR_RATING: u8 = 5;
 pub const MAX_TRADE_EXPIRATION_TIMER: u64 = 172800; // 2 days
 pub const MAX_TRADE_DISPUTE_TIMER: u64 = 86400; // 1 day
 pub const MIN_ITEMS_PER_PAGE: u32 = 1; // Pagination
 pub const MAX_ITEMS_PER_PAGE: u32 = 30; // Pagination
+pub const MAX_OFFER_BATCH_SIZE: usize = 25;
+pub const MAX_OFFER_SCAN: u32 = 100; // Offers read per OffersBy page
+pub const MAX_REINDEX_BATCH_SIZE: usize = 50; // Entries saved per Reindex call
+pub const SOLVENCY_ATTESTATION_TTL: u64 = 3600; // 1 hour
@@ -1,6 +1,10 @@
+use crate::errors::ContractError;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt::{self};
+use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 
@@ -167,3 +171,37 @@ impl fmt::Display for FiatCurrency {
         write!(f, "{:?}", self)
     }
 }
+
+impl FromStr for FiatCurrency {
+    type Err = ContractError;
+
+    /// Parses an exact currency code, see `normalize_currency` for other cases.
+    fn from_str(currency: &str) -> Result<Self, Self::Err> {
+        FiatCurrency::deserialize(StrDeserializer::<ValueError>::new(currency)).map_err(|_| {
+            ContractError::InvalidCurrency {
+                currency: currency.to_string(),
+            }
+        })
+    }
+}
+
+/// Upper-cases `currency` and checks it names a supported fiat currency, so
+/// `"usd"`, `"Usd"` and `"USD"` all end up as `"USD"`.
+pub fn normalize_currency(currency: &str) -> Result<String, ContractError> {
+    let normalized = currency.trim().to_uppercase();
+    FiatCurrency::from_str(&normalized).map_err(|_| ContractError::InvalidCurrency {
+        currency: currency.to_string(),
+    })?;
+    Ok(normalized)
+}
+
+/// `deserialize_with` helper accepting a fiat currency code in any case.
+pub fn deserialize_fiat_currency<'de, D>(deserializer: D) -> Result<FiatCurrency, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let currency = String::deserialize(deserializer)?;
+    normalize_currency(&currency)
+        .and_then(|normalized| FiatCurrency::from_str(&normalized))
+        .map_err(D::Error::custom)
+}
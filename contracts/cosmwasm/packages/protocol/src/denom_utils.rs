@@ -1,5 +1,10 @@
+use crate::errors::ContractError;
+use cosmwasm_std::Addr;
 use cw20::Denom;
 
+const IBC_PREFIX: &str = "ibc/";
+const FACTORY_PREFIX: &str = "factory/";
+
 pub fn denom_to_string(denom: &Denom) -> String {
     match denom {
         Denom::Native(s) => s.clone(),
@@ -13,3 +18,52 @@ pub fn denom_is_native(denom: Denom) -> bool {
         Denom::Cw20(_) => false,
     }
 }
+
+/// Canonical form of an offer denom, so one asset maps to one order book.
+/// Native denoms are case-sensitive and only trimmed, except for the hash of
+/// an `ibc/` denom, which is upper-cased, and the creator address of a
+/// `factory/` denom, which is lower-cased. Cw20 addresses are lower-cased.
+/// Fails on denoms the bank module would not accept.
+pub fn normalize_denom(denom: &Denom) -> Result<Denom, ContractError> {
+    match denom {
+        Denom::Native(native) => {
+            let native = native.trim();
+            let valid = (3..=128).contains(&native.len())
+                && native.starts_with(|c: char| c.is_ascii_alphabetic())
+                && native
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c));
+            if !valid {
+                return Err(invalid_denom(native));
+            }
+
+            let normalized = if let Some(hash) = native.strip_prefix(IBC_PREFIX) {
+                format!("{}{}", IBC_PREFIX, hash.to_uppercase())
+            } else if let Some(rest) = native.strip_prefix(FACTORY_PREFIX) {
+                match rest.split_once('/') {
+                    Some((creator, subdenom)) => {
+                        format!("{}{}/{}", FACTORY_PREFIX, creator.to_lowercase(), subdenom)
+                    }
+                    None => return Err(invalid_denom(native)),
+                }
+            } else {
+                native.to_string()
+            };
+            Ok(Denom::Native(normalized))
+        }
+        Denom::Cw20(addr) => {
+            let addr = addr.as_str().trim();
+            if addr.is_empty() || !addr.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(invalid_denom(addr));
+            }
+            Ok(Denom::Cw20(Addr::unchecked(addr.to_lowercase())))
+        }
+    }
+}
+
+fn invalid_denom(denom: &str) -> ContractError {
+    ContractError::InvalidParameter {
+        parameter: "denom".to_string(),
+        message: Some(format!("{} is not a valid denom.", denom)),
+    }
+}
@@ -1,97 +1,206 @@
+use crate::currencies::FiatCurrency;
 use crate::offer::OfferState;
 use crate::trade::TradeState;
+use crate::trade_state::CanonicalTradeState;
 use cosmwasm_std::{Addr, StdError, Uint128, Uint256, Uint64};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ContractError {
     /// General Errors
-    #[error("Unauthorized.")]
+    #[error("[{code}] Unauthorized.", code = self.code())]
     Unauthorized { owner: Addr, caller: Addr },
-    #[error("Unauthorized.")]
+    #[error("[{code}] Unauthorized.", code = self.code())]
     UnauthorizedMultipleOwnership { owners: Vec<Addr>, caller: Addr },
-    #[error("The parameter {0} is invalid. {1}", parameter, message.clone().unwrap_or_default())]
+    #[error("[{code}] The parameter {0} is invalid. {1}", parameter, message.clone().unwrap_or_default(), code = self.code())]
     InvalidParameter {
         parameter: String,
         message: Option<String>,
     },
-    #[error("{0}")]
+    #[error("[{code}] {0}", code = self.code())]
     Std(#[from] StdError),
+    #[error("[{code}] The protocol is paused.", code = self.code())]
+    ContractPaused {},
+    #[error("[{code}] Only one coin can be sent. Received: {denoms:?}.", code = self.code())]
+    UnexpectedCoins { denoms: Vec<String> },
+    #[error("[{code}] Unknown fiat currency: {currency}.", code = self.code())]
+    InvalidCurrency { currency: String },
     /// Hub Errors
-    #[error("Hub already registered.")]
+    #[error("[{code}] Hub already registered.", code = self.code())]
     HubAlreadyRegistered {},
-    #[error("The sum of `chain_fee_pct`, `burn_fee_pct` and `warchest_fee_pct` must be less than {0}%.", max_platform_fee.to_string())]
+    #[error("[{code}] The sum of `chain_fee_pct`, `burn_fee_pct` and `warchest_fee_pct` must be less than {0}%.", max_platform_fee.to_string(), code = self.code())]
     InvalidPlatformFee { max_platform_fee: Uint64 },
     /// Offer Errors
-    #[error("Min amount must be greater than Max amount.")]
+    #[error("[{code}] Min amount must be greater than Max amount.", code = self.code())]
     InvalidMinMax { min: Uint128, max: Uint128 },
-    #[error("Amount is outside of offer amount range.")]
+    #[error("[{code}] Amount is outside of offer amount range.", code = self.code())]
     InvalidOfferAmount {
         amount: Uint128,
         min_amount: Uint128,
         max_amount: Uint128,
     },
-    #[error("Invalid state change.")]
+    #[error("[{code}] Invalid state change.", code = self.code())]
     InvalidOfferStateChange { from: OfferState, to: OfferState },
-    #[error("Offer max amount: {max_amount:?} is above the trading limit: {trading_limit:?}.")]
+    #[error("[{code}] Offer max amount: {max_amount:?} is above the trading limit: {trading_limit:?}.", code = self.code())]
     OfferMaxAboveTradingLimit {
         max_amount: Uint256,
         trading_limit: Uint256,
     },
-    #[error("Offer not found.")]
+    #[error("[{code}] Offer min amount: {min_amount:?} is below the trading limit: {trading_limit:?}.", code = self.code())]
+    OfferMinBelowTradingLimit {
+        min_amount: Uint256,
+        trading_limit: Uint256,
+    },
+    #[error("[{code}] Offer not found.", code = self.code())]
     OfferNotFound { offer_id: String },
-    #[error("Value out of range.")]
+    #[error("[{code}] Offer {offer_id} has no pending update.", code = self.code())]
+    NoPendingOfferUpdate { offer_id: u64 },
+    #[error("[{code}] Pending offer update can't be applied before {apply_at}.", code = self.code())]
+    PendingOfferUpdateNotReady { apply_at: u64 },
+    #[error("[{code}] Value out of range.", code = self.code())]
     ValueOutOfRange {
         value: usize,
         range_start: usize,
         range_end: usize,
     },
+    #[error("[{code}] Offer {offer_id} already has {limit} active trades.", code = self.code())]
+    OfferActiveTradesLimitReached { offer_id: u64, limit: u32 },
+    #[error("[{code}] Offer {offer_id} expired at {expires_at}.", code = self.code())]
+    OfferExpired { offer_id: u64, expires_at: u64 },
+    #[error("[{code}] Offer {offer_id} has not expired.", code = self.code())]
+    OfferNotExpired {
+        offer_id: u64,
+        expires_at: Option<u64>,
+    },
+    #[error("[{code}] Taker {taker} is blocked by {maker}.", code = self.code())]
+    TakerBlocked { maker: Addr, taker: Addr },
+    #[error("[{code}] Offers for {denom} in {fiat_currency} are not supported.", code = self.code())]
+    UnsupportedPair {
+        fiat_currency: FiatCurrency,
+        denom: String,
+    },
     /// Trade Errors
-    #[error(
-        "Fund escrow error. Required amount: {required_amount:?}, Sent amount: {sent_amount:?}."
-    )]
+    #[error("[{code}] Fund escrow error. Required amount: {required_amount:?}, Sent amount: {sent_amount:?}.", code = self.code())]
     FundEscrowError {
         required_amount: Uint128,
         sent_amount: Uint128,
     },
-    #[error("Dispute requested too early. Time to enable dispute: {time_to_dispute:?}")]
+    #[error("[{code}] Dispute requested too early. Time to enable dispute: {time_to_dispute:?}", code = self.code())]
     PrematureDisputeRequest { time_to_dispute: u64 },
-    #[error("Invalid denom. Expected: {expected:?}, Received: {received:?}.")]
+    #[error("[{code}] Invalid denom. Expected: {expected:?}, Received: {received:?}.", code = self.code())]
     InvalidDenom { expected: String, received: String },
-    #[error("Invalid price for denom. Must be greater than zero.")]
+    #[error("[{code}] Invalid price for denom. Must be greater than zero.", code = self.code())]
     InvalidPriceForDenom {},
-    #[error("Invalid sender, must be Trade's buyer or seller.")]
+    #[error("[{code}] Invalid sender, must be Trade's buyer or seller.", code = self.code())]
     InvalidSender {
         sender: Addr,
         buyer: Addr,
         seller: Addr,
     },
-    #[error("Invalid trade amount. Amount: {amount:?}. Min: {min_amount:?}. Max: {max_amount:?}.")]
+    #[error("[{code}] Invalid trade amount. Amount: {amount:?}. Min: {min_amount:?}. Max: {max_amount:?}.", code = self.code())]
     InvalidTradeAmount {
         amount: Uint256,
         min_amount: Uint256,
         max_amount: Uint256,
     },
-    #[error("Trade state is invalid.")]
+    #[error("[{code}] Trade state is invalid.", code = self.code())]
     InvalidTradeState {
         current: TradeState,
         expected: TradeState,
     },
-    #[error("Invalid trade state change.")]
+    #[error("[{code}] Invalid trade state change.", code = self.code())]
     InvalidTradeStateChange { from: TradeState, to: TradeState },
-    #[error("Refund error: Not Expired")]
+    #[error("[{code}] Refund error: Not Expired", code = self.code())]
     RefundErrorNotExpired { message: String, trade: String },
-    #[error("This trade has expired.")]
+    #[error("[{code}] This trade has expired.", code = self.code())]
     TradeExpired { expired_at: u64, created_at: u64 },
-    #[error("Swap Error: received amount is less than expected.")]
+    #[error("[{code}] Swap Error: received amount is less than expected.", code = self.code())]
     SwapErrorInvalidAmount {},
-    #[error("Swap Error: missing denom.")]
+    #[error("[{code}] Swap Error: missing denom.", code = self.code())]
     SwapErrorMissingDenom { expected_denom: String },
-    #[error("Unknown reply id: {reply_id:?}")]
+    #[error("[{code}] Unknown reply id: {reply_id:?}", code = self.code())]
     UnknownReplyId { reply_id: u64 },
+    #[error("[{code}] No eligible arbitrator available for {fiat}.", code = self.code())]
+    NoArbitratorAvailable { fiat: FiatCurrency },
+    #[error("[{code}] Arbitrator {arbitrator} is a party to the trade.", code = self.code())]
+    SelfAssignedArbitrator { arbitrator: Addr },
+    #[error("[{code}] Not enough offer liquidity. Requested: {requested}, Available: {available}.", code = self.code())]
+    InsufficientOfferLiquidity {
+        requested: Uint128,
+        available: Uint128,
+    },
+    #[error("[{code}] Trade has not passed its payment deadline.", code = self.code())]
+    TradeNotOverdue { payment_deadline: Option<u64> },
+    #[error("[{code}] Release amount {amount} exceeds the remaining escrow {remaining}.", code = self.code())]
+    ReleaseExceedsRemaining { amount: Uint128, remaining: Uint128 },
+    #[error("[{code}] Trade has not passed its funding deadline.", code = self.code())]
+    FundingDeadlineNotPassed { funding_deadline: Option<u64> },
+    #[error("[{code}] The arbitrator of trade {trade_id} was already rated by this party.", code = self.code())]
+    ArbitratorAlreadyRated { trade_id: u64 },
+    #[error("[{code}] Trade state {state} has no equivalent on the target platform.", code = self.code())]
+    UnmappedTradeState { state: CanonicalTradeState },
     /// Profile Errors
-    #[error("Active offers limit reached. Limit: {limit:?}.")]
+    #[error("[{code}] Active offers limit reached. Limit: {limit:?}.", code = self.code())]
     ActiveOffersLimitReached { limit: u8 },
-    #[error("Active trades limit reached. Limit: {limit:?}.")]
+    #[error("[{code}] Active trades limit reached. Limit: {limit:?}.", code = self.code())]
     ActiveTradesLimitReached { limit: u8 },
+    #[error("[{code}] Invalid contact. {message}", code = self.code())]
+    InvalidContact { message: String },
+}
+
+impl ContractError {
+    /// Stable numeric code for off-chain consumers, shown in brackets at the
+    /// start of every error message. Codes are grouped by the contract area
+    /// the error belongs to and must never be reused.
+    pub fn code(&self) -> u32 {
+        match self {
+            ContractError::Unauthorized { .. } => 1,
+            ContractError::UnauthorizedMultipleOwnership { .. } => 2,
+            ContractError::InvalidParameter { .. } => 3,
+            ContractError::Std(_) => 4,
+            ContractError::ContractPaused {} => 5,
+            ContractError::UnexpectedCoins { .. } => 6,
+            ContractError::InvalidCurrency { .. } => 7,
+            ContractError::HubAlreadyRegistered {} => 100,
+            ContractError::InvalidPlatformFee { .. } => 101,
+            ContractError::InvalidMinMax { .. } => 200,
+            ContractError::InvalidOfferAmount { .. } => 201,
+            ContractError::InvalidOfferStateChange { .. } => 202,
+            ContractError::OfferMaxAboveTradingLimit { .. } => 203,
+            ContractError::OfferNotFound { .. } => 204,
+            ContractError::NoPendingOfferUpdate { .. } => 205,
+            ContractError::PendingOfferUpdateNotReady { .. } => 206,
+            ContractError::ValueOutOfRange { .. } => 207,
+            ContractError::OfferActiveTradesLimitReached { .. } => 208,
+            ContractError::OfferMinBelowTradingLimit { .. } => 209,
+            ContractError::OfferExpired { .. } => 210,
+            ContractError::OfferNotExpired { .. } => 211,
+            ContractError::TakerBlocked { .. } => 212,
+            ContractError::UnsupportedPair { .. } => 213,
+            ContractError::FundEscrowError { .. } => 300,
+            ContractError::PrematureDisputeRequest { .. } => 301,
+            ContractError::InvalidDenom { .. } => 302,
+            ContractError::InvalidPriceForDenom {} => 303,
+            ContractError::InvalidSender { .. } => 304,
+            ContractError::InvalidTradeAmount { .. } => 305,
+            ContractError::InvalidTradeState { .. } => 306,
+            ContractError::InvalidTradeStateChange { .. } => 307,
+            ContractError::RefundErrorNotExpired { .. } => 308,
+            ContractError::TradeExpired { .. } => 309,
+            ContractError::SwapErrorInvalidAmount {} => 310,
+            ContractError::SwapErrorMissingDenom { .. } => 311,
+            ContractError::UnknownReplyId { .. } => 312,
+            ContractError::NoArbitratorAvailable { .. } => 313,
+            ContractError::SelfAssignedArbitrator { .. } => 314,
+            ContractError::UnmappedTradeState { .. } => 315,
+            ContractError::InsufficientOfferLiquidity { .. } => 316,
+            ContractError::TradeNotOverdue { .. } => 317,
+            ContractError::ReleaseExceedsRemaining { .. } => 318,
+            ContractError::FundingDeadlineNotPassed { .. } => 319,
+            ContractError::ArbitratorAlreadyRated { .. } => 320,
+            ContractError::ActiveOffersLimitReached { .. } => 400,
+            ContractError::ActiveTradesLimitReached { .. } => 401,
+            ContractError::InvalidContact { .. } => 402,
+        }
+    }
 }
@@ -1,8 +1,9 @@
-use crate::constants::{MAX_ITEMS_PER_PAGE, MIN_ITEMS_PER_PAGE, OFFER_DESCRIPTION_LIMIT};
+use crate::constants::{ENCRYPTION_KEY_LIMIT, MAX_ITEMS_PER_PAGE, MIN_ITEMS_PER_PAGE};
 use crate::errors::ContractError;
-use crate::offer::OfferType;
-use crate::trade::{Trade, TradeState};
-use cosmwasm_std::{Addr, Uint128, Uint256};
+use crate::hub::{HubConfig, TextLimits};
+use crate::offer::{Offer, OfferType};
+use crate::trade::{calc_fiat_amount, Trade, TradeState};
+use cosmwasm_std::{Addr, MessageInfo, Uint128, Uint256};
 use cw2::ContractVersion;
 
 pub fn assert_multiple_ownership(caller: Addr, owners: Vec<Addr>) -> Result<(), ContractError> {
@@ -37,6 +38,65 @@ pub fn assert_sender_is_buyer_or_seller(
     }
 }
 
+/// Requires `info` to carry exactly one coin: `expected` of `denom`.
+pub fn assert_sent_funds(
+    info: &MessageInfo,
+    expected: Uint128,
+    denom: &str,
+) -> Result<(), ContractError> {
+    let coin = match info.funds.as_slice() {
+        [coin] => coin,
+        [] => {
+            return Err(ContractError::InvalidDenom {
+                expected: denom.to_string(),
+                received: String::new(),
+            })
+        }
+        coins => {
+            return Err(ContractError::UnexpectedCoins {
+                denoms: coins.iter().map(|coin| coin.denom.clone()).collect(),
+            })
+        }
+    };
+    if coin.denom != denom {
+        return Err(ContractError::InvalidDenom {
+            expected: denom.to_string(),
+            received: coin.denom.clone(),
+        });
+    }
+    if coin.amount != expected {
+        return Err(ContractError::FundEscrowError {
+            required_amount: expected,
+            sent_amount: coin.amount,
+        });
+    }
+    Ok(())
+}
+
+/// Rejects creating offers and trades while the hub has the protocol paused.
+pub fn assert_not_paused(hub_config: &HubConfig) -> Result<(), ContractError> {
+    if hub_config.paused {
+        Err(ContractError::ContractPaused {})
+    } else {
+        Ok(())
+    }
+}
+
+/// Rejects an arbitrator who is also the buyer or seller of the trade.
+pub fn assert_valid_arbitrator(
+    arbitrator: &Addr,
+    buyer: &Addr,
+    seller: &Addr,
+) -> Result<(), ContractError> {
+    if arbitrator.eq(buyer) || arbitrator.eq(seller) {
+        Err(ContractError::SelfAssignedArbitrator {
+            arbitrator: arbitrator.clone(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
 pub fn assert_trade_state_change_is_valid(
     from: TradeState,
     from_allowed: TradeState,
@@ -61,6 +121,37 @@ pub fn assert_trade_state_change(
     }
 }
 
+/// The single table of legal trade state changes, every `Trade::set_state`
+/// goes through it.
+pub fn assert_transition(from: TradeState, to: TradeState) -> Result<(), ContractError> {
+    use TradeState::*;
+    let allowed: &[TradeState] = match from {
+        RequestCreated => &[
+            RequestAccepted,
+            RequestCanceled,
+            RequestExpired,
+            EscrowFunded,
+        ],
+        RequestAccepted => &[RequestCanceled, RequestExpired, EscrowFunded],
+        EscrowFunded => &[
+            FiatDeposited,
+            EscrowCanceled,
+            EscrowRefunded,
+            EscrowDisputed,
+        ],
+        EscrowCanceled => &[EscrowRefunded],
+        FiatDeposited => &[EscrowReleased, EscrowDisputed, EscrowRefunded],
+        EscrowDisputed => &[SettledForMaker, SettledForTaker, EscrowRefunded],
+        RequestCanceled | RequestExpired | EscrowRefunded | EscrowReleased | SettledForMaker
+        | SettledForTaker => &[],
+    };
+    if allowed.contains(&to) {
+        Ok(())
+    } else {
+        Err(ContractError::InvalidTradeStateChange { from, to })
+    }
+}
+
 // Asserts that min value is lower than max value
 pub fn assert_min_g_max(min: Uint128, max: Uint128) -> Result<(), ContractError> {
     if min >= max {
@@ -84,6 +175,30 @@ pub fn assert_offer_max_inside_trading_limit(
     }
 }
 
+/// Checks the USD value of an offer's amount range against the hub trade
+/// limits, catching ranges that are off by the denom's decimals.
+/// `denom_usd_price` is the offer's price from `calc_denom_fiat_price`.
+pub fn assert_offer_notional_bounds(
+    min_amount: Uint128,
+    max_amount: Uint128,
+    denom_usd_price: Uint256,
+    hub_config: &HubConfig,
+) -> Result<(), ContractError> {
+    let trading_limit = Uint256::from(hub_config.trade_limit_max);
+    let max_amount = calc_fiat_amount(max_amount, denom_usd_price);
+    assert_offer_max_inside_trading_limit(max_amount, trading_limit)?;
+
+    let trading_limit = Uint256::from(hub_config.trade_limit_min);
+    let min_amount = calc_fiat_amount(min_amount, denom_usd_price);
+    if min_amount < trading_limit {
+        return Err(ContractError::OfferMinBelowTradingLimit {
+            min_amount,
+            trading_limit,
+        });
+    }
+    Ok(())
+}
+
 pub fn assert_value_in_range(
     min: Uint128,
     max: Uint128,
@@ -133,20 +248,112 @@ pub fn assert_trade_state_and_type(
     }
 }
 
-pub fn assert_offer_description_valid(description: Option<String>) -> Result<(), ContractError> {
-    let description = description.unwrap_or(String::new());
-    return if description.len() > OFFER_DESCRIPTION_LIMIT {
-        let mut message = "The description can not be longer than ".to_string();
-        message.push_str(OFFER_DESCRIPTION_LIMIT.to_string().as_str());
-        message.push_str(" characters.");
+pub fn assert_max_active_trades_valid(max_active_trades: Option<u32>) -> Result<(), ContractError> {
+    match max_active_trades {
+        Some(0) => Err(ContractError::InvalidParameter {
+            parameter: "max_active_trades".to_string(),
+            message: Some("The max_active_trades must be greater than 0.".to_string()),
+        }),
+        _ => Ok(()),
+    }
+}
 
+/// Fails when the offer already has as many open trades as its owner allows.
+pub fn assert_offer_trade_capacity(offer: &Offer) -> Result<(), ContractError> {
+    match offer.max_active_trades {
+        Some(limit) if offer.active_trades_count >= limit => {
+            Err(ContractError::OfferActiveTradesLimitReached {
+                offer_id: offer.id,
+                limit,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn assert_offer_description_valid(
+    description: Option<String>,
+    limit: u32,
+) -> Result<(), ContractError> {
+    assert_text_length("description", &description.unwrap_or_default(), limit)
+}
+
+/// A dispute needs a reason for the arbitrator, the evidence link is optional.
+pub fn assert_dispute_evidence_valid(
+    reason: &str,
+    evidence_uri: &Option<String>,
+    limits: &TextLimits,
+) -> Result<(), ContractError> {
+    if reason.trim().is_empty() {
+        return Err(ContractError::InvalidParameter {
+            parameter: "reason".to_string(),
+            message: Some("The reason can not be empty.".to_string()),
+        });
+    }
+    assert_text_length("reason", reason, limits.dispute_reason)?;
+    assert_text_length(
+        "evidence_uri",
+        evidence_uri.as_deref().unwrap_or_default(),
+        limits.evidence_uri,
+    )
+}
+
+pub fn assert_text_length(parameter: &str, value: &str, limit: u32) -> Result<(), ContractError> {
+    if value.len() > limit as usize {
         Err(ContractError::InvalidParameter {
-            parameter: "description".to_string(),
-            message: Some(message),
+            parameter: parameter.to_string(),
+            message: Some(format!(
+                "The {} can not be longer than {} characters.",
+                parameter, limit
+            )),
         })
     } else {
         Ok(())
-    };
+    }
+}
+
+/// Checks an owner contact before it is stored on the profile: the sealed
+/// contact must fit `limit` and the key must be base64 or hex encoded.
+pub fn assert_contact_valid(
+    contact: &str,
+    encryption_key: &str,
+    limit: u32,
+) -> Result<(), ContractError> {
+    if contact.len() > limit as usize {
+        return Err(ContractError::InvalidContact {
+            message: format!("The contact can not be longer than {} characters.", limit),
+        });
+    }
+    if encryption_key.len() > ENCRYPTION_KEY_LIMIT as usize {
+        return Err(ContractError::InvalidContact {
+            message: format!(
+                "The encryption key can not be longer than {} characters.",
+                ENCRYPTION_KEY_LIMIT
+            ),
+        });
+    }
+    if !is_hex(encryption_key) && !is_base64(encryption_key) {
+        return Err(ContractError::InvalidContact {
+            message: "The encryption key must be base64 or hex encoded.".to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn is_hex(value: &str) -> bool {
+    !value.is_empty()
+        && value.as_bytes().chunks_exact(2).remainder().is_empty()
+        && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_base64(value: &str) -> bool {
+    let data = value.trim_end_matches('=');
+    !data.is_empty()
+        && value.as_bytes().chunks_exact(4).remainder().is_empty()
+        && value.len() - data.len() <= 2
+        && data
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
 }
 
 pub fn assert_migration_parameters(
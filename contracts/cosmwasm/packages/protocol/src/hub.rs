@@ -1,3 +1,7 @@
+use crate::constants::{
+    CONTACT_LIMIT, DISPUTE_REASON_LIMIT, EVIDENCE_URI_LIMIT, OFFER_DESCRIPTION_LIMIT,
+};
+use crate::currencies::FiatCurrency;
 use cosmwasm_std::{Addr, Decimal, Uint128};
 use cw20::Denom;
 use schemars::JsonSchema;
@@ -13,7 +17,23 @@ pub struct InstantiateMsg {
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     UpdateConfig(HubConfig),
-    UpdateAdmin { admin_addr: Addr },
+    UpdateAdmin {
+        admin_addr: Addr,
+    },
+    SetPaused {
+        paused: bool,
+    },
+    /// Lists the pair for new offers, or delists it when `allowed` is false.
+    /// The list only applies while the pair whitelist is enabled.
+    SetPairAllowed {
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        allowed: bool,
+    },
+    /// Restricts new offers to the listed pairs while enabled.
+    SetPairWhitelistEnabled {
+        enabled: bool,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -21,6 +41,17 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     Config {},
     Admin {},
+    /// Whether new offers can be created for the pair.
+    IsPairAllowed {
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+    },
+    /// Whether the pair whitelist is enabled, and the listed pairs in
+    /// ascending order.
+    AllowedPairs {
+        limit: u32,
+        start_after: Option<CurrencyPair>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -49,6 +80,109 @@ pub struct HubConfig {
     pub trade_dispute_timer: u64,
     pub trade_limit_min: Uint128, // in USD
     pub trade_limit_max: Uint128, // in USD
+    /// Discount on protocol fees for trades in `local_denom`.
+    #[serde(default)]
+    pub local_denom_fee_discount_pct: Decimal,
+    /// Delay before an offer update takes effect, zero applies it immediately.
+    #[serde(default)]
+    pub offer_update_delay: u64, // in seconds
+    #[serde(default)]
+    pub text_limits: TextLimits,
+    /// Time a funded trade has to settle before anyone can push it into
+    /// dispute with `CrankOverdue`, zero disables auto-dispute.
+    #[serde(default)]
+    pub trade_payment_timer: u64, // in seconds
+    /// Time a trade has to get its escrow funded before the seller can cancel
+    /// it with `CancelUnfunded`, zero disables the deadline.
+    #[serde(default)]
+    pub funding_deadline_seconds: u64,
+    /// Stops new offers and trades while set, trades already open can still settle.
+    #[serde(default)]
+    pub paused: bool,
+    /// How the protocol fees are shared between maker and taker. Trades keep
+    /// the split they were created under.
+    #[serde(default)]
+    pub fee_split: FeeSplit,
+    /// Denom the chain and warchest fees are paid in. Unset, they are paid in
+    /// each trade's own denom.
+    #[serde(default)]
+    pub fee_denom: Option<Denom>,
+    /// Share of a canceled escrow, in basis points, kept for the chain fee
+    /// collector when it is refunded to the seller. Zero refunds it in full.
+    #[serde(default)]
+    pub cancellation_fee_bps: u16,
+}
+
+impl HubConfig {
+    /// Part of a canceled escrow's `amount` retained as the cancellation fee,
+    /// rounded down.
+    pub fn cancellation_fee(&self, amount: Uint128) -> Uint128 {
+        amount.multiply_ratio(self.cancellation_fee_bps, 10_000u128)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CurrencyPair {
+    pub fiat_currency: FiatCurrency,
+    pub denom: Denom,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowedPairsResponse {
+    pub enabled: bool,
+    pub pairs: Vec<CurrencyPair>,
+}
+
+/// Percentages of the protocol fees borne by the maker and the taker, adding
+/// up to 100.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeSplit {
+    pub maker_pct: u8,
+    pub taker_pct: u8,
+}
+
+impl Default for FeeSplit {
+    fn default() -> Self {
+        FeeSplit {
+            maker_pct: 100,
+            taker_pct: 0,
+        }
+    }
+}
+
+impl FeeSplit {
+    pub fn is_valid(&self) -> bool {
+        self.maker_pct as u16 + self.taker_pct as u16 == 100
+    }
+
+    /// Splits `fees` into `(maker_share, taker_share)`. The maker's share is
+    /// rounded down so the two always add up to `fees`.
+    pub fn split(&self, fees: Uint128) -> (Uint128, Uint128) {
+        let maker_share = fees.multiply_ratio(self.maker_pct, 100u128);
+        (maker_share, fees - maker_share)
+    }
+}
+
+/// Maximum lengths, in bytes, of user supplied text. Part of the config query
+/// so clients can validate before submitting.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(default)]
+pub struct TextLimits {
+    pub description: u32,
+    pub contact: u32,
+    pub dispute_reason: u32,
+    pub evidence_uri: u32,
+}
+
+impl Default for TextLimits {
+    fn default() -> Self {
+        TextLimits {
+            description: OFFER_DESCRIPTION_LIMIT,
+            contact: CONTACT_LIMIT,
+            dispute_reason: DISPUTE_REASON_LIMIT,
+            evidence_uri: EVIDENCE_URI_LIMIT,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
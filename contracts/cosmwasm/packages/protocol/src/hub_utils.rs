@@ -1,8 +1,11 @@
+use crate::currencies::FiatCurrency;
 use crate::hub::{Admin, HubConfig, QueryMsg};
 use cosmwasm_std::{
-    to_json_binary, Addr, CustomQuery, Deps, QuerierWrapper, QueryRequest, Response, Storage,
-    WasmQuery,
+    to_json_binary, Addr, CustomQuery, Deps, QuerierWrapper, QueryRequest, Response, StdResult,
+    Storage, WasmQuery,
 };
+use cw2::get_contract_version;
+use cw20::Denom;
 use cw_storage_plus::Item;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -63,6 +66,24 @@ pub struct HubAddr {
     pub addr: Addr,
 }
 
+/// The deployed contract's cw2 name and version, and whether it has been
+/// registered with a hub yet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VersionResponse {
+    pub name: String,
+    pub version: String,
+    pub hub_registered: bool,
+}
+
+pub fn query_version(storage: &dyn Storage) -> StdResult<VersionResponse> {
+    let contract_version = get_contract_version(storage)?;
+    Ok(VersionResponse {
+        name: contract_version.contract,
+        version: contract_version.version,
+        hub_registered: HUB_ADDR.may_load(storage)?.is_some(),
+    })
+}
+
 pub fn query_hub_config<T: CustomQuery>(
     querier: &QuerierWrapper<T>,
     hub_addr: String,
@@ -76,6 +97,21 @@ pub fn query_hub_config<T: CustomQuery>(
     res
 }
 
+pub fn is_pair_allowed<T: CustomQuery>(
+    querier: &QuerierWrapper<T>,
+    hub_addr: String,
+    fiat_currency: FiatCurrency,
+    denom: Denom,
+) -> StdResult<bool> {
+    querier.query_wasm_smart(
+        hub_addr,
+        &QueryMsg::IsPairAllowed {
+            fiat_currency,
+            denom,
+        },
+    )
+}
+
 pub fn query_hub_admin<T: CustomQuery>(querier: &QuerierWrapper<T>, hub_addr: String) -> Admin {
     let res: Admin = querier
         .query(&QueryRequest::Wasm(WasmQuery::Smart {
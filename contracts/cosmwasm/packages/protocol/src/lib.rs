@@ -8,4 +8,9 @@ pub mod hub_utils;
 pub mod offer;
 pub mod price;
 pub mod profile;
+pub mod storage_utils;
 pub mod trade;
+pub mod trade_state;
+
+#[cfg(test)]
+mod test;
@@ -1,12 +1,16 @@
+use crate::constants::{MAX_OFFER_SCAN, SOLVENCY_ATTESTATION_TTL};
 use crate::currencies::FiatCurrency;
 use crate::denom_utils::denom_to_string;
-use crate::guards::validate_min_max_items_per_page;
+use crate::guards::{assert_offer_trade_capacity, validate_min_max_items_per_page};
 use crate::hub_utils::get_hub_config;
 use crate::profile::{load_profile, load_profiles, Profile};
 use crate::trade::{TradeResponse, TradeState};
-use cosmwasm_std::{Addr, CustomQuery, Deps, Order, QuerierWrapper, StdResult, Storage, Uint128};
+use cosmwasm_std::{
+    to_json_binary, Addr, CosmosMsg, CustomQuery, Deps, Order, QuerierWrapper, StdResult, Storage,
+    SubMsg, Uint128, WasmMsg,
+};
 use cw20::Denom;
-use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, MultiIndex};
+use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, Map, MultiIndex};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self};
@@ -19,11 +23,12 @@ pub struct OfferIndexes<'a> {
     pub owner: MultiIndex<'a, Addr, Offer, u64>,
     pub filter: MultiIndex<'a, String, Offer, u64>,
     pub state: MultiIndex<'a, String, Offer, u64>,
+    pub rate: MultiIndex<'a, (String, u128), Offer, u64>,
 }
 
 impl<'a> IndexList<Offer> for OfferIndexes<'a> {
     fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Offer>> + '_> {
-        let v: Vec<&dyn Index<Offer>> = vec![&self.owner, &self.filter, &self.state];
+        let v: Vec<&dyn Index<Offer>> = vec![&self.owner, &self.filter, &self.state, &self.rate];
         Box::new(v.into_iter())
     }
 }
@@ -32,15 +37,7 @@ pub fn offers() -> IndexedMap<u64, Offer, OfferIndexes<'static>> {
     let indexes = OfferIndexes {
         owner: MultiIndex::new(|_, d: &Offer| d.owner.clone(), "offers", "offers__owner"),
         filter: MultiIndex::new(
-            |_, offer: &Offer| {
-                format!(
-                    "{}{}{}{}",
-                    offer.offer_type.to_string(),
-                    &offer.fiat_currency.to_string(),
-                    &denom_to_string(&offer.denom),
-                    &offer.state.to_string()
-                )
-            },
+            |_, offer: &Offer| filter_key(offer),
             "offers",
             "offers__filter",
         ),
@@ -49,10 +46,43 @@ pub fn offers() -> IndexedMap<u64, Offer, OfferIndexes<'static>> {
             "offers",
             "offers__state",
         ),
+        rate: MultiIndex::new(
+            |_, offer: &Offer| (filter_key(offer), offer.rate.u128()),
+            "offers",
+            "offers__rate",
+        ),
     };
     IndexedMap::new("offers", indexes)
 }
 
+fn filter_key(offer: &Offer) -> String {
+    filter_prefix(
+        &offer.offer_type,
+        &offer.fiat_currency,
+        &offer.denom,
+        &offer.state,
+    )
+}
+
+/// Key the `filter` and `rate` indexes group offers by.
+pub fn filter_prefix(
+    offer_type: &OfferType,
+    fiat_currency: &FiatCurrency,
+    denom: &Denom,
+    state: &OfferState,
+) -> String {
+    format!(
+        "{}{}{}{}",
+        offer_type,
+        fiat_currency,
+        denom_to_string(denom),
+        state
+    )
+}
+
+/// Latest solvency attestation per offer id.
+pub const SOLVENCY_ATTESTATIONS: Map<u64, SolvencyAttestation> = Map::new("solvency_attestations");
+
 ///Messages
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {}
@@ -62,12 +92,26 @@ pub struct OfferMsg {
     pub offer_type: OfferType,
     pub owner_contact: String,
     pub owner_encryption_key: String,
+    #[serde(deserialize_with = "crate::currencies::deserialize_fiat_currency")]
     pub fiat_currency: FiatCurrency,
     pub rate: Uint128,
     pub denom: Denom,
     pub min_amount: Uint128,
     pub max_amount: Uint128,
     pub description: Option<String>,
+    /// Caps how many trades can be open against the offer at once.
+    #[serde(default)]
+    pub max_active_trades: Option<u32>,
+    /// Block time in seconds from which the offer is no longer listed.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Puts the offer back on the market as its trades settle.
+    #[serde(default)]
+    pub auto_repost: bool,
+    /// Deposits `max_amount` with the offer contract when the offer is
+    /// created. Only sell offers in a native denom can be prefunded.
+    #[serde(default)]
+    pub prefunded: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -80,14 +124,83 @@ pub struct OfferUpdateMsg {
     pub max_amount: Uint128,
     pub state: OfferState,
     pub description: Option<String>,
+    #[serde(default)]
+    pub max_active_trades: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     //TODO: Change to Create(OfferMsg)
-    Create { offer: OfferMsg },
-    UpdateOffer { offer_update: OfferUpdateMsg },
+    Create {
+        offer: OfferMsg,
+    },
+    /// Creates several offers in one message, failing all of them if any is invalid.
+    CreateBatch {
+        offers: Vec<OfferMsg>,
+    },
+    UpdateOffer {
+        offer_update: OfferUpdateMsg,
+    },
+    /// Shifts the rate of each of the sender's active offers by `delta_bps`
+    /// of its current rate, rounded down, going through the same update delay
+    /// as `UpdateOffer`. At most `MAX_OFFER_BATCH_SIZE` offers with an id
+    /// above `start_after` are updated, and the response's `next_start_after`
+    /// attribute is set while more remain.
+    UpdateAllRates {
+        delta_bps: i32,
+        #[serde(default)]
+        start_after: Option<u64>,
+    },
+    /// Drops a queued update before it takes effect. Owner only.
+    CancelPendingUpdate {
+        id: u64,
+    },
+    /// Applies a queued update once its delay has passed. Callable by anyone.
+    ApplyPendingUpdate {
+        id: u64,
+    },
+    /// Snapshots the owner's balance of the offer denom as a solvency
+    /// attestation. Owner only, sell offers only.
+    AttestSolvency {
+        id: u64,
+    },
+    /// Counts a trade against the offer when it is created and releases it
+    /// when it closes. `amount` is the trade amount, filled from the offer on
+    /// creation and given back if the trade closes without settling. Trade
+    /// contract only.
+    UpdateActiveTrades {
+        id: u64,
+        trade_state: TradeState,
+        #[serde(default)]
+        amount: Uint128,
+    },
+    /// Saves the offer to the sender's favorites, or removes it if it is
+    /// already saved. Unknown offer ids are ignored.
+    ToggleFavorite {
+        offer_id: u64,
+    },
+    /// Archives an offer past its `expires_at`. Callable by anyone.
+    Expire {
+        id: u64,
+    },
+    /// Stops `taker` from opening trades on any of the sender's offers.
+    BlockTaker {
+        taker: Addr,
+    },
+    UnblockTaker {
+        taker: Addr,
+    },
+    /// Saves up to `limit` offers with an id above `start_after` again, so
+    /// indexes added after they were stored cover them. `limit` defaults to
+    /// and is capped at `MAX_REINDEX_BATCH_SIZE`. Hub admin only. The
+    /// response's `next_start_after` attribute is set while more remain.
+    Reindex {
+        #[serde(default)]
+        start_after: Option<u64>,
+        #[serde(default)]
+        limit: Option<u32>,
+    },
     RegisterHub {},
 }
 
@@ -98,6 +211,10 @@ pub enum QueryMsg {
     Offer {
         id: u64,
     },
+    /// Active offers matching the filter, newest first, sorted by `order`
+    /// within the page. With `min_owner_reputation` set, offers whose owner
+    /// released fewer trades are skipped, at most `MAX_OFFER_SCAN` offers are
+    /// scanned per page so continue from `next_cursor`.
     OffersBy {
         offer_type: OfferType,
         fiat_currency: FiatCurrency,
@@ -105,12 +222,88 @@ pub enum QueryMsg {
         order: OfferOrder,
         limit: u32,
         last: Option<u64>,
+        #[serde(default)]
+        min_owner_reputation: Option<u64>,
     },
     OffersByOwner {
         owner: Addr,
         limit: u32,
         last: Option<u64>,
     },
+    /// Active offers matching the filter in ascending id order. Ids never
+    /// change, so paging with `next_cursor` is stable across offer updates.
+    OffersById {
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        limit: u32,
+        after: Option<u64>,
+    },
+    /// Active offers matching the filter with a rate inside the inclusive
+    /// `min_rate`..=`max_rate` band, cheapest first. `last` is the id of the
+    /// last offer of the previous page.
+    OffersByRate {
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        min_rate: Option<Uint128>,
+        max_rate: Option<Uint128>,
+        limit: u32,
+        last: Option<u64>,
+    },
+    /// The best priced active offer a taker can open an `amount` trade on,
+    /// the lowest rate among sell offers and the highest among buy offers.
+    BestOffer {
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        amount: Uint128,
+    },
+    /// Active offers `taker` can open a trade on, best rate for takers first.
+    /// Expired offers, the taker's own, offers whose maker blocked the taker
+    /// and offers with no room for another trade are skipped. At most
+    /// `MAX_OFFER_SCAN` offers are read.
+    TakeableOffers {
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        taker: Addr,
+    },
+    /// Id the next created offer will receive. Advisory only, another offer
+    /// created in the same block takes it first.
+    NextOfferId {},
+    PendingUpdate {
+        id: u64,
+    },
+    /// Offers saved by `owner` in ascending id order. Each offer is loaded as
+    /// it is now, so its `state` shows whether it was paused or archived since.
+    Favorites {
+        owner: Addr,
+        limit: u32,
+        start_after: Option<u64>,
+    },
+    /// Offer counts per state, overall and per fiat currency.
+    Stats {},
+    /// Takers blocked by `maker` in ascending address order.
+    BlockedTakers {
+        maker: Addr,
+        limit: u32,
+        start_after: Option<Addr>,
+    },
+    /// Whether `maker` has blocked `taker`.
+    IsTakerBlocked {
+        maker: Addr,
+        taker: Addr,
+    },
+    /// Contract name and version, and whether the hub is registered.
+    Version {},
+}
+
+/// An offer update waiting for the hub's `offer_update_delay` to pass.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOfferUpdate {
+    pub update: OfferUpdateMsg,
+    pub apply_at: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -118,6 +311,57 @@ pub struct OffersCount {
     pub count: u64,
 }
 
+/// Number of offers in each state.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct OfferStateCounts {
+    pub active: u64,
+    pub paused: u64,
+    pub archived: u64,
+    #[serde(default)]
+    pub filled: u64,
+}
+
+impl OfferStateCounts {
+    /// Moves one offer from the `from` state, if it had one, to `to`.
+    pub fn record(&mut self, from: Option<&OfferState>, to: &OfferState) {
+        if let Some(from) = from {
+            let count = self.count_mut(from);
+            *count = count.saturating_sub(1);
+        }
+        *self.count_mut(to) += 1;
+    }
+
+    pub fn merge(&mut self, other: &OfferStateCounts) {
+        self.active += other.active;
+        self.paused += other.paused;
+        self.archived += other.archived;
+        self.filled += other.filled;
+    }
+
+    fn count_mut(&mut self, state: &OfferState) -> &mut u64 {
+        match state {
+            OfferState::Active => &mut self.active,
+            OfferState::Paused => &mut self.paused,
+            OfferState::Archive => &mut self.archived,
+            OfferState::Filled => &mut self.filled,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FiatOfferStats {
+    pub fiat_currency: FiatCurrency,
+    pub counts: OfferStateCounts,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OfferStats {
+    /// Offers ever created, including archived ones.
+    pub total_created: u64,
+    pub totals: OfferStateCounts,
+    pub by_fiat: Vec<FiatOfferStats>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Offer {
     pub id: u64,
@@ -131,12 +375,109 @@ pub struct Offer {
     pub denom: Denom,
     pub state: OfferState,
     pub timestamp: u64,
+    #[serde(default)]
+    pub max_active_trades: Option<u32>,
+    /// Trades created against the offer that have not closed yet.
+    #[serde(default)]
+    pub active_trades_count: u32,
+    /// The owner's profile key version when the offer was created.
+    #[serde(default)]
+    pub owner_key_version: u32,
+    /// Block time in seconds from which the offer counts as archived.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Amount held by the offer's open and settled trades.
+    #[serde(default)]
+    pub filled_amount: Uint128,
+    /// Gives settled trades' amounts back to the offer and reactivates it
+    /// once it is filled, instead of leaving it to the owner.
+    #[serde(default)]
+    pub auto_repost: bool,
+    /// Funds the owner deposited with a prefunded offer, held by the offer
+    /// contract until the offer is archived.
+    #[serde(default)]
+    pub deposit: Uint128,
+}
+
+impl Offer {
+    /// Amount still available to new trades.
+    pub fn remaining_amount(&self) -> Uint128 {
+        self.max_amount.saturating_sub(self.filled_amount)
+    }
+
+    /// Whether the offer contract still holds a deposit for the offer.
+    pub fn is_backed(&self) -> bool {
+        !self.deposit.is_zero()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    /// The offer as it should be shown at `now`, an expired offer reads as
+    /// archived until someone calls `Expire` on it.
+    pub fn at(mut self, now: u64) -> Offer {
+        if self.is_expired(now) {
+            self.state = OfferState::Archive;
+        }
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct OfferResponse {
     pub offer: Offer,
     pub profile: Profile,
+    #[serde(default)]
+    pub solvency: Option<SolvencyStatus>,
+    #[serde(default)]
+    pub remaining_amount: Uint128,
+    /// Whether the owner deposited the offer's funds up front.
+    #[serde(default)]
+    pub backed: bool,
+}
+
+/// The maker's on-chain balance of the offer denom at `attested_at`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolvencyAttestation {
+    pub balance: Uint128,
+    pub attested_at: u64,
+}
+
+/// A solvency attestation as shown to takers, `stale` once it is older than
+/// `SOLVENCY_ATTESTATION_TTL`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolvencyStatus {
+    pub balance: Uint128,
+    pub attested_at: u64,
+    pub stale: bool,
+}
+
+impl SolvencyAttestation {
+    pub fn status(&self, now: u64) -> SolvencyStatus {
+        SolvencyStatus {
+            balance: self.balance,
+            attested_at: self.attested_at,
+            stale: now.saturating_sub(self.attested_at) > SOLVENCY_ATTESTATION_TTL,
+        }
+    }
+}
+
+pub fn load_solvency(
+    storage: &dyn Storage,
+    offer_id: u64,
+    now: u64,
+) -> StdResult<Option<SolvencyStatus>> {
+    Ok(SOLVENCY_ATTESTATIONS
+        .may_load(storage, offer_id)?
+        .map(|attestation| attestation.status(now)))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OffersPage {
+    pub offers: Vec<OfferResponse>,
+    /// Pass as `after` to fetch the next page, `None` on the last page.
+    pub next_cursor: Option<u64>,
 }
 
 pub struct OfferModel<'a> {
@@ -177,12 +518,14 @@ impl<'a> OfferModel<'a> {
         self.offer.max_amount = msg.max_amount;
         self.offer.state = msg.state;
         self.offer.description = msg.description;
+        self.offer.max_active_trades = msg.max_active_trades;
         OfferModel::store(self.storage, &self.offer).unwrap();
         &self.offer
     }
 
     pub fn query_by_owner(
         deps: Deps,
+        now: u64,
         owner: Addr,
         limit: u32,
         last: Option<u64>,
@@ -205,7 +548,14 @@ impl<'a> OfferModel<'a> {
                         offer.clone().owner,
                     )
                     .unwrap();
-                    Ok(OfferResponse { offer, profile })
+                    let solvency = load_solvency(deps.storage, offer.id, now)?;
+                    Ok(OfferResponse {
+                        remaining_amount: offer.remaining_amount(),
+                        backed: offer.is_backed(),
+                        offer: offer.at(now),
+                        profile,
+                        solvency,
+                    })
                 })
             })
             .collect();
@@ -213,15 +563,187 @@ impl<'a> OfferModel<'a> {
         Ok(result)
     }
 
+    pub fn query_by_id(
+        deps: Deps,
+        now: u64,
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        limit: u32,
+        after: Option<u64>,
+    ) -> StdResult<OffersPage> {
+        let hub_config = get_hub_config(deps);
+        let range_from = after.map(Bound::exclusive);
+        let limit = validate_min_max_items_per_page(limit) as usize;
+
+        let prefix = filter_prefix(&offer_type, &fiat_currency, &denom, &OfferState::Active);
+
+        // Read one extra offer to know whether another page exists.
+        let mut page: Vec<Offer> = offers()
+            .idx
+            .filter
+            .prefix(prefix)
+            .range(deps.storage, range_from, None, Order::Ascending)
+            .filter(|item| !matches!(item, Ok((_, offer)) if offer.is_expired(now)))
+            .take(limit + 1)
+            .map(|item| item.map(|(_, offer)| offer))
+            .collect::<StdResult<_>>()?;
+        let next_cursor = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|offer| offer.id)
+        } else {
+            None
+        };
+
+        let offers = page
+            .into_iter()
+            .map(|offer| {
+                let profile = load_profile(
+                    &deps.querier,
+                    hub_config.profile_addr.to_string(),
+                    offer.owner.clone(),
+                )?;
+                let solvency = load_solvency(deps.storage, offer.id, now)?;
+                Ok(OfferResponse {
+                    remaining_amount: offer.remaining_amount(),
+                    backed: offer.is_backed(),
+                    offer,
+                    profile,
+                    solvency,
+                })
+            })
+            .collect::<StdResult<_>>()?;
+
+        Ok(OffersPage {
+            offers,
+            next_cursor,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn query_by_rate(
+        deps: Deps,
+        now: u64,
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        min_rate: Option<Uint128>,
+        max_rate: Option<Uint128>,
+        limit: u32,
+        last: Option<u64>,
+    ) -> StdResult<Vec<OfferResponse>> {
+        let hub_config = get_hub_config(deps);
+        let limit = validate_min_max_items_per_page(limit) as usize;
+        let min_rate = min_rate.map_or(0, |rate| rate.u128());
+        let max_rate = max_rate.map_or(u128::MAX, |rate| rate.u128());
+        if min_rate > max_rate {
+            return Ok(vec![]);
+        }
+
+        // Index keys are (rate, id), so resume right after the last offer
+        // unless its rate has since moved below the band.
+        let band_start = (min_rate, 0);
+        let start = match last {
+            Some(id) => Some((offers().load(deps.storage, id)?.rate.u128(), id)),
+            None => None,
+        };
+        let start = match start {
+            Some(start) if start > band_start => Bound::exclusive(start),
+            _ => Bound::inclusive(band_start),
+        };
+        let end = Bound::inclusive((max_rate, u64::MAX));
+
+        let prefix = filter_prefix(&offer_type, &fiat_currency, &denom, &OfferState::Active);
+        offers()
+            .idx
+            .rate
+            .sub_prefix(prefix)
+            .range(deps.storage, Some(start), Some(end), Order::Ascending)
+            .filter(|item| !matches!(item, Ok((_, offer)) if offer.is_expired(now)))
+            .take(limit)
+            .map(|item| {
+                let (_, offer) = item?;
+                let profile = load_profile(
+                    &deps.querier,
+                    hub_config.profile_addr.to_string(),
+                    offer.owner.clone(),
+                )?;
+                let solvency = load_solvency(deps.storage, offer.id, now)?;
+                Ok(OfferResponse {
+                    remaining_amount: offer.remaining_amount(),
+                    backed: offer.is_backed(),
+                    offer,
+                    profile,
+                    solvency,
+                })
+            })
+            .collect()
+    }
+
+    /// Walks the rate index from the best rate for takers and returns the
+    /// first offer that takes `amount` and has room for another trade.
+    pub fn best_offer(
+        deps: Deps,
+        now: u64,
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        denom: Denom,
+        amount: Uint128,
+    ) -> StdResult<Option<OfferResponse>> {
+        // Takers buy from sell offers, so the lowest rate is best there.
+        let order = match offer_type {
+            OfferType::Sell => Order::Ascending,
+            OfferType::Buy => Order::Descending,
+        };
+        let prefix = filter_prefix(&offer_type, &fiat_currency, &denom, &OfferState::Active);
+        let best = offers()
+            .idx
+            .rate
+            .sub_prefix(prefix)
+            .range(deps.storage, None, None, order)
+            .map(|item| item.map(|(_, offer)| offer))
+            .find(|item| match item {
+                Ok(offer) => {
+                    !offer.is_expired(now)
+                        && offer.min_amount <= amount
+                        && amount <= offer.remaining_amount()
+                        && assert_offer_trade_capacity(offer).is_ok()
+                }
+                Err(_) => true,
+            })
+            .transpose()?;
+
+        best.map(|offer| {
+            let hub_config = get_hub_config(deps);
+            let profile = load_profile(
+                &deps.querier,
+                hub_config.profile_addr.to_string(),
+                offer.owner.clone(),
+            )?;
+            let solvency = load_solvency(deps.storage, offer.id, now)?;
+            Ok(OfferResponse {
+                remaining_amount: offer.remaining_amount(),
+                backed: offer.is_backed(),
+                offer,
+                profile,
+                solvency,
+            })
+        })
+        .transpose()
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn query_by<T: CustomQuery>(
         deps: Deps<T>,
+        now: u64,
         offer_type: OfferType,
         fiat_currency: FiatCurrency,
         denom: Denom,
         order: OfferOrder,
         limit: u32,
         last: Option<u64>,
-    ) -> StdResult<Vec<OfferResponse>> {
+        min_owner_reputation: Option<u64>,
+    ) -> StdResult<OffersPage> {
         let hub_config = get_hub_config(deps);
         let storage = deps.storage;
         let std_order = Order::Descending;
@@ -236,41 +758,55 @@ impl<'a> OfferModel<'a> {
         )
         .unwrap();
 
-        let prefix = fiat_currency.to_string()
-            + &offer_type.to_string()
-            + &denom_to_string(&denom)
-            + &*OfferState::Active.to_string();
+        let prefix = filter_prefix(&offer_type, &fiat_currency, &denom, &OfferState::Active);
 
-        let mut result: Vec<OfferResponse> = offers()
+        let mut candidates = offers()
             .idx
             .filter
             .prefix(prefix)
             .range(storage, None, range_from, std_order)
-            .flat_map(|item| {
-                item.and_then(|(_, offer)| {
-                    let profile_found = profiles
-                        .clone()
-                        .into_iter()
-                        .find(|profile| profile.addr.eq(&offer.owner));
-
-                    let profile = if profile_found.is_some() {
-                        profile_found.unwrap()
-                    } else {
-                        let new_profile = load_profile(
-                            &deps.querier,
-                            hub_config.profile_addr.to_string(),
-                            offer.owner.clone(),
-                        )
-                        .unwrap();
-                        profiles.push(new_profile.clone());
-                        new_profile
-                    };
-
-                    Ok(OfferResponse { offer, profile })
-                })
-            })
-            .take(limit as usize)
-            .collect();
+            .filter(|item| !matches!(item, Ok((_, offer)) if offer.is_expired(now)));
+
+        let mut result: Vec<OfferResponse> = vec![];
+        let mut scanned = 0;
+        let mut last_scanned = None;
+        while result.len() < limit as usize && scanned < MAX_OFFER_SCAN {
+            let offer = match candidates.next() {
+                Some(item) => item?.1,
+                None => break,
+            };
+            scanned += 1;
+            last_scanned = Some(offer.id);
+
+            let profile = match profiles
+                .iter()
+                .find(|profile| profile.addr.eq(&offer.owner))
+            {
+                Some(profile) => profile.clone(),
+                None => {
+                    let new_profile = load_profile(
+                        &deps.querier,
+                        hub_config.profile_addr.to_string(),
+                        offer.owner.clone(),
+                    )?;
+                    profiles.push(new_profile.clone());
+                    new_profile
+                }
+            };
+            if matches!(min_owner_reputation, Some(min) if profile.released_trades_count < min) {
+                continue;
+            }
+
+            let solvency = load_solvency(storage, offer.id, now)?;
+            result.push(OfferResponse {
+                remaining_amount: offer.remaining_amount(),
+                backed: offer.is_backed(),
+                offer,
+                profile,
+                solvency,
+            });
+        }
+        let next_cursor = candidates.next().and(last_scanned);
 
         match order {
             OfferOrder::TradesCount => {
@@ -285,7 +821,10 @@ impl<'a> OfferModel<'a> {
             }
         }
 
-        Ok(result)
+        Ok(OffersPage {
+            offers: result,
+            next_cursor,
+        })
     }
 }
 
@@ -343,10 +882,31 @@ pub enum OfferState {
     Active,
     Paused,
     Archive,
+    /// Too little of the offer is left to cover `min_amount`.
+    Filled,
 }
 
 // Price
 
+// Messages
+pub fn update_offer_active_trades_msg(
+    offer_contract: String,
+    id: u64,
+    trade_state: TradeState,
+    amount: Uint128,
+) -> SubMsg {
+    SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: offer_contract,
+        msg: to_json_binary(&ExecuteMsg::UpdateActiveTrades {
+            id,
+            trade_state,
+            amount,
+        })
+        .unwrap(),
+        funds: vec![],
+    }))
+}
+
 // Queries
 pub fn load_offer<T: CustomQuery>(
     querier: &QuerierWrapper<T>,
@@ -355,6 +915,34 @@ pub fn load_offer<T: CustomQuery>(
 ) -> StdResult<OfferResponse> {
     querier.query_wasm_smart(offer_contract, &QueryMsg::Offer { id: offer_id })
 }
+
+pub fn is_taker_blocked<T: CustomQuery>(
+    querier: &QuerierWrapper<T>,
+    offer_contract: String,
+    maker: Addr,
+    taker: Addr,
+) -> StdResult<bool> {
+    querier.query_wasm_smart(offer_contract, &QueryMsg::IsTakerBlocked { maker, taker })
+}
+
+pub fn load_takeable_offers<T: CustomQuery>(
+    querier: &QuerierWrapper<T>,
+    offer_contract: String,
+    offer_type: OfferType,
+    fiat_currency: FiatCurrency,
+    denom: Denom,
+    taker: Addr,
+) -> StdResult<Vec<Offer>> {
+    querier.query_wasm_smart(
+        offer_contract,
+        &QueryMsg::TakeableOffers {
+            offer_type,
+            fiat_currency,
+            denom,
+            taker,
+        },
+    )
+}
 // Migration
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -19,6 +19,10 @@ pub enum ExecuteMsg {
         contact: String,
         encryption_key: String,
     },
+    /// Replaces the sender's encryption key without touching their contact.
+    RotateEncryptionKey {
+        new_key: String,
+    },
     UpdateActiveOffers {
         profile_addr: Addr,
         offer_state: OfferState,
@@ -127,6 +131,10 @@ pub struct Profile {
     pub contact: Option<String>,
     pub encryption_key: Option<String>,
     pub active_offers_count: u8,
+    /// Bumped every time `encryption_key` changes, offers and trades record it
+    /// so historical contact blobs can be matched with the key that sealed them.
+    #[serde(default)]
+    pub key_version: u32,
 }
 
 impl Profile {
@@ -141,6 +149,16 @@ impl Profile {
             encryption_key: None,
             active_offers_count: 0,
             active_trades_count: 0,
+            key_version: 0,
+        }
+    }
+
+    /// The key version the profile will be at once `encryption_key` is set.
+    pub fn key_version_for(&self, encryption_key: &str) -> u32 {
+        if self.encryption_key.as_deref() == Some(encryption_key) {
+            self.key_version
+        } else {
+            self.key_version + 1
         }
     }
 }
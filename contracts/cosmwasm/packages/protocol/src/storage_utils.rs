@@ -0,0 +1,51 @@
+use cosmwasm_std::{Order, StdResult, Storage};
+use cw_storage_plus::{Bound, IndexList, IndexedMap};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::constants::MAX_REINDEX_BATCH_SIZE;
+
+/// Page size for a `Reindex` call, `MAX_REINDEX_BATCH_SIZE` unless a smaller
+/// `limit` is given.
+pub fn reindex_limit(limit: Option<u32>) -> usize {
+    limit.map_or(MAX_REINDEX_BATCH_SIZE, |limit| {
+        (limit as usize).clamp(1, MAX_REINDEX_BATCH_SIZE)
+    })
+}
+
+/// Saves up to `limit` entries of `map` with an id above `start_after` again,
+/// so indexes added after they were stored cover them. Returns the saved
+/// entries and, while more remain, the id to pass as the next `start_after`.
+pub fn reindex_page<T, I>(
+    storage: &mut dyn Storage,
+    map: &IndexedMap<u64, T, I>,
+    start_after: Option<u64>,
+    limit: usize,
+) -> StdResult<(Vec<T>, Option<u64>)>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    // Read one extra entry to know whether another call is needed.
+    let mut page: Vec<(u64, T)> = map
+        .range(
+            storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit + 1)
+        .collect::<StdResult<_>>()?;
+    let next_start_after = if page.len() > limit {
+        page.truncate(limit);
+        page.last().map(|(id, _)| *id)
+    } else {
+        None
+    };
+    let mut saved = Vec::with_capacity(page.len());
+    for (id, item) in page {
+        map.save(storage, id, &item)?;
+        saved.push(item);
+    }
+    Ok((saved, next_start_after))
+}
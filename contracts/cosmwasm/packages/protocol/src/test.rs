@@ -0,0 +1,630 @@
+use std::convert::TryFrom;
+
+use cosmwasm_std::testing::message_info;
+use cosmwasm_std::{
+    coin, from_json, to_json_string, Addr, Coin, Decimal, StdError, Uint128, Uint256, Uint64,
+};
+use cw20::Denom;
+
+use crate::constants::ENCRYPTION_KEY_LIMIT;
+use crate::currencies::{normalize_currency, FiatCurrency};
+use crate::denom_utils::normalize_denom;
+use crate::errors::ContractError;
+use crate::guards::{
+    assert_contact_valid, assert_dispute_evidence_valid, assert_offer_description_valid,
+    assert_sent_funds, assert_text_length, assert_transition, assert_valid_arbitrator,
+};
+use crate::hub::{FeeSplit, HubConfig, TextLimits};
+use crate::offer::{OfferMsg, OfferState};
+use crate::trade::{calculate_fees, TradeState};
+use crate::trade_state::{CanonicalTradeState, SolanaTradeStatus};
+
+fn hub_config() -> HubConfig {
+    HubConfig {
+        offer_addr: Addr::unchecked("offer"),
+        trade_addr: Addr::unchecked("trade"),
+        profile_addr: Addr::unchecked("profile"),
+        price_addr: Addr::unchecked("price"),
+        price_provider_addr: Addr::unchecked("price_provider"),
+        local_market_addr: Addr::unchecked("local_market"),
+        local_denom: Denom::Native("local".to_string()),
+        chain_fee_collector_addr: Addr::unchecked("chain_fee_collector"),
+        warchest_addr: Addr::unchecked("warchest"),
+        active_offers_limit: 3,
+        active_trades_limit: 10,
+        arbitration_fee_pct: Decimal::percent(1),
+        burn_fee_pct: Decimal::permille(2),
+        chain_fee_pct: Decimal::permille(3),
+        warchest_fee_pct: Decimal::permille(5),
+        trade_expiration_timer: 1200,
+        trade_dispute_timer: 3600,
+        trade_limit_min: Uint128::new(1),
+        trade_limit_max: Uint128::new(100),
+        local_denom_fee_discount_pct: Decimal::percent(50),
+        offer_update_delay: 0,
+        text_limits: TextLimits::default(),
+        trade_payment_timer: 0,
+        funding_deadline_seconds: 0,
+        paused: false,
+        fee_split: FeeSplit::default(),
+        fee_denom: None,
+        cancellation_fee_bps: 0,
+    }
+}
+
+#[test]
+fn cosmwasm_trade_states_round_trip() {
+    let states = vec![
+        TradeState::RequestCreated,
+        TradeState::RequestCanceled,
+        TradeState::RequestExpired,
+        TradeState::RequestAccepted,
+        TradeState::EscrowFunded,
+        TradeState::EscrowCanceled,
+        TradeState::EscrowRefunded,
+        TradeState::FiatDeposited,
+        TradeState::EscrowReleased,
+        TradeState::EscrowDisputed,
+        TradeState::SettledForMaker,
+        TradeState::SettledForTaker,
+    ];
+    for state in states {
+        let canonical = CanonicalTradeState::from(state.clone());
+        assert_eq!(TradeState::try_from(canonical).unwrap(), state);
+    }
+}
+
+#[test]
+fn solana_trade_statuses_round_trip() {
+    let statuses = vec![
+        SolanaTradeStatus::Open,
+        SolanaTradeStatus::InProgress,
+        SolanaTradeStatus::Completed,
+        SolanaTradeStatus::Cancelled,
+        SolanaTradeStatus::Disputed,
+    ];
+    for status in statuses {
+        let canonical = CanonicalTradeState::from(status);
+        assert_eq!(SolanaTradeStatus::try_from(canonical).unwrap(), status);
+    }
+}
+
+#[test]
+fn unmapped_canonical_states_are_rejected() {
+    assert!(TradeState::try_from(CanonicalTradeState::InProgress).is_err());
+    assert!(SolanaTradeStatus::try_from(CanonicalTradeState::FiatDeposited).is_err());
+}
+
+#[test]
+fn local_denom_trades_get_fee_discount() {
+    let hub_config = hub_config();
+    let amount = Uint128::new(1_000_000);
+
+    let full = calculate_fees(&hub_config, amount, &Denom::Native("uatom".to_string()));
+    assert_eq!(full.burn_amount, Uint128::new(2_000));
+    assert_eq!(full.chain_amount, Uint128::new(3_000));
+    assert_eq!(full.warchest_amount, Uint128::new(5_000));
+
+    let discounted = calculate_fees(&hub_config, amount, &hub_config.local_denom);
+    assert_eq!(discounted.burn_amount, Uint128::new(1_000));
+    assert_eq!(discounted.chain_amount, Uint128::new(1_500));
+    assert_eq!(discounted.warchest_amount, Uint128::new(2_500));
+    assert_eq!(discounted.total_fees(), Uint128::new(5_000));
+}
+
+#[test]
+fn text_guards_use_configured_limits() {
+    let limits = TextLimits {
+        description: 4,
+        contact: 8,
+        dispute_reason: 6,
+        evidence_uri: 10,
+    };
+
+    assert!(assert_offer_description_valid(Some("abcd".to_string()), limits.description).is_ok());
+    assert!(assert_offer_description_valid(Some("abcde".to_string()), limits.description).is_err());
+    assert!(assert_offer_description_valid(None, limits.description).is_ok());
+
+    assert!(assert_text_length("owner_contact", "12345678", limits.contact).is_ok());
+    let err = assert_text_length("owner_contact", "123456789", limits.contact).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "[3] The parameter owner_contact is invalid. The owner_contact can not be longer than 8 characters."
+    );
+
+    let uri = Some("ipfs://abc".to_string());
+    assert!(assert_dispute_evidence_valid("unpaid", &uri, &limits).is_ok());
+    assert!(assert_dispute_evidence_valid("unpaid", &None, &limits).is_ok());
+    assert!(assert_dispute_evidence_valid("  ", &None, &limits).is_err());
+    assert!(assert_dispute_evidence_valid("not paid", &None, &limits).is_err());
+    assert!(
+        assert_dispute_evidence_valid("unpaid", &Some("ipfs://abcd".to_string()), &limits).is_err()
+    );
+}
+
+#[test]
+fn stored_config_without_text_limits_uses_defaults() {
+    let current = to_json_string(&hub_config()).unwrap();
+    let stored = current.replace(
+        r#","text_limits":{"description":140,"contact":1024,"dispute_reason":500,"evidence_uri":256}"#,
+        "",
+    );
+    assert_ne!(stored, current);
+
+    let config: HubConfig = from_json(stored).unwrap();
+    assert_eq!(config.text_limits, TextLimits::default());
+
+    // Limits stored before the dispute limits existed keep their values
+    let stored = current.replace(r#","dispute_reason":500,"evidence_uri":256"#, "");
+    let stored = stored.replace(r#""description":140"#, r#""description":280"#);
+    let config: HubConfig = from_json(stored).unwrap();
+    assert_eq!(config.text_limits.description, 280);
+    assert_eq!(
+        config.text_limits.dispute_reason,
+        TextLimits::default().dispute_reason
+    );
+}
+
+#[test]
+fn trade_parties_can_not_arbitrate() {
+    let buyer = Addr::unchecked("buyer");
+    let seller = Addr::unchecked("seller");
+
+    assert!(assert_valid_arbitrator(&Addr::unchecked("arbitrator"), &buyer, &seller).is_ok());
+    for party in [&buyer, &seller] {
+        let err = assert_valid_arbitrator(party, &buyer, &seller).unwrap_err();
+        assert!(
+            matches!(err, ContractError::SelfAssignedArbitrator { arbitrator } if &arbitrator == party)
+        );
+    }
+}
+
+#[test]
+fn sent_funds_must_be_exactly_the_expected_coin() {
+    let cases: Vec<(&str, Vec<Coin>, Option<&str>)> = vec![
+        ("exact", vec![coin(100, "uatom")], None),
+        (
+            "no coins",
+            vec![],
+            Some(r#"[302] Invalid denom. Expected: "uatom", Received: ""."#),
+        ),
+        (
+            "wrong denom",
+            vec![coin(100, "uosmo")],
+            Some(r#"[302] Invalid denom. Expected: "uatom", Received: "uosmo"."#),
+        ),
+        (
+            "too little",
+            vec![coin(99, "uatom")],
+            Some(
+                r#"[300] Fund escrow error. Required amount: Uint128(100), Sent amount: Uint128(99)."#,
+            ),
+        ),
+        (
+            "too much",
+            vec![coin(101, "uatom")],
+            Some(
+                r#"[300] Fund escrow error. Required amount: Uint128(100), Sent amount: Uint128(101)."#,
+            ),
+        ),
+        (
+            "extra coin",
+            vec![coin(100, "uatom"), coin(1, "uosmo")],
+            Some(r#"[6] Only one coin can be sent. Received: ["uatom", "uosmo"]."#),
+        ),
+    ];
+
+    for (case, funds, expected) in cases {
+        let info = message_info(&Addr::unchecked("seller"), &funds);
+        let result = assert_sent_funds(&info, Uint128::new(100), "uatom");
+        assert_eq!(
+            result.map_err(|err| err.to_string()).err().as_deref(),
+            expected,
+            "{}",
+            case
+        );
+    }
+}
+
+#[test]
+fn currencies_are_case_folded_and_validated() {
+    for currency in ["usd", "Usd", "USD", " usd "] {
+        assert_eq!(normalize_currency(currency).unwrap(), "USD");
+    }
+    for currency in ["", "us", "usdx", "XYZ"] {
+        assert!(matches!(
+            normalize_currency(currency),
+            Err(ContractError::InvalidCurrency { currency: c }) if c == currency
+        ));
+    }
+
+    // Offer messages accept the currency in any case
+    let msg: OfferMsg = from_json(
+        r#"{"offer_type":"sell","owner_contact":"c","owner_encryption_key":"k","fiat_currency":"brl","rate":"100","denom":{"native":"uatom"},"min_amount":"1","max_amount":"2","description":null}"#,
+    )
+    .unwrap();
+    assert_eq!(msg.fiat_currency, FiatCurrency::BRL);
+}
+
+#[test]
+fn denoms_are_normalized_to_one_canonical_form() {
+    let native = |denom: &str| Denom::Native(denom.to_string());
+    let cases = [
+        (" uatom ", "uatom"),
+        // Bank denoms are case-sensitive
+        ("uATOM", "uATOM"),
+        (
+            "ibc/27394fb092d2eccd56123c74f36e4c1f926001ceada9ca97ea622b25f41e5eb2",
+            "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+        ),
+        (
+            "factory/Neutron1Creator/MyToken",
+            "factory/neutron1creator/MyToken",
+        ),
+    ];
+    for (denom, expected) in cases {
+        assert_eq!(normalize_denom(&native(denom)).unwrap(), native(expected));
+    }
+    assert_eq!(
+        normalize_denom(&Denom::Cw20(Addr::unchecked("Cosmos1Token"))).unwrap(),
+        Denom::Cw20(Addr::unchecked("cosmos1token"))
+    );
+
+    for denom in ["", "ua", "1atom", "u atom", "factory/creator"] {
+        assert!(
+            matches!(
+                normalize_denom(&native(denom)),
+                Err(ContractError::InvalidParameter { parameter, .. }) if parameter == "denom"
+            ),
+            "{}",
+            denom
+        );
+    }
+}
+
+#[test]
+fn contacts_need_a_bounded_blob_and_an_encoded_key() {
+    assert!(assert_contact_valid("12345678", "bWFrZXJfa2V5", 8).is_ok());
+    assert!(assert_contact_valid("", "cm90YXRlZF9rZXk=", 8).is_ok());
+    assert!(assert_contact_valid("", "0a1B2c", 8).is_ok());
+
+    let err = assert_contact_valid("123456789", "bWFrZXJfa2V5", 8).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "[402] Invalid contact. The contact can not be longer than 8 characters."
+    );
+    for key in ["", "maker_key", "abc", "bWFr=ZXJf", "bWFrZXJfa2V5===="] {
+        assert!(matches!(
+            assert_contact_valid("", key, 8),
+            Err(ContractError::InvalidContact { .. })
+        ));
+    }
+    let long_key = "a".repeat(ENCRYPTION_KEY_LIMIT as usize + 2);
+    assert!(assert_contact_valid("", &long_key, 8).is_err());
+}
+
+#[test]
+fn only_listed_trade_transitions_are_allowed() {
+    use TradeState::*;
+    let states = vec![
+        RequestCreated,
+        RequestCanceled,
+        RequestExpired,
+        RequestAccepted,
+        EscrowFunded,
+        EscrowCanceled,
+        EscrowRefunded,
+        FiatDeposited,
+        EscrowReleased,
+        EscrowDisputed,
+        SettledForMaker,
+        SettledForTaker,
+    ];
+    let allowed = vec![
+        (RequestCreated, RequestAccepted),
+        (RequestCreated, RequestCanceled),
+        (RequestCreated, RequestExpired),
+        (RequestCreated, EscrowFunded),
+        (RequestAccepted, RequestCanceled),
+        (RequestAccepted, RequestExpired),
+        (RequestAccepted, EscrowFunded),
+        (EscrowFunded, FiatDeposited),
+        (EscrowFunded, EscrowCanceled),
+        (EscrowFunded, EscrowRefunded),
+        (EscrowFunded, EscrowDisputed),
+        (EscrowCanceled, EscrowRefunded),
+        (FiatDeposited, EscrowReleased),
+        (FiatDeposited, EscrowDisputed),
+        (FiatDeposited, EscrowRefunded),
+        (EscrowDisputed, SettledForMaker),
+        (EscrowDisputed, SettledForTaker),
+        (EscrowDisputed, EscrowRefunded),
+    ];
+
+    for from in &states {
+        for to in &states {
+            let result = assert_transition(from.clone(), to.clone());
+            if allowed.contains(&(from.clone(), to.clone())) {
+                assert!(result.is_ok(), "{} -> {} should be allowed", from, to);
+            } else {
+                assert!(
+                    matches!(result, Err(ContractError::InvalidTradeStateChange { .. })),
+                    "{} -> {} should be rejected",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn error_codes_are_stable_and_unique() {
+    let addr = Addr::unchecked("addr");
+    let errors = vec![
+        (
+            ContractError::Unauthorized {
+                owner: addr.clone(),
+                caller: addr.clone(),
+            },
+            1,
+        ),
+        (
+            ContractError::UnauthorizedMultipleOwnership {
+                owners: vec![],
+                caller: addr.clone(),
+            },
+            2,
+        ),
+        (
+            ContractError::InvalidParameter {
+                parameter: "p".to_string(),
+                message: None,
+            },
+            3,
+        ),
+        (ContractError::Std(StdError::generic_err("std")), 4),
+        (ContractError::ContractPaused {}, 5),
+        (ContractError::UnexpectedCoins { denoms: vec![] }, 6),
+        (
+            ContractError::InvalidCurrency {
+                currency: "XYZ".to_string(),
+            },
+            7,
+        ),
+        (ContractError::HubAlreadyRegistered {}, 100),
+        (
+            ContractError::InvalidPlatformFee {
+                max_platform_fee: Uint64::zero(),
+            },
+            101,
+        ),
+        (
+            ContractError::InvalidMinMax {
+                min: Uint128::zero(),
+                max: Uint128::zero(),
+            },
+            200,
+        ),
+        (
+            ContractError::InvalidOfferAmount {
+                amount: Uint128::zero(),
+                min_amount: Uint128::zero(),
+                max_amount: Uint128::zero(),
+            },
+            201,
+        ),
+        (
+            ContractError::InvalidOfferStateChange {
+                from: OfferState::Active,
+                to: OfferState::Archive,
+            },
+            202,
+        ),
+        (
+            ContractError::OfferMaxAboveTradingLimit {
+                max_amount: Uint256::zero(),
+                trading_limit: Uint256::zero(),
+            },
+            203,
+        ),
+        (
+            ContractError::OfferNotFound {
+                offer_id: "1".to_string(),
+            },
+            204,
+        ),
+        (ContractError::NoPendingOfferUpdate { offer_id: 1 }, 205),
+        (
+            ContractError::PendingOfferUpdateNotReady { apply_at: 1 },
+            206,
+        ),
+        (
+            ContractError::ValueOutOfRange {
+                value: 100,
+                range_start: 0,
+                range_end: 99,
+            },
+            207,
+        ),
+        (
+            ContractError::OfferActiveTradesLimitReached {
+                offer_id: 1,
+                limit: 1,
+            },
+            208,
+        ),
+        (
+            ContractError::OfferMinBelowTradingLimit {
+                min_amount: Uint256::zero(),
+                trading_limit: Uint256::zero(),
+            },
+            209,
+        ),
+        (
+            ContractError::OfferExpired {
+                offer_id: 1,
+                expires_at: 0,
+            },
+            210,
+        ),
+        (
+            ContractError::OfferNotExpired {
+                offer_id: 1,
+                expires_at: None,
+            },
+            211,
+        ),
+        (
+            ContractError::TakerBlocked {
+                maker: Addr::unchecked("maker"),
+                taker: Addr::unchecked("taker"),
+            },
+            212,
+        ),
+        (
+            ContractError::UnsupportedPair {
+                fiat_currency: FiatCurrency::USD,
+                denom: "uatom".to_string(),
+            },
+            213,
+        ),
+        (
+            ContractError::FundEscrowError {
+                required_amount: Uint128::zero(),
+                sent_amount: Uint128::zero(),
+            },
+            300,
+        ),
+        (
+            ContractError::PrematureDisputeRequest { time_to_dispute: 1 },
+            301,
+        ),
+        (
+            ContractError::InvalidDenom {
+                expected: "a".to_string(),
+                received: "b".to_string(),
+            },
+            302,
+        ),
+        (ContractError::InvalidPriceForDenom {}, 303),
+        (
+            ContractError::InvalidSender {
+                sender: addr.clone(),
+                buyer: addr.clone(),
+                seller: addr.clone(),
+            },
+            304,
+        ),
+        (
+            ContractError::InvalidTradeAmount {
+                amount: Uint256::zero(),
+                min_amount: Uint256::zero(),
+                max_amount: Uint256::zero(),
+            },
+            305,
+        ),
+        (
+            ContractError::InvalidTradeState {
+                current: TradeState::RequestCreated,
+                expected: TradeState::EscrowFunded,
+            },
+            306,
+        ),
+        (
+            ContractError::InvalidTradeStateChange {
+                from: TradeState::RequestCreated,
+                to: TradeState::EscrowReleased,
+            },
+            307,
+        ),
+        (
+            ContractError::RefundErrorNotExpired {
+                message: "m".to_string(),
+                trade: "t".to_string(),
+            },
+            308,
+        ),
+        (
+            ContractError::TradeExpired {
+                expired_at: 1,
+                created_at: 0,
+            },
+            309,
+        ),
+        (ContractError::SwapErrorInvalidAmount {}, 310),
+        (
+            ContractError::SwapErrorMissingDenom {
+                expected_denom: "a".to_string(),
+            },
+            311,
+        ),
+        (ContractError::UnknownReplyId { reply_id: 1 }, 312),
+        (
+            ContractError::NoArbitratorAvailable {
+                fiat: FiatCurrency::USD,
+            },
+            313,
+        ),
+        (
+            ContractError::SelfAssignedArbitrator {
+                arbitrator: addr.clone(),
+            },
+            314,
+        ),
+        (
+            ContractError::UnmappedTradeState {
+                state: CanonicalTradeState::InProgress,
+            },
+            315,
+        ),
+        (
+            ContractError::InsufficientOfferLiquidity {
+                requested: Uint128::zero(),
+                available: Uint128::zero(),
+            },
+            316,
+        ),
+        (
+            ContractError::TradeNotOverdue {
+                payment_deadline: None,
+            },
+            317,
+        ),
+        (
+            ContractError::ReleaseExceedsRemaining {
+                amount: Uint128::zero(),
+                remaining: Uint128::zero(),
+            },
+            318,
+        ),
+        (
+            ContractError::FundingDeadlineNotPassed {
+                funding_deadline: None,
+            },
+            319,
+        ),
+        (ContractError::ArbitratorAlreadyRated { trade_id: 1 }, 320),
+        (ContractError::ActiveOffersLimitReached { limit: 1 }, 400),
+        (ContractError::ActiveTradesLimitReached { limit: 1 }, 401),
+        (
+            ContractError::InvalidContact {
+                message: "m".to_string(),
+            },
+            402,
+        ),
+    ];
+
+    let mut codes: Vec<u32> = errors
+        .iter()
+        .map(|(error, expected)| {
+            assert_eq!(error.code(), *expected, "{:?}", error);
+            // Clients read the code off the front of the message
+            assert!(error.to_string().starts_with(&format!("[{}] ", expected)));
+            error.code()
+        })
+        .collect();
+    codes.sort_unstable();
+    codes.dedup();
+    assert_eq!(codes.len(), errors.len());
+}
@@ -2,8 +2,8 @@ use std::fmt::{self, Display};
 use std::ops::{Add};
 
 use cosmwasm_std::{
-    Addr, BlockInfo, Coin, CustomQuery, Deps, Env, MessageInfo, Order, StdResult, Storage,
-    Uint128, Uint256,
+    attr, to_json_string, Addr, Attribute, BlockInfo, Coin, CustomQuery, Decimal, Deps, Env,
+    MessageInfo, Order, StdResult, Storage, Uint128, Uint256,
 };
 use cw20::Denom;
 use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, Item, Map, MultiIndex, UniqueIndex};
@@ -12,13 +12,24 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::currencies::FiatCurrency;
-use crate::guards::assert_range_0_to_99;
-use crate::offer::Arbitrator;
+use crate::denom_utils::denom_to_string;
+use crate::errors::ContractError;
+use crate::guards::{assert_range_0_to_99, assert_transition};
+use crate::hub::{FeeSplit, HubConfig};
+use crate::offer::{Arbitrator, OfferType};
 use crate::profile::Profile;
 
 pub const DENOM_CONVERSION_ROUTE: Map<&str, Vec<ConversionRoute>> =
     Map::new("denom_conversion_route");
 pub const DENOM_CONVERSION_STEP: Item<ConversionStep> = Item::new("denom_conversion_step");
+/// Chain and warchest fees being swapped into the hub's `fee_denom`.
+pub const FEE_CONVERSION: Item<FeeConversion> = Item::new("fee_conversion");
+/// Latest dispute evidence of each trade party, keyed by trade id and party.
+pub const DISPUTE_EVIDENCE: Map<(u64, &Addr), DisputeEvidence> = Map::new("dispute_evidence");
+/// Dispute track record of each arbitrator, shared across fiats.
+pub const ARBITRATOR_STATS: Map<&Addr, ArbitratorStats> = Map::new("arbitrator_stats");
+/// Ratings given to the arbitrator of a settled trade, keyed by trade id and party.
+pub const ARBITRATOR_RATINGS: Map<(u64, &Addr), u8> = Map::new("arbitrator_ratings");
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {}
@@ -27,6 +38,7 @@ pub struct InstantiateMsg {}
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Create(NewTrade),
+    TakeBest(TakeBestTrade),
     AcceptRequest {
         trade_id: u64,
         maker_contact: String,
@@ -41,10 +53,24 @@ pub enum ExecuteMsg {
     ReleaseEscrow {
         trade_id: u64,
     },
+    /// Releases part of the escrow to the buyer. The trade settles once the
+    /// whole amount has been released.
+    ReleasePartial {
+        trade_id: u64,
+        amount: Uint128,
+    },
     DisputeEscrow {
         trade_id: u64,
         buyer_contact: String,
         seller_contact: String,
+        reason: String,
+        evidence_uri: Option<String>,
+    },
+    /// Adds or replaces the sender's evidence on a disputed trade.
+    SubmitDisputeEvidence {
+        trade_id: u64,
+        reason: String,
+        evidence_uri: Option<String>,
     },
     FiatDeposited {
         trade_id: u64,
@@ -52,6 +78,11 @@ pub enum ExecuteMsg {
     CancelRequest {
         trade_id: u64,
     },
+    /// Cancels a trade whose escrow was not funded within the hub's
+    /// `funding_deadline_seconds`, giving its slot back to the offer.
+    CancelUnfunded {
+        trade_id: u64,
+    },
     NewArbitrator {
         arbitrator: Addr,
         fiat: FiatCurrency,
@@ -65,6 +96,39 @@ pub enum ExecuteMsg {
         trade_id: u64,
         winner: Addr,
     },
+    /// Settles a dispute by sharing the escrow between buyer and seller. The
+    /// amounts add up to what `SettleDispute` would pay the winner, the escrow
+    /// left after the arbitration fee and the buyer's share of protocol fees.
+    ResolveSplit {
+        trade_id: u64,
+        buyer_amount: Uint128,
+        seller_amount: Uint128,
+    },
+    /// Rates the arbitrator of a trade they settled, from 1 to
+    /// `MAX_ARBITRATOR_RATING`. Each party of the trade rates once.
+    RateArbitrator {
+        trade_id: u64,
+        rating: u8,
+    },
+    /// Moves a funded trade past its payment deadline into dispute. Callable by anyone.
+    CrankOverdue {
+        trade_id: u64,
+    },
+    /// Refunds the escrow of a funded or disputed trade to the seller and
+    /// closes it as refunded. Hub admin only, for trades no party can move.
+    AdminCancel {
+        trade_id: u64,
+    },
+    /// Saves up to `limit` trades with an id above `start_after` again, so
+    /// indexes added after they were stored cover them. `limit` defaults to
+    /// and is capped at `MAX_REINDEX_BATCH_SIZE`. Hub admin only. The
+    /// response's `next_start_after` attribute is set while more remain.
+    Reindex {
+        #[serde(default)]
+        start_after: Option<u64>,
+        #[serde(default)]
+        limit: Option<u32>,
+    },
     RegisterHub {},
     RegisterConversionRouteForDenom {
         denom: Denom,
@@ -91,6 +155,104 @@ pub enum QueryMsg {
     ArbitratorsFiat {
         fiat: FiatCurrency,
     },
+    ArbitratorStats {
+        arbitrator: Addr,
+    },
+    /// Id the next created trade will receive. Advisory only, another trade
+    /// created in the same block takes it first.
+    NextTradeId {},
+    /// Trades currently in `state`, oldest first.
+    TradesByState {
+        state: TradeState,
+        limit: u32,
+        start_after: Option<u64>,
+    },
+    DisputeInfo {
+        trade_id: u64,
+    },
+    /// Contract name and version, and whether the hub is registered.
+    Version {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DisputeEvidence {
+    pub reason: String,
+    pub evidence_uri: Option<String>,
+    pub submitted_at: u64,
+}
+
+/// Disputes an arbitrator has settled and the ratings the trade parties gave
+/// them, used to weight arbitrator selection.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct ArbitratorStats {
+    pub resolved_disputes: u64,
+    pub ratings_count: u64,
+    pub ratings_total: u64,
+}
+
+impl ArbitratorStats {
+    /// Settled disputes beyond this stop adding to an arbitrator's weight.
+    const MAX_WEIGHTED_RESOLUTIONS: u64 = 20;
+
+    /// Rating assumed for an arbitrator nobody rated yet.
+    const UNRATED: u64 = 3;
+
+    pub fn average_rating(&self) -> u64 {
+        self.ratings_total
+            .checked_div(self.ratings_count)
+            .unwrap_or(Self::UNRATED)
+    }
+
+    /// Selection weight: every arbitrator starts at 1, and each settled
+    /// dispute adds the arbitrator's average rating.
+    pub fn weight(&self) -> u64 {
+        1 + self.resolved_disputes.min(Self::MAX_WEIGHTED_RESOLUTIONS) * self.average_rating()
+    }
+}
+
+/// A state transition of a trade, emitted as JSON in the `trade_event`
+/// attribute so indexers can follow escrows without parsing each action's
+/// own attributes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TradeEvent {
+    pub trade_id: u64,
+    pub from_state: TradeState,
+    pub to_state: TradeState,
+    pub actor: Addr,
+    pub amount: Uint128,
+    pub denom: String,
+    pub block_height: u64,
+}
+
+impl TradeEvent {
+    pub fn new(
+        trade: &Trade,
+        from_state: TradeState,
+        actor: &Addr,
+        amount: Uint128,
+        env: &Env,
+    ) -> TradeEvent {
+        TradeEvent {
+            trade_id: trade.id,
+            from_state,
+            to_state: trade.get_state(),
+            actor: actor.clone(),
+            amount,
+            denom: denom_to_string(&trade.denom),
+            block_height: env.block.height,
+        }
+    }
+
+    pub fn to_attribute(&self) -> StdResult<Attribute> {
+        Ok(attr("trade_event", to_json_string(self)?))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DisputeInfoResponse {
+    pub trade_id: u64,
+    pub buyer: Option<DisputeEvidence>,
+    pub seller: Option<DisputeEvidence>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -119,6 +281,30 @@ impl FeeInfo {
     }
 }
 
+/// Returns a FeeInfo struct containing the protocol fees charged on `amount` of `denom`.
+/// Trades in the hub's `local_denom` get `local_denom_fee_discount_pct` off every fee.
+pub fn calculate_fees(hub_config: &HubConfig, amount: Uint128, denom: &Denom) -> FeeInfo {
+    let discount = if denom_to_string(denom).eq(&denom_to_string(&hub_config.local_denom)) {
+        Decimal::one() - hub_config.local_denom_fee_discount_pct
+    } else {
+        Decimal::one()
+    };
+    let fee_amount = |fee_pct: Decimal| {
+        (fee_pct * discount * Decimal::from_ratio(amount.u128(), 1u128)).to_uint_floor()
+    };
+
+    FeeInfo {
+        burn_amount: fee_amount(hub_config.burn_fee_pct),
+        chain_amount: fee_amount(hub_config.chain_fee_pct),
+        warchest_amount: fee_amount(hub_config.warchest_fee_pct),
+    }
+}
+
+/// The arbitrator's cut of the `amount` they settle a dispute over.
+pub fn calc_arbitration_fee(hub_config: &HubConfig, amount: Uint128) -> Uint128 {
+    (hub_config.arbitration_fee_pct * Decimal::from_ratio(amount.u128(), 1u128)).to_uint_floor()
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ConversionRoute {
@@ -135,6 +321,16 @@ pub struct ConversionStep {
     pub step: u8,
 }
 
+/// Fees sent to be swapped into `previous_balance.denom`. Whatever the swap
+/// returns is shared between the collectors in proportion to their fees.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FeeConversion {
+    pub previous_balance: Coin,
+    pub chain_amount: Uint128,
+    pub warchest_amount: Uint128,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct MigrateMsg {}
@@ -156,6 +352,19 @@ pub struct NewTrade {
     pub taker_contact: String,
 }
 
+/// Fills `amount` across the best priced active offers matching the filter,
+/// creating one trade per offer used. The taker is the sender.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TakeBestTrade {
+    pub offer_type: OfferType,
+    pub fiat_currency: FiatCurrency,
+    pub denom: Denom,
+    pub amount: Uint128,
+    pub profile_taker_contact: String,
+    pub profile_taker_encryption_key: String,
+    pub taker_contact: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct SwapMsg {
@@ -211,6 +420,22 @@ pub struct Trade {
     pub denom_fiat_price: Uint256,
     pub state_history: Vec<TradeStateItem>,
     state: TradeState,
+    /// Set when the escrow is funded if the hub enables auto-dispute. Past
+    /// it an unsettled trade can be pushed into dispute by anyone.
+    #[serde(default)]
+    pub payment_deadline: Option<u64>,
+    /// Escrow already paid out to the buyer through partial releases.
+    #[serde(default)]
+    pub released_amount: Uint128,
+    /// Profile key versions of the buyer and seller when the trade was
+    /// created, telling which of their keys decrypts the trade contacts.
+    #[serde(default)]
+    pub buyer_key_version: u32,
+    #[serde(default)]
+    pub seller_key_version: u32,
+    /// The hub's fee split when the trade was created.
+    #[serde(default)]
+    pub fee_split: FeeSplit,
 }
 
 impl Trade {
@@ -253,9 +478,40 @@ impl Trade {
             denom_fiat_price,
             state_history,
             state: TradeState::RequestCreated,
+            payment_deadline: None,
+            released_amount: Uint128::zero(),
+            buyer_key_version: 0,
+            seller_key_version: 0,
+            fee_split: FeeSplit::default(),
         };
     }
 
+    /// Splits `fees` into `(seller_share, buyer_share)` given the offer's
+    /// `maker`. The seller adds their share to the escrow and the buyer's is
+    /// taken from what is released to them.
+    pub fn fee_shares(&self, fees: Uint128, maker: &Addr) -> (Uint128, Uint128) {
+        let (maker_share, taker_share) = self.fee_split.split(fees);
+        if self.seller.eq(maker) {
+            (maker_share, taker_share)
+        } else {
+            (taker_share, maker_share)
+        }
+    }
+
+    /// The trade party facing the offer's `maker`.
+    pub fn taker(&self, maker: &Addr) -> Addr {
+        if self.seller.eq(maker) {
+            self.buyer.clone()
+        } else {
+            self.seller.clone()
+        }
+    }
+
+    /// Escrow amount that has not been released to the buyer yet.
+    pub fn remaining_amount(&self) -> Uint128 {
+        self.amount.saturating_sub(self.released_amount)
+    }
+
     pub fn get_state(&self) -> TradeState {
         return self.state.clone();
     }
@@ -264,7 +520,13 @@ impl Trade {
         return self.expires_at.ne(&0) && block_time > self.expires_at;
     }
 
-    pub fn set_state(&mut self, new_state: TradeState, env: &Env, info: &MessageInfo) {
+    pub fn set_state(
+        &mut self,
+        new_state: TradeState,
+        env: &Env,
+        info: &MessageInfo,
+    ) -> Result<(), ContractError> {
+        assert_transition(self.state.clone(), new_state.clone())?;
         // if the escrow is canceled or fiat is already deposited, the trade can no longer expire
         if vec![
             TradeState::RequestCanceled,
@@ -284,6 +546,7 @@ impl Trade {
             timestamp: block.time.seconds(),
         };
         self.state_history.push(new_trade_state);
+        Ok(())
     }
 }
 
@@ -306,8 +569,13 @@ pub struct TradeResponse {
     pub created_at: u64,
     pub expires_at: u64,
     pub enables_dispute_at: Option<u64>,
+    pub payment_deadline: Option<u64>,
     pub denom: Denom,
     pub amount: Uint128,
+    pub released_amount: Uint128,
+    pub buyer_key_version: u32,
+    pub seller_key_version: u32,
+    pub fee_split: FeeSplit,
     pub fiat: FiatCurrency,
     pub denom_fiat_price: Uint256,
     pub state_history: Vec<TradeStateItem>,
@@ -363,8 +631,13 @@ impl TradeResponse {
             created_at: trade.created_at,
             expires_at: trade.expires_at,
             enables_dispute_at: trade.enables_dispute_at,
+            payment_deadline: trade.payment_deadline,
             denom: trade.denom,
             amount: trade.amount,
+            released_amount: trade.released_amount,
+            buyer_key_version: trade.buyer_key_version,
+            seller_key_version: trade.seller_key_version,
+            fee_split: trade.fee_split,
             fiat: trade.fiat,
             denom_fiat_price: trade.denom_fiat_price,
             state_history: trade.state_history,
@@ -386,7 +659,7 @@ pub struct TradeModel<'a> {
 }
 
 impl TradeModel<'_> {
-    pub fn size(storage: &mut dyn Storage) -> usize {
+    pub fn size(storage: &dyn Storage) -> usize {
         trades()
             .range(storage, None, None, Order::Descending)
             .count()
@@ -447,6 +720,24 @@ impl TradeModel<'_> {
         Ok(result)
     }
 
+    pub fn trades_by_state(
+        storage: &dyn Storage,
+        state: TradeState,
+        limit: usize,
+        start_after: Option<u64>,
+    ) -> StdResult<Vec<Trade>> {
+        let range_from = start_after.map(Bound::exclusive);
+
+        trades()
+            .idx
+            .state
+            .prefix(state.to_string())
+            .range(storage, range_from, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, trade)| trade))
+            .collect()
+    }
+
     pub fn trades_by_arbitrator(
         storage: &dyn Storage,
         arbitrator: String,
@@ -487,11 +778,12 @@ pub struct TradeIndexes<'a> {
     // pk goes to second tuple element
     pub collection: UniqueIndex<'a, u64, Trade, u64>,
     pub arbitrator: MultiIndex<'a, String, Trade, u64>,
+    pub state: MultiIndex<'a, String, Trade, u64>,
 }
 
 impl<'a> IndexList<Trade> for TradeIndexes<'a> {
     fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Trade>> + '_> {
-        let v: Vec<&dyn Index<Trade>> = vec![&self.collection, &self.arbitrator];
+        let v: Vec<&dyn Index<Trade>> = vec![&self.collection, &self.arbitrator, &self.state];
         Box::new(v.into_iter())
     }
 }
@@ -505,6 +797,7 @@ pub fn trades() -> IndexedMap<u64, Trade, TradeIndexes<'static>> {
             pk_namespace,
             "trades__arbitrator",
         ),
+        state: MultiIndex::new(|_, t| t.state.to_string(), pk_namespace, "trades__state"),
     };
     IndexedMap::new(pk_namespace, indexes)
 }
@@ -580,29 +873,52 @@ impl ArbitratorModel {
         Ok(result)
     }
 
+    /// Picks one of the fiat's arbitrators, skipping any `parties` of the
+    /// trade. Each is picked in proportion to its `ArbitratorStats::weight`.
     pub fn get_arbitrator_random<T: CustomQuery>(
         deps: Deps<T>,
         random_value: usize,
         fiat: FiatCurrency,
-    ) -> Arbitrator {
-        assert_range_0_to_99(random_value).unwrap();
+        parties: &[Addr],
+    ) -> Result<Arbitrator, ContractError> {
+        assert_range_0_to_99(random_value)?;
         let storage = deps.storage;
         let result: Vec<Arbitrator> = arbitrators()
             .idx
             .fiat
             .prefix(fiat.to_string())
             .range(storage, None, None, Order::Descending)
-            .take(10)
             .flat_map(|item| item.and_then(|(_, arbitrator)| Ok(arbitrator)))
+            .filter(|arbitrator| !parties.contains(&arbitrator.arbitrator))
+            .take(10)
             .collect();
         let arbitrator_count = result.len();
+        if arbitrator_count == 0 {
+            return Err(ContractError::NoArbitratorAvailable { fiat });
+        }
+
+        let weights: Vec<u64> = result
+            .iter()
+            .map(|arbitrator| {
+                ARBITRATOR_STATS
+                    .may_load(storage, &arbitrator.arbitrator)
+                    .map(|stats| stats.unwrap_or_default().weight())
+            })
+            .collect::<StdResult<_>>()?;
 
         // Random range: 0..99
-        // Mapped range: 0..result.len()-1
+        // Mapped range: 0..total_weight-1
         // Formula is:
         // RandomValue * (MaxMappedRange + 1) / (MaxRandomRange + 1)
-        let random_index = random_value * arbitrator_count / (99 + 1);
-        result[random_index].clone()
+        let total_weight: u64 = weights.iter().sum();
+        let mut target = random_value as u64 * total_weight / (99 + 1);
+        for (arbitrator, weight) in result.iter().zip(weights) {
+            if target < weight {
+                return Ok(arbitrator.clone());
+            }
+            target -= weight;
+        }
+        Ok(result[arbitrator_count - 1].clone())
     }
 }
 
@@ -643,3 +959,14 @@ pub fn calc_denom_fiat_price(offer_rate: Uint128, denom_fiat_price: Uint256) ->
         .unwrap_or_default(); //% 100
     offer_rate.checked_mul(denom_fiat_price).unwrap_or_default()
 }
+
+/// Value of `amount` at a price from `calc_denom_fiat_price`, in the unit of
+/// the hub's `trade_limit_min` and `trade_limit_max`.
+pub fn calc_fiat_amount(amount: Uint128, denom_fiat_price: Uint256) -> Uint256 {
+    let fiat_amount = (Uint256::from(amount) * denom_fiat_price)
+        .checked_div(Uint256::from_u128(100u128))
+        .unwrap_or(Uint256::zero());
+    fiat_amount
+        .checked_div(Uint256::from_u128(1_000_000u128))
+        .unwrap_or(Uint256::zero())
+}
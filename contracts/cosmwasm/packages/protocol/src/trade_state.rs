@@ -0,0 +1,126 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ContractError;
+use crate::trade::TradeState;
+
+/// Platform independent trade lifecycle. Every state of both the cosmwasm
+/// `TradeState` and the Solana `TradeStatus` has exactly one canonical state,
+/// so mirrored trades can be compared without a hand-maintained mapping.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CanonicalTradeState {
+    RequestCreated,
+    RequestCanceled,
+    RequestExpired,
+    RequestAccepted,
+    EscrowFunded,
+    /// Escrow is funded and a taker has committed to it. Solana funds the
+    /// escrow before the taker accepts, cosmwasm accepts before funding.
+    InProgress,
+    EscrowCanceled,
+    EscrowRefunded,
+    FiatDeposited,
+    EscrowReleased,
+    EscrowDisputed,
+    SettledForMaker,
+    SettledForTaker,
+}
+
+impl fmt::Display for CanonicalTradeState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Mirror of the Solana trade program's `TradeStatus`, in declaration order
+/// so the Borsh variant index matches the on-chain account layout.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SolanaTradeStatus {
+    Open,
+    InProgress,
+    Completed,
+    Cancelled,
+    Disputed,
+}
+
+impl From<TradeState> for CanonicalTradeState {
+    fn from(state: TradeState) -> Self {
+        match state {
+            TradeState::RequestCreated => CanonicalTradeState::RequestCreated,
+            TradeState::RequestCanceled => CanonicalTradeState::RequestCanceled,
+            TradeState::RequestExpired => CanonicalTradeState::RequestExpired,
+            TradeState::RequestAccepted => CanonicalTradeState::RequestAccepted,
+            TradeState::EscrowFunded => CanonicalTradeState::EscrowFunded,
+            TradeState::EscrowCanceled => CanonicalTradeState::EscrowCanceled,
+            TradeState::EscrowRefunded => CanonicalTradeState::EscrowRefunded,
+            TradeState::FiatDeposited => CanonicalTradeState::FiatDeposited,
+            TradeState::EscrowReleased => CanonicalTradeState::EscrowReleased,
+            TradeState::EscrowDisputed => CanonicalTradeState::EscrowDisputed,
+            TradeState::SettledForMaker => CanonicalTradeState::SettledForMaker,
+            TradeState::SettledForTaker => CanonicalTradeState::SettledForTaker,
+        }
+    }
+}
+
+impl TryFrom<CanonicalTradeState> for TradeState {
+    type Error = ContractError;
+
+    fn try_from(state: CanonicalTradeState) -> Result<Self, Self::Error> {
+        match state {
+            CanonicalTradeState::RequestCreated => Ok(TradeState::RequestCreated),
+            CanonicalTradeState::RequestCanceled => Ok(TradeState::RequestCanceled),
+            CanonicalTradeState::RequestExpired => Ok(TradeState::RequestExpired),
+            CanonicalTradeState::RequestAccepted => Ok(TradeState::RequestAccepted),
+            CanonicalTradeState::EscrowFunded => Ok(TradeState::EscrowFunded),
+            CanonicalTradeState::EscrowCanceled => Ok(TradeState::EscrowCanceled),
+            CanonicalTradeState::EscrowRefunded => Ok(TradeState::EscrowRefunded),
+            CanonicalTradeState::FiatDeposited => Ok(TradeState::FiatDeposited),
+            CanonicalTradeState::EscrowReleased => Ok(TradeState::EscrowReleased),
+            CanonicalTradeState::EscrowDisputed => Ok(TradeState::EscrowDisputed),
+            CanonicalTradeState::SettledForMaker => Ok(TradeState::SettledForMaker),
+            CanonicalTradeState::SettledForTaker => Ok(TradeState::SettledForTaker),
+            CanonicalTradeState::InProgress => Err(ContractError::UnmappedTradeState { state }),
+        }
+    }
+}
+
+impl From<SolanaTradeStatus> for CanonicalTradeState {
+    fn from(status: SolanaTradeStatus) -> Self {
+        match status {
+            SolanaTradeStatus::Open => CanonicalTradeState::EscrowFunded,
+            SolanaTradeStatus::InProgress => CanonicalTradeState::InProgress,
+            SolanaTradeStatus::Completed => CanonicalTradeState::EscrowReleased,
+            SolanaTradeStatus::Cancelled => CanonicalTradeState::EscrowRefunded,
+            SolanaTradeStatus::Disputed => CanonicalTradeState::EscrowDisputed,
+        }
+    }
+}
+
+impl TryFrom<CanonicalTradeState> for SolanaTradeStatus {
+    type Error = ContractError;
+
+    fn try_from(state: CanonicalTradeState) -> Result<Self, Self::Error> {
+        match state {
+            CanonicalTradeState::EscrowFunded => Ok(SolanaTradeStatus::Open),
+            CanonicalTradeState::InProgress => Ok(SolanaTradeStatus::InProgress),
+            CanonicalTradeState::EscrowReleased => Ok(SolanaTradeStatus::Completed),
+            CanonicalTradeState::EscrowRefunded => Ok(SolanaTradeStatus::Cancelled),
+            CanonicalTradeState::EscrowDisputed => Ok(SolanaTradeStatus::Disputed),
+            CanonicalTradeState::RequestCreated
+            | CanonicalTradeState::RequestCanceled
+            | CanonicalTradeState::RequestExpired
+            | CanonicalTradeState::RequestAccepted
+            | CanonicalTradeState::EscrowCanceled
+            | CanonicalTradeState::FiatDeposited
+            | CanonicalTradeState::SettledForMaker
+            | CanonicalTradeState::SettledForTaker => {
+                Err(ContractError::UnmappedTradeState { state })
+            }
+        }
+    }
+}
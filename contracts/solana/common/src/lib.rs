@@ -1,9 +1,27 @@
 use anchor_lang::prelude::*;
 use solana_program::{
+    hash::hash,
     instruction::{AccountMeta, Instruction},
-    program::invoke,
+    program::{invoke, invoke_signed},
 };
 
+/// Anchor instruction discriminator: the first 8 bytes of `sha256("global:<name>")`.
+pub fn instruction_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+/// Deserializes `info` as `T` if it is an initialized account owned by `T`'s
+/// program. Lets read-only instructions report on accounts that may not exist yet.
+pub fn load_if_initialized<T: AccountDeserialize + Owner>(info: &AccountInfo) -> Option<T> {
+    if info.owner != &T::owner() || info.data_is_empty() {
+        return None;
+    }
+    let data = info.try_borrow_data().ok()?;
+    T::try_deserialize(&mut &data[..]).ok()
+}
+
 pub fn verify_price<'info>(
     price_program: &AccountInfo<'info>,
     price_oracle: &AccountInfo<'info>,
@@ -85,3 +103,170 @@ pub fn verify_trade_completion<'info>(
 
     Ok(())
 }
+
+/// Reserves `amount` of an offer's remaining availability. `trade` must be the
+/// trade PDA, signing through `signer_seeds`.
+pub fn reserve_offer_amount<'info>(
+    offer_program: &AccountInfo<'info>,
+    offer: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
+    trade: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    invoke_offer_reservation(
+        "reserve_amount",
+        offer_program,
+        offer,
+        token_mint,
+        trade,
+        signer_seeds,
+        amount,
+    )
+}
+
+/// Returns `amount` previously reserved by `trade` to the offer's availability.
+pub fn release_offer_amount<'info>(
+    offer_program: &AccountInfo<'info>,
+    offer: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
+    trade: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    invoke_offer_reservation(
+        "release_amount",
+        offer_program,
+        offer,
+        token_mint,
+        trade,
+        signer_seeds,
+        amount,
+    )
+}
+
+/// Turns `amount` previously reserved by `trade` into a fill of the offer.
+pub fn consume_offer_amount<'info>(
+    offer_program: &AccountInfo<'info>,
+    offer: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
+    trade: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    invoke_offer_reservation(
+        "consume_amount",
+        offer_program,
+        offer,
+        token_mint,
+        trade,
+        signer_seeds,
+        amount,
+    )
+}
+
+/// Asks the offer program whether the taker of `seller`'s trade with `buyer`
+/// meets the offer's minimum taker reputation and the maker still meets its
+/// minimum completion rate. The offer's type decides which side took it.
+/// `buyer` must have signed the outer instruction.
+pub fn check_offer_taker<'info>(
+    offer_program: &AccountInfo<'info>,
+    offer: &AccountInfo<'info>,
+    seller: &Pubkey,
+    buyer: &AccountInfo<'info>,
+    taker_profile: &AccountInfo<'info>,
+    maker_profile: &AccountInfo<'info>,
+) -> Result<()> {
+    let offer_accounts = vec![
+        AccountMeta::new_readonly(offer.key(), false),
+        AccountMeta::new_readonly(buyer.key(), true),
+        AccountMeta::new_readonly(taker_profile.key(), false),
+        AccountMeta::new_readonly(maker_profile.key(), false),
+    ];
+    let mut data = instruction_discriminator("check_taker").to_vec();
+    seller.serialize(&mut data)?;
+
+    invoke(
+        &Instruction {
+            program_id: *offer_program.key,
+            accounts: offer_accounts,
+            data,
+        },
+        &[
+            offer.to_account_info(),
+            buyer.to_account_info(),
+            taker_profile.to_account_info(),
+            maker_profile.to_account_info(),
+            offer_program.to_account_info(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Asks the offer program whether `depositor` is the side of the offer that
+/// funds the escrow, and whether the offer is of `offer_type`. `depositor`
+/// must have signed the outer instruction.
+pub fn check_offer_depositor<'info, T: AnchorSerialize>(
+    offer_program: &AccountInfo<'info>,
+    offer: &AccountInfo<'info>,
+    depositor: &AccountInfo<'info>,
+    offer_type: T,
+) -> Result<()> {
+    let offer_accounts = vec![
+        AccountMeta::new_readonly(offer.key(), false),
+        AccountMeta::new_readonly(depositor.key(), true),
+    ];
+    let mut data = instruction_discriminator("check_depositor").to_vec();
+    offer_type.serialize(&mut data)?;
+
+    invoke(
+        &Instruction {
+            program_id: *offer_program.key,
+            accounts: offer_accounts,
+            data,
+        },
+        &[
+            offer.to_account_info(),
+            depositor.to_account_info(),
+            offer_program.to_account_info(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn invoke_offer_reservation<'info>(
+    instruction_name: &str,
+    offer_program: &AccountInfo<'info>,
+    offer: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
+    trade: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let offer_accounts = vec![
+        AccountMeta::new(offer.key(), false),
+        AccountMeta::new_readonly(token_mint.key(), false),
+        AccountMeta::new_readonly(trade.key(), true),
+    ];
+    let mut data = instruction_discriminator(instruction_name).to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+
+    invoke_signed(
+        &Instruction {
+            program_id: *offer_program.key,
+            accounts: offer_accounts,
+            data,
+        },
+        &[
+            offer.to_account_info(),
+            token_mint.to_account_info(),
+            trade.to_account_info(),
+            offer_program.to_account_info(),
+        ],
+        signer_seeds,
+    )?;
+
+    Ok(())
+}
@@ -1,10 +1,29 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use profile::{self, Profile as ProfileAccount};
 use trade::program::Trade as TradeProgram;
 use trade::{self, Trade};
 
 declare_id!("52CejgfZEeefMzvYqJ7RmcT4NzemCDZf4nsX3kywuw2B");
 
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const OFFER_SEED: &[u8] = b"offer";
+
+/// Offer PDA of `creator`: `["offer", creator]` under the offer program. A
+/// creator has one offer at a time.
+pub fn derive_offer(creator: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[OFFER_SEED, creator.as_ref()], &ID)
+}
+
+/// Every account address clients derive, re-exported from the program that
+/// owns it so off-chain code does not copy seed layouts.
+pub mod pda {
+    pub use crate::{derive_offer, OFFER_SEED};
+    pub use profile::{derive_profile, PROFILE_SEED};
+    pub use trade::{derive_trade, TRADE_SEED};
+}
+
 // Constants for account sizes
 pub const MINT_SIZE: usize = 82;
 pub const TOKEN_ACCOUNT_SIZE: usize = 165;
@@ -13,12 +32,42 @@ pub const TOKEN_ACCOUNT_SIZE: usize = 165;
 pub mod offer {
     use super::*;
 
+    /// Creates the config with the program's upgrade authority as admin, so
+    /// nobody can claim it first after a deploy.
+    pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.paused = false;
+        config.bump = ctx.bumps.config;
+
+        msg!("Offer config initialized");
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+        msg!("Offer program paused: {}", paused);
+        Ok(())
+    }
+
+    /// Read-only status for monitoring, returned through return data.
+    pub fn health(ctx: Context<Health>) -> Result<ProgramHealth> {
+        let config =
+            common::load_if_initialized::<OfferConfig>(&ctx.accounts.config.to_account_info());
+        Ok(ProgramHealth {
+            version: VERSION.to_string(),
+            initialized: config.is_some(),
+            paused: config.is_some_and(|config| config.paused),
+        })
+    }
+
     pub fn create_offer(
         ctx: Context<CreateOffer>,
         amount: u64,
         price_per_token: u64,
         min_amount: u64,
         max_amount: u64,
+        offer_type: OfferType,
     ) -> Result<()> {
         require!(amount > 0, OfferError::InvalidAmount);
         require!(price_per_token > 0, OfferError::InvalidPrice);
@@ -35,6 +84,7 @@ pub mod offer {
         offer.min_amount = min_amount;
         offer.max_amount = max_amount;
         offer.status = OfferStatus::Active;
+        offer.offer_type = offer_type;
         offer.created_at = Clock::get()?.unix_timestamp;
         offer.updated_at = Clock::get()?.unix_timestamp;
 
@@ -67,12 +117,98 @@ pub mod offer {
             offer.min_amount <= offer.max_amount && offer.max_amount <= offer.amount,
             OfferError::InvalidAmounts
         );
+        require!(
+            u128::from(offer.max_amount)
+                >= u128::from(offer.filled_amount) + u128::from(offer.reserved_amount),
+            OfferError::InvalidAmounts
+        );
 
         offer.updated_at = Clock::get()?.unix_timestamp;
         msg!("Offer updated successfully");
         Ok(())
     }
 
+    /// Sets how long the offer's current price stays takeable. Zero removes
+    /// the expiry; otherwise the maker has to keep refreshing it.
+    pub fn refresh_quote(ctx: Context<UpdateOffer>, quote_valid_until: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            quote_valid_until == 0 || quote_valid_until > now,
+            OfferError::InvalidQuoteExpiry
+        );
+
+        let offer = &mut ctx.accounts.offer;
+        offer.quote_valid_until = quote_valid_until;
+        offer.updated_at = now;
+        msg!("Offer quote valid until {}", quote_valid_until);
+        Ok(())
+    }
+
+    /// Sets the profile reputation a taker needs to accept trades against the
+    /// offer. Zero lets anyone take it.
+    pub fn set_min_taker_reputation(
+        ctx: Context<UpdateOffer>,
+        min_taker_reputation: u32,
+    ) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        offer.min_taker_reputation = min_taker_reputation;
+        offer.updated_at = Clock::get()?.unix_timestamp;
+        msg!("Offer minimum taker reputation: {}", min_taker_reputation);
+        Ok(())
+    }
+
+    /// Sets the completion rate, in basis points, the maker's profile must
+    /// keep for takers to accept trades against the offer. Zero turns it off.
+    pub fn set_min_maker_completion(
+        ctx: Context<UpdateOffer>,
+        min_maker_completion_bps: u16,
+    ) -> Result<()> {
+        require!(
+            min_maker_completion_bps <= 10_000,
+            OfferError::InvalidCompletionRate
+        );
+        let offer = &mut ctx.accounts.offer;
+        offer.min_maker_completion_bps = min_maker_completion_bps;
+        offer.updated_at = Clock::get()?.unix_timestamp;
+        msg!(
+            "Offer minimum maker completion rate: {} bps",
+            min_maker_completion_bps
+        );
+        Ok(())
+    }
+
+    /// Invoked by the trade program when the buyer accepts `seller`'s trade
+    /// against the offer. The taker is the buyer of a sell offer and the
+    /// seller of a buy offer. A taker without a profile counts as zero
+    /// reputation, a maker without one has no history to hold against them.
+    pub fn check_taker(ctx: Context<CheckTaker>, _seller: Pubkey) -> Result<()> {
+        let reputation = common::load_if_initialized::<ProfileAccount>(
+            &ctx.accounts.taker_profile.to_account_info(),
+        )
+        .map_or(0, |profile| profile.reputation_score);
+        require!(
+            ctx.accounts.offer.admits_taker(reputation),
+            OfferError::TakerReputationTooLow
+        );
+        let maker = common::load_if_initialized::<ProfileAccount>(
+            &ctx.accounts.maker_profile.to_account_info(),
+        );
+        require!(
+            ctx.accounts.offer.admits_maker(maker.as_ref()),
+            OfferError::MakerCompletionRateTooLow
+        );
+        Ok(())
+    }
+
+    /// Invoked by the trade program before a trade's escrow is funded. The
+    /// trade has to claim the offer's type, and the depositor has to be the
+    /// side of the offer that sells the tokens.
+    pub fn check_depositor(ctx: Context<CheckDepositor>, offer_type: OfferType) -> Result<()> {
+        ctx.accounts
+            .offer
+            .check_depositor(offer_type, &ctx.accounts.depositor.key())
+    }
+
     pub fn pause_offer(ctx: Context<OfferStatusUpdate>) -> Result<()> {
         let offer = &mut ctx.accounts.offer;
         require!(
@@ -127,6 +263,12 @@ pub mod offer {
             amount <= ctx.accounts.offer.amount,
             OfferError::InsufficientAmount
         );
+        require!(
+            ctx.accounts
+                .offer
+                .is_quote_fresh(Clock::get()?.unix_timestamp),
+            OfferError::QuoteExpired
+        );
 
         // Calculate total price
         let _total_price = amount
@@ -146,17 +288,14 @@ pub mod offer {
                 &[ctx.bumps.trade],
             ]];
 
-            // Transfer tokens to escrow using CpiContext
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.seller_token_account.to_account_info(),
-                        to: ctx.accounts.escrow_account.to_account_info(),
-                        authority: ctx.accounts.trade.to_account_info(),
-                    },
-                    seeds,
-                ),
+            // Transfer tokens to escrow through whichever token program owns the mint
+            trade::transfer_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.seller_token_account,
+                &ctx.accounts.escrow_account,
+                &ctx.accounts.token_mint,
+                &ctx.accounts.trade.to_account_info(),
+                seeds,
                 transfer_amount,
             )?;
         }
@@ -169,6 +308,107 @@ pub mod offer {
         msg!("Offer taken successfully for {} tokens", amount);
         Ok(())
     }
+
+    pub fn reserve_amount(ctx: Context<UpdateReservation>, amount: u64) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        require!(
+            offer.status == OfferStatus::Active,
+            OfferError::InvalidStatus
+        );
+        require!(
+            amount > 0 && amount >= offer.min_amount,
+            OfferError::InvalidAmount
+        );
+        require!(
+            amount <= offer.available_amount(),
+            OfferError::InsufficientAmount
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(offer.is_quote_fresh(now), OfferError::QuoteExpired);
+
+        offer.reserved_amount = offer
+            .reserved_amount
+            .checked_add(amount)
+            .ok_or(OfferError::CalculationError)?;
+        offer.updated_at = now;
+
+        msg!(
+            "Reserved {} tokens, {} remaining",
+            amount,
+            offer.available_amount()
+        );
+        Ok(())
+    }
+
+    /// Gives `amount` reserved by a trade that didn't go through back to the
+    /// offer's availability.
+    pub fn release_amount(ctx: Context<UpdateReservation>, amount: u64) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        offer.take_reservation(amount)?;
+        offer.updated_at = Clock::get()?.unix_timestamp;
+
+        msg!(
+            "Released {} tokens, {} remaining",
+            amount,
+            offer.available_amount()
+        );
+        Ok(())
+    }
+
+    /// Turns `amount` reserved by a trade that settled to the buyer into a
+    /// fill.
+    pub fn consume_amount(ctx: Context<UpdateReservation>, amount: u64) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        offer.take_reservation(amount)?;
+        offer.filled_amount = offer
+            .filled_amount
+            .checked_add(amount)
+            .ok_or(OfferError::CalculationError)?;
+        offer.updated_at = Clock::get()?.unix_timestamp;
+
+        msg!(
+            "Filled {} tokens, {} remaining",
+            amount,
+            offer.available_amount()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = OfferConfig::LEN,
+        seeds = [b"offer_config"],
+        bump
+    )]
+    pub config: Account<'info, OfferConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Offer>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ OfferError::UnauthorizedInitializer
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"offer_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, OfferConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Health<'info> {
+    /// CHECK: may not be initialized yet, which is part of what is reported
+    #[account(seeds = [b"offer_config"], bump)]
+    pub config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
@@ -177,13 +417,19 @@ pub struct CreateOffer<'info> {
         init,
         payer = creator,
         space = Offer::LEN,
-        seeds = [b"offer".as_ref(), creator.key().as_ref()],
+        seeds = [OFFER_SEED, creator.key().as_ref()],
         bump
     )]
     pub offer: Account<'info, Offer>,
+    #[account(
+        seeds = [b"offer_config"],
+        bump = config.bump,
+        constraint = !config.paused @ OfferError::ProgramPaused
+    )]
+    pub config: Account<'info, OfferConfig>,
     #[account(mut)]
     pub creator: Signer<'info>,
-    pub token_mint: Account<'info, token::Mint>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
@@ -192,7 +438,7 @@ pub struct CreateOffer<'info> {
 pub struct UpdateOffer<'info> {
     #[account(
         mut,
-        seeds = [b"offer".as_ref(), creator.key().as_ref()],
+        seeds = [OFFER_SEED, creator.key().as_ref()],
         bump,
         has_one = creator
     )]
@@ -204,7 +450,7 @@ pub struct UpdateOffer<'info> {
 pub struct OfferStatusUpdate<'info> {
     #[account(
         mut,
-        seeds = [b"offer".as_ref(), creator.key().as_ref()],
+        seeds = [OFFER_SEED, creator.key().as_ref()],
         bump,
         has_one = creator
     )]
@@ -219,7 +465,7 @@ pub struct TakeOffer<'info> {
         has_one = creator,
         has_one = token_mint,
         constraint = offer.status == OfferStatus::Active,
-        seeds = [b"offer", creator.key().as_ref()],
+        seeds = [OFFER_SEED, creator.key().as_ref()],
         bump
     )]
     pub offer: Account<'info, Offer>,
@@ -227,7 +473,7 @@ pub struct TakeOffer<'info> {
     #[account(mut)]
     pub creator: Signer<'info>,
 
-    pub token_mint: Account<'info, token::Mint>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
@@ -235,7 +481,7 @@ pub struct TakeOffer<'info> {
         constraint = seller_token_account.owner == creator.key(),
         token::mint = token_mint
     )]
-    pub seller_token_account: Account<'info, token::TokenAccount>,
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
@@ -244,10 +490,10 @@ pub struct TakeOffer<'info> {
         token::mint = token_mint,
         token::authority = trade
     )]
-    pub escrow_account: Account<'info, token::TokenAccount>,
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        seeds = [b"trade", creator.key().as_ref(), token_mint.key().as_ref()],
+        seeds = [trade::TRADE_SEED, creator.key().as_ref(), token_mint.key().as_ref()],
         bump,
         seeds::program = trade_program.key()
     )]
@@ -256,10 +502,65 @@ pub struct TakeOffer<'info> {
     #[account(mut)]
     pub buyer: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub trade_program: Program<'info, TradeProgram>,
 }
 
+#[derive(Accounts)]
+#[instruction(seller: Pubkey)]
+pub struct CheckTaker<'info> {
+    pub offer: Account<'info, Offer>,
+    pub buyer: Signer<'info>,
+    /// CHECK: the taker's profile PDA, read as zero reputation if not created yet
+    #[account(
+        address = profile::derive_profile(offer.taker(&seller, &buyer.key())).0
+            @ OfferError::InvalidTakerProfile
+    )]
+    pub taker_profile: UncheckedAccount<'info>,
+    /// CHECK: the offer creator's profile PDA, which may not be created yet
+    #[account(address = profile::derive_profile(&offer.creator).0 @ OfferError::InvalidMakerProfile)]
+    pub maker_profile: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CheckDepositor<'info> {
+    pub offer: Account<'info, Offer>,
+    pub depositor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateReservation<'info> {
+    #[account(mut, has_one = token_mint)]
+    pub offer: Account<'info, Offer>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: trade PDA signing via `invoke_signed`; only the trade program can
+    /// produce a signature for an account it owns.
+    #[account(signer, owner = trade::ID @ OfferError::InvalidTradeAuthority)]
+    pub trade: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ProgramHealth {
+    pub version: String,
+    pub initialized: bool,
+    pub paused: bool,
+}
+
+#[account]
+pub struct OfferConfig {
+    pub admin: Pubkey,
+    pub paused: bool,
+    pub bump: u8,
+}
+
+impl OfferConfig {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // admin
+        1 + // paused
+        1 + // bump
+        64; // padding for future updates
+}
+
 #[account]
 #[derive(Default)]
 pub struct Offer {
@@ -272,6 +573,20 @@ pub struct Offer {
     pub status: OfferStatus,
     pub created_at: i64,
     pub updated_at: i64,
+    pub filled_amount: u64,
+    /// Unix timestamp after which the price can no longer be taken, 0 if the
+    /// quote never goes stale.
+    pub quote_valid_until: i64,
+    /// Profile reputation a taker needs to accept a trade, 0 for anyone.
+    pub min_taker_reputation: u32,
+    /// Completion rate in basis points the maker's profile must keep for
+    /// trades to be accepted, 0 for no minimum.
+    pub min_maker_completion_bps: u16,
+    /// Which side of the trade the creator takes, and so who funds the escrow.
+    pub offer_type: OfferType,
+    /// Amount held by trades that are still open or in progress. Settled
+    /// trades move theirs into `filled_amount` or give it back.
+    pub reserved_amount: u64,
 }
 
 impl Offer {
@@ -286,7 +601,81 @@ impl Offer {
         6 +      // padding for alignment
         8 +      // created_at
         8 +      // updated_at
-        256; // padding for future updates
+        8 +      // filled_amount
+        8 +      // quote_valid_until
+        4 +      // min_taker_reputation
+        2 +      // min_maker_completion_bps
+        1 +      // offer_type
+        8 +      // reserved_amount
+        241; // padding for future updates
+
+    /// Amount still open for new trades once fills and active reservations
+    /// are counted.
+    pub fn available_amount(&self) -> u64 {
+        self.max_amount
+            .saturating_sub(self.filled_amount)
+            .saturating_sub(self.reserved_amount)
+    }
+
+    /// Takes `amount` a trade reserved off the active reservations. Trades
+    /// reserved before `reserved_amount` existed were counted in
+    /// `filled_amount`, so whatever `reserved_amount` can't cover comes from
+    /// there.
+    pub fn take_reservation(&mut self, amount: u64) -> Result<()> {
+        let reserved = amount.min(self.reserved_amount);
+        self.reserved_amount -= reserved;
+        self.filled_amount = self
+            .filled_amount
+            .checked_sub(amount - reserved)
+            .ok_or(OfferError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn is_quote_fresh(&self, now: i64) -> bool {
+        self.quote_valid_until == 0 || now <= self.quote_valid_until
+    }
+
+    pub fn admits_taker(&self, reputation: u32) -> bool {
+        reputation >= self.min_taker_reputation
+    }
+
+    pub fn admits_maker(&self, maker: Option<&ProfileAccount>) -> bool {
+        match maker {
+            Some(profile) => profile.meets_completion_rate(self.min_maker_completion_bps),
+            None => true,
+        }
+    }
+
+    /// The side of a trade opposite the offer's creator: the buyer of a sell
+    /// offer and the seller of a buy offer.
+    pub fn taker<'a>(&self, seller: &'a Pubkey, buyer: &'a Pubkey) -> &'a Pubkey {
+        match self.offer_type {
+            OfferType::Sell => buyer,
+            OfferType::Buy => seller,
+        }
+    }
+
+    /// The seller funds the escrow: the creator of a sell offer, and anyone
+    /// but the creator of a buy offer.
+    pub fn check_depositor(&self, offer_type: OfferType, depositor: &Pubkey) -> Result<()> {
+        require!(offer_type == self.offer_type, OfferError::OfferTypeMismatch);
+        let creator_deposits = *depositor == self.creator;
+        require!(
+            creator_deposits == (self.offer_type == OfferType::Sell),
+            OfferError::WrongEscrowDepositor
+        );
+        Ok(())
+    }
+}
+
+/// A sell offer's creator sells tokens to takers and funds each escrow, a buy
+/// offer's creator buys from takers who fund it instead. Offers created
+/// before the type existed read as `Sell`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum OfferType {
+    #[default]
+    Sell,
+    Buy,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug, Default)]
@@ -311,6 +700,30 @@ pub enum OfferError {
     InvalidPrice,
     #[msg("Insufficient amount available")]
     InsufficientAmount,
+    #[msg("Reservation must be signed by a trade account")]
+    InvalidTradeAuthority,
+    #[msg("Offer program is paused")]
+    ProgramPaused,
+    #[msg("Offer quote has expired, the maker must refresh it")]
+    QuoteExpired,
+    #[msg("Quote expiry must be in the future")]
+    InvalidQuoteExpiry,
+    #[msg("Taker reputation is below the offer's minimum")]
+    TakerReputationTooLow,
+    #[msg("Profile account does not belong to the taker")]
+    InvalidTakerProfile,
+    #[msg("Completion rate must be at most 10000 basis points")]
+    InvalidCompletionRate,
+    #[msg("Maker's completion rate is below the offer's minimum")]
+    MakerCompletionRateTooLow,
+    #[msg("Profile account does not belong to the offer creator")]
+    InvalidMakerProfile,
+    #[msg("Offer type does not match the offer")]
+    OfferTypeMismatch,
+    #[msg("Escrow must be funded by the selling side of the offer")]
+    WrongEscrowDepositor,
+    #[msg("Only the program's upgrade authority can initialize the config")]
+    UnauthorizedInitializer,
 }
 
 #[cfg(test)]
@@ -328,6 +741,107 @@ mod tests {
 
     const TRADE_PROGRAM_ID: &str = "8c2oLSoAo2FG2HpyvhfNghRTzpQRV4k3wR7jWPA4rHpH";
 
+    #[test]
+    fn pda_helpers_match_account_seeds() {
+        let creator = Pubkey::new_unique();
+        let token_mint = Pubkey::new_unique();
+
+        let (offer, bump) = pda::derive_offer(&creator);
+        let seeds: &[&[u8]] = &[b"offer", creator.as_ref(), &[bump]];
+        assert_eq!(Pubkey::create_program_address(seeds, &ID).unwrap(), offer);
+
+        let (trade, bump) = pda::derive_trade(&creator, &token_mint);
+        let seeds: &[&[u8]] = &[b"trade", creator.as_ref(), token_mint.as_ref(), &[bump]];
+        assert_eq!(
+            Pubkey::create_program_address(seeds, &trade::ID).unwrap(),
+            trade
+        );
+    }
+
+    fn offer_of(creator: Pubkey, offer_type: OfferType) -> Offer {
+        Offer {
+            creator,
+            token_mint: Pubkey::new_unique(),
+            amount: 1_000_000,
+            price_per_token: 100_000,
+            min_amount: 100_000,
+            max_amount: 1_000_000,
+            status: OfferStatus::Active,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_000,
+            filled_amount: 0,
+            quote_valid_until: 0,
+            min_taker_reputation: 0,
+            min_maker_completion_bps: 0,
+            offer_type,
+            reserved_amount: 0,
+        }
+    }
+
+    #[test]
+    fn reservations_are_released_or_filled() {
+        let mut offer = offer_of(Pubkey::new_unique(), OfferType::Sell);
+        offer.reserved_amount = 300_000;
+        assert_eq!(offer.available_amount(), 700_000);
+
+        // Consumed by a completed trade
+        offer.take_reservation(200_000).unwrap();
+        offer.filled_amount += 200_000;
+        assert_eq!(
+            (offer.reserved_amount, offer.filled_amount),
+            (100_000, 200_000)
+        );
+        assert_eq!(offer.available_amount(), 700_000);
+
+        // Released by a cancelled one
+        offer.take_reservation(100_000).unwrap();
+        assert_eq!((offer.reserved_amount, offer.filled_amount), (0, 200_000));
+        assert_eq!(offer.available_amount(), 800_000);
+
+        // A trade reserved before the split was counted as filled
+        offer.take_reservation(150_000).unwrap();
+        assert_eq!(offer.filled_amount, 50_000);
+        assert_eq!(
+            offer.take_reservation(50_001).unwrap_err(),
+            OfferError::CalculationError.into()
+        );
+    }
+
+    #[test]
+    fn only_the_selling_side_funds_the_escrow() {
+        let (creator, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let sell = offer_of(creator, OfferType::Sell);
+        assert!(sell.check_depositor(OfferType::Sell, &creator).is_ok());
+        assert_eq!(
+            sell.check_depositor(OfferType::Sell, &taker).unwrap_err(),
+            OfferError::WrongEscrowDepositor.into()
+        );
+
+        let buy = offer_of(creator, OfferType::Buy);
+        assert!(buy.check_depositor(OfferType::Buy, &taker).is_ok());
+        assert_eq!(
+            buy.check_depositor(OfferType::Buy, &creator).unwrap_err(),
+            OfferError::WrongEscrowDepositor.into()
+        );
+        assert_eq!(
+            buy.check_depositor(OfferType::Sell, &taker).unwrap_err(),
+            OfferError::OfferTypeMismatch.into()
+        );
+    }
+
+    #[test]
+    fn the_taker_depends_on_the_offer_type() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let sell = offer_of(seller, OfferType::Sell);
+        assert_eq!(sell.taker(&seller, &buyer), &buyer);
+
+        // The creator of a buy offer is the buyer, sellers take it
+        let buy = offer_of(buyer, OfferType::Buy);
+        assert_eq!(buy.taker(&seller, &buyer), &seller);
+    }
+
     #[tokio::test]
     async fn test_offer_flow() {
         // Initialize program test environment
@@ -447,6 +961,7 @@ mod tests {
                 data.extend_from_slice(&1_000u64.to_le_bytes()); // price_per_token
                 data.extend_from_slice(&100_000u64.to_le_bytes()); // min_amount
                 data.extend_from_slice(&1_000_000u64.to_le_bytes()); // max_amount
+                data.push(0); // offer_type: Sell
                 data
             },
         };
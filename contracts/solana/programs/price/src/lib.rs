@@ -1,8 +1,28 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
 use solana_program::msg;
 
 declare_id!("8uzArQW1YiLwh2CLQhMU1Ya774EMEbdbpgux6Tf8z1rn");
 
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const MAX_PRICES: usize = 10;
+pub const DEFAULT_MAX_STALENESS_SECONDS: i64 = 3600;
+pub const MAX_PROVIDERS: usize = 5;
+pub const MAX_ROUTE_HOPS: usize = 3;
+pub const MAX_DENOM_LEN: usize = 44;
+pub const MAX_CURRENCY_LEN: usize = 32;
+/// How far ahead of the cluster clock a submitted `updated_at` may be.
+pub const MAX_CLOCK_DRIFT_SECONDS: i64 = 30;
+/// Prices are fixed point with 5 decimals, matching `CurrencyPrice::usd_price`.
+pub const PRICE_SCALE: u128 = 100_000;
+/// Widest confidence interval, in basis points of the price, that trades are
+/// still verified against.
+pub const MAX_CONFIDENCE_BPS: u64 = 500;
+/// Largest move from the stored price, in basis points, that new oracles
+/// accept in one update.
+pub const DEFAULT_MAX_DEVIATION_BPS: u16 = 2_000;
+
 #[program]
 pub mod price {
     use super::*;
@@ -10,36 +30,257 @@ pub mod price {
     pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
         let state = &mut ctx.accounts.state;
         state.admin = ctx.accounts.admin.key();
-        state.price_provider = ctx.accounts.admin.key(); // Initially set to admin
         state.is_initialized = true;
         state.prices = Vec::new();
+        state.max_staleness_seconds = DEFAULT_MAX_STALENESS_SECONDS;
+        state.providers = Vec::new();
+        state.paused = false;
+        state.max_deviation_bps = DEFAULT_MAX_DEVIATION_BPS;
 
         msg!("Price oracle initialized successfully");
         Ok(())
     }
 
-    pub fn update_prices(ctx: Context<UpdatePrices>, prices: Vec<CurrencyPrice>) -> Result<()> {
-        let oracle = &mut ctx.accounts.oracle;
+    pub fn add_provider(ctx: Context<AddProvider>, provider: Pubkey) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(
+            !state.providers.contains(&provider),
+            PriceError::ProviderAlreadyRegistered
+        );
+        require!(
+            state.providers.len() < MAX_PROVIDERS,
+            PriceError::TooManyProviders
+        );
+        state.providers.push(provider);
+
+        let submission = &mut ctx.accounts.submission;
+        submission.state = state.key();
+        submission.provider = provider;
+        submission.prices = Vec::new();
+        submission.bump = ctx.bumps.submission;
+
+        msg!("Price provider {} registered", provider);
+        Ok(())
+    }
 
-        // Validate the price provider
+    pub fn remove_provider(ctx: Context<UpdateConfig>, provider: Pubkey) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        let index = state
+            .providers
+            .iter()
+            .position(|p| *p == provider)
+            .ok_or(PriceError::ProviderNotFound)?;
+        state.providers.remove(index);
+
+        msg!("Price provider {} removed", provider);
+        Ok(())
+    }
+
+    /// Applies every valid entry to the provider's submission and skips the
+    /// rest, returning one result per entry in input order through return
+    /// data. A price that moved further than `max_deviation_bps` from the
+    /// oracle's stored one is quarantined as `DeviationExceeded` until the
+    /// admin forces it through `force_price`.
+    pub fn submit_prices(
+        ctx: Context<SubmitPrices>,
+        prices: Vec<PriceUpdate>,
+    ) -> Result<Vec<PriceUpdateResult>> {
+        let state = &ctx.accounts.state;
         require!(
-            ctx.accounts.price_provider.key() == oracle.price_provider,
+            state.providers.contains(&ctx.accounts.provider.key()),
             PriceError::InvalidPriceProvider
         );
 
-        // Update all prices
-        oracle.prices = prices.clone();
+        let submission: &mut ProviderPrices = &mut ctx.accounts.submission;
+        let now = Clock::get()?.unix_timestamp;
+        check_price_batch(&prices, now)?;
+        let mut results = Vec::with_capacity(prices.len());
+        for price in prices.iter() {
+            let result = match check_price(&submission.prices, price, 0) {
+                PriceUpdateResult::Applied
+                    if check_price(&state.prices, price, state.max_deviation_bps)
+                        == PriceUpdateResult::DeviationExceeded =>
+                {
+                    PriceUpdateResult::DeviationExceeded
+                }
+                result => result,
+            };
+            if result == PriceUpdateResult::Applied {
+                upsert_price(
+                    &mut submission.prices,
+                    &mut submission.confidences,
+                    &price.currency,
+                    price.usd_price,
+                    price.confidence,
+                    now,
+                )?;
+            }
+            results.push(result);
+        }
+
+        let applied = results
+            .iter()
+            .filter(|result| **result == PriceUpdateResult::Applied)
+            .count();
+        msg!("Provider submitted {} of {} prices", applied, prices.len());
+        Ok(results)
+    }
+
+    /// Writes the median of all fresh provider submissions for `currency` to
+    /// the oracle. Provider submission accounts are passed as remaining accounts,
+    /// and more than half of the registered providers must have a fresh price
+    /// among them.
+    pub fn aggregate_prices<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AggregatePrices<'info>>,
+        currency: String,
+    ) -> Result<()> {
+        let state_key = ctx.accounts.state.key();
+        let state: &mut PriceState = &mut ctx.accounts.state;
+        let now = Clock::get()?.unix_timestamp;
 
-        msg!("Updated {} prices in the oracle", prices.len());
+        let mut seen: Vec<Pubkey> = Vec::new();
+        let mut fresh_prices: Vec<u64> = Vec::new();
+        let mut fresh_confidences: Vec<u64> = Vec::new();
+        for account_info in ctx.remaining_accounts.iter() {
+            let submission: Account<ProviderPrices> = Account::try_from(account_info)?;
+            require!(
+                submission.state == state_key
+                    && state.providers.contains(&submission.provider)
+                    && !seen.contains(&submission.provider),
+                PriceError::InvalidProviderSubmission
+            );
+            seen.push(submission.provider);
+
+            if let Some(index) = submission
+                .prices
+                .iter()
+                .position(|p| p.currency == currency)
+            {
+                let price = &submission.prices[index];
+                if now.saturating_sub(price.updated_at) <= state.staleness_limit() {
+                    fresh_prices.push(price.usd_price);
+                    fresh_confidences.push(price_confidence(&submission.confidences, index));
+                }
+            }
+        }
+
+        require!(
+            fresh_prices.len() * 2 > state.providers.len(),
+            PriceError::QuorumNotMet
+        );
+        let median = median_price(&mut fresh_prices).ok_or(PriceError::NoFreshPrices)?;
+        let confidence = median_price(&mut fresh_confidences).unwrap_or_default();
+        if let Some(stored) = state.prices.iter().find(|p| p.currency == currency) {
+            require!(
+                !exceeds_deviation(stored.usd_price, median, state.max_deviation_bps),
+                PriceError::DeviationExceeded
+            );
+        }
+        upsert_price(
+            &mut state.prices,
+            &mut state.confidences,
+            &currency,
+            median,
+            confidence,
+            now,
+        )?;
+
+        msg!(
+            "Aggregated {} price {} from {} providers",
+            currency,
+            median,
+            fresh_prices.len()
+        );
+        Ok(())
+    }
+
+    pub fn set_max_staleness(ctx: Context<UpdateConfig>, max_staleness_seconds: i64) -> Result<()> {
+        require!(max_staleness_seconds > 0, PriceError::InvalidStaleness);
+        ctx.accounts.state.max_staleness_seconds = max_staleness_seconds;
+
+        msg!("Max price staleness set to {}s", max_staleness_seconds);
         Ok(())
     }
 
+    /// Sets how far, in basis points, a price may move from the stored one in
+    /// a single update. Zero turns the circuit breaker off.
+    pub fn set_max_deviation(ctx: Context<UpdateConfig>, max_deviation_bps: u16) -> Result<()> {
+        ctx.accounts.state.max_deviation_bps = max_deviation_bps;
+
+        msg!("Max price deviation set to {}bps", max_deviation_bps);
+        Ok(())
+    }
+
+    /// Stores `price` even if it moved past `max_deviation_bps`, for the
+    /// admin to accept a legitimate large move the breaker quarantined.
+    pub fn force_price(ctx: Context<UpdateConfig>, price: PriceUpdate) -> Result<()> {
+        let state: &mut PriceState = &mut ctx.accounts.state;
+        let now = Clock::get()?.unix_timestamp;
+        check_price_batch(std::slice::from_ref(&price), now)?;
+        require!(
+            check_price(&state.prices, &price, 0) == PriceUpdateResult::Applied,
+            PriceError::InvalidForcedPrice
+        );
+        upsert_price(
+            &mut state.prices,
+            &mut state.confidences,
+            &price.currency,
+            price.usd_price,
+            price.confidence,
+            now,
+        )?;
+
+        msg!("Forced {} price {}", price.currency, price.usd_price);
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.state.paused = paused;
+        msg!("Price oracle paused: {}", paused);
+        Ok(())
+    }
+
+    /// Read-only status of an oracle state account, returned through return data.
+    pub fn health(ctx: Context<Health>) -> Result<ProgramHealth> {
+        let state = common::load_if_initialized::<PriceState>(&ctx.accounts.state)
+            .filter(|state| state.is_initialized);
+        Ok(ProgramHealth {
+            version: VERSION.to_string(),
+            initialized: state.is_some(),
+            paused: state.is_some_and(|state| state.paused),
+        })
+    }
+
+    /// Every stored price with its age against the cluster clock, stalest
+    /// first, returned through return data so keepers know what to refresh.
+    pub fn get_price_freshness(ctx: Context<GetPriceFreshness>) -> Result<Vec<PriceFreshness>> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(price_freshness(&ctx.accounts.oracle.prices, now))
+    }
+
     pub fn register_price_route(
         ctx: Context<RegisterPriceRoute>,
         denom: String,
         route: Vec<PriceRoute>,
     ) -> Result<()> {
+        require!(
+            denom.len() <= MAX_DENOM_LEN
+                && !route.is_empty()
+                && route.len() <= MAX_ROUTE_HOPS
+                && route.iter().all(|hop| {
+                    hop.offer_asset.len() <= MAX_DENOM_LEN
+                        && hop.ask_asset.len() <= MAX_DENOM_LEN
+                        && hop.offer_asset != hop.ask_asset
+                        && hop.offer_reserve != hop.ask_reserve
+                })
+                && route
+                    .windows(2)
+                    .all(|hops| hops[0].ask_asset == hops[1].offer_asset),
+            PriceError::InvalidPriceRoute
+        );
+
         let route_data = &mut ctx.accounts.route_data;
+        route_data.state = ctx.accounts.state.key();
         route_data.denom = denom;
         route_data.route = route;
 
@@ -47,36 +288,78 @@ pub mod price {
         Ok(())
     }
 
+    /// The route registered for `denom` in `route_data`, returned through
+    /// return data so keepers can rebuild the routing graph. There is no
+    /// on-chain listing: an oracle's routes are found with `getProgramAccounts`
+    /// on the `PriceRouteData` discriminator and a memcmp on its `state` key,
+    /// stored right after the discriminator.
+    pub fn get_price_route(ctx: Context<GetPriceRoute>, denom: String) -> Result<Vec<PriceRoute>> {
+        let route_data = &ctx.accounts.route_data;
+        require!(route_data.denom == denom, PriceError::InvalidPriceRoute);
+        Ok(route_data.route.clone())
+    }
+
+    /// Walks the registered route for `denom` and returns the composed price.
+    /// Each hop expects its registered `offer_reserve` followed by its
+    /// `ask_reserve` in the remaining accounts, holding the hop's assets and
+    /// owned by the pool.
+    pub fn resolve_price<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ResolvePrice<'info>>,
+        denom: String,
+    ) -> Result<u64> {
+        let route_data = &ctx.accounts.route_data;
+        require!(route_data.denom == denom, PriceError::InvalidPriceRoute);
+        require!(
+            ctx.remaining_accounts.len() == route_data.route.len() * 2,
+            PriceError::InvalidPriceRoute
+        );
+
+        let mut price = PRICE_SCALE;
+        for (hop, reserves) in route_data
+            .route
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(2))
+        {
+            require!(
+                reserves[0].key() == hop.offer_reserve && reserves[1].key() == hop.ask_reserve,
+                PriceError::InvalidPriceRoute
+            );
+            let offer_reserve: Account<TokenAccount> = Account::try_from(&reserves[0])?;
+            let ask_reserve: Account<TokenAccount> = Account::try_from(&reserves[1])?;
+            require!(
+                offer_reserve.owner == hop.pool
+                    && ask_reserve.owner == hop.pool
+                    && offer_reserve.mint.to_string() == hop.offer_asset
+                    && ask_reserve.mint.to_string() == hop.ask_asset,
+                PriceError::InvalidPriceRoute
+            );
+            require!(offer_reserve.amount > 0, PriceError::InvalidPriceRoute);
+
+            price = price
+                .checked_mul(ask_reserve.amount as u128)
+                .ok_or(PriceError::PriceOverflow)?
+                / offer_reserve.amount as u128;
+        }
+
+        let price = u64::try_from(price).map_err(|_| PriceError::PriceOverflow)?;
+        msg!("Resolved {} price {}", denom, price);
+        Ok(price)
+    }
+
     pub fn verify_price_for_trade(
         ctx: Context<VerifyPrice>,
         trade_price: u64,
         currency: String,
         tolerance_bps: u16, // Basis points (1/10000) of allowed deviation
     ) -> Result<()> {
-        let oracle = &ctx.accounts.oracle;
-        require!(oracle.is_initialized, PriceError::NotInitialized);
-
-        // Find the reference price for the given currency
-        let reference_price = oracle
-            .prices
-            .iter()
-            .find(|p| p.currency == currency)
-            .ok_or(PriceError::PriceNotFound)?;
-
-        // Calculate allowed deviation range
-        let tolerance = (reference_price.usd_price as u128)
-            .checked_mul(tolerance_bps as u128)
-            .unwrap_or(0)
-            .checked_div(10000)
-            .unwrap_or(0) as u64;
-
-        let min_allowed = reference_price.usd_price.saturating_sub(tolerance);
-        let max_allowed = reference_price.usd_price.saturating_add(tolerance);
-
-        require!(
-            trade_price >= min_allowed && trade_price <= max_allowed,
-            PriceError::PriceOutOfRange
-        );
+        let now = Clock::get()?.unix_timestamp;
+        check_trade_price(
+            &ctx.accounts.oracle,
+            trade_price,
+            &currency,
+            tolerance_bps,
+            now,
+        )?;
 
         msg!(
             "Price verified successfully within {}bps tolerance",
@@ -96,7 +379,12 @@ pub struct Initialize<'info> {
             32 + // admin
             32 + // price_provider
             4 + // vec length
-            10 * (4 + 32 + 8 + 8) // space for 10 prices (string length + string + price + timestamp)
+            MAX_PRICES * (4 + 32 + 8 + 8) + // space for 10 prices (string length + string + price + timestamp)
+            8 + // max_staleness_seconds
+            4 + MAX_PROVIDERS * 32 + // providers
+            1 + // paused
+            2 + // max_deviation_bps
+            4 + MAX_PRICES * 8 // confidences
     )]
     pub state: Account<'info, PriceState>,
     #[account(mut)]
@@ -105,18 +393,64 @@ pub struct Initialize<'info> {
 }
 
 #[derive(Accounts)]
-pub struct UpdatePrices<'info> {
+pub struct UpdateConfig<'info> {
+    #[account(mut, has_one = admin)]
+    pub state: Account<'info, PriceState>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(provider: Pubkey)]
+pub struct AddProvider<'info> {
+    #[account(mut, has_one = admin)]
+    pub state: Account<'info, PriceState>,
+    #[account(
+        init,
+        payer = admin,
+        space = ProviderPrices::LEN,
+        seeds = [b"provider_prices", state.key().as_ref(), provider.as_ref()],
+        bump
+    )]
+    pub submission: Account<'info, ProviderPrices>,
     #[account(mut)]
-    pub oracle: Account<'info, PriceState>,
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitPrices<'info> {
+    #[account(constraint = !state.paused @ PriceError::OraclePaused)]
+    pub state: Account<'info, PriceState>,
     #[account(
-        constraint = price_provider.key() == oracle.price_provider @ PriceError::InvalidPriceProvider
+        mut,
+        seeds = [b"provider_prices", state.key().as_ref(), provider.key().as_ref()],
+        bump = submission.bump,
+        has_one = provider
     )]
-    pub price_provider: Signer<'info>,
+    pub submission: Account<'info, ProviderPrices>,
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AggregatePrices<'info> {
+    #[account(mut, constraint = !state.paused @ PriceError::OraclePaused)]
+    pub state: Account<'info, PriceState>,
+}
+
+#[derive(Accounts)]
+pub struct Health<'info> {
+    /// CHECK: may not be an initialized oracle, which is part of what is reported
+    pub state: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetPriceFreshness<'info> {
+    pub oracle: Account<'info, PriceState>,
 }
 
 #[derive(Accounts)]
 pub struct RegisterPriceRoute<'info> {
-    #[account(init, payer = admin, space = 8 + std::mem::size_of::<PriceRouteData>())]
+    #[account(init, payer = admin, space = PriceRouteData::LEN)]
     pub route_data: Account<'info, PriceRouteData>,
     #[account(mut, has_one = admin)]
     pub state: Account<'info, PriceState>,
@@ -125,6 +459,18 @@ pub struct RegisterPriceRoute<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct GetPriceRoute<'info> {
+    pub route_data: Account<'info, PriceRouteData>,
+}
+
+#[derive(Accounts)]
+pub struct ResolvePrice<'info> {
+    pub state: Account<'info, PriceState>,
+    #[account(has_one = state @ PriceError::InvalidPriceRoute)]
+    pub route_data: Account<'info, PriceRouteData>,
+}
+
 #[derive(Accounts)]
 pub struct VerifyPrice<'info> {
     pub oracle: Account<'info, PriceState>,
@@ -135,16 +481,81 @@ pub struct VerifyPrice<'info> {
 pub struct PriceState {
     pub is_initialized: bool,
     pub admin: Pubkey,
+    /// Retired single price provider, kept so existing accounts keep their
+    /// layout. Prices only come from `providers` through `aggregate_prices`,
+    /// so an oracle created before them has no provider until the admin
+    /// registers this one, or others, with `add_provider`.
     pub price_provider: Pubkey,
     pub prices: Vec<CurrencyPrice>,
+    /// Zero on oracles created before it existed, read through
+    /// `staleness_limit`.
+    pub max_staleness_seconds: i64,
+    pub providers: Vec<Pubkey>,
+    pub paused: bool,
+    /// Largest move from the stored price, in basis points, an update may
+    /// make. Zero accepts any move.
+    pub max_deviation_bps: u16,
+    /// Confidence of each entry of `prices`, by index. Kept out of
+    /// `CurrencyPrice` so oracles created before it keep their layout; they
+    /// read an empty list, which is a confidence of zero for every price.
+    pub confidences: Vec<u64>,
+}
+
+impl PriceState {
+    /// Oldest a price may be to count as fresh. `set_max_staleness` never
+    /// stores zero, so a zero is an oracle from before the setting and gets
+    /// `DEFAULT_MAX_STALENESS_SECONDS` instead of treating every price as
+    /// stale.
+    pub fn staleness_limit(&self) -> i64 {
+        if self.max_staleness_seconds > 0 {
+            self.max_staleness_seconds
+        } else {
+            DEFAULT_MAX_STALENESS_SECONDS
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ProgramHealth {
+    pub version: String,
+    pub initialized: bool,
+    pub paused: bool,
+}
+
+#[account]
+pub struct ProviderPrices {
+    pub state: Pubkey,
+    pub provider: Pubkey,
+    pub prices: Vec<CurrencyPrice>,
+    /// Confidence of each entry of `prices`, by index.
+    pub confidences: Vec<u64>,
+    pub bump: u8,
+}
+
+impl ProviderPrices {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // state
+        32 + // provider
+        4 + MAX_PRICES * (4 + 32 + 8 + 8) + // prices
+        4 + MAX_PRICES * 8 + // confidences
+        1; // bump
 }
 
 #[account]
 pub struct PriceRouteData {
+    /// Oracle the route was registered on.
+    pub state: Pubkey,
     pub denom: String,
     pub route: Vec<PriceRoute>,
 }
 
+impl PriceRouteData {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // state
+        4 + MAX_DENOM_LEN + // denom
+        4 + MAX_ROUTE_HOPS * (2 * (4 + MAX_DENOM_LEN) + 3 * 32); // route (assets + pool + reserves)
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct CurrencyPrice {
     pub currency: String,
@@ -152,10 +563,47 @@ pub struct CurrencyPrice {
     pub updated_at: i64,
 }
 
+/// A price as submitted by a provider or forced by the admin.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PriceUpdate {
+    pub currency: String,
+    pub usd_price: u64,
+    pub updated_at: i64,
+    /// Half-width of the provider's confidence interval around `usd_price`,
+    /// in the same fixed point.
+    pub confidence: u64,
+}
+
+/// When a currency's price was last written, from `get_price_freshness`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct PriceFreshness {
+    pub currency: String,
+    pub updated_at: i64,
+    /// Seconds since `updated_at`, 0 for prices stamped ahead of the clock.
+    pub age_seconds: i64,
+}
+
+/// Outcome of a single entry of `submit_prices`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PriceUpdateResult {
+    Applied,
+    InvalidCurrency,
+    InvalidPrice,
+    TooManyPrices,
+    DeviationExceeded,
+}
+
+/// One pool hop of a price route, swapping `offer_asset` for `ask_asset`. The
+/// next hop, if any, offers this hop's `ask_asset`.
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct PriceRoute {
     pub offer_asset: String,
+    pub ask_asset: String,
     pub pool: Pubkey,
+    /// The pool's token account holding `offer_asset`.
+    pub offer_reserve: Pubkey,
+    /// The pool's token account holding `ask_asset`.
+    pub ask_reserve: Pubkey,
 }
 
 #[error_code]
@@ -170,7 +618,283 @@ pub enum PriceError {
     PriceNotFound,
     #[msg("Trade price is outside allowed range")]
     PriceOutOfRange,
+    #[msg("Stored price is older than the allowed staleness window")]
+    StalePrice,
+    #[msg("Too many prices for the oracle")]
+    TooManyPrices,
+    #[msg("Staleness window must be greater than zero")]
+    InvalidStaleness,
+    #[msg("Price provider is already registered")]
+    ProviderAlreadyRegistered,
+    #[msg("Price provider limit reached")]
+    TooManyProviders,
+    #[msg("Price provider is not registered")]
+    ProviderNotFound,
+    #[msg("Invalid or duplicated provider submission")]
+    InvalidProviderSubmission,
+    #[msg("No fresh provider prices to aggregate")]
+    NoFreshPrices,
+    #[msg("Resolved price does not fit in a u64")]
+    PriceOverflow,
+    #[msg("Price oracle is paused")]
+    OraclePaused,
+    #[msg("Currency appears more than once in the update")]
+    DuplicateCurrency,
+    #[msg("Price is timestamped in the future")]
+    FuturePrice,
+    #[msg("Price confidence interval is too wide to trade on")]
+    ConfidenceTooWide,
+    #[msg("Price moved further from the stored price than the oracle allows")]
+    DeviationExceeded,
+    #[msg("Forced price has an invalid currency or price")]
+    InvalidForcedPrice,
+    #[msg("Too few registered providers have a fresh price to aggregate")]
+    QuorumNotMet,
+}
+
+fn upsert_price(
+    prices: &mut Vec<CurrencyPrice>,
+    confidences: &mut Vec<u64>,
+    currency: &str,
+    usd_price: u64,
+    confidence: u64,
+    now: i64,
+) -> Result<()> {
+    let index = match prices.iter().position(|p| p.currency == currency) {
+        Some(index) => {
+            prices[index].usd_price = usd_price;
+            prices[index].updated_at = now;
+            index
+        }
+        None => {
+            require!(prices.len() < MAX_PRICES, PriceError::TooManyPrices);
+            prices.push(CurrencyPrice {
+                currency: currency.to_string(),
+                usd_price,
+                updated_at: now,
+            });
+            prices.len() - 1
+        }
+    };
+    confidences.resize(prices.len(), 0);
+    confidences[index] = confidence;
+    Ok(())
+}
+
+/// Confidence of the price at `index`, zero for prices stored before
+/// confidences were.
+fn price_confidence(confidences: &[u64], index: usize) -> u64 {
+    confidences.get(index).copied().unwrap_or_default()
+}
+
+/// Rejects a whole update that is longer than the oracle can hold, names a
+/// currency twice or carries a timestamp ahead of the cluster clock.
+fn check_price_batch(prices: &[PriceUpdate], now: i64) -> Result<()> {
+    require!(prices.len() <= MAX_PRICES, PriceError::TooManyPrices);
+    for (index, price) in prices.iter().enumerate() {
+        require!(
+            price.updated_at <= now.saturating_add(MAX_CLOCK_DRIFT_SECONDS),
+            PriceError::FuturePrice
+        );
+        require!(
+            !prices[..index].iter().any(|p| p.currency == price.currency),
+            PriceError::DuplicateCurrency
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `trade_price` is within `tolerance_bps` of the oracle's fresh
+/// `currency` price, widened by the price's confidence. Prices less certain
+/// than `MAX_CONFIDENCE_BPS` are refused outright. Programs holding the
+/// oracle account can call it directly instead of going through
+/// `verify_price_for_trade`.
+pub fn check_trade_price(
+    oracle: &PriceState,
+    trade_price: u64,
+    currency: &str,
+    tolerance_bps: u16,
+    now: i64,
+) -> Result<()> {
+    require!(oracle.is_initialized, PriceError::NotInitialized);
+
+    // Find the reference price for the given currency
+    let index = oracle
+        .prices
+        .iter()
+        .position(|p| p.currency == currency)
+        .ok_or(PriceError::PriceNotFound)?;
+    let reference_price = &oracle.prices[index];
+    let confidence = price_confidence(&oracle.confidences, index);
+
+    require!(
+        now.saturating_sub(reference_price.updated_at) <= oracle.staleness_limit(),
+        PriceError::StalePrice
+    );
+    require!(
+        (confidence as u128) * 10000
+            <= (reference_price.usd_price as u128) * MAX_CONFIDENCE_BPS as u128,
+        PriceError::ConfidenceTooWide
+    );
+
+    // Calculate allowed deviation range
+    let tolerance = ((reference_price.usd_price as u128)
+        .checked_mul(tolerance_bps as u128)
+        .unwrap_or(0)
+        .checked_div(10000)
+        .unwrap_or(0) as u64)
+        .saturating_add(confidence);
+
+    let min_allowed = reference_price.usd_price.saturating_sub(tolerance);
+    let max_allowed = reference_price.usd_price.saturating_add(tolerance);
+
+    require!(
+        trade_price >= min_allowed && trade_price <= max_allowed,
+        PriceError::PriceOutOfRange
+    );
+    Ok(())
+}
+
+/// Validates one price update on its own against the currently stored prices.
+fn check_price(
+    prices: &[CurrencyPrice],
+    price: &PriceUpdate,
+    max_deviation_bps: u16,
+) -> PriceUpdateResult {
+    let stored = prices.iter().find(|p| p.currency == price.currency);
+    if price.currency.is_empty() || price.currency.len() > MAX_CURRENCY_LEN {
+        PriceUpdateResult::InvalidCurrency
+    } else if price.usd_price == 0 {
+        PriceUpdateResult::InvalidPrice
+    } else if stored.is_some_and(|stored| {
+        exceeds_deviation(stored.usd_price, price.usd_price, max_deviation_bps)
+    }) {
+        PriceUpdateResult::DeviationExceeded
+    } else if prices.len() >= MAX_PRICES && stored.is_none() {
+        PriceUpdateResult::TooManyPrices
+    } else {
+        PriceUpdateResult::Applied
+    }
+}
+
+/// Whether `price` is more than `max_deviation_bps` away from `previous`.
+/// Never true with the breaker turned off.
+fn exceeds_deviation(previous: u64, price: u64, max_deviation_bps: u16) -> bool {
+    max_deviation_bps > 0
+        && (previous.abs_diff(price) as u128) * 10_000
+            > (previous as u128) * max_deviation_bps as u128
+}
+
+/// Ages of `prices` at `now`, sorted stalest first. Prices of the same age
+/// keep their stored order.
+fn price_freshness(prices: &[CurrencyPrice], now: i64) -> Vec<PriceFreshness> {
+    let mut freshness: Vec<PriceFreshness> = prices
+        .iter()
+        .map(|price| PriceFreshness {
+            currency: price.currency.clone(),
+            updated_at: price.updated_at,
+            age_seconds: now.saturating_sub(price.updated_at).max(0),
+        })
+        .collect();
+    freshness.sort_by_key(|entry| std::cmp::Reverse(entry.age_seconds));
+    freshness
+}
+
+/// Median of `prices`; the mean of the two middle values for an even count.
+fn median_price(prices: &mut [u64]) -> Option<u64> {
+    if prices.is_empty() {
+        return None;
+    }
+    prices.sort_unstable();
+    let mid = prices.len() / 2;
+    if prices.len().is_multiple_of(2) {
+        Some(((prices[mid - 1] as u128 + prices[mid] as u128) / 2) as u64)
+    } else {
+        Some(prices[mid])
+    }
 }
 
 // Re-export for CPI
 pub use price::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oracles_from_before_confidences_keep_their_prices() {
+        // An oracle as the first release initialized it: prices directly
+        // after the provider, and zeroed slack up to the allocated space.
+        let legacy_space = 1 + 32 + 32 + 4 + 10 * (4 + 32 + 8 + 8);
+        let prices = vec![
+            CurrencyPrice {
+                currency: "USD".to_string(),
+                usd_price: 100_000,
+                updated_at: 1_700_000_000,
+            },
+            CurrencyPrice {
+                currency: "EUR".to_string(),
+                usd_price: 110_000,
+                updated_at: 1_700_000_000,
+            },
+        ];
+        let mut data = Vec::new();
+        true.serialize(&mut data).unwrap();
+        Pubkey::new_unique().serialize(&mut data).unwrap();
+        Pubkey::new_unique().serialize(&mut data).unwrap();
+        prices.serialize(&mut data).unwrap();
+        data.resize(legacy_space, 0);
+
+        let mut state = PriceState::deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(state.prices.len(), 2);
+        assert_eq!(state.prices[1].usd_price, 110_000);
+        assert_eq!(state.max_deviation_bps, 0);
+        assert!(state.confidences.is_empty());
+        assert_eq!(price_confidence(&state.confidences, 1), 0);
+
+        upsert_price(
+            &mut state.prices,
+            &mut state.confidences,
+            "EUR",
+            111_000,
+            500,
+            1_700_000_060,
+        )
+        .unwrap();
+        assert_eq!(state.confidences, vec![0, 500]);
+        assert!(state.try_to_vec().unwrap().len() <= legacy_space);
+    }
+
+    #[test]
+    fn oracles_from_before_staleness_use_the_default() {
+        let provider = Pubkey::new_unique();
+        let mut data = Vec::new();
+        true.serialize(&mut data).unwrap();
+        Pubkey::new_unique().serialize(&mut data).unwrap();
+        provider.serialize(&mut data).unwrap();
+        vec![CurrencyPrice {
+            currency: "USD".to_string(),
+            usd_price: 100_000,
+            updated_at: 1_700_000_000,
+        }]
+        .serialize(&mut data)
+        .unwrap();
+        data.resize(1 + 32 + 32 + 4 + 10 * (4 + 32 + 8 + 8), 0);
+
+        let mut state = PriceState::deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(state.max_staleness_seconds, 0);
+        assert_eq!(state.staleness_limit(), DEFAULT_MAX_STALENESS_SECONDS);
+        assert!(state.providers.is_empty());
+        assert_eq!(state.price_provider, provider);
+
+        let fresh = 1_700_000_000 + DEFAULT_MAX_STALENESS_SECONDS;
+        assert!(check_trade_price(&state, 100_000, "USD", 0, fresh).is_ok());
+        assert_eq!(
+            check_trade_price(&state, 100_000, "USD", 0, fresh + 1).unwrap_err(),
+            PriceError::StalePrice.into()
+        );
+
+        state.max_staleness_seconds = 60;
+        assert_eq!(state.staleness_limit(), 60);
+    }
+}
@@ -16,7 +16,46 @@ pub const PROFILE_SIZE: usize = 8 + // discriminator
     1 + // is_verified
     8 + // created_at
     8 + // updated_at
-    64; // padding for future updates
+    4 + // disputes_lost
+    4 + // trades_cancelled
+    2 + // completion_rate_bps
+    54; // padding for future updates
+
+/// Reputation given to both parties of a completed trade.
+pub const COMPLETION_REWARD: u32 = 1;
+/// Reputation taken from a party that loses a dispute.
+pub const DISPUTE_LOSS_PENALTY: u32 = 5;
+/// Reputation taken from a party that walks away from an accepted trade.
+pub const CANCELLATION_PENALTY: u32 = 2;
+/// Settled trades a profile needs before its completion rate is held against it.
+pub const MIN_COMPLETION_HISTORY: u32 = 10;
+/// A profile loses `REPUTATION_DECAY_POINTS` for every full period it goes
+/// without an update.
+pub const REPUTATION_DECAY_PERIOD: i64 = 30 * 24 * 60 * 60; // 30 days
+pub const REPUTATION_DECAY_POINTS: u32 = 1;
+
+pub const PROFILE_SEED: &[u8] = b"profile";
+
+// The trade program depends on this crate, so it is referenced by id.
+pub const TRADE_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("437aWt9WrLYquEwJsVe3B3kANP77ZCvn4gs4hJBNLefG");
+
+/// Profile PDA of `owner`: `["profile", owner]` under the profile program.
+pub fn derive_profile(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PROFILE_SEED, owner.as_ref()], &ID)
+}
+
+/// Whether `trade` holds a `Trade` account of the trade program. Only the
+/// trade program writes its accounts, so a signing account that passes is a
+/// trade PDA signing through `invoke_signed`, not one created for the trade
+/// program by someone else, which would still be zeroed.
+pub fn is_trade_account(trade: &AccountInfo) -> bool {
+    let discriminator = &solana_program::hash::hash(b"account:Trade").to_bytes()[..8];
+    *trade.owner == TRADE_PROGRAM_ID
+        && trade
+            .try_borrow_data()
+            .is_ok_and(|data| data.starts_with(discriminator))
+}
 
 #[program]
 pub mod profile {
@@ -46,6 +85,7 @@ pub mod profile {
 
     pub fn update_profile(ctx: Context<UpdateProfile>, username: Option<String>) -> Result<()> {
         let profile = &mut ctx.accounts.profile;
+        profile.touch(Clock::get()?.unix_timestamp);
 
         if let Some(new_username) = username {
             require!(
@@ -57,13 +97,15 @@ pub mod profile {
             profile.username_len = new_username.len() as u32;
         }
 
-        profile.updated_at = Clock::get()?.unix_timestamp;
         msg!("Profile updated successfully");
         Ok(())
     }
 
+    /// Moves the reputation by `score_delta`. The trade program calls it
+    /// with `COMPLETION_REWARD` for both parties of a completed trade.
     pub fn update_reputation(ctx: Context<UpdateReputation>, score_delta: i32) -> Result<()> {
         let profile = &mut ctx.accounts.profile;
+        profile.touch(Clock::get()?.unix_timestamp);
 
         // Update reputation score, ensuring it doesn't underflow
         if score_delta < 0 && profile.reputation_score < score_delta.abs() as u32 {
@@ -74,15 +116,14 @@ pub mod profile {
             profile.reputation_score += score_delta as u32;
         }
 
-        profile.updated_at = Clock::get()?.unix_timestamp;
         msg!("Reputation updated successfully");
         Ok(())
     }
 
     pub fn verify_profile(ctx: Context<VerifyProfile>) -> Result<()> {
         let profile = &mut ctx.accounts.profile;
+        profile.touch(Clock::get()?.unix_timestamp);
         profile.is_verified = true;
-        profile.updated_at = Clock::get()?.unix_timestamp;
 
         msg!("Profile verified successfully");
         Ok(())
@@ -90,8 +131,9 @@ pub mod profile {
 
     pub fn record_trade_completion(ctx: Context<RecordTrade>) -> Result<()> {
         let profile = &mut ctx.accounts.profile;
+        profile.touch(Clock::get()?.unix_timestamp);
         profile.trades_completed += 1;
-        profile.updated_at = Clock::get()?.unix_timestamp;
+        profile.refresh_completion_rate();
 
         msg!("Trade completion recorded successfully");
         Ok(())
@@ -99,22 +141,44 @@ pub mod profile {
 
     pub fn record_trade_dispute(ctx: Context<RecordTrade>) -> Result<()> {
         let profile = &mut ctx.accounts.profile;
-        profile.trades_disputed += 1;
-        profile.updated_at = Clock::get()?.unix_timestamp;
+        profile.record_dispute(Clock::get()?.unix_timestamp);
 
         msg!("Trade dispute recorded successfully");
         Ok(())
     }
 
+    /// Penalizes the party that lost an arbitrated dispute.
+    pub fn record_dispute_loss(ctx: Context<RecordTrade>) -> Result<()> {
+        let profile = &mut ctx.accounts.profile;
+        profile.touch(Clock::get()?.unix_timestamp);
+        profile.disputes_lost += 1;
+        profile.reputation_score = profile
+            .reputation_score
+            .saturating_sub(DISPUTE_LOSS_PENALTY);
+
+        msg!("Dispute loss recorded successfully");
+        Ok(())
+    }
+
+    /// Penalizes the party at fault for a cancelled trade.
+    pub fn record_trade_cancellation(ctx: Context<RecordTrade>) -> Result<()> {
+        let profile = &mut ctx.accounts.profile;
+        profile.record_cancellation(Clock::get()?.unix_timestamp);
+
+        msg!("Trade cancellation recorded successfully");
+        Ok(())
+    }
+
     pub fn verify_trade_completion(ctx: Context<VerifyTradeCompletion>) -> Result<()> {
         // Verify trade completion using common module
         common::verify_trade_completion(&ctx.accounts.trade_program, &ctx.accounts.trade)?;
 
         // Update profile statistics
         let profile = &mut ctx.accounts.profile;
+        profile.touch(Clock::get()?.unix_timestamp);
         profile.trades_completed += 1;
+        profile.refresh_completion_rate();
         profile.reputation_score = profile.reputation_score.saturating_add(1);
-        profile.updated_at = Clock::get()?.unix_timestamp;
 
         msg!("Trade verification and profile update completed successfully");
         Ok(())
@@ -156,7 +220,9 @@ pub struct UpdateReputation<'info> {
         bump
     )]
     pub profile: Account<'info, Profile>,
-    pub authority: Signer<'info>,
+    /// CHECK: the trade PDA, signing through the trade program
+    #[account(signer, constraint = is_trade_account(&trade) @ ProfileError::InvalidTradeProgram)]
+    pub trade: UncheckedAccount<'info>,
     /// CHECK: Owner of the profile
     pub owner: AccountInfo<'info>,
 }
@@ -184,8 +250,9 @@ pub struct RecordTrade<'info> {
     pub profile: Account<'info, Profile>,
     /// CHECK: Owner of the profile
     pub owner: AccountInfo<'info>,
-    /// CHECK: Trade program account, validated in program logic
-    pub trade_program: UncheckedAccount<'info>,
+    /// CHECK: the trade PDA, signing through the trade program
+    #[account(signer, constraint = is_trade_account(&trade) @ ProfileError::InvalidTradeProgram)]
+    pub trade: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
@@ -216,6 +283,66 @@ pub struct Profile {
     pub is_verified: bool,
     pub created_at: i64,
     pub updated_at: i64,
+    pub disputes_lost: u32,
+    pub trades_cancelled: u32,
+    /// `completed / (completed + cancelled + disputed)` in basis points, as
+    /// of the last recorded trade. 0 until the first one.
+    pub completion_rate_bps: u16,
+}
+
+impl Profile {
+    /// Applies reputation decay for the time since the last update and marks
+    /// the profile updated at `now`. Decay is only ever computed here, so an
+    /// idle profile catches up on its next update.
+    pub fn touch(&mut self, now: i64) {
+        let idle_periods = (now - self.updated_at).max(0) / REPUTATION_DECAY_PERIOD;
+        let decay = u32::try_from(idle_periods)
+            .unwrap_or(u32::MAX)
+            .saturating_mul(REPUTATION_DECAY_POINTS);
+        self.reputation_score = self.reputation_score.saturating_sub(decay);
+        self.updated_at = now;
+    }
+
+    pub fn record_cancellation(&mut self, now: i64) {
+        self.touch(now);
+        self.trades_cancelled += 1;
+        self.reputation_score = self.reputation_score.saturating_sub(CANCELLATION_PENALTY);
+        self.refresh_completion_rate();
+    }
+
+    pub fn record_dispute(&mut self, now: i64) {
+        self.touch(now);
+        self.trades_disputed += 1;
+        self.refresh_completion_rate();
+    }
+
+    /// Trades that ended completed, cancelled through the profile's fault or
+    /// disputed.
+    pub fn settled_trades(&self) -> u32 {
+        self.trades_completed
+            .saturating_add(self.trades_cancelled)
+            .saturating_add(self.trades_disputed)
+    }
+
+    /// Computed from the counters, so it is also right for profiles that
+    /// have not recorded a trade since `completion_rate_bps` was added.
+    pub fn completion_rate_bps(&self) -> u16 {
+        match self.settled_trades() {
+            0 => 0,
+            settled => (u64::from(self.trades_completed) * 10_000 / u64::from(settled)) as u16,
+        }
+    }
+
+    pub fn refresh_completion_rate(&mut self) {
+        self.completion_rate_bps = self.completion_rate_bps();
+    }
+
+    /// Whether the completion rate reaches `min_bps`. Profiles with fewer
+    /// than `MIN_COMPLETION_HISTORY` settled trades always pass, their rate
+    /// says too little yet.
+    pub fn meets_completion_rate(&self, min_bps: u16) -> bool {
+        self.settled_trades() < MIN_COMPLETION_HISTORY || self.completion_rate_bps() >= min_bps
+    }
 }
 
 #[error_code]
@@ -233,6 +360,90 @@ pub use profile::*;
 
 #[cfg(test)]
 mod tests {
+    use super::*;
 
-    // Add tests here
+    fn profile_at(reputation_score: u32, updated_at: i64) -> Profile {
+        Profile {
+            reputation_score,
+            updated_at,
+            ..Profile::default()
+        }
+    }
+
+    #[test]
+    fn active_profile_does_not_decay() {
+        let mut profile = profile_at(10, 1_700_000_000);
+        profile.touch(1_700_000_000 + REPUTATION_DECAY_PERIOD - 1);
+        assert_eq!(profile.reputation_score, 10);
+        assert_eq!(
+            profile.updated_at,
+            1_700_000_000 + REPUTATION_DECAY_PERIOD - 1
+        );
+    }
+
+    #[test]
+    fn idle_profile_decays_per_full_period() {
+        let mut profile = profile_at(10, 1_700_000_000);
+        profile.touch(1_700_000_000 + 3 * REPUTATION_DECAY_PERIOD + 1);
+        assert_eq!(profile.reputation_score, 10 - 3 * REPUTATION_DECAY_POINTS);
+
+        let mut dormant = profile_at(2, 0);
+        dormant.touch(1_700_000_000);
+        assert_eq!(dormant.reputation_score, 0);
+    }
+
+    #[test]
+    fn cancellations_are_counted_and_penalized() {
+        let mut profile = profile_at(3, 1_700_000_000);
+        profile.record_cancellation(1_700_000_060);
+        assert_eq!(profile.trades_cancelled, 1);
+        assert_eq!(profile.reputation_score, 3 - CANCELLATION_PENALTY);
+
+        profile.record_cancellation(1_700_000_120);
+        assert_eq!(profile.trades_cancelled, 2);
+        assert_eq!(profile.reputation_score, 0);
+    }
+
+    #[test]
+    fn low_completion_rate_fails_once_history_is_long_enough() {
+        let unreliable = Profile {
+            trades_completed: 6,
+            trades_cancelled: 3,
+            trades_disputed: 1,
+            ..Profile::default()
+        };
+        assert_eq!(unreliable.completion_rate_bps(), 6_000);
+        assert!(unreliable.meets_completion_rate(6_000));
+        assert!(!unreliable.meets_completion_rate(8_000));
+    }
+
+    #[test]
+    fn new_profiles_pass_any_completion_rate() {
+        let mut newcomer = Profile {
+            trades_completed: 1,
+            trades_cancelled: 8,
+            ..Profile::default()
+        };
+        assert!(newcomer.settled_trades() < MIN_COMPLETION_HISTORY);
+        assert!(newcomer.meets_completion_rate(10_000));
+
+        newcomer.record_cancellation(1_700_000_000);
+        assert_eq!(newcomer.completion_rate_bps, 1_000);
+        assert!(!newcomer.meets_completion_rate(10_000));
+        assert!(Profile::default().meets_completion_rate(10_000));
+    }
+
+    #[test]
+    fn disputes_count_against_the_completion_rate() {
+        let mut maker = Profile {
+            trades_completed: 9,
+            ..Profile::default()
+        };
+        assert!(maker.meets_completion_rate(10_000));
+
+        maker.record_dispute(1_700_000_000);
+        assert_eq!(maker.trades_disputed, 1);
+        assert_eq!(maker.completion_rate_bps, 9_000);
+        assert!(!maker.meets_completion_rate(10_000));
+    }
 }
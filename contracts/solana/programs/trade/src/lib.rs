@@ -1,22 +1,425 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::sysvar::rent::Rent;
+use anchor_lang::system_program;
 
-use anchor_spl::token::{self, Token};
+use anchor_spl::token;
+use anchor_spl::token_2022::{self, spl_token_2022};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface};
+use spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
 
 // Add imports for external programs
-use price::program::Price;
 use price::{self, PriceState};
 use profile::program::Profile;
 use profile::{self, Profile as ProfileAccount};
 
 declare_id!("437aWt9WrLYquEwJsVe3B3kANP77ZCvn4gs4hJBNLefG");
 
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Layout version written to new `Trade` accounts. Trades created before the
+/// version byte existed read it from zeroed padding as 0.
+pub const TRADE_VERSION: u8 = 9;
+
+// The offer program depends on this crate, so it is referenced by id and
+// invoked through `common` instead of the generated CPI module.
+pub const OFFER_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("52CejgfZEeefMzvYqJ7RmcT4NzemCDZf4nsX3kywuw2B");
+
+pub const TRADE_SEED: &[u8] = b"trade";
+
+pub const TRADE_REGISTRY_SEED: &[u8] = b"trade_registry";
+
+pub const TRADE_UNITS_SEED: &[u8] = b"trade_units";
+
+/// Bytes of `Trade::memo`. Shorter memos are padded with zeros.
+pub const MEMO_LEN: usize = 32;
+
+/// Upper bound for `TradeConfig::fee_bps`, 10% of the traded amount.
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// Largest deviation from the oracle USD price a trade completes at, 1%.
+pub const PRICE_TOLERANCE_BPS: u16 = 100;
+
+/// Most trades a single `complete_trades_batch` call settles.
+pub const MAX_BATCH_TRADES: usize = 10;
+
+/// Remaining accounts each trade takes in `complete_trades_batch`, in order:
+/// trade, seller, buyer, escrow account, buyer token account, token mint,
+/// buyer profile, seller profile, offer and fee collector. The seller and
+/// buyer must sign. The fee collector is only read while a fee is charged
+/// and the offer only for a trade that has one, pass the trade program id in
+/// their place otherwise.
+pub const BATCH_TRADE_ACCOUNTS: usize = 10;
+
+/// Logs `$step` and the compute units left when built with the `verbose`
+/// feature, and compiles to nothing otherwise.
+macro_rules! log_compute_units {
+    ($step:expr) => {
+        #[cfg(feature = "verbose")]
+        {
+            msg!($step);
+            anchor_lang::solana_program::log::sol_log_compute_units();
+        }
+    };
+}
+
+/// Trade PDA of a seller for `token_mint`: `["trade", seller, token_mint]`
+/// under the trade program. A seller has one trade per mint at a time.
+pub fn derive_trade(seller: &Pubkey, token_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TRADE_SEED, seller.as_ref(), token_mint.as_ref()], &ID)
+}
+
+/// Trade units PDA of `token_mint`: `["trade_units", token_mint]` under the
+/// trade program. Overrides the config's trade units for that mint.
+pub fn derive_trade_units(token_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TRADE_UNITS_SEED, token_mint.as_ref()], &ID)
+}
+
+/// Offset of `Trade::seller` in the account data, right after the discriminator.
+pub const TRADE_SELLER_OFFSET: usize = 8;
+
+/// Offset of `Trade::buyer`. It is an `Option`, so the tag byte comes first
+/// and the pubkey of an accepted trade starts one byte later.
+pub const TRADE_BUYER_OFFSET: usize = TRADE_SELLER_OFFSET + 32;
+
+/// `getProgramAccounts` memcmp filters, as `(offset, bytes)`, matching the
+/// trades `user` sold on and the trades they accepted. RPC filters are
+/// combined with AND, so each one goes in its own request.
+pub fn trade_filters_for_user(user: &Pubkey) -> Vec<(usize, Vec<u8>)> {
+    let mut buyer = vec![1];
+    buyer.extend_from_slice(user.as_ref());
+    vec![
+        (TRADE_SELLER_OFFSET, user.to_bytes().to_vec()),
+        (TRADE_BUYER_OFFSET, buyer),
+    ]
+}
+
+/// Zero pads `memo` into the fixed size `Trade::memo`.
+pub fn encode_memo(memo: &str) -> Result<[u8; MEMO_LEN]> {
+    require!(memo.len() <= MEMO_LEN, TradeError::MemoTooLong);
+    let mut bytes = [0u8; MEMO_LEN];
+    bytes[..memo.len()].copy_from_slice(memo.as_bytes());
+    Ok(bytes)
+}
+
+/// The memo stored by `encode_memo`, without its zero padding.
+pub fn decode_memo(memo: &[u8; MEMO_LEN]) -> String {
+    let len = memo.iter().position(|byte| *byte == 0).unwrap_or(MEMO_LEN);
+    String::from_utf8_lossy(&memo[..len]).into_owned()
+}
+
+/// Upgrades the serialized trade in `data`, which must span `Trade::LEN`
+/// bytes, to `TRADE_VERSION` and returns the version it was at.
+pub fn migrate_trade_data(data: &mut [u8]) -> Result<u8> {
+    let mut trade = Trade::try_deserialize(&mut &data[..])?;
+    require!(trade.version < TRADE_VERSION, TradeError::AlreadyMigrated);
+    let from_version = trade.version;
+    trade.migrate();
+    trade.try_serialize(&mut &mut data[..])?;
+    Ok(from_version)
+}
+
+/// Fails unless the oracle's live USD price is within `min..=max`.
+pub fn check_price_bounds(oracle: &PriceState, min: u64, max: u64) -> Result<()> {
+    let live_price = oracle
+        .prices
+        .iter()
+        .find(|price| price.currency == "USD")
+        .map(|price| price.usd_price);
+    require!(
+        live_price.is_some_and(|price| (min..=max).contains(&price)),
+        TradeError::PriceOutOfBounds
+    );
+    Ok(())
+}
+
+/// Amount a fee withdrawal moves out of a collector holding `balance`: the
+/// `requested` amount, or everything when none is given.
+pub fn fee_withdrawal_amount(requested: Option<u64>, balance: u64) -> Result<u64> {
+    let amount = requested.unwrap_or(balance);
+    require!(
+        amount > 0 && amount <= balance,
+        TradeError::InvalidFeeWithdrawal
+    );
+    Ok(amount)
+}
+
+/// Fee a Token-2022 `mint` with the transfer fee extension withholds from a
+/// transfer of `amount` in `epoch`. Zero for every other mint.
+pub fn mint_transfer_fee(mint: &AccountInfo, amount: u64, epoch: u64) -> Result<u64> {
+    if *mint.owner != token_2022::ID {
+        return Ok(0);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    match mint.get_extension::<TransferFeeConfig>() {
+        Ok(config) => Ok(config
+            .calculate_epoch_fee(epoch, amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Moves `amount` of `mint` between token accounts of either token program.
+/// Legacy mints use a plain transfer, Token-2022 mints need `transfer_checked`
+/// so extensions like transfer fees are applied.
+pub fn transfer_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    authority: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let cpi_program = token_program.to_account_info();
+    if token_program.key() == token::ID {
+        token::transfer(
+            CpiContext::new_with_signer(
+                cpi_program,
+                token::Transfer {
+                    from: from.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: authority.clone(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )
+    } else {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                cpi_program,
+                token_interface::TransferChecked {
+                    from: from.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: authority.clone(),
+                },
+                signer_seeds,
+            ),
+            amount,
+            mint.decimals,
+        )
+    }
+}
+
 #[program]
 pub mod trade {
     use super::*;
 
-    pub fn create_trade(ctx: Context<CreateTrade>, amount: u64, price: u64) -> Result<()> {
+    /// Creates the config with the program's upgrade authority as admin, so
+    /// nobody can claim it first after a deploy.
+    pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.paused = false;
+        config.bump = ctx.bumps.config;
+
+        msg!("Trade config initialized");
+        Ok(())
+    }
+
+    /// Creates the counter that numbers trades. `create_trade` needs it, so
+    /// it has to exist before the first trade is opened.
+    pub fn initialize_registry(ctx: Context<InitializeRegistry>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        registry.trade_count = 0;
+        registry.bump = ctx.bumps.registry;
+
+        msg!("Trade registry initialized");
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+        msg!("Trade program paused: {}", paused);
+        Ok(())
+    }
+
+    /// Seconds a trade may sit in dispute before anyone can refund it to the
+    /// seller through `dispute_timeout`. Zero turns the timeout off.
+    pub fn set_dispute_ttl(ctx: Context<UpdateConfig>, dispute_ttl_seconds: u32) -> Result<()> {
+        ctx.accounts.config.dispute_ttl_seconds = dispute_ttl_seconds;
+        msg!("Dispute TTL set to {} seconds", dispute_ttl_seconds);
+        Ok(())
+    }
+
+    /// Seconds after a trade is accepted before either party can dispute it.
+    /// Applies to trades accepted from now on. Zero allows immediate disputes.
+    pub fn set_dispute_cooldown(
+        ctx: Context<UpdateConfig>,
+        dispute_cooldown_seconds: u32,
+    ) -> Result<()> {
+        ctx.accounts.config.dispute_cooldown_seconds = dispute_cooldown_seconds;
+        msg!(
+            "Dispute cooldown set to {} seconds",
+            dispute_cooldown_seconds
+        );
+        Ok(())
+    }
+
+    /// Protocol fee taken from the buyer's payout on completion, in basis points.
+    pub fn set_fee_bps(ctx: Context<UpdateConfig>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, TradeError::FeeTooHigh);
+        ctx.accounts.config.fee_bps = fee_bps;
+        msg!("Trade fee set to {} bps", fee_bps);
+        Ok(())
+    }
+
+    /// Flat fee in lamports the seller pays into the config account for every
+    /// trade created, withdrawn with `withdraw_creation_fees`. Zero waives it.
+    pub fn set_creation_fee(ctx: Context<UpdateConfig>, creation_fee_lamports: u64) -> Result<()> {
+        ctx.accounts.config.creation_fee_lamports = creation_fee_lamports;
+        msg!(
+            "Trade creation fee set to {} lamports",
+            creation_fee_lamports
+        );
+        Ok(())
+    }
+
+    /// Bounds on a trade's amount in whole tokens of its mint, scaled by the
+    /// mint's decimals when a trade is created. Zero leaves that side open.
+    pub fn set_trade_units(
+        ctx: Context<UpdateConfig>,
+        min_trade_units: u64,
+        max_trade_units: u64,
+    ) -> Result<()> {
+        require!(
+            max_trade_units == 0 || min_trade_units <= max_trade_units,
+            TradeError::InvalidTradeUnits
+        );
+        let config = &mut ctx.accounts.config;
+        config.min_trade_units = min_trade_units;
+        config.max_trade_units = max_trade_units;
+        msg!(
+            "Trade units set to {}..={}",
+            min_trade_units,
+            max_trade_units
+        );
+        Ok(())
+    }
+
+    /// Trade units for `token_mint` only, used in place of the config's
+    /// `set_trade_units` bounds for trades in that mint. Zero leaves that side
+    /// open.
+    pub fn set_mint_trade_units(
+        ctx: Context<SetMintTradeUnits>,
+        min_trade_units: u64,
+        max_trade_units: u64,
+    ) -> Result<()> {
+        require!(
+            max_trade_units == 0 || min_trade_units <= max_trade_units,
+            TradeError::InvalidTradeUnits
+        );
+        let trade_units = &mut ctx.accounts.trade_units;
+        trade_units.min_trade_units = min_trade_units;
+        trade_units.max_trade_units = max_trade_units;
+        trade_units.bump = ctx.bumps.trade_units;
+        msg!(
+            "Trade units for {} set to {}..={}",
+            ctx.accounts.token_mint.key(),
+            min_trade_units,
+            max_trade_units
+        );
+        Ok(())
+    }
+
+    /// Drops the trade units of `token_mint`, so its trades follow the
+    /// config's bounds again.
+    pub fn clear_mint_trade_units(ctx: Context<ClearMintTradeUnits>) -> Result<()> {
+        msg!("Trade units for {} cleared", ctx.accounts.trade_units.key());
+        Ok(())
+    }
+
+    /// Escrow balances below `dust_threshold` left on a settled trade can be
+    /// swept to the fee collector with `sweep_dust`. Zero disables sweeping.
+    pub fn set_dust_threshold(ctx: Context<UpdateConfig>, dust_threshold: u64) -> Result<()> {
+        ctx.accounts.config.dust_threshold = dust_threshold;
+        msg!("Dust threshold set to {}", dust_threshold);
+        Ok(())
+    }
+
+    /// Previews how completing a trade of `amount` of `token_mint` splits the
+    /// escrow, returned through return data. Uses the same calculation as
+    /// `complete_trade`, including the mint's transfer fee.
+    pub fn quote_trade(ctx: Context<QuoteTrade>, amount: u64) -> Result<TradeQuote> {
+        let epoch = Clock::get()?.epoch;
+        ctx.accounts.config.quote_for_mint(
+            amount,
+            &ctx.accounts.token_mint.to_account_info(),
+            epoch,
+        )
+    }
+
+    /// Read-only status for monitoring, returned through return data.
+    pub fn health(ctx: Context<Health>) -> Result<ProgramHealth> {
+        let config =
+            common::load_if_initialized::<TradeConfig>(&ctx.accounts.config.to_account_info());
+        Ok(ProgramHealth {
+            version: VERSION.to_string(),
+            initialized: config.is_some(),
+            paused: config.is_some_and(|config| config.paused),
+        })
+    }
+
+    /// The trade together with both parties' profile stats, returned through
+    /// return data so clients need a single simulation instead of three fetches.
+    pub fn trade_summary(ctx: Context<GetTradeSummary>) -> Result<TradeSummary> {
+        let trade = &ctx.accounts.trade;
+        let buyer_profile = match trade.buyer {
+            Some(buyer) => {
+                require_keys_eq!(
+                    ctx.accounts.buyer_profile.key(),
+                    profile::derive_profile(&buyer).0,
+                    TradeError::InvalidProfile
+                );
+                ProfileSummary::load(&ctx.accounts.buyer_profile)
+            }
+            None => None,
+        };
+
+        Ok(TradeSummary {
+            seller: trade.seller,
+            buyer: trade.buyer,
+            amount: trade.amount,
+            price: trade.price,
+            token_mint: trade.token_mint,
+            status: trade.status.clone(),
+            created_at: trade.created_at,
+            updated_at: trade.updated_at,
+            seller_profile: ProfileSummary::load(&ctx.accounts.seller_profile),
+            buyer_profile,
+        })
+    }
+
+    pub fn create_trade(
+        ctx: Context<CreateTrade>,
+        amount: u64,
+        price: u64,
+        taker_lock_seconds: u32,
+        offer_type: OfferType,
+        memo: String,
+    ) -> Result<()> {
+        let memo = encode_memo(&memo)?;
+        let mint_units = common::load_if_initialized::<MintTradeUnits>(&ctx.accounts.trade_units);
+        ctx.accounts.config.check_trade_amount(
+            amount,
+            ctx.accounts.token_mint.decimals,
+            mint_units.as_ref(),
+        )?;
+        // The offer program knows who made the offer, and so which side funds it
+        common::check_offer_depositor(
+            &ctx.accounts.offer_program.to_account_info(),
+            &ctx.accounts.offer.to_account_info(),
+            &ctx.accounts.seller.to_account_info(),
+            offer_type,
+        )?;
+
+        let trade_id = ctx.accounts.registry.next_id()?;
         let trade = &mut ctx.accounts.trade;
+        trade.id = trade_id;
         trade.seller = ctx.accounts.seller.key();
         trade.buyer = None;
         trade.amount = amount;
@@ -27,124 +430,285 @@ pub mod trade {
         trade.created_at = Clock::get()?.unix_timestamp;
         trade.updated_at = Clock::get()?.unix_timestamp;
         trade.bump = ctx.bumps.trade;
+        trade.offer = ctx.accounts.offer.key();
+        trade.version = TRADE_VERSION;
+        trade.taker_lock_seconds = taker_lock_seconds;
+        trade.accepted_at = 0;
+        trade.disputed_at = 0;
+        trade.dispute_available_at = 0;
+        trade.offer_type = offer_type;
+        trade.memo = memo;
+
+        let config = &mut ctx.accounts.config;
+        let creation_fee = config.creation_fee_lamports;
+        trade.creation_fee_paid = creation_fee;
+        config.refundable_creation_fees =
+            config.refundable_creation_fees.saturating_add(creation_fee);
+        if creation_fee > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.seller.to_account_info(),
+                        to: ctx.accounts.config.to_account_info(),
+                    },
+                ),
+                creation_fee,
+            )?;
+        }
 
         // Transfer tokens to escrow
-        let transfer_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            token::Transfer {
-                from: ctx.accounts.seller_token_account.to_account_info(),
-                to: ctx.accounts.escrow_account.to_account_info(),
-                authority: ctx.accounts.seller.to_account_info(),
-            },
-        );
-        token::transfer(transfer_ctx, amount)?;
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.escrow_account,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.seller.to_account_info(),
+            &[],
+            amount,
+        )?;
+
+        // A transfer fee on the mint is withheld from the escrow, the trade
+        // covers what actually arrived
+        ctx.accounts.escrow_account.reload()?;
+        let received = ctx.accounts.escrow_account.amount;
+        ctx.accounts.trade.amount = received;
+
+        // Reserve the amount against the offer so it cannot be oversold
+        let seller_key = ctx.accounts.seller.key();
+        let token_mint = ctx.accounts.token_mint.key();
+        let seeds = &[
+            b"trade",
+            seller_key.as_ref(),
+            token_mint.as_ref(),
+            &[ctx.bumps.trade],
+        ];
+        common::reserve_offer_amount(
+            &ctx.accounts.offer_program.to_account_info(),
+            &ctx.accounts.offer.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.trade.to_account_info(),
+            &[&seeds[..]],
+            received,
+        )?;
 
+        emit!(TradeCreated {
+            trade: ctx.accounts.trade.key(),
+            trade_id,
+            seller: seller_key,
+            offer: ctx.accounts.offer.key(),
+            amount: received,
+            memo: decode_memo(&memo),
+        });
         msg!("Trade created successfully");
         Ok(())
     }
 
+    /// Upgrades a trade created before `TRADE_VERSION` in place. Trades
+    /// allocated with less than `Trade::LEN` bytes can't be loaded as a
+    /// `Trade` at all, so they are grown first, `payer` covering the extra
+    /// rent.
+    pub fn migrate_trade(ctx: Context<MigrateTrade>) -> Result<()> {
+        let trade = ctx.accounts.trade.to_account_info();
+        if trade.data_len() < Trade::LEN {
+            let rent = Rent::get()?
+                .minimum_balance(Trade::LEN)
+                .saturating_sub(trade.lamports());
+            if rent > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.payer.to_account_info(),
+                            to: trade.clone(),
+                        },
+                    ),
+                    rent,
+                )?;
+            }
+            trade.realloc(Trade::LEN, true)?;
+        }
+        let from_version = migrate_trade_data(&mut trade.try_borrow_mut_data()?)?;
+
+        msg!(
+            "Trade migrated from version {} to {}",
+            from_version,
+            TRADE_VERSION
+        );
+        Ok(())
+    }
+
+    /// Takes the open trade as its buyer. The offer program rejects takers
+    /// below the offer's minimum reputation, which is the seller on a buy
+    /// offer.
     pub fn accept_trade(ctx: Context<AcceptTrade>) -> Result<()> {
-        let trade = &mut ctx.accounts.trade;
         require!(
-            trade.status == TradeStatus::Open,
+            ctx.accounts.trade.status == TradeStatus::Open,
             TradeError::InvalidTradeStatus
         );
+        let offer = ctx.accounts.offer.as_deref();
+        if let Some(offer) = ctx.accounts.trade.reserved_offer(offer)? {
+            common::check_offer_taker(
+                &ctx.accounts.offer_program.to_account_info(),
+                &offer,
+                &ctx.accounts.trade.seller,
+                &ctx.accounts.buyer.to_account_info(),
+                &ctx.accounts.taker_profile.to_account_info(),
+                &ctx.accounts.maker_profile.to_account_info(),
+            )?;
+        }
+
+        let trade = &mut ctx.accounts.trade;
+        // A taker accepting earns the protocol the creation fee
+        let config = &mut ctx.accounts.config;
+        config.refundable_creation_fees = config
+            .refundable_creation_fees
+            .saturating_sub(trade.creation_fee_paid);
 
         trade.buyer = Some(ctx.accounts.buyer.key());
         trade.status = TradeStatus::InProgress;
         trade.updated_at = Clock::get()?.unix_timestamp;
+        trade.accepted_at = trade.updated_at;
+        trade.dispute_available_at =
+            trade.accepted_at + i64::from(ctx.accounts.config.dispute_cooldown_seconds);
 
         msg!("Trade accepted successfully");
         Ok(())
     }
 
-    pub fn complete_trade(ctx: Context<CompleteTrade>) -> Result<()> {
-        require!(
-            ctx.accounts.trade.status == TradeStatus::InProgress,
-            TradeError::InvalidTradeStatus
-        );
+    /// Releases the escrow to the buyer. With `unwrap` set on a wSOL trade the
+    /// buyer's token account is closed afterwards, paying its wrapped SOL and
+    /// rent to the buyer. Other mints ignore `unwrap`. The oracle's live USD
+    /// price has to be within `min_acceptable_price..=max_acceptable_price`.
+    pub fn complete_trade(
+        ctx: Context<CompleteTrade>,
+        unwrap: bool,
+        min_acceptable_price: u64,
+        max_acceptable_price: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.trade.check_completion(
+            ctx.accounts.escrow_account.amount,
+            &ctx.accounts.price_oracle,
+            min_acceptable_price,
+            max_acceptable_price,
+            now,
+        )?;
 
-        // Verify price using CPI
-        let cpi_program = ctx.accounts.price_program.to_account_info();
-        let cpi_accounts = price::cpi::accounts::VerifyPrice {
-            oracle: ctx.accounts.price_oracle.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-
-        // Call verify_price_for_trade with the correct parameters
-        price::cpi::verify_price_for_trade(
-            cpi_ctx,
-            ctx.accounts.trade.price,
-            "USD".to_string(),
-            100, // 1% tolerance
+        let quote = ctx.accounts.config.quote_for_mint(
+            ctx.accounts.trade.amount,
+            &ctx.accounts.token_mint.to_account_info(),
+            Clock::get()?.epoch,
         )?;
+        let offer = ctx.accounts.offer.as_deref();
+        let offer = ctx.accounts.trade.reserved_offer(offer)?;
+        Completion {
+            trade: &mut ctx.accounts.trade,
+            seller: ctx.accounts.seller.to_account_info(),
+            buyer: ctx.accounts.buyer.to_account_info(),
+            escrow_account: &ctx.accounts.escrow_account,
+            buyer_token_account: &ctx.accounts.buyer_token_account,
+            token_mint: &ctx.accounts.token_mint,
+            fee_collector: ctx.accounts.fee_collector.as_deref(),
+            buyer_profile: ctx.accounts.buyer_profile.to_account_info(),
+            seller_profile: ctx.accounts.seller_profile.to_account_info(),
+            offer,
+            token_program: &ctx.accounts.token_program,
+            profile_program: ctx.accounts.profile_program.to_account_info(),
+            offer_program: ctx.accounts.offer_program.to_account_info(),
+        }
+        .settle(&quote, now)?;
 
-        // Transfer tokens from escrow to buyer
-        let trade_account_info = ctx.accounts.trade.to_account_info();
-        let seller_key = ctx.accounts.seller.key();
-        let token_mint = ctx.accounts.trade.token_mint;
-        let seeds = &[
-            b"trade",
-            seller_key.as_ref(),
-            token_mint.as_ref(),
-            &[ctx.accounts.trade.bump],
-        ];
-        let signer = &[&seeds[..]];
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            token::Transfer {
-                from: ctx.accounts.escrow_account.to_account_info(),
-                to: ctx.accounts.buyer_token_account.to_account_info(),
-                authority: trade_account_info,
-            },
-            signer,
-        );
-        token::transfer(transfer_ctx, ctx.accounts.trade.amount)?;
+        if unwrap && ctx.accounts.token_mint.key() == token::spl_token::native_mint::ID {
+            token_interface::close_account(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: ctx.accounts.buyer_token_account.to_account_info(),
+                    destination: ctx.accounts.buyer.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ))?;
+            msg!("Unwrapped the buyer's wSOL");
+        }
 
-        // Update profiles using CPI
-        let buyer_profile_ctx = CpiContext::new(
-            ctx.accounts.profile_program.to_account_info(),
-            profile::cpi::accounts::RecordTrade {
-                profile: ctx.accounts.buyer_profile.to_account_info(),
-                owner: ctx.accounts.buyer.to_account_info(),
-                trade_program: ctx.accounts.trade.to_account_info(),
-            },
+        msg!(
+            "Trade completed successfully, {} to the buyer and {} in fees",
+            quote.net_amount,
+            quote.fee_amount
         );
-        profile::cpi::record_trade_completion(buyer_profile_ctx)?;
+        Ok(())
+    }
 
-        let seller_profile_ctx = CpiContext::new(
-            ctx.accounts.profile_program.to_account_info(),
-            profile::cpi::accounts::RecordTrade {
-                profile: ctx.accounts.seller_profile.to_account_info(),
-                owner: ctx.accounts.seller.to_account_info(),
-                trade_program: ctx.accounts.trade.to_account_info(),
-            },
+    /// Completes every trade laid out in the remaining accounts as described on
+    /// `BATCH_TRADE_ACCOUNTS`, up to `MAX_BATCH_TRADES`, with the checks and
+    /// payouts of `complete_trade`. A trade that cannot be completed is skipped
+    /// instead of failing the batch, and one result per trade is returned in
+    /// input order through return data. A failed token transfer can't be caught
+    /// and would still fail the whole batch, so the escrow balance and frozen
+    /// token accounts are checked before any transfer.
+    pub fn complete_trades_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CompleteTradesBatch<'info>>,
+        min_acceptable_price: u64,
+        max_acceptable_price: u64,
+    ) -> Result<Vec<TradeCompletionResult>> {
+        let batch = ctx.remaining_accounts.chunks_exact(BATCH_TRADE_ACCOUNTS);
+        require!(
+            batch.remainder().is_empty() && batch.len() <= MAX_BATCH_TRADES,
+            TradeError::InvalidBatchAccounts
         );
-        profile::cpi::record_trade_completion(seller_profile_ctx)?;
 
-        // Update trade status after all CPIs
-        let trade = &mut ctx.accounts.trade;
-        trade.status = TradeStatus::Completed;
-        trade.updated_at = Clock::get()?.unix_timestamp;
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        let bounds = (min_acceptable_price, max_acceptable_price);
+        let mut results = Vec::with_capacity(batch.len());
+        for accounts in batch {
+            let result = match BatchTrade::load(accounts, ctx.accounts, bounds, &clock) {
+                Ok(mut batch_trade) => {
+                    batch_trade.complete(ctx.accounts, now)?;
+                    TradeCompletionResult::Completed
+                }
+                Err(result) => {
+                    msg!("Skipped trade {}: {:?}", accounts[0].key(), result);
+                    result
+                }
+            };
+            results.push(result);
+        }
 
-        msg!("Trade completed successfully");
-        Ok(())
+        let completed = results
+            .iter()
+            .filter(|result| **result == TradeCompletionResult::Completed)
+            .count();
+        msg!("Completed {} of {} trades", completed, results.len());
+        Ok(results)
     }
 
+    /// Cancels a trade, for the reason `Trade::derive_cancel_reason` derives. A
+    /// canceller at fault loses reputation if they have a profile. The
+    /// creation fee is only given back to a seller withdrawing a trade no
+    /// taker accepted, see `Trade::creation_fee_refund`.
     pub fn cancel_trade(ctx: Context<CancelTrade>) -> Result<()> {
         // Verify trade status and store values we need
         let bump;
         let token_mint;
         let amount;
+        let reason;
+        let fee_refund;
+        let now = Clock::get()?.unix_timestamp;
         {
             let trade = &ctx.accounts.trade;
-            require!(
-                trade.status == TradeStatus::Open,
-                TradeError::InvalidTradeStatus
-            );
+            reason = trade.derive_cancel_reason(
+                &ctx.accounts.canceller.key(),
+                ctx.accounts
+                    .counterparty
+                    .as_ref()
+                    .map(|signer| signer.key()),
+                now,
+            )?;
             bump = trade.bump;
             token_mint = trade.token_mint;
             amount = trade.amount;
+            fee_refund = trade.creation_fee_refund();
         }
 
         let seller_key = ctx.accounts.seller.key();
@@ -153,194 +717,1831 @@ pub mod trade {
         // Return tokens from escrow to seller
         let seeds = &[b"trade", seller_key.as_ref(), token_mint.as_ref(), &[bump]];
         let signer = &[&seeds[..]];
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            token::Transfer {
-                from: ctx.accounts.escrow_account.to_account_info(),
-                to: ctx.accounts.seller_token_account.to_account_info(),
-                authority: trade_account_info,
-            },
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_account,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.token_mint,
+            &trade_account_info,
             signer,
-        );
-        token::transfer(transfer_ctx, amount)?;
+            amount,
+        )?;
+
+        // Give the reserved amount back to the offer
+        let offer = ctx.accounts.offer.as_deref();
+        if let Some(offer) = ctx.accounts.trade.reserved_offer(offer)? {
+            common::release_offer_amount(
+                &ctx.accounts.offer_program.to_account_info(),
+                &offer,
+                &ctx.accounts.token_mint.to_account_info(),
+                &ctx.accounts.trade.to_account_info(),
+                signer,
+                amount,
+            )?;
+        }
+
+        // Profiles are optional, so a canceller without one has nothing to lose
+        let canceller_profile = ctx.accounts.canceller_profile.to_account_info();
+        if reason.is_at_fault()
+            && common::load_if_initialized::<ProfileAccount>(&canceller_profile).is_some()
+        {
+            profile::cpi::record_trade_cancellation(CpiContext::new_with_signer(
+                ctx.accounts.profile_program.to_account_info(),
+                profile::cpi::accounts::RecordTrade {
+                    profile: canceller_profile,
+                    owner: ctx.accounts.canceller.to_account_info(),
+                    trade: trade_account_info,
+                },
+                signer,
+            ))?;
+        }
+
+        // The config only pays out fees it holds above its own rent
+        ctx.accounts.config.refundable_creation_fees = ctx
+            .accounts
+            .config
+            .refundable_creation_fees
+            .saturating_sub(fee_refund);
+        let config = ctx.accounts.config.to_account_info();
+        let available = config
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(config.data_len()));
+        let fee_refund = fee_refund.min(available);
+        if fee_refund > 0 {
+            **config.try_borrow_mut_lamports()? -= fee_refund;
+            **ctx.accounts.seller.try_borrow_mut_lamports()? += fee_refund;
+        }
 
         // Update trade status
         let trade = &mut ctx.accounts.trade;
         trade.status = TradeStatus::Cancelled;
-        trade.updated_at = Clock::get()?.unix_timestamp;
+        trade.cancel_reason = Some(reason);
+        trade.updated_at = now;
+        trade.creation_fee_paid -= fee_refund;
 
+        emit!(TradeCancelled {
+            trade: trade.key(),
+            trade_id: trade.id,
+            canceller: ctx.accounts.canceller.key(),
+            reason,
+            fee_refund,
+        });
         msg!("Trade cancelled successfully");
         Ok(())
     }
 
-    pub fn dispute_trade(ctx: Context<DisputeTrade>) -> Result<()> {
+    /// Lets the seller add whatever the escrow is missing of the trade
+    /// amount, so a short escrow doesn't leave `complete_trade` stuck.
+    pub fn top_up_escrow(ctx: Context<TopUpEscrow>) -> Result<()> {
+        let shortfall = ctx
+            .accounts
+            .trade
+            .escrow_shortfall(ctx.accounts.escrow_account.amount)?;
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.escrow_account,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.seller.to_account_info(),
+            &[],
+            shortfall,
+        )?;
+
+        // A transfer fee would leave the escrow short again
+        ctx.accounts.escrow_account.reload()?;
         let trade = &mut ctx.accounts.trade;
+        trade.check_escrow_funded(ctx.accounts.escrow_account.amount)?;
+        trade.updated_at = Clock::get()?.unix_timestamp;
 
-        // Verify disputer is either buyer or seller
-        let disputer_key = ctx.accounts.disputer.key();
-        require!(
-            trade.seller == disputer_key || trade.buyer == Some(disputer_key),
-            TradeError::UnauthorizedDisputer
-        );
+        emit!(EscrowToppedUp {
+            trade: trade.key(),
+            trade_id: trade.id,
+            amount: shortfall,
+        });
+        msg!("Escrow topped up with {}", shortfall);
+        Ok(())
+    }
+
+    /// Moves a dust balance left in a settled trade's escrow to the fee
+    /// collector, so `close_trade` can reclaim the escrow's rent afterwards.
+    pub fn sweep_dust(ctx: Context<SweepDust>) -> Result<()> {
+        let dust = ctx.accounts.escrow_account.amount;
+        let trade = &ctx.accounts.trade;
+        trade.check_sweep(&ctx.accounts.sweeper.key(), &ctx.accounts.config, dust)?;
+
+        let seeds = &[
+            b"trade",
+            trade.seller.as_ref(),
+            trade.token_mint.as_ref(),
+            &[trade.bump],
+        ];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_account,
+            &ctx.accounts.fee_collector,
+            &ctx.accounts.token_mint,
+            &trade.to_account_info(),
+            &[&seeds[..]],
+            dust,
+        )?;
+
+        msg!("Swept {} dust to the fee collector", dust);
+        Ok(())
+    }
+
+    /// Sends collected trade fees to a treasury account, the whole collector
+    /// balance when no `amount` is given.
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: Option<u64>) -> Result<()> {
+        let fee_collector = &ctx.accounts.fee_collector;
+        let amount = fee_withdrawal_amount(amount, fee_collector.amount)?;
+
+        let config = &ctx.accounts.config;
+        let seeds = &[b"trade_config".as_ref(), &[config.bump]];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            fee_collector,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_mint,
+            &config.to_account_info(),
+            &[&seeds[..]],
+            amount,
+        )?;
+
+        emit!(FeesWithdrawn {
+            fee_collector: fee_collector.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Sends creation fee lamports the config has collected to `destination`,
+    /// all of them when no `amount` is given. The config keeps its own rent
+    /// and the fees of trades that can still be withdrawn with a refund.
+    pub fn withdraw_creation_fees(
+        ctx: Context<WithdrawCreationFees>,
+        amount: Option<u64>,
+    ) -> Result<()> {
+        let refundable = ctx.accounts.config.refundable_creation_fees;
+        let config = ctx.accounts.config.to_account_info();
+        let collected = config
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(config.data_len()))
+            .saturating_sub(refundable);
+        let amount = fee_withdrawal_amount(amount, collected)?;
+        **config.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
 
+        emit!(FeesWithdrawn {
+            fee_collector: config.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Reclaims the rent of a settled trade: the empty escrow and the trade
+    /// account are both closed to the seller, who paid for them.
+    pub fn close_trade(ctx: Context<CloseTrade>) -> Result<()> {
+        let trade = &ctx.accounts.trade;
+        trade.check_close(&ctx.accounts.closer.key())?;
         require!(
-            trade.status == TradeStatus::InProgress,
-            TradeError::InvalidTradeStatus
+            ctx.accounts.escrow_account.amount == 0,
+            TradeError::EscrowNotEmpty
         );
 
+        let seeds = &[
+            b"trade",
+            trade.seller.as_ref(),
+            trade.token_mint.as_ref(),
+            &[trade.bump],
+        ];
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::CloseAccount {
+                account: ctx.accounts.escrow_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: trade.to_account_info(),
+            },
+            &[&seeds[..]],
+        ))?;
+
+        msg!("Trade closed, rent returned to the seller");
+        Ok(())
+    }
+
+    pub fn dispute_trade(ctx: Context<DisputeTrade>) -> Result<()> {
+        let trade = &mut ctx.accounts.trade;
+        let now = Clock::get()?.unix_timestamp;
+        trade.check_dispute(&ctx.accounts.disputer.key(), now)?;
+
         trade.status = TradeStatus::Disputed;
-        trade.updated_at = Clock::get()?.unix_timestamp;
+        trade.updated_at = now;
+        trade.disputed_at = trade.updated_at;
+
+        let seller_key = trade.seller;
+        let token_mint = trade.token_mint;
+        let bump = trade.bump;
+        let seeds = &[b"trade", seller_key.as_ref(), token_mint.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+
+        // A dispute counts against both parties' completion rate, as far as
+        // they have a profile
+        let parties = [
+            (&ctx.accounts.seller_profile, &ctx.accounts.seller),
+            (&ctx.accounts.buyer_profile, &ctx.accounts.buyer),
+        ];
+        for (profile, owner) in parties {
+            let profile = profile.to_account_info();
+            if common::load_if_initialized::<ProfileAccount>(&profile).is_none() {
+                continue;
+            }
+            profile::cpi::record_trade_dispute(CpiContext::new_with_signer(
+                ctx.accounts.profile_program.to_account_info(),
+                profile::cpi::accounts::RecordTrade {
+                    profile,
+                    owner: owner.to_account_info(),
+                    trade: ctx.accounts.trade.to_account_info(),
+                },
+                signer,
+            ))?;
+        }
 
         msg!("Trade disputed successfully");
         Ok(())
     }
-}
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum TradeStatus {
-    Open,
-    InProgress,
-    Completed,
-    Cancelled,
-    Disputed,
-}
+    /// Settles a disputed trade, arbitrated by the config admin. The escrow
+    /// goes to the winner and the loser's profile records the lost dispute. A
+    /// buyer who wins is paid as `complete_trade` would, less the protocol fee.
+    /// A refund to the seller carries no fee, like a cancellation.
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, buyer_wins: bool) -> Result<()> {
+        require!(
+            ctx.accounts.trade.status == TradeStatus::Disputed,
+            TradeError::InvalidTradeStatus
+        );
 
-#[account]
-pub struct Trade {
-    pub seller: Pubkey,
-    pub buyer: Option<Pubkey>,
-    pub amount: u64,
-    pub price: u64,
-    pub token_mint: Pubkey,
-    pub escrow_account: Pubkey,
-    pub status: TradeStatus,
-    pub created_at: i64,
-    pub updated_at: i64,
-    pub bump: u8,
-}
+        let trade_account_info = ctx.accounts.trade.to_account_info();
+        let seller_key = ctx.accounts.seller.key();
+        let token_mint = ctx.accounts.trade.token_mint;
+        let amount = ctx.accounts.trade.amount;
+        let seeds = &[
+            b"trade",
+            seller_key.as_ref(),
+            token_mint.as_ref(),
+            &[ctx.accounts.trade.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let (payout, fee_amount) = if buyer_wins {
+            let quote = ctx.accounts.config.quote_for_mint(
+                amount,
+                &ctx.accounts.token_mint.to_account_info(),
+                Clock::get()?.epoch,
+            )?;
+            (quote.payout_amount(), quote.fee_amount)
+        } else {
+            (amount, 0)
+        };
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_account,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.token_mint,
+            &trade_account_info,
+            signer,
+            payout,
+        )?;
+        if fee_amount > 0 {
+            let fee_collector = ctx
+                .accounts
+                .fee_collector
+                .as_ref()
+                .ok_or(TradeError::MissingFeeCollector)?;
+            transfer_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_account,
+                fee_collector,
+                &ctx.accounts.token_mint,
+                &trade_account_info,
+                signer,
+                fee_amount,
+            )?;
+        }
 
-#[derive(Accounts)]
-#[instruction(amount: u64, price: u64)]
+        // Escrow paid to the buyer fills the offer like a completion, escrow
+        // returned to the seller ends the trade like a cancellation
+        let settle_reservation = if buyer_wins {
+            common::consume_offer_amount
+        } else {
+            common::release_offer_amount
+        };
+        let offer = ctx.accounts.offer.as_deref();
+        if let Some(offer) = ctx.accounts.trade.reserved_offer(offer)? {
+            settle_reservation(
+                &ctx.accounts.offer_program.to_account_info(),
+                &offer,
+                &ctx.accounts.token_mint.to_account_info(),
+                &trade_account_info,
+                signer,
+                amount,
+            )?;
+        }
+
+        let loser = if buyer_wins {
+            ctx.accounts.seller.to_account_info()
+        } else {
+            ctx.accounts.buyer.to_account_info()
+        };
+        let loser_profile_ctx = CpiContext::new_with_signer(
+            ctx.accounts.profile_program.to_account_info(),
+            profile::cpi::accounts::RecordTrade {
+                profile: ctx.accounts.loser_profile.to_account_info(),
+                owner: loser,
+                trade: trade_account_info,
+            },
+            signer,
+        );
+        profile::cpi::record_dispute_loss(loser_profile_ctx)?;
+
+        let trade = &mut ctx.accounts.trade;
+        trade.status = if buyer_wins {
+            TradeStatus::Completed
+        } else {
+            TradeStatus::Cancelled
+        };
+        trade.updated_at = Clock::get()?.unix_timestamp;
+
+        msg!(
+            "Dispute resolved in favor of the {}",
+            if buyer_wins { "buyer" } else { "seller" }
+        );
+        Ok(())
+    }
+
+    /// Refunds a dispute the arbitrator left unresolved past the configured
+    /// TTL back to the seller. Callable by anyone so funds cannot stay frozen.
+    pub fn dispute_timeout(ctx: Context<DisputeTimeout>) -> Result<()> {
+        // Trades disputed before `disputed_at` existed count from their last update
+        if ctx.accounts.trade.version < TRADE_VERSION {
+            ctx.accounts.trade.migrate();
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let overdue_seconds = ctx
+            .accounts
+            .trade
+            .dispute_overdue_seconds(ctx.accounts.config.dispute_ttl_seconds, now)?;
+
+        let trade_account_info = ctx.accounts.trade.to_account_info();
+        let seller_key = ctx.accounts.seller.key();
+        let token_mint = ctx.accounts.trade.token_mint;
+        let amount = ctx.accounts.trade.amount;
+        let seeds = &[
+            b"trade",
+            seller_key.as_ref(),
+            token_mint.as_ref(),
+            &[ctx.accounts.trade.bump],
+        ];
+        let signer = &[&seeds[..]];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_account,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.token_mint,
+            &trade_account_info,
+            signer,
+            amount,
+        )?;
+
+        let offer = ctx.accounts.offer.as_deref();
+        if let Some(offer) = ctx.accounts.trade.reserved_offer(offer)? {
+            common::release_offer_amount(
+                &ctx.accounts.offer_program.to_account_info(),
+                &offer,
+                &ctx.accounts.token_mint.to_account_info(),
+                &trade_account_info,
+                signer,
+                amount,
+            )?;
+        }
+
+        let trade = &mut ctx.accounts.trade;
+        trade.status = TradeStatus::Cancelled;
+        trade.cancel_reason = Some(CancelReason::Expired);
+        trade.updated_at = now;
+
+        emit!(DisputeTimedOut {
+            trade: trade.key(),
+            trade_id: trade.id,
+            overdue_seconds,
+        });
+        msg!("Dispute timed out, escrow refunded to the seller");
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ProfileSummary {
+    pub owner: Pubkey,
+    pub reputation_score: u32,
+    pub trades_completed: u32,
+    pub trades_disputed: u32,
+    pub disputes_lost: u32,
+    pub is_verified: bool,
+}
+
+impl ProfileSummary {
+    fn load(info: &AccountInfo) -> Option<Self> {
+        common::load_if_initialized::<ProfileAccount>(info).map(|profile| ProfileSummary {
+            owner: profile.owner,
+            reputation_score: profile.reputation_score,
+            trades_completed: profile.trades_completed,
+            trades_disputed: profile.trades_disputed,
+            disputes_lost: profile.disputes_lost,
+            is_verified: profile.is_verified,
+        })
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct TradeSummary {
+    pub seller: Pubkey,
+    pub buyer: Option<Pubkey>,
+    pub amount: u64,
+    pub price: u64,
+    pub token_mint: Pubkey,
+    pub status: TradeStatus,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub seller_profile: Option<ProfileSummary>,
+    pub buyer_profile: Option<ProfileSummary>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct TradeQuote {
+    /// What the seller escrows.
+    pub gross_amount: u64,
+    pub fee_amount: u64,
+    /// What the buyer receives.
+    pub net_amount: u64,
+    /// What a Token-2022 mint's transfer fee withholds from the buyer's payout.
+    pub transfer_fee: u64,
+}
+
+impl TradeQuote {
+    /// What leaves the escrow for the buyer, before the transfer fee.
+    pub fn payout_amount(&self) -> u64 {
+        self.net_amount + self.transfer_fee
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ProgramHealth {
+    pub version: String,
+    pub initialized: bool,
+    pub paused: bool,
+}
+
+/// Outcome of a single trade of `complete_trades_batch`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum TradeCompletionResult {
+    Completed,
+    /// The trade is not in progress.
+    InvalidStatus,
+    /// The seller or the buyer did not sign.
+    MissingSignature,
+    /// An account does not belong to the trade.
+    InvalidAccounts,
+    /// The trade price is off the oracle price, the oracle price is stale or
+    /// outside the accepted price bounds.
+    PriceRejected,
+    /// The escrow holds less than the trade amount.
+    EscrowUnderfunded,
+}
+
+impl TradeCompletionResult {
+    /// Result of a trade that failed `Trade::check_completion` with `err`.
+    fn from_completion_error(err: &Error) -> Self {
+        if *err == TradeError::EscrowUnderfunded.into() {
+            TradeCompletionResult::EscrowUnderfunded
+        } else if *err == TradeError::InvalidTradeStatus.into()
+            || *err == TradeError::TradeAlreadyCompleted.into()
+        {
+            TradeCompletionResult::InvalidStatus
+        } else {
+            TradeCompletionResult::PriceRejected
+        }
+    }
+}
+
+/// Accounts a completion pays the escrow out to and records on, shared by
+/// `complete_trade` and `complete_trades_batch`.
+struct Completion<'a, 'info> {
+    trade: &'a mut Account<'info, Trade>,
+    seller: AccountInfo<'info>,
+    buyer: AccountInfo<'info>,
+    escrow_account: &'a InterfaceAccount<'info, TokenAccount>,
+    buyer_token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    token_mint: &'a InterfaceAccount<'info, Mint>,
+    fee_collector: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    buyer_profile: AccountInfo<'info>,
+    seller_profile: AccountInfo<'info>,
+    /// `None` for a trade that reserved against no offer, see
+    /// `Trade::reserved_offer`
+    offer: Option<AccountInfo<'info>>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    profile_program: AccountInfo<'info>,
+    offer_program: AccountInfo<'info>,
+}
+
+impl<'a, 'info> Completion<'a, 'info> {
+    /// Marks the trade completed, pays the buyer and the fee collector as
+    /// `quote` splits the escrow, fills the offer with the trade's reservation,
+    /// if it has one, and records the completion on both profiles, which
+    /// earns each `profile::COMPLETION_REWARD` reputation.
+    /// The trade must have passed `Trade::check_completion`.
+    fn settle(self, quote: &TradeQuote, now: i64) -> Result<()> {
+        // Persist the completed status before any CPI, so a re-entrant call
+        // already sees the trade as completed
+        self.trade.status = TradeStatus::Completed;
+        self.trade.updated_at = now;
+        self.trade.exit(&crate::ID)?;
+
+        let trade_account_info = self.trade.to_account_info();
+        let seeds = &[
+            TRADE_SEED,
+            self.trade.seller.as_ref(),
+            self.trade.token_mint.as_ref(),
+            &[self.trade.bump],
+        ];
+        let signer = &[&seeds[..]];
+        log_compute_units!("complete_trade: escrow transfer");
+        transfer_tokens(
+            self.token_program,
+            self.escrow_account,
+            self.buyer_token_account,
+            self.token_mint,
+            &trade_account_info,
+            signer,
+            quote.payout_amount(),
+        )?;
+        if quote.fee_amount > 0 {
+            let fee_collector = self.fee_collector.ok_or(TradeError::MissingFeeCollector)?;
+            transfer_tokens(
+                self.token_program,
+                self.escrow_account,
+                fee_collector,
+                self.token_mint,
+                &trade_account_info,
+                signer,
+                quote.fee_amount,
+            )?;
+        }
+
+        if let Some(offer) = &self.offer {
+            common::consume_offer_amount(
+                &self.offer_program,
+                offer,
+                &self.token_mint.to_account_info(),
+                &trade_account_info,
+                signer,
+                self.trade.amount,
+            )?;
+        }
+
+        log_compute_units!("complete_trade: profile CPIs");
+        for (profile, owner) in [
+            (self.buyer_profile, self.buyer),
+            (self.seller_profile, self.seller),
+        ] {
+            profile::cpi::record_trade_completion(CpiContext::new_with_signer(
+                self.profile_program.clone(),
+                profile::cpi::accounts::RecordTrade {
+                    profile: profile.clone(),
+                    owner: owner.clone(),
+                    trade: trade_account_info.clone(),
+                },
+                signer,
+            ))?;
+            profile::cpi::update_reputation(
+                CpiContext::new_with_signer(
+                    self.profile_program.clone(),
+                    profile::cpi::accounts::UpdateReputation {
+                        profile,
+                        trade: trade_account_info.clone(),
+                        owner,
+                    },
+                    signer,
+                ),
+                profile::COMPLETION_REWARD as i32,
+            )?;
+        }
+        log_compute_units!("complete_trade: profiles updated");
+        Ok(())
+    }
+}
+
+/// A trade of `complete_trades_batch` whose accounts passed every check
+/// `CompleteTrade` and `complete_trade` would make.
+struct BatchTrade<'info> {
+    trade: Account<'info, Trade>,
+    seller: &'info AccountInfo<'info>,
+    buyer: &'info AccountInfo<'info>,
+    escrow_account: InterfaceAccount<'info, TokenAccount>,
+    buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+    token_mint: InterfaceAccount<'info, Mint>,
+    buyer_profile: &'info AccountInfo<'info>,
+    seller_profile: &'info AccountInfo<'info>,
+    offer: Option<&'info AccountInfo<'info>>,
+    fee_collector: Option<InterfaceAccount<'info, TokenAccount>>,
+    quote: TradeQuote,
+}
+
+impl<'info> BatchTrade<'info> {
+    fn load(
+        accounts: &'info [AccountInfo<'info>],
+        shared: &CompleteTradesBatch<'info>,
+        (min_price, max_price): (u64, u64),
+        clock: &Clock,
+    ) -> std::result::Result<Self, TradeCompletionResult> {
+        use TradeCompletionResult::*;
+        let invalid = |_: Error| InvalidAccounts;
+
+        let trade: Account<Trade> = Account::try_from(&accounts[0]).map_err(invalid)?;
+        if trade.status != TradeStatus::InProgress {
+            return Err(InvalidStatus);
+        }
+        let (seller, buyer) = (&accounts[1], &accounts[2]);
+        if seller.key() != trade.seller || trade.buyer != Some(buyer.key()) {
+            return Err(InvalidAccounts);
+        }
+        if !seller.is_signer || !buyer.is_signer {
+            return Err(MissingSignature);
+        }
+
+        let escrow_account: InterfaceAccount<TokenAccount> =
+            InterfaceAccount::try_from(&accounts[3]).map_err(invalid)?;
+        let buyer_token_account: InterfaceAccount<TokenAccount> =
+            InterfaceAccount::try_from(&accounts[4]).map_err(invalid)?;
+        let token_mint: InterfaceAccount<Mint> =
+            InterfaceAccount::try_from(&accounts[5]).map_err(invalid)?;
+        if escrow_account.key() != trade.escrow_account
+            || *accounts[3].owner != shared.token_program.key()
+            || buyer_token_account.mint != trade.token_mint
+            || buyer_token_account.owner != buyer.key()
+            || token_mint.key() != trade.token_mint
+            || escrow_account.is_frozen()
+            || buyer_token_account.is_frozen()
+        {
+            return Err(InvalidAccounts);
+        }
+
+        let (buyer_profile, seller_profile) = (&accounts[6], &accounts[7]);
+        let is_profile_of = |info: &AccountInfo, owner: &Pubkey| {
+            common::load_if_initialized::<ProfileAccount>(info)
+                .is_some_and(|profile| profile.owner == *owner)
+        };
+        if !is_profile_of(buyer_profile, buyer.key) || !is_profile_of(seller_profile, seller.key) {
+            return Err(InvalidAccounts);
+        }
+        // A trade without an offer ignores whatever is in the offer's slot
+        let offer = if !trade.has_offer() {
+            None
+        } else if accounts[8].key() == trade.offer && accounts[8].is_writable {
+            Some(&accounts[8])
+        } else {
+            return Err(InvalidAccounts);
+        };
+
+        let quote = shared
+            .config
+            .quote_for_mint(trade.amount, &accounts[5], clock.epoch)
+            .map_err(invalid)?;
+        let fee_collector = if quote.fee_amount > 0 {
+            let fee_collector: InterfaceAccount<TokenAccount> =
+                InterfaceAccount::try_from(&accounts[9]).map_err(invalid)?;
+            if fee_collector.owner != shared.config.key()
+                || fee_collector.mint != trade.token_mint
+                || fee_collector.is_frozen()
+            {
+                return Err(InvalidAccounts);
+            }
+            Some(fee_collector)
+        } else {
+            None
+        };
+
+        trade
+            .check_completion(
+                escrow_account.amount,
+                &shared.price_oracle,
+                min_price,
+                max_price,
+                clock.unix_timestamp,
+            )
+            .map_err(|err| TradeCompletionResult::from_completion_error(&err))?;
+
+        Ok(BatchTrade {
+            trade,
+            seller,
+            buyer,
+            escrow_account,
+            buyer_token_account,
+            token_mint,
+            buyer_profile,
+            seller_profile,
+            offer,
+            fee_collector,
+            quote,
+        })
+    }
+
+    fn complete(&mut self, shared: &CompleteTradesBatch<'info>, now: i64) -> Result<()> {
+        Completion {
+            trade: &mut self.trade,
+            seller: self.seller.clone(),
+            buyer: self.buyer.clone(),
+            escrow_account: &self.escrow_account,
+            buyer_token_account: &self.buyer_token_account,
+            token_mint: &self.token_mint,
+            fee_collector: self.fee_collector.as_ref(),
+            buyer_profile: self.buyer_profile.clone(),
+            seller_profile: self.seller_profile.clone(),
+            offer: self.offer.cloned(),
+            token_program: &shared.token_program,
+            profile_program: shared.profile_program.to_account_info(),
+            offer_program: shared.offer_program.to_account_info(),
+        }
+        .settle(&self.quote, now)
+    }
+}
+
+#[account]
+pub struct TradeConfig {
+    pub admin: Pubkey,
+    pub paused: bool,
+    pub bump: u8,
+    /// Seconds before an unresolved dispute can be timed out, 0 disables it.
+    pub dispute_ttl_seconds: u32,
+    /// Protocol fee on completed trades, at most `MAX_FEE_BPS`.
+    pub fee_bps: u16,
+    /// Lamports charged to the seller for each created trade.
+    pub creation_fee_lamports: u64,
+    /// Seconds after acceptance before a trade can be disputed.
+    pub dispute_cooldown_seconds: u32,
+    /// Smallest trade in whole tokens, 0 for no minimum.
+    pub min_trade_units: u64,
+    /// Largest trade in whole tokens, 0 for no maximum.
+    pub max_trade_units: u64,
+    /// Escrow balances below this can be swept by `sweep_dust`, 0 disables it.
+    pub dust_threshold: u64,
+    /// Creation fees of trades no taker accepted yet, which `cancel_trade`
+    /// gives back and `withdraw_creation_fees` leaves in place.
+    pub refundable_creation_fees: u64,
+}
+
+impl TradeConfig {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // admin
+        1 + // paused
+        1 + // bump
+        4 + // dispute_ttl_seconds
+        2 + // fee_bps
+        8 + // creation_fee_lamports
+        4 + // dispute_cooldown_seconds
+        8 + // min_trade_units
+        8 + // max_trade_units
+        8 + // dust_threshold
+        8 + // refundable_creation_fees
+        14; // padding for future updates
+
+    /// Splits `amount` into the protocol fee, rounded down, and the payout.
+    pub fn quote(&self, amount: u64) -> TradeQuote {
+        let fee_amount = (u128::from(amount) * u128::from(self.fee_bps) / 10_000) as u64;
+        TradeQuote {
+            gross_amount: amount,
+            fee_amount,
+            net_amount: amount - fee_amount,
+            transfer_fee: 0,
+        }
+    }
+
+    /// `quote` of `amount` of `mint`, less the transfer fee the mint withholds
+    /// from the buyer's payout in `epoch`.
+    pub fn quote_for_mint(
+        &self,
+        amount: u64,
+        mint: &AccountInfo,
+        epoch: u64,
+    ) -> Result<TradeQuote> {
+        let mut quote = self.quote(amount);
+        quote.transfer_fee = mint_transfer_fee(mint, quote.net_amount, epoch)?;
+        quote.net_amount -= quote.transfer_fee;
+        Ok(quote)
+    }
+
+    /// Fails unless `amount` of a mint with `decimals` lies within the trade
+    /// units set for the mint, or the configured ones if it has none.
+    pub fn check_trade_amount(
+        &self,
+        amount: u64,
+        decimals: u8,
+        mint_units: Option<&MintTradeUnits>,
+    ) -> Result<()> {
+        let (min_units, max_units) = match mint_units {
+            Some(units) => (units.min_trade_units, units.max_trade_units),
+            None => (self.min_trade_units, self.max_trade_units),
+        };
+        if min_units == 0 && max_units == 0 {
+            return Ok(());
+        }
+        // A bound too large to scale is beyond any u64 amount
+        let scale = |units: u64| {
+            10u128
+                .checked_pow(u32::from(decimals))
+                .and_then(|unit| unit.checked_mul(u128::from(units)))
+        };
+        let amount = u128::from(amount);
+        let above_min = match scale(min_units) {
+            Some(min) => amount >= min,
+            None => false,
+        };
+        let below_max = match scale(max_units) {
+            Some(max) => amount <= max,
+            None => true,
+        };
+        require!(
+            (min_units == 0 || above_min) && (max_units == 0 || below_max),
+            TradeError::TradeAmountOutOfRange
+        );
+        Ok(())
+    }
+}
+
+/// Trade units of a single mint, overriding `TradeConfig`'s for its trades.
+#[account]
+pub struct MintTradeUnits {
+    /// Smallest trade in whole tokens, 0 for no minimum.
+    pub min_trade_units: u64,
+    /// Largest trade in whole tokens, 0 for no maximum.
+    pub max_trade_units: u64,
+    pub bump: u8,
+}
+
+impl MintTradeUnits {
+    pub const LEN: usize = 8 + // discriminator
+        8 + // min_trade_units
+        8 + // max_trade_units
+        1 + // bump
+        32; // padding for future updates
+}
+
+/// Numbers trades in the order they are created. Trade PDAs are keyed by
+/// seller and mint, so the id is what tells trades apart over time.
+#[account]
+pub struct TradeRegistry {
+    /// Trades created so far, which is also the id of the latest one.
+    pub trade_count: u64,
+    pub bump: u8,
+}
+
+impl TradeRegistry {
+    pub const LEN: usize = 8 + // discriminator
+        8 + // trade_count
+        1 + // bump
+        32; // padding for future updates
+
+    /// Counts a new trade and returns its id. Ids start at 1.
+    pub fn next_id(&mut self) -> Result<u64> {
+        self.trade_count = self
+            .trade_count
+            .checked_add(1)
+            .ok_or(TradeError::TradeCountOverflow)?;
+        Ok(self.trade_count)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum TradeStatus {
+    Open,
+    InProgress,
+    Completed,
+    Cancelled,
+    Disputed,
+}
+
+/// Mirror of the offer program's `OfferType`, which this program can not
+/// depend on. Sell offers are funded by their maker, buy offers by the taker.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum OfferType {
+    #[default]
+    Sell,
+    Buy,
+}
+
+/// Why a trade was cancelled. Only the cancellations that leave the other
+/// party stranded count against the canceller's reputation.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum CancelReason {
+    /// The seller pulled the trade before a taker accepted it.
+    MakerWithdrew,
+    /// The taker walked away from a trade they accepted.
+    TakerBackedOut,
+    /// The dispute outlived the dispute TTL and was refunded to the seller.
+    Expired,
+    /// Both parties signed off on the cancellation.
+    MutualAgreement,
+}
+
+impl CancelReason {
+    /// Whether the cancellation counts against the canceller.
+    pub fn is_at_fault(&self) -> bool {
+        *self == CancelReason::TakerBackedOut
+    }
+}
+
+#[event]
+pub struct TradeCreated {
+    pub trade: Pubkey,
+    pub trade_id: u64,
+    pub seller: Pubkey,
+    pub offer: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+}
+
+#[event]
+pub struct EscrowToppedUp {
+    pub trade: Pubkey,
+    pub trade_id: u64,
+    pub amount: u64,
+}
+
+/// Fees withdrawn by `withdraw_fees`, or by `withdraw_creation_fees` with the
+/// config as the `fee_collector`.
+#[event]
+pub struct FeesWithdrawn {
+    pub fee_collector: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TradeCancelled {
+    pub trade: Pubkey,
+    pub trade_id: u64,
+    pub canceller: Pubkey,
+    pub reason: CancelReason,
+    /// Creation fee lamports given back to the seller.
+    pub fee_refund: u64,
+}
+
+#[event]
+pub struct DisputeTimedOut {
+    pub trade: Pubkey,
+    pub trade_id: u64,
+    /// How long past the dispute TTL the trade was refunded.
+    pub overdue_seconds: i64,
+}
+
+#[account]
+pub struct Trade {
+    pub seller: Pubkey,
+    pub buyer: Option<Pubkey>,
+    pub amount: u64,
+    pub price: u64,
+    pub token_mint: Pubkey,
+    pub escrow_account: Pubkey,
+    pub status: TradeStatus,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub bump: u8,
+    /// Offer the trade reserved against, the default key for trades opened
+    /// before offers were recorded, see `Trade::has_offer`.
+    pub offer: Pubkey,
+    pub version: u8,
+    /// Seconds after `created_at` the trade is held for takers, during which
+    /// the seller can't withdraw it.
+    pub taker_lock_seconds: u32,
+    /// When the taker accepted, 0 while the trade is open.
+    pub accepted_at: i64,
+    /// When the trade was disputed, 0 if it never was.
+    pub disputed_at: i64,
+    /// Earliest time either party may dispute, set when the taker accepts.
+    pub dispute_available_at: i64,
+    /// Why the trade was cancelled, `None` unless it was cancelled.
+    pub cancel_reason: Option<CancelReason>,
+    /// Type of the offer the trade was opened against, checked by the offer
+    /// program when the seller funded the escrow.
+    pub offer_type: OfferType,
+    /// Off-chain reference the seller attached, like an invoice id. UTF-8
+    /// padded with zeros, see `decode_memo`.
+    pub memo: [u8; MEMO_LEN],
+    /// Creation fee lamports the seller paid and has not been refunded, see
+    /// `Trade::creation_fee_refund`.
+    pub creation_fee_paid: u64,
+    /// Sequence number from the `TradeRegistry`, 0 for trades created before
+    /// the registry existed. Those were allocated short of this field and
+    /// only read it once `migrate_trade` has grown them.
+    pub id: u64,
+}
+
+impl Trade {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // seller
+        (1 + 32) + // buyer (Option<Pubkey>) - 1 for the tag, 32 for the pubkey
+        8 + // amount
+        8 + // price
+        32 + // token_mint
+        32 + // escrow_account
+        2 + // status (1 for enum discriminator, 1 for variant)
+        8 + // created_at
+        8 + // updated_at
+        1 + // bump
+        32 + // offer
+        1 + // version
+        4 + // taker_lock_seconds
+        8 + // accepted_at
+        8 + // disputed_at
+        8 + // dispute_available_at
+        2 + // cancel_reason (Option<CancelReason>)
+        1 + // offer_type
+        MEMO_LEN + // memo
+        8 + // creation_fee_paid
+        8 + // id
+        16; // padding for future updates
+
+    /// Sets defaults for every field added since the trade's version.
+    pub fn migrate(&mut self) {
+        if self.version < 3 {
+            self.taker_lock_seconds = 0;
+            self.accepted_at = 0;
+        }
+        if self.version < 4 {
+            // dispute_trade was the last update of a trade still in dispute
+            self.disputed_at = if self.status == TradeStatus::Disputed {
+                self.updated_at
+            } else {
+                0
+            };
+        }
+        if self.version < 5 {
+            // accepted before the cooldown existed, so disputable right away
+            self.dispute_available_at = 0;
+        }
+        if self.version < 6 {
+            // cancelled before reasons were recorded
+            self.cancel_reason = None;
+        }
+        if self.version < 7 {
+            // every trade was funded by the maker of a sell offer
+            self.offer_type = OfferType::Sell;
+        }
+        if self.version < 8 {
+            // fees were not recorded, so there is nothing to refund
+            self.creation_fee_paid = 0;
+        }
+        if self.version < 9 {
+            // opened before trades were numbered
+            self.id = 0;
+        }
+        self.version = TRADE_VERSION;
+    }
+
+    /// Whether the trade reserved its amount against an offer. Trades opened
+    /// before offers were recorded read a default `offer` once migrated.
+    pub fn has_offer(&self) -> bool {
+        self.offer != Pubkey::default()
+    }
+
+    /// The offer account a settlement has to pass the trade's reservation
+    /// to, out of the `offer` it was given. `None` for a trade without an
+    /// offer, which has no reservation and skips the offer program.
+    pub fn reserved_offer<'info>(
+        &self,
+        offer: Option<&AccountInfo<'info>>,
+    ) -> Result<Option<AccountInfo<'info>>> {
+        if !self.has_offer() {
+            return Ok(None);
+        }
+        let offer = offer.ok_or(TradeError::MissingOffer)?;
+        Ok(Some(offer.clone()))
+    }
+
+    /// Seconds a disputed trade is past its `dispute_ttl_seconds` deadline.
+    /// Fails unless the trade is disputed and the deadline has passed.
+    pub fn dispute_overdue_seconds(&self, dispute_ttl_seconds: u32, now: i64) -> Result<i64> {
+        require!(
+            self.status == TradeStatus::Disputed,
+            TradeError::InvalidTradeStatus
+        );
+        require!(dispute_ttl_seconds > 0, TradeError::DisputeTimeoutDisabled);
+
+        let deadline = self.disputed_at + i64::from(dispute_ttl_seconds);
+        require!(now > deadline, TradeError::DisputeNotExpired);
+        Ok(now - deadline)
+    }
+
+    /// Either party can dispute an accepted trade once its dispute cooldown
+    /// has passed.
+    pub fn check_dispute(&self, disputer: &Pubkey, now: i64) -> Result<()> {
+        require!(
+            self.seller == *disputer || self.buyer == Some(*disputer),
+            TradeError::UnauthorizedDisputer
+        );
+        require!(
+            self.status == TradeStatus::InProgress,
+            TradeError::InvalidTradeStatus
+        );
+        require!(
+            now >= self.dispute_available_at,
+            TradeError::DisputeTooEarly
+        );
+        Ok(())
+    }
+
+    /// A settled trade's escrow balance can be swept by the config admin or
+    /// the seller while it is below the dust threshold.
+    pub fn check_sweep(&self, sweeper: &Pubkey, config: &TradeConfig, dust: u64) -> Result<()> {
+        require!(
+            matches!(self.status, TradeStatus::Completed | TradeStatus::Cancelled),
+            TradeError::InvalidTradeStatus
+        );
+        require!(
+            *sweeper == config.admin || *sweeper == self.seller,
+            TradeError::UnauthorizedSweeper
+        );
+        require!(
+            dust > 0 && dust < config.dust_threshold,
+            TradeError::NotDust
+        );
+        Ok(())
+    }
+
+    /// Tokens the escrow of a funded, unsettled trade is missing of `amount`.
+    /// Fails if nothing is missing.
+    pub fn escrow_shortfall(&self, escrow_balance: u64) -> Result<u64> {
+        require!(
+            matches!(self.status, TradeStatus::Open | TradeStatus::InProgress),
+            TradeError::InvalidTradeStatus
+        );
+        let shortfall = self.amount.saturating_sub(escrow_balance);
+        require!(shortfall > 0, TradeError::EscrowNotShort);
+        Ok(shortfall)
+    }
+
+    /// Fails unless the trade can be completed at `now`: in progress with a
+    /// funded escrow, its price within `PRICE_TOLERANCE_BPS` of the oracle's
+    /// fresh USD price and that price within `min_price..=max_price`.
+    pub fn check_completion(
+        &self,
+        escrow_balance: u64,
+        oracle: &PriceState,
+        min_price: u64,
+        max_price: u64,
+        now: i64,
+    ) -> Result<()> {
+        require!(
+            self.status != TradeStatus::Completed,
+            TradeError::TradeAlreadyCompleted
+        );
+        require!(
+            self.status == TradeStatus::InProgress,
+            TradeError::InvalidTradeStatus
+        );
+        self.check_escrow_funded(escrow_balance)?;
+        price::check_trade_price(oracle, self.price, "USD", PRICE_TOLERANCE_BPS, now)?;
+        check_price_bounds(oracle, min_price, max_price)
+    }
+
+    /// Fails unless the escrow holds at least the trade amount. Anyone can
+    /// send tokens to the escrow, so a surplus must not block settling the
+    /// trade. It stays behind for `sweep_dust` once the trade is settled.
+    pub fn check_escrow_funded(&self, escrow_balance: u64) -> Result<()> {
+        require!(escrow_balance >= self.amount, TradeError::EscrowUnderfunded);
+        Ok(())
+    }
+
+    /// Only a completed or cancelled trade can be closed, by either party.
+    pub fn check_close(&self, closer: &Pubkey) -> Result<()> {
+        require!(
+            matches!(self.status, TradeStatus::Completed | TradeStatus::Cancelled),
+            TradeError::InvalidTradeStatus
+        );
+        require!(
+            self.seller == *closer || self.buyer == Some(*closer),
+            TradeError::UnauthorizedCloser
+        );
+        Ok(())
+    }
+
+    /// Creation fee to give back on cancelling: all of it while no taker has
+    /// accepted the trade, which only its seller can cancel, and nothing
+    /// after. Once taken the trade has cost its taker, and the fee is earned.
+    pub fn creation_fee_refund(&self) -> u64 {
+        if self.status == TradeStatus::Open {
+            self.creation_fee_paid
+        } else {
+            0
+        }
+    }
+
+    /// An open trade can only be cancelled by its seller, once its taker lock
+    /// has elapsed at `now`, an accepted one only by its taker. There is no
+    /// on-chain record of the fiat payment, so a seller who could cancel an
+    /// accepted trade could keep the fiat and take the escrow back. Past
+    /// acceptance the seller needs the taker's signature or a dispute.
+    pub fn check_cancel(&self, canceller: &Pubkey, now: i64) -> Result<()> {
+        match self.status {
+            TradeStatus::Open => {
+                require_keys_eq!(*canceller, self.seller, TradeError::UnauthorizedCanceller);
+                require!(
+                    now >= self.created_at + i64::from(self.taker_lock_seconds),
+                    TradeError::TakerLockActive
+                );
+            }
+            TradeStatus::InProgress => require!(
+                self.buyer == Some(*canceller),
+                TradeError::UnauthorizedCanceller
+            ),
+            _ => return err!(TradeError::InvalidTradeStatus),
+        }
+        Ok(())
+    }
+
+    /// Why `canceller` is cancelling at `now`, decided from the trade rather
+    /// than taken from the caller so no one can pick a reason that spares
+    /// them:
+    /// - The seller of an open trade withdraws it once the taker lock has
+    ///   elapsed, `MakerWithdrew`.
+    /// - The taker of an accepted trade backs out, `TakerBackedOut`.
+    /// - Either party of an accepted trade with the other one signing as
+    ///   `counterparty` cancels by `MutualAgreement`. It is the only way a
+    ///   seller can cancel an accepted trade.
+    pub fn derive_cancel_reason(
+        &self,
+        canceller: &Pubkey,
+        counterparty: Option<Pubkey>,
+        now: i64,
+    ) -> Result<CancelReason> {
+        let Some(counterparty) = counterparty else {
+            self.check_cancel(canceller, now)?;
+            return Ok(if self.status == TradeStatus::Open {
+                CancelReason::MakerWithdrew
+            } else {
+                CancelReason::TakerBackedOut
+            });
+        };
+
+        require!(
+            self.status == TradeStatus::InProgress,
+            TradeError::InvalidCancelReason
+        );
+        let other = if *canceller == self.seller {
+            self.buyer
+        } else if self.buyer == Some(*canceller) {
+            Some(self.seller)
+        } else {
+            return err!(TradeError::UnauthorizedCanceller);
+        };
+        require!(other == Some(counterparty), TradeError::MissingCounterparty);
+        Ok(CancelReason::MutualAgreement)
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = TradeConfig::LEN,
+        seeds = [b"trade_config"],
+        bump
+    )]
+    pub config: Account<'info, TradeConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Trade>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ TradeError::UnauthorizedInitializer
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeRegistry<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = TradeRegistry::LEN,
+        seeds = [TRADE_REGISTRY_SEED],
+        bump
+    )]
+    pub registry: Account<'info, TradeRegistry>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"trade_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, TradeConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMintTradeUnits<'info> {
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = MintTradeUnits::LEN,
+        seeds = [TRADE_UNITS_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub trade_units: Account<'info, MintTradeUnits>,
+    #[account(seeds = [b"trade_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, TradeConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClearMintTradeUnits<'info> {
+    #[account(
+        mut,
+        close = admin,
+        seeds = [TRADE_UNITS_SEED, token_mint.key().as_ref()],
+        bump = trade_units.bump
+    )]
+    pub trade_units: Account<'info, MintTradeUnits>,
+    #[account(seeds = [b"trade_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, TradeConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    /// CHECK: only used as a seed
+    pub token_mint: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetTradeSummary<'info> {
+    pub trade: Account<'info, Trade>,
+    /// CHECK: the seller's profile PDA, reported as missing if not created yet
+    #[account(address = profile::derive_profile(&trade.seller).0 @ TradeError::InvalidProfile)]
+    pub seller_profile: UncheckedAccount<'info>,
+    /// CHECK: the buyer's profile PDA, checked in the handler because an open
+    /// trade has no buyer yet and any account can be passed
+    pub buyer_profile: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteTrade<'info> {
+    #[account(seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct Health<'info> {
+    /// CHECK: may not be initialized yet, which is part of what is reported
+    #[account(seeds = [b"trade_config"], bump)]
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, price: u64)]
 pub struct CreateTrade<'info> {
     #[account(
-        init,
-        payer = seller,
-        space = 8 + // discriminator
-            32 + // seller
-            (1 + 32) + // buyer (Option<Pubkey>) - 1 for the tag, 32 for the pubkey
-            8 + // amount
-            8 + // price
-            32 + // token_mint
-            32 + // escrow_account
-            2 + // status (1 for enum discriminator, 1 for variant)
-            8 + // created_at
-            8 + // updated_at
-            1 + // bump
-            64, // padding for future updates
-        seeds = [b"trade", seller.key().as_ref(), token_mint.key().as_ref()],
-        bump
+        init,
+        payer = seller,
+        space = Trade::LEN,
+        seeds = [b"trade", seller.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub trade: Account<'info, Trade>,
+    /// Also collects the creation fee
+    #[account(
+        mut,
+        seeds = [b"trade_config"],
+        bump = config.bump,
+        constraint = !config.paused @ TradeError::ProgramPaused
+    )]
+    pub config: Account<'info, TradeConfig>,
+    #[account(mut, seeds = [TRADE_REGISTRY_SEED], bump = registry.bump)]
+    pub registry: Account<'info, TradeRegistry>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = seller,
+        token::mint = token_mint,
+        token::authority = trade,
+        token::token_program = token_program,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: validated by the offer program during the reservation CPI
+    #[account(mut)]
+    pub offer: UncheckedAccount<'info>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
+    /// CHECK: the mint's trade units PDA, read only if it was set
+    #[account(address = derive_trade_units(&token_mint.key()).0 @ TradeError::InvalidTradeUnits)]
+    pub trade_units: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateTrade<'info> {
+    /// CHECK: owned by this program, deserialized by `migrate_trade_data`
+    /// once grown to `Trade::LEN`
+    #[account(mut, owner = crate::ID)]
+    pub trade: UncheckedAccount<'info>,
+    /// Pays the rent of the bytes a short trade is grown by
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptTrade<'info> {
+    #[account(mut)]
+    pub trade: Account<'info, Trade>,
+    /// Stops holding the trade's creation fee for a refund
+    #[account(mut, seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    pub buyer: Signer<'info>,
+    /// CHECK: must be the offer the trade reserved against, omitted for a
+    /// trade without one, see `Trade::reserved_offer`
+    #[account(address = trade.offer)]
+    pub offer: Option<UncheckedAccount<'info>>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
+    /// CHECK: the profile PDA of the buyer on a sell offer and of the seller
+    /// on a buy offer, validated by the offer program
+    pub taker_profile: UncheckedAccount<'info>,
+    /// CHECK: the offer creator's profile PDA, validated by the offer program
+    pub maker_profile: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CompleteTrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
+        bump,
+    )]
+    pub trade: Account<'info, Trade>,
+    #[account(constraint = seller.key() == trade.seller)]
+    pub seller: Signer<'info>,
+    /// Writable to receive the lamports of an unwrapped wSOL account
+    #[account(mut, constraint = buyer.key() == trade.buyer.unwrap())]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = escrow_account.key() == trade.escrow_account
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == trade.token_mint,
+        constraint = buyer_token_account.owner == buyer.key()
+    )]
+    pub buyer_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = trade.token_mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    /// Receives the protocol fee, only needed while `config.fee_bps` is set
+    #[account(
+        mut,
+        constraint = fee_collector.owner == config.key() @ TradeError::InvalidFeeCollector,
+        constraint = fee_collector.mint == trade.token_mint @ TradeError::InvalidFeeCollector
+    )]
+    pub fee_collector: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub price_oracle: Account<'info, PriceState>,
+
+    // Profile accounts with proper constraints
+    #[account(
+        mut,
+        seeds = [b"profile", buyer.key().as_ref()],
+        bump,
+        seeds::program = profile_program.key()
+    )]
+    pub buyer_profile: Account<'info, ProfileAccount>,
+    #[account(
+        mut,
+        seeds = [b"profile", seller.key().as_ref()],
+        bump,
+        seeds::program = profile_program.key()
+    )]
+    pub seller_profile: Account<'info, ProfileAccount>,
+    pub profile_program: Program<'info, Profile>,
+    /// CHECK: must be the offer the trade reserved against, omitted for a
+    /// trade without one, see `Trade::reserved_offer`
+    #[account(mut, address = trade.offer)]
+    pub offer: Option<UncheckedAccount<'info>>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CompleteTradesBatch<'info> {
+    #[account(seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub price_oracle: Account<'info, PriceState>,
+    pub profile_program: Program<'info, Profile>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
+        bump = trade.bump,
+    )]
+    pub trade: Account<'info, Trade>,
+    /// Seller or taker, see `Trade::derive_cancel_reason`
+    pub canceller: Signer<'info>,
+    /// The other party, only needed to cancel by mutual agreement
+    pub counterparty: Option<Signer<'info>>,
+    /// Pays back the creation fee of a trade withdrawn before a taker
+    /// accepted it
+    #[account(mut, seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    /// CHECK: must be the trade's seller, receives the creation fee refund
+    #[account(mut, address = trade.seller)]
+    pub seller: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = escrow_account.key() == trade.escrow_account
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == trade.token_mint,
+        constraint = seller_token_account.owner == seller.key()
+    )]
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = trade.token_mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: must be the offer the trade reserved against, omitted for a
+    /// trade without one, see `Trade::reserved_offer`
+    #[account(mut, address = trade.offer)]
+    pub offer: Option<UncheckedAccount<'info>>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
+    /// CHECK: the canceller's profile PDA, which may not be initialized
+    #[account(
+        mut,
+        address = profile::derive_profile(&canceller.key()).0 @ TradeError::InvalidProfile
+    )]
+    pub canceller_profile: UncheckedAccount<'info>,
+    pub profile_program: Program<'info, Profile>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
+        bump = trade.bump,
+    )]
+    pub trade: Account<'info, Trade>,
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        constraint = escrow_account.key() == trade.escrow_account
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == trade.token_mint,
+        constraint = seller_token_account.owner == seller.key()
+    )]
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = trade.token_mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SweepDust<'info> {
+    #[account(
+        seeds = [b"trade", trade.seller.as_ref(), trade.token_mint.as_ref()],
+        bump = trade.bump,
+    )]
+    pub trade: Account<'info, Trade>,
+    #[account(seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    /// Config admin or seller, see `Trade::check_sweep`
+    pub sweeper: Signer<'info>,
+    #[account(
+        mut,
+        constraint = escrow_account.key() == trade.escrow_account
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fee_collector.owner == config.key() @ TradeError::InvalidFeeCollector,
+        constraint = fee_collector.mint == trade.token_mint @ TradeError::InvalidFeeCollector
+    )]
+    pub fee_collector: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = trade.token_mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(seeds = [b"trade_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, TradeConfig>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        constraint = fee_collector.owner == config.key() @ TradeError::InvalidFeeCollector
+    )]
+    pub fee_collector: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = destination.mint == fee_collector.mint)]
+    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = fee_collector.mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCreationFees<'info> {
+    #[account(mut, seeds = [b"trade_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, TradeConfig>,
+    pub admin: Signer<'info>,
+    /// CHECK: only receives lamports
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
+        bump = trade.bump,
+        close = seller,
     )]
     pub trade: Account<'info, Trade>,
-    #[account(mut)]
-    pub seller: Signer<'info>,
-    pub token_mint: Account<'info, token::Mint>,
-    #[account(mut)]
-    pub seller_token_account: Account<'info, token::TokenAccount>,
+    /// Seller or taker, see `Trade::check_close`
+    pub closer: Signer<'info>,
+    /// CHECK: receives the rent, must be the trade's seller
+    #[account(mut, address = trade.seller)]
+    pub seller: UncheckedAccount<'info>,
     #[account(
-        init,
-        payer = seller,
-        token::mint = token_mint,
-        token::authority = trade,
+        mut,
+        constraint = escrow_account.key() == trade.escrow_account
     )]
-    pub escrow_account: Account<'info, token::TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct AcceptTrade<'info> {
-    #[account(mut)]
+pub struct DisputeTrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
+        bump = trade.bump,
+    )]
     pub trade: Account<'info, Trade>,
-    pub buyer: Signer<'info>,
+    pub disputer: Signer<'info>,
+    /// CHECK: only used as a key, must be the trade's seller
+    #[account(address = trade.seller)]
+    pub seller: UncheckedAccount<'info>,
+    /// CHECK: only used as a key, must be the trade's buyer
+    #[account(constraint = trade.buyer == Some(buyer.key()))]
+    pub buyer: UncheckedAccount<'info>,
+    /// CHECK: the seller's profile PDA, which may not be initialized
+    #[account(
+        mut,
+        address = profile::derive_profile(&seller.key()).0 @ TradeError::InvalidProfile
+    )]
+    pub seller_profile: UncheckedAccount<'info>,
+    /// CHECK: the buyer's profile PDA, which may not be initialized
+    #[account(
+        mut,
+        address = profile::derive_profile(&buyer.key()).0 @ TradeError::InvalidProfile
+    )]
+    pub buyer_profile: UncheckedAccount<'info>,
+    pub profile_program: Program<'info, Profile>,
 }
 
 #[derive(Accounts)]
-pub struct CompleteTrade<'info> {
+#[instruction(buyer_wins: bool)]
+pub struct ResolveDispute<'info> {
     #[account(
         mut,
         seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
-        bump,
+        bump = trade.bump,
     )]
     pub trade: Account<'info, Trade>,
-    #[account(constraint = seller.key() == trade.seller)]
-    pub seller: Signer<'info>,
-    #[account(constraint = buyer.key() == trade.buyer.unwrap())]
-    pub buyer: Signer<'info>,
+    #[account(seeds = [b"trade_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, TradeConfig>,
+    pub admin: Signer<'info>,
+    /// CHECK: only used as a key, must be the trade's seller
+    #[account(address = trade.seller)]
+    pub seller: UncheckedAccount<'info>,
+    /// CHECK: only used as a key, must be the trade's buyer
+    #[account(constraint = trade.buyer == Some(buyer.key()))]
+    pub buyer: UncheckedAccount<'info>,
     #[account(
         mut,
         constraint = escrow_account.key() == trade.escrow_account
     )]
-    pub escrow_account: Box<Account<'info, token::TokenAccount>>,
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        constraint = buyer_token_account.mint == trade.token_mint,
-        constraint = buyer_token_account.owner == buyer.key()
+        constraint = recipient_token_account.mint == trade.token_mint,
+        constraint = recipient_token_account.owner
+            == if buyer_wins { buyer.key() } else { seller.key() }
     )]
-    pub buyer_token_account: Box<Account<'info, token::TokenAccount>>,
-    pub token_program: Program<'info, Token>,
-
-    // Price verification accounts with proper constraints
-    pub price_oracle: Account<'info, PriceState>,
-    pub price_program: Program<'info, Price>,
-
-    // Profile accounts with proper constraints
+    pub recipient_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = trade.token_mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Required when the buyer wins and the config charges a fee
     #[account(
         mut,
-        seeds = [b"profile", buyer.key().as_ref()],
-        bump,
-        seeds::program = profile_program.key()
+        constraint = fee_collector.owner == config.key() @ TradeError::InvalidFeeCollector,
+        constraint = fee_collector.mint == trade.token_mint @ TradeError::InvalidFeeCollector
     )]
-    pub buyer_profile: Account<'info, ProfileAccount>,
+    pub fee_collector: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    /// CHECK: must be the offer the trade reserved against, omitted for a
+    /// trade without one, see `Trade::reserved_offer`
+    #[account(mut, address = trade.offer)]
+    pub offer: Option<UncheckedAccount<'info>>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
     #[account(
         mut,
-        seeds = [b"profile", seller.key().as_ref()],
-        bump,
-        seeds::program = profile_program.key()
+        address = profile::derive_profile(
+            &if buyer_wins { seller.key() } else { buyer.key() }
+        ).0 @ TradeError::InvalidProfile
     )]
-    pub seller_profile: Account<'info, ProfileAccount>,
+    pub loser_profile: Account<'info, ProfileAccount>,
     pub profile_program: Program<'info, Profile>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct CancelTrade<'info> {
+pub struct DisputeTimeout<'info> {
     #[account(
         mut,
         seeds = [b"trade", seller.key().as_ref(), trade.token_mint.as_ref()],
         bump = trade.bump,
     )]
     pub trade: Account<'info, Trade>,
-    #[account(constraint = seller.key() == trade.seller)]
-    pub seller: Signer<'info>,
+    #[account(seeds = [b"trade_config"], bump = config.bump)]
+    pub config: Account<'info, TradeConfig>,
+    /// CHECK: only used as a key, must be the trade's seller
+    #[account(address = trade.seller)]
+    pub seller: UncheckedAccount<'info>,
     #[account(
         mut,
         constraint = escrow_account.key() == trade.escrow_account
     )]
-    pub escrow_account: Box<Account<'info, token::TokenAccount>>,
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         constraint = seller_token_account.mint == trade.token_mint,
         constraint = seller_token_account.owner == seller.key()
     )]
-    pub seller_token_account: Box<Account<'info, token::TokenAccount>>,
-    pub token_program: Program<'info, Token>,
-}
-
-#[derive(Accounts)]
-pub struct DisputeTrade<'info> {
-    #[account(mut)]
-    pub trade: Account<'info, Trade>,
-    pub disputer: Signer<'info>,
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = trade.token_mint)]
+    pub token_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: must be the offer the trade reserved against, omitted for a
+    /// trade without one, see `Trade::reserved_offer`
+    #[account(mut, address = trade.offer)]
+    pub offer: Option<UncheckedAccount<'info>>,
+    /// CHECK: address is checked against the known offer program id
+    #[account(address = OFFER_PROGRAM_ID)]
+    pub offer_program: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[error_code]
@@ -349,4 +2550,861 @@ pub enum TradeError {
     InvalidTradeStatus,
     #[msg("Unauthorized disputer")]
     UnauthorizedDisputer,
+    #[msg("Trade program is paused")]
+    ProgramPaused,
+    #[msg("Trade is already at the current version")]
+    AlreadyMigrated,
+    #[msg("Profile account does not belong to the trade party")]
+    InvalidProfile,
+    #[msg("Only the seller or the taker can cancel this trade")]
+    UnauthorizedCanceller,
+    #[msg("The seller cannot withdraw the trade until its taker lock elapses")]
+    TakerLockActive,
+    #[msg("Dispute timeout is not configured")]
+    DisputeTimeoutDisabled,
+    #[msg("Dispute has not been open for the dispute TTL yet")]
+    DisputeNotExpired,
+    #[msg("Fee exceeds the maximum")]
+    FeeTooHigh,
+    #[msg("A fee collector is required while a trade fee is set")]
+    MissingFeeCollector,
+    #[msg("Fee collector must be a trade config token account for the trade mint")]
+    InvalidFeeCollector,
+    #[msg("Only the seller or the taker can close this trade")]
+    UnauthorizedCloser,
+    #[msg("Escrow still holds tokens")]
+    EscrowNotEmpty,
+    #[msg("Oracle price is outside the accepted price bounds")]
+    PriceOutOfBounds,
+    #[msg("Batch accounts must be whole groups of trade accounts within the batch limit")]
+    InvalidBatchAccounts,
+    #[msg("Trade is already completed")]
+    TradeAlreadyCompleted,
+    #[msg("Trade cannot be disputed until its dispute cooldown has passed")]
+    DisputeTooEarly,
+    #[msg("Minimum trade units can't exceed the maximum")]
+    InvalidTradeUnits,
+    #[msg("Trade amount is outside the configured trade units for its mint")]
+    TradeAmountOutOfRange,
+    #[msg("Cancel reason does not apply to this trade or canceller")]
+    InvalidCancelReason,
+    #[msg("Cancelling by mutual agreement needs the other party's signature")]
+    MissingCounterparty,
+    #[msg("Only the config admin or the seller can sweep escrow dust")]
+    UnauthorizedSweeper,
+    #[msg("Escrow balance is empty or not below the dust threshold")]
+    NotDust,
+    #[msg("Memo is longer than 32 bytes")]
+    MemoTooLong,
+    #[msg("Escrow holds less than the trade amount")]
+    EscrowUnderfunded,
+    #[msg("Escrow already holds the trade amount")]
+    EscrowNotShort,
+    #[msg("Fee withdrawal must be positive and within the collected fees")]
+    InvalidFeeWithdrawal,
+    #[msg("Trade registry count overflowed")]
+    TradeCountOverflow,
+    #[msg("Only the program's upgrade authority can initialize the config")]
+    UnauthorizedInitializer,
+    #[msg("The offer the trade reserved against is required")]
+    MissingOffer,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    /// `Trade` as laid out before the version byte and the offer existed.
+    #[derive(AnchorSerialize)]
+    struct TradeV1 {
+        seller: Pubkey,
+        buyer: Option<Pubkey>,
+        amount: u64,
+        price: u64,
+        token_mint: Pubkey,
+        escrow_account: Pubkey,
+        status: TradeStatus,
+        created_at: i64,
+        updated_at: i64,
+        bump: u8,
+    }
+
+    /// Space the first trades were allocated with.
+    const V1_TRADE_LEN: usize = 236;
+
+    /// Later than any timestamp of the `accepted_trade` fixture.
+    const NOW: i64 = 1_700_000_200;
+
+    #[test]
+    fn derived_addresses_match_account_seeds() {
+        let seller = Pubkey::new_unique();
+        let token_mint = Pubkey::new_unique();
+
+        let (trade, bump) = derive_trade(&seller, &token_mint);
+        let seeds: &[&[u8]] = &[b"trade", seller.as_ref(), token_mint.as_ref(), &[bump]];
+        assert_eq!(Pubkey::create_program_address(seeds, &ID).unwrap(), trade);
+
+        let (profile, bump) = profile::derive_profile(&seller);
+        let seeds: &[&[u8]] = &[b"profile", seller.as_ref(), &[bump]];
+        assert_eq!(
+            Pubkey::create_program_address(seeds, &profile::ID).unwrap(),
+            profile
+        );
+    }
+
+    #[test]
+    fn profile_accepts_only_trade_accounts_as_signers() {
+        assert_eq!(profile::TRADE_PROGRAM_ID, ID);
+
+        let mut data = Vec::new();
+        let trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        trade.try_serialize(&mut data).unwrap();
+        let (key, mut lamports) = (Pubkey::new_unique(), 0);
+        let info = AccountInfo::new(&key, true, true, &mut lamports, &mut data, &ID, false, 0);
+        assert!(profile::is_trade_account(&info));
+
+        // An account assigned to this program by anyone else is still zeroed
+        let (mut lamports, mut zeroed) = (0, vec![0; Trade::LEN]);
+        let info = AccountInfo::new(&key, true, true, &mut lamports, &mut zeroed, &ID, false, 0);
+        assert!(!profile::is_trade_account(&info));
+
+        let mut data = Vec::new();
+        trade.try_serialize(&mut data).unwrap();
+        let (mut lamports, other) = (0, Pubkey::new_unique());
+        let info = AccountInfo::new(&key, true, true, &mut lamports, &mut data, &other, false, 0);
+        assert!(!profile::is_trade_account(&info));
+    }
+
+    fn accepted_trade(seller: Pubkey, buyer: Pubkey, taker_lock_seconds: u32) -> Trade {
+        Trade {
+            seller,
+            buyer: Some(buyer),
+            amount: 1_000_000,
+            price: 100_000,
+            token_mint: Pubkey::new_unique(),
+            escrow_account: Pubkey::new_unique(),
+            status: TradeStatus::InProgress,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_100,
+            bump: 254,
+            offer: Pubkey::new_unique(),
+            version: TRADE_VERSION,
+            taker_lock_seconds,
+            accepted_at: 1_700_000_100,
+            disputed_at: 0,
+            dispute_available_at: 1_700_000_100,
+            cancel_reason: None,
+            offer_type: OfferType::Sell,
+            memo: [0; MEMO_LEN],
+            creation_fee_paid: 0,
+            id: 7,
+        }
+    }
+
+    #[test]
+    fn user_filters_match_the_trade_layout() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = Vec::new();
+        accepted_trade(seller, buyer, 0)
+            .try_serialize(&mut data)
+            .unwrap();
+        let matches = |(offset, bytes): &(usize, Vec<u8>)| {
+            data.get(*offset..offset + bytes.len()) == Some(bytes.as_slice())
+        };
+
+        let seller_filters = trade_filters_for_user(&seller);
+        assert!(matches(&seller_filters[0]));
+        assert!(!matches(&seller_filters[1]));
+        let buyer_filters = trade_filters_for_user(&buyer);
+        assert!(!matches(&buyer_filters[0]));
+        assert!(matches(&buyer_filters[1]));
+    }
+
+    #[test]
+    fn seller_cannot_cancel_an_accepted_trade() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trade = accepted_trade(seller, buyer, 0);
+
+        assert_eq!(
+            trade.check_cancel(&seller, NOW).unwrap_err(),
+            TradeError::UnauthorizedCanceller.into()
+        );
+        assert!(trade.check_cancel(&buyer, NOW).is_ok());
+        assert_eq!(
+            trade.check_cancel(&Pubkey::new_unique(), NOW).unwrap_err(),
+            TradeError::UnauthorizedCanceller.into()
+        );
+
+        trade.status = TradeStatus::Open;
+        trade.buyer = None;
+        assert!(trade.check_cancel(&seller, NOW).is_ok());
+    }
+
+    #[test]
+    fn seller_cannot_withdraw_inside_the_taker_lock() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trade = accepted_trade(seller, buyer, 600);
+        trade.status = TradeStatus::Open;
+        trade.buyer = None;
+        let unlocked_at = trade.created_at + 600;
+
+        assert_eq!(
+            trade
+                .derive_cancel_reason(&seller, None, unlocked_at - 1)
+                .unwrap_err(),
+            TradeError::TakerLockActive.into()
+        );
+        assert_eq!(
+            trade
+                .derive_cancel_reason(&seller, None, unlocked_at)
+                .unwrap(),
+            CancelReason::MakerWithdrew
+        );
+
+        // The lock only holds back the seller of an open trade
+        trade.status = TradeStatus::InProgress;
+        trade.buyer = Some(buyer);
+        assert_eq!(
+            trade
+                .derive_cancel_reason(&buyer, None, trade.created_at)
+                .unwrap(),
+            CancelReason::TakerBackedOut
+        );
+    }
+
+    #[test]
+    fn cancel_reasons_follow_from_the_trade() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let trade = accepted_trade(seller, buyer, 0);
+
+        let backed_out = trade.derive_cancel_reason(&buyer, None, NOW).unwrap();
+        assert_eq!(backed_out, CancelReason::TakerBackedOut);
+        assert!(backed_out.is_at_fault());
+        for (canceller, counterparty) in [(seller, buyer), (buyer, seller)] {
+            let mutual = trade
+                .derive_cancel_reason(&canceller, Some(counterparty), NOW)
+                .unwrap();
+            assert_eq!(mutual, CancelReason::MutualAgreement);
+            assert!(!mutual.is_at_fault());
+        }
+
+        let mut open = trade.clone();
+        open.status = TradeStatus::Open;
+        open.buyer = None;
+        let withdrew = open.derive_cancel_reason(&seller, None, NOW).unwrap();
+        assert_eq!(withdrew, CancelReason::MakerWithdrew);
+        assert!(!withdrew.is_at_fault());
+
+        assert_eq!(
+            trade.derive_cancel_reason(&seller, None, NOW).unwrap_err(),
+            TradeError::UnauthorizedCanceller.into()
+        );
+        assert_eq!(
+            trade
+                .derive_cancel_reason(&seller, Some(Pubkey::new_unique()), NOW)
+                .unwrap_err(),
+            TradeError::MissingCounterparty.into()
+        );
+        assert_eq!(
+            trade
+                .derive_cancel_reason(&Pubkey::new_unique(), Some(seller), NOW)
+                .unwrap_err(),
+            TradeError::UnauthorizedCanceller.into()
+        );
+        assert_eq!(
+            open.derive_cancel_reason(&seller, Some(buyer), NOW)
+                .unwrap_err(),
+            TradeError::InvalidCancelReason.into()
+        );
+    }
+
+    #[test]
+    fn cancel_reason_round_trips_through_the_account() {
+        let mut trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        trade.status = TradeStatus::Cancelled;
+        trade.cancel_reason = Some(CancelReason::TakerBackedOut);
+
+        let mut data = Vec::new();
+        trade.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= Trade::LEN);
+        let stored = Trade::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(stored.cancel_reason, Some(CancelReason::TakerBackedOut));
+    }
+
+    #[test]
+    fn memos_round_trip_through_the_account() {
+        let mut trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        trade.memo = encode_memo("INV-2024-0042").unwrap();
+
+        let mut data = Vec::new();
+        trade.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= Trade::LEN);
+        let stored = Trade::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(decode_memo(&stored.memo), "INV-2024-0042");
+
+        assert_eq!(decode_memo(&encode_memo("").unwrap()), "");
+        let full = "x".repeat(MEMO_LEN);
+        assert_eq!(decode_memo(&encode_memo(&full).unwrap()), full);
+        assert_eq!(
+            encode_memo(&"x".repeat(MEMO_LEN + 1)).unwrap_err(),
+            TradeError::MemoTooLong.into()
+        );
+    }
+
+    #[test]
+    fn creation_fee_is_kept_once_a_taker_accepted() {
+        let mut trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        trade.creation_fee_paid = 5_000_000;
+        assert_eq!(trade.creation_fee_refund(), 0);
+
+        trade.status = TradeStatus::Open;
+        trade.buyer = None;
+        assert_eq!(trade.creation_fee_refund(), 5_000_000);
+    }
+
+    #[test]
+    fn disputes_open_once_the_cooldown_passes() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trade = accepted_trade(seller, buyer, 0);
+        trade.dispute_available_at = trade.accepted_at + 3600;
+        let last_cooldown_second = trade.dispute_available_at - 1;
+
+        for party in [&seller, &buyer] {
+            assert_eq!(
+                trade
+                    .check_dispute(party, last_cooldown_second)
+                    .unwrap_err(),
+                TradeError::DisputeTooEarly.into()
+            );
+            assert!(trade
+                .check_dispute(party, trade.dispute_available_at)
+                .is_ok());
+        }
+        assert_eq!(
+            trade
+                .check_dispute(&Pubkey::new_unique(), trade.dispute_available_at)
+                .unwrap_err(),
+            TradeError::UnauthorizedDisputer.into()
+        );
+    }
+
+    #[test]
+    fn only_dust_on_a_settled_trade_can_be_swept() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trade = accepted_trade(seller, buyer, 0);
+        let config = TradeConfig {
+            admin: Pubkey::new_unique(),
+            paused: false,
+            bump: 255,
+            dispute_ttl_seconds: 0,
+            fee_bps: 0,
+            creation_fee_lamports: 0,
+            dispute_cooldown_seconds: 0,
+            min_trade_units: 0,
+            max_trade_units: 0,
+            dust_threshold: 100,
+            refundable_creation_fees: 0,
+        };
+
+        assert_eq!(
+            trade.check_sweep(&seller, &config, 5).unwrap_err(),
+            TradeError::InvalidTradeStatus.into()
+        );
+        trade.status = TradeStatus::Cancelled;
+        assert!(trade.check_sweep(&seller, &config, 5).is_ok());
+        trade.status = TradeStatus::Completed;
+        assert!(trade.check_sweep(&seller, &config, 5).is_ok());
+        assert!(trade.check_sweep(&config.admin, &config, 99).is_ok());
+        assert_eq!(
+            trade.check_sweep(&buyer, &config, 5).unwrap_err(),
+            TradeError::UnauthorizedSweeper.into()
+        );
+        for balance in [0, 100] {
+            assert_eq!(
+                trade.check_sweep(&seller, &config, balance).unwrap_err(),
+                TradeError::NotDust.into()
+            );
+        }
+    }
+
+    #[test]
+    fn short_escrows_are_topped_up_to_the_trade_amount() {
+        let mut trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        // Funded 1% short of the 1_000_000 trade amount
+        let funded = 990_000;
+        assert_eq!(
+            trade.check_escrow_funded(funded).unwrap_err(),
+            TradeError::EscrowUnderfunded.into()
+        );
+        let shortfall = trade.escrow_shortfall(funded).unwrap();
+        assert_eq!(shortfall, 10_000);
+        assert!(trade.check_escrow_funded(funded + shortfall).is_ok());
+        // A surplus someone sent to the escrow doesn't block completion
+        assert!(trade.check_escrow_funded(funded + shortfall + 1).is_ok());
+        assert_eq!(
+            trade.escrow_shortfall(funded + shortfall).unwrap_err(),
+            TradeError::EscrowNotShort.into()
+        );
+
+        trade.status = TradeStatus::Completed;
+        assert_eq!(
+            trade.escrow_shortfall(funded).unwrap_err(),
+            TradeError::InvalidTradeStatus.into()
+        );
+    }
+
+    #[test]
+    fn only_a_party_can_close_a_settled_trade() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trade = accepted_trade(seller, buyer, 0);
+        assert_eq!(
+            trade.check_close(&seller).unwrap_err(),
+            TradeError::InvalidTradeStatus.into()
+        );
+
+        for status in [TradeStatus::Completed, TradeStatus::Cancelled] {
+            trade.status = status;
+            assert!(trade.check_close(&seller).is_ok());
+            assert!(trade.check_close(&buyer).is_ok());
+            assert_eq!(
+                trade.check_close(&Pubkey::new_unique()).unwrap_err(),
+                TradeError::UnauthorizedCloser.into()
+            );
+        }
+    }
+
+    #[test]
+    fn live_price_must_be_within_the_accepted_bounds() {
+        let oracle = PriceState {
+            is_initialized: true,
+            prices: vec![price::CurrencyPrice {
+                currency: "USD".to_string(),
+                usd_price: 100_000,
+                updated_at: 1_700_000_000,
+            }],
+            ..PriceState::default()
+        };
+        assert!(check_price_bounds(&oracle, 99_000, 101_000).is_ok());
+        assert!(check_price_bounds(&oracle, 100_000, 100_000).is_ok());
+        for (min, max) in [(100_001, 110_000), (90_000, 99_999), (101_000, 99_000)] {
+            assert_eq!(
+                check_price_bounds(&oracle, min, max).unwrap_err(),
+                TradeError::PriceOutOfBounds.into()
+            );
+        }
+        assert_eq!(
+            check_price_bounds(&PriceState::default(), 0, u64::MAX).unwrap_err(),
+            TradeError::PriceOutOfBounds.into()
+        );
+    }
+
+    #[test]
+    fn batch_and_single_completion_share_the_same_checks() {
+        let trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        let oracle = PriceState {
+            is_initialized: true,
+            prices: vec![price::CurrencyPrice {
+                currency: "USD".to_string(),
+                usd_price: 100_000,
+                updated_at: 1_700_000_000,
+            }],
+            max_staleness_seconds: 60,
+            ..PriceState::default()
+        };
+        let now = 1_700_000_030;
+        let check = |trade: &Trade, escrow, min, max, now| {
+            trade
+                .check_completion(escrow, &oracle, min, max, now)
+                .map_err(|err| TradeCompletionResult::from_completion_error(&err))
+        };
+        assert!(check(&trade, 1_000_000, 0, u64::MAX, now).is_ok());
+        assert_eq!(
+            check(&trade, 999_999, 0, u64::MAX, now).unwrap_err(),
+            TradeCompletionResult::EscrowUnderfunded
+        );
+        assert_eq!(
+            check(&trade, 1_000_000, 100_001, u64::MAX, now).unwrap_err(),
+            TradeCompletionResult::PriceRejected
+        );
+        assert_eq!(
+            check(&trade, 1_000_000, 0, u64::MAX, now + 60).unwrap_err(),
+            TradeCompletionResult::PriceRejected
+        );
+
+        for status in [TradeStatus::Completed, TradeStatus::Disputed] {
+            let trade = Trade {
+                status,
+                ..trade.clone()
+            };
+            assert_eq!(
+                check(&trade, 1_000_000, 0, u64::MAX, now).unwrap_err(),
+                TradeCompletionResult::InvalidStatus
+            );
+        }
+    }
+
+    #[test]
+    fn fee_withdrawals_stay_within_the_collector_balance() {
+        assert_eq!(fee_withdrawal_amount(None, 1_500).unwrap(), 1_500);
+        assert_eq!(fee_withdrawal_amount(Some(500), 1_500).unwrap(), 500);
+        assert_eq!(fee_withdrawal_amount(Some(1_500), 1_500).unwrap(), 1_500);
+        for (requested, balance) in [(Some(1_501), 1_500), (Some(0), 1_500), (None, 0)] {
+            assert_eq!(
+                fee_withdrawal_amount(requested, balance).unwrap_err(),
+                TradeError::InvalidFeeWithdrawal.into()
+            );
+        }
+    }
+
+    #[test]
+    fn registry_hands_out_sequential_trade_ids() {
+        let mut registry = TradeRegistry {
+            trade_count: 0,
+            bump: 255,
+        };
+        assert_eq!(registry.next_id().unwrap(), 1);
+        assert_eq!(registry.next_id().unwrap(), 2);
+        assert_eq!(registry.trade_count, 2);
+
+        registry.trade_count = u64::MAX;
+        assert_eq!(
+            registry.next_id().unwrap_err(),
+            TradeError::TradeCountOverflow.into()
+        );
+    }
+
+    #[test]
+    fn quote_splits_the_fee_from_the_payout() {
+        let config = TradeConfig {
+            admin: Pubkey::new_unique(),
+            paused: false,
+            bump: 255,
+            dispute_ttl_seconds: 0,
+            fee_bps: 250,
+            creation_fee_lamports: 0,
+            dispute_cooldown_seconds: 0,
+            min_trade_units: 0,
+            max_trade_units: 0,
+            dust_threshold: 0,
+            refundable_creation_fees: 0,
+        };
+        let quote = config.quote(1_000_003);
+        assert_eq!(quote.gross_amount, 1_000_003);
+        assert_eq!(quote.fee_amount, 25_000);
+        assert_eq!(quote.net_amount, 975_003);
+
+        let max = TradeConfig {
+            fee_bps: MAX_FEE_BPS,
+            ..config
+        };
+        assert_eq!(max.quote(u64::MAX).fee_amount, u64::MAX / 10);
+        assert_eq!(max.quote(9).fee_amount, 0);
+    }
+
+    #[test]
+    fn quote_subtracts_the_mint_transfer_fee() {
+        use spl_token_2022::extension::{ExtensionType, StateWithExtensionsMut};
+
+        let config = TradeConfig {
+            admin: Pubkey::new_unique(),
+            paused: false,
+            bump: 255,
+            dispute_ttl_seconds: 0,
+            fee_bps: 250,
+            creation_fee_lamports: 0,
+            dispute_cooldown_seconds: 0,
+            min_trade_units: 0,
+            max_trade_units: 0,
+            dust_threshold: 0,
+            refundable_creation_fees: 0,
+        };
+        // A Token-2022 mint charging 1%, capped at 5_000, from epoch 10
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+            ExtensionType::TransferFeeConfig,
+        ])
+        .unwrap();
+        let mut data = vec![0; len];
+        let mut mint =
+            StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+                .unwrap();
+        let fee_config = mint.init_extension::<TransferFeeConfig>(true).unwrap();
+        fee_config.newer_transfer_fee.epoch = 10.into();
+        fee_config.newer_transfer_fee.maximum_fee = 5_000.into();
+        fee_config.newer_transfer_fee.transfer_fee_basis_points = 100.into();
+        mint.base.is_initialized = true;
+        mint.pack_base();
+        mint.init_account_type().unwrap();
+
+        let (key, mut lamports) = (Pubkey::new_unique(), 0);
+        let mint = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &token_2022::ID,
+            false,
+            0,
+        );
+        let quote = config.quote_for_mint(100_000, &mint, 10).unwrap();
+        assert_eq!(quote.fee_amount, 2_500);
+        assert_eq!(quote.transfer_fee, 975);
+        assert_eq!(quote.net_amount, 96_525);
+        assert_eq!(quote.payout_amount(), 97_500);
+        assert_eq!(
+            config
+                .quote_for_mint(1_000_000, &mint, 10)
+                .unwrap()
+                .transfer_fee,
+            5_000
+        );
+        // Before the fee takes effect and on legacy mints nothing is withheld
+        assert_eq!(
+            config.quote_for_mint(100_000, &mint, 9).unwrap(),
+            config.quote(100_000)
+        );
+
+        let (key, mut lamports, mut data) = (Pubkey::new_unique(), 0, vec![0; 82]);
+        let legacy = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &token::ID,
+            false,
+            0,
+        );
+        assert_eq!(
+            config.quote_for_mint(100_000, &legacy, 10).unwrap(),
+            config.quote(100_000)
+        );
+    }
+
+    #[test]
+    fn trade_amounts_are_checked_in_whole_tokens_of_the_mint() {
+        let config = TradeConfig {
+            admin: Pubkey::new_unique(),
+            paused: false,
+            bump: 255,
+            dispute_ttl_seconds: 0,
+            fee_bps: 0,
+            creation_fee_lamports: 0,
+            dispute_cooldown_seconds: 0,
+            min_trade_units: 1,
+            max_trade_units: 1_000,
+            dust_threshold: 0,
+            refundable_creation_fees: 0,
+        };
+
+        // 1 token of a 6 decimal mint is 1_000_000 base units
+        assert_eq!(
+            config.check_trade_amount(999_999, 6, None).unwrap_err(),
+            TradeError::TradeAmountOutOfRange.into()
+        );
+        assert!(config.check_trade_amount(1_000_000, 6, None).is_ok());
+        assert!(config.check_trade_amount(1_000_000_000, 6, None).is_ok());
+        assert_eq!(
+            config
+                .check_trade_amount(1_000_000_001, 6, None)
+                .unwrap_err(),
+            TradeError::TradeAmountOutOfRange.into()
+        );
+        // The same amount is far too small for a 9 decimal mint
+        assert_eq!(
+            config.check_trade_amount(1_000_000, 9, None).unwrap_err(),
+            TradeError::TradeAmountOutOfRange.into()
+        );
+        // Bounds too large to scale don't overflow
+        assert_eq!(
+            config.check_trade_amount(u64::MAX, 255, None).unwrap_err(),
+            TradeError::TradeAmountOutOfRange.into()
+        );
+        let huge_max = TradeConfig {
+            min_trade_units: 0,
+            max_trade_units: u64::MAX,
+            ..config
+        };
+        assert!(huge_max.check_trade_amount(u64::MAX, 38, None).is_ok());
+
+        let unbounded = TradeConfig {
+            min_trade_units: 0,
+            max_trade_units: 0,
+            ..config
+        };
+        assert!(unbounded.check_trade_amount(1, 255, None).is_ok());
+        assert!(unbounded.check_trade_amount(u64::MAX, 0, None).is_ok());
+
+        // A mint's own trade units replace the config's
+        let mint_units = MintTradeUnits {
+            min_trade_units: 0,
+            max_trade_units: 10,
+            bump: 255,
+        };
+        assert!(config.check_trade_amount(1, 6, Some(&mint_units)).is_ok());
+        assert_eq!(
+            unbounded
+                .check_trade_amount(10_000_001, 6, Some(&mint_units))
+                .unwrap_err(),
+            TradeError::TradeAmountOutOfRange.into()
+        );
+    }
+
+    #[test]
+    fn dispute_times_out_only_past_the_ttl() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trade = accepted_trade(seller, buyer, 0);
+        assert_eq!(
+            trade
+                .dispute_overdue_seconds(3600, 1_800_000_000)
+                .unwrap_err(),
+            TradeError::InvalidTradeStatus.into()
+        );
+
+        trade.status = TradeStatus::Disputed;
+        trade.disputed_at = 1_700_000_200;
+        let deadline = trade.disputed_at + 3600;
+        assert_eq!(
+            trade.dispute_overdue_seconds(3600, deadline).unwrap_err(),
+            TradeError::DisputeNotExpired.into()
+        );
+        assert_eq!(
+            trade.dispute_overdue_seconds(0, deadline + 1).unwrap_err(),
+            TradeError::DisputeTimeoutDisabled.into()
+        );
+        assert_eq!(
+            trade.dispute_overdue_seconds(3600, deadline + 90).unwrap(),
+            90
+        );
+    }
+
+    /// `Trade::LEN` before the creation fee was recorded.
+    const V7_TRADE_LEN: usize = 268;
+
+    #[test]
+    fn migrates_a_short_accepted_trade_once_grown() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut v7 = accepted_trade(seller, buyer, 30);
+        v7.version = 7;
+        v7.status = TradeStatus::Cancelled;
+        v7.cancel_reason = Some(CancelReason::TakerBackedOut);
+        v7.creation_fee_paid = 0;
+        v7.id = 0;
+        let mut data = Vec::new();
+        v7.try_serialize(&mut data).unwrap();
+        data.resize(V7_TRADE_LEN, 0);
+
+        // Too short for the fields added since, so it can't even be loaded
+        assert!(Trade::try_deserialize(&mut &data[..]).is_err());
+
+        // What `migrate_trade` does after the realloc
+        data.resize(Trade::LEN, 0);
+        assert_eq!(migrate_trade_data(&mut data).unwrap(), 7);
+
+        let migrated = Trade::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(migrated.version, TRADE_VERSION);
+        assert_eq!(migrated.seller, seller);
+        assert_eq!(migrated.buyer, Some(buyer));
+        assert_eq!(migrated.amount, v7.amount);
+        assert!(migrated.status == TradeStatus::Cancelled);
+        assert_eq!(migrated.taker_lock_seconds, 30);
+        assert_eq!(migrated.accepted_at, v7.accepted_at);
+        assert_eq!(migrated.cancel_reason, Some(CancelReason::TakerBackedOut));
+        assert_eq!(decode_memo(&migrated.memo), "");
+        assert_eq!(migrated.creation_fee_paid, 0);
+        assert_eq!(migrated.id, 0);
+
+        assert_eq!(
+            migrate_trade_data(&mut data).unwrap_err(),
+            TradeError::AlreadyMigrated.into()
+        );
+    }
+
+    #[test]
+    fn unnumbered_v1_trades_get_id_zero_once_grown() {
+        let v1 = TradeV1 {
+            seller: Pubkey::new_unique(),
+            buyer: Some(Pubkey::new_unique()),
+            amount: 1_000_000,
+            price: 100_000,
+            token_mint: Pubkey::new_unique(),
+            escrow_account: Pubkey::new_unique(),
+            status: TradeStatus::InProgress,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_100,
+            bump: 254,
+        };
+        let mut data = vec![0u8; V1_TRADE_LEN];
+        data[..8].copy_from_slice(&Trade::DISCRIMINATOR);
+        v1.serialize(&mut &mut data[8..]).unwrap();
+        assert!(Trade::try_deserialize(&mut &data[..]).is_err());
+
+        data.resize(Trade::LEN, 0);
+        assert_eq!(migrate_trade_data(&mut data).unwrap(), 0);
+
+        let migrated = Trade::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(migrated.id, 0);
+        assert_eq!(migrated.version, TRADE_VERSION);
+        assert_eq!(migrated.buyer, v1.buyer);
+        assert_eq!(migrated.offer, Pubkey::default());
+        assert!(migrated.status == TradeStatus::InProgress);
+    }
+
+    #[test]
+    fn v1_trades_settle_without_an_offer() {
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let v1 = TradeV1 {
+            seller,
+            buyer: Some(buyer),
+            amount: 1_000_000,
+            price: 100_000,
+            token_mint: Pubkey::new_unique(),
+            escrow_account: Pubkey::new_unique(),
+            status: TradeStatus::InProgress,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_100,
+            bump: 254,
+        };
+        let mut data = vec![0u8; V1_TRADE_LEN];
+        data[..8].copy_from_slice(&Trade::DISCRIMINATOR);
+        v1.serialize(&mut &mut data[8..]).unwrap();
+        data.resize(Trade::LEN, 0);
+        migrate_trade_data(&mut data).unwrap();
+        let migrated = Trade::try_deserialize(&mut &data[..]).unwrap();
+
+        // Nothing was reserved, so the taker can back out without an offer
+        assert!(!migrated.has_offer());
+        assert!(migrated.reserved_offer(None).unwrap().is_none());
+        assert_eq!(
+            migrated.derive_cancel_reason(&buyer, None, NOW).unwrap(),
+            CancelReason::TakerBackedOut
+        );
+        assert!(migrated.check_dispute(&seller, 1_700_000_200).is_ok());
+
+        // A trade that reserved against an offer still has to pass it
+        let trade = accepted_trade(seller, buyer, 0);
+        assert!(trade.has_offer());
+        assert_eq!(
+            trade.reserved_offer(None).unwrap_err(),
+            TradeError::MissingOffer.into()
+        );
+        let (key, mut lamports, mut data) = (trade.offer, 0, vec![]);
+        let offer = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &ID, false, 0);
+        assert_eq!(
+            trade.reserved_offer(Some(&offer)).unwrap().unwrap().key(),
+            trade.offer
+        );
+    }
+
+    #[test]
+    fn migration_dates_pending_disputes_from_their_last_update() {
+        let mut trade = accepted_trade(Pubkey::new_unique(), Pubkey::new_unique(), 0);
+        trade.version = 3;
+        trade.status = TradeStatus::Disputed;
+
+        trade.migrate();
+        assert_eq!(trade.version, TRADE_VERSION);
+        assert_eq!(trade.disputed_at, trade.updated_at);
+    }
 }
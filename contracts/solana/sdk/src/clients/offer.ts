@@ -1,7 +1,8 @@
 import { Program, AnchorProvider, Idl, BN } from '@project-serum/anchor';
 import { Connection, Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from '@solana/web3.js';
 import { TOKEN_PROGRAM_ID } from '@solana/spl-token';
-import { Offer, OfferStatus } from '../types';
+import { Offer, OfferStatus, OfferType, OfferConfig, ProgramHealth } from '../types';
+import { deriveOffer, deriveProgramData } from '../pda';
 
 export class OfferClient {
   private program: Program;
@@ -16,23 +17,78 @@ export class OfferClient {
     this.connection = provider.connection;
   }
 
+  /** Creates the config. `admin` must be the program's upgrade authority. */
+  async initializeConfig(admin: Keypair): Promise<PublicKey> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .initializeConfig()
+      .accounts({
+        config,
+        admin: admin.publicKey,
+        program: this.program.programId,
+        programData: deriveProgramData(this.program.programId)[0],
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    return config;
+  }
+
+  async setPaused(admin: Keypair, paused: boolean): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setPaused(paused)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async getConfig(): Promise<OfferConfig | null> {
+    const [config] = await this.findConfigAddress();
+    return await this.program.account.offerConfig.fetchNullable(config);
+  }
+
+  async health(): Promise<ProgramHealth> {
+    const [config] = await this.findConfigAddress();
+    return await this.program.methods
+      .health()
+      .accounts({
+        config,
+      })
+      .view();
+  }
+
+  async findConfigAddress(): Promise<[PublicKey, number]> {
+    return await PublicKey.findProgramAddress(
+      [Buffer.from("offer_config")],
+      this.program.programId
+    );
+  }
+
   async createOffer(
     creator: Keypair,
     tokenMint: PublicKey,
     amount: BN,
     pricePerToken: BN,
     minAmount: BN,
-    maxAmount: BN
+    maxAmount: BN,
+    offerType: OfferType = OfferType.Sell
   ): Promise<PublicKey> {
-    const [offerPDA] = await PublicKey.findProgramAddress(
-      [Buffer.from("offer"), creator.publicKey.toBuffer()],
-      this.program.programId
-    );
+    const [offerPDA] = deriveOffer(creator.publicKey, this.program.programId);
+
+    const [config] = await this.findConfigAddress();
 
     await this.program.methods
-      .createOffer(amount, pricePerToken, minAmount, maxAmount)
+      .createOffer(amount, pricePerToken, minAmount, maxAmount, { [offerType]: {} })
       .accounts({
         offer: offerPDA,
+        config,
         creator: creator.publicKey,
         tokenMint,
         systemProgram: SystemProgram.programId,
@@ -61,6 +117,51 @@ export class OfferClient {
       .rpc();
   }
 
+  async refreshQuote(
+    offerPDA: PublicKey,
+    creator: Keypair,
+    quoteValidUntil: BN
+  ): Promise<void> {
+    await this.program.methods
+      .refreshQuote(quoteValidUntil)
+      .accounts({
+        offer: offerPDA,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+  }
+
+  async setMinTakerReputation(
+    offerPDA: PublicKey,
+    creator: Keypair,
+    minTakerReputation: number
+  ): Promise<void> {
+    await this.program.methods
+      .setMinTakerReputation(minTakerReputation)
+      .accounts({
+        offer: offerPDA,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+  }
+
+  async setMinMakerCompletion(
+    offerPDA: PublicKey,
+    creator: Keypair,
+    minMakerCompletionBps: number
+  ): Promise<void> {
+    await this.program.methods
+      .setMinMakerCompletion(minMakerCompletionBps)
+      .accounts({
+        offer: offerPDA,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+  }
+
   async pauseOffer(
     offerPDA: PublicKey,
     creator: Keypair
@@ -113,7 +214,8 @@ export class OfferClient {
     buyer: Keypair,
     buyerTokenAccount: PublicKey,
     tradeProgram: PublicKey,
-    amount: BN
+    amount: BN,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
   ): Promise<void> {
     await this.program.methods
       .takeOffer(amount)
@@ -126,7 +228,7 @@ export class OfferClient {
         trade: tradePDA,
         buyer: buyer.publicKey,
         buyerTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram,
         systemProgram: SystemProgram.programId,
         rent: SYSVAR_RENT_PUBKEY,
         tradeProgram,
@@ -147,14 +249,17 @@ export class OfferClient {
       status: this.convertOfferStatus(account.status),
       createdAt: account.createdAt.toNumber(),
       updatedAt: account.updatedAt.toNumber(),
+      filledAmount: account.filledAmount,
+      reservedAmount: account.reservedAmount,
+      quoteValidUntil: account.quoteValidUntil.toNumber(),
+      minTakerReputation: account.minTakerReputation,
+      minMakerCompletionBps: account.minMakerCompletionBps,
+      offerType: Object.keys(account.offerType)[0] as OfferType,
     };
   }
 
   async findOfferAddress(creator: PublicKey): Promise<[PublicKey, number]> {
-    return await PublicKey.findProgramAddress(
-      [Buffer.from("offer"), creator.toBuffer()],
-      this.program.programId
-    );
+    return deriveOffer(creator, this.program.programId);
   }
 
   private convertOfferStatus(status: any): OfferStatus {
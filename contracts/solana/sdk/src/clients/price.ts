@@ -1,6 +1,23 @@
 import { Program, AnchorProvider, Idl, BN } from '@project-serum/anchor';
 import { Connection, Keypair, PublicKey, SystemProgram } from '@solana/web3.js';
-import { CurrencyPrice } from '../types';
+import {
+  CurrencyPrice,
+  PriceFreshness,
+  PriceRoute,
+  PriceUpdate,
+  PriceUpdateResult,
+  ProgramHealth,
+  RegisteredPriceRoute,
+} from '../types';
+
+// Variant order of the program's `PriceUpdateResult` enum
+const PRICE_UPDATE_RESULTS = [
+  PriceUpdateResult.Applied,
+  PriceUpdateResult.InvalidCurrency,
+  PriceUpdateResult.InvalidPrice,
+  PriceUpdateResult.TooManyPrices,
+  PriceUpdateResult.DeviationExceeded,
+];
 
 export class PriceClient {
   private program: Program;
@@ -30,21 +47,252 @@ export class PriceClient {
       .rpc();
   }
 
-  async updatePrices(
-    oracle: PublicKey,
-    priceProvider: Keypair,
-    prices: CurrencyPrice[]
+  async setMaxStaleness(
+    state: PublicKey,
+    admin: Keypair,
+    maxStalenessSeconds: BN
+  ): Promise<void> {
+    await this.program.methods
+      .setMaxStaleness(maxStalenessSeconds)
+      .accounts({
+        state,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /** Zero turns the price deviation circuit breaker off. */
+  async setMaxDeviation(
+    state: PublicKey,
+    admin: Keypair,
+    maxDeviationBps: number
   ): Promise<void> {
     await this.program.methods
-      .updatePrices(prices)
+      .setMaxDeviation(maxDeviationBps)
+      .accounts({
+        state,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /** Stores a price the deviation circuit breaker quarantined. */
+  async forcePrice(
+    state: PublicKey,
+    admin: Keypair,
+    price: PriceUpdate
+  ): Promise<void> {
+    await this.program.methods
+      .forcePrice(price)
+      .accounts({
+        state,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async setPaused(
+    state: PublicKey,
+    admin: Keypair,
+    paused: boolean
+  ): Promise<void> {
+    await this.program.methods
+      .setPaused(paused)
+      .accounts({
+        state,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async health(state: PublicKey): Promise<ProgramHealth> {
+    return await this.program.methods
+      .health()
+      .accounts({
+        state,
+      })
+      .view();
+  }
+
+  /** Age of every stored price, stalest first. */
+  async getPriceFreshness(oracle: PublicKey): Promise<PriceFreshness[]> {
+    return await this.program.methods
+      .getPriceFreshness()
       .accounts({
         oracle,
-        priceProvider: priceProvider.publicKey,
       })
-      .signers([priceProvider])
+      .view();
+  }
+
+  async addProvider(
+    state: PublicKey,
+    admin: Keypair,
+    provider: PublicKey
+  ): Promise<PublicKey> {
+    const [submission] = await this.findProviderPricesAddress(state, provider);
+
+    await this.program.methods
+      .addProvider(provider)
+      .accounts({
+        state,
+        submission,
+        admin: admin.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    return submission;
+  }
+
+  async removeProvider(
+    state: PublicKey,
+    admin: Keypair,
+    provider: PublicKey
+  ): Promise<void> {
+    await this.program.methods
+      .removeProvider(provider)
+      .accounts({
+        state,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * Submits a registered provider's prices and returns one result per entry,
+   * in input order. Invalid entries are skipped by the program without
+   * failing the others.
+   */
+  async submitPrices(
+    state: PublicKey,
+    provider: Keypair,
+    prices: PriceUpdate[]
+  ): Promise<PriceUpdateResult[]> {
+    const [submission] = await this.findProviderPricesAddress(state, provider.publicKey);
+
+    const signature = await this.program.methods
+      .submitPrices(prices)
+      .accounts({
+        state,
+        submission,
+        provider: provider.publicKey,
+      })
+      .signers([provider])
+      .rpc({ commitment: 'confirmed' });
+
+    const tx = await this.connection.getTransaction(signature, {
+      commitment: 'confirmed',
+      maxSupportedTransactionVersion: 0,
+    });
+    // Return data is borsh encoded `Vec<PriceUpdateResult>`. Trailing zero
+    // bytes are trimmed, so pad before reading.
+    const returned = Buffer.from(tx?.meta?.returnData?.data[0] ?? '', 'base64');
+    const data = Buffer.concat([returned, Buffer.alloc(4 + prices.length)]);
+    const count = data.readUInt32LE(0);
+    return Array.from(data.subarray(4, 4 + count)).map(
+      (variant) => PRICE_UPDATE_RESULTS[variant]
+    );
+  }
+
+  /**
+   * Writes the median of the given provider submissions to the oracle. More
+   * than half of the registered providers must have a fresh price among them.
+   */
+  async aggregatePrices(
+    state: PublicKey,
+    currency: string,
+    submissions: PublicKey[]
+  ): Promise<void> {
+    await this.program.methods
+      .aggregatePrices(currency)
+      .accounts({
+        state,
+      })
+      .remainingAccounts(
+        submissions.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }))
+      )
       .rpc();
   }
 
+  async findProviderPricesAddress(
+    state: PublicKey,
+    provider: PublicKey
+  ): Promise<[PublicKey, number]> {
+    return await PublicKey.findProgramAddress(
+      [Buffer.from("provider_prices"), state.toBuffer(), provider.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  async registerPriceRoute(
+    state: PublicKey,
+    admin: Keypair,
+    routeData: Keypair,
+    denom: string,
+    route: PriceRoute[]
+  ): Promise<void> {
+    await this.program.methods
+      .registerPriceRoute(denom, route)
+      .accounts({
+        routeData: routeData.publicKey,
+        state,
+        admin: admin.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin, routeData])
+      .rpc();
+  }
+
+  async getPriceRoute(routeData: PublicKey, denom: string): Promise<PriceRoute[]> {
+    return await this.program.methods
+      .getPriceRoute(denom)
+      .accounts({
+        routeData,
+      })
+      .view();
+  }
+
+  /**
+   * Every route registered on `state` with the denom it prices, so a keeper
+   * can rebuild the routing graph on startup. Routes store their oracle right
+   * after the account discriminator.
+   */
+  async listRoutes(state: PublicKey): Promise<RegisteredPriceRoute[]> {
+    const accounts = await this.program.account.priceRouteData.all([
+      { memcmp: { offset: 8, bytes: state.toBase58() } },
+    ]);
+    return accounts.map(({ publicKey, account }) => ({
+      routeData: publicKey,
+      denom: account.denom,
+      route: account.route,
+    }));
+  }
+
+  /** Reserves must be each hop's `offerReserve` followed by its `askReserve`. */
+  async resolvePrice(
+    state: PublicKey,
+    routeData: PublicKey,
+    denom: string,
+    reserves: PublicKey[]
+  ): Promise<BN> {
+    return await this.program.methods
+      .resolvePrice(denom)
+      .accounts({
+        state,
+        routeData,
+      })
+      .remainingAccounts(
+        reserves.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }))
+      )
+      .view();
+  }
+
   async verifyPriceForTrade(
     oracle: PublicKey,
     tradePrice: BN,
@@ -68,6 +316,12 @@ export class PriceClient {
     admin: PublicKey;
     priceProvider: PublicKey;
     prices: CurrencyPrice[];
+    maxStalenessSeconds: BN;
+    providers: PublicKey[];
+    paused: boolean;
+    maxDeviationBps: number;
+    /** Confidence of each entry of `prices`, empty for older oracles. */
+    confidences: BN[];
   }> {
     const account = await this.program.account.priceState.fetch(oracle);
     return {
@@ -75,6 +329,11 @@ export class PriceClient {
       admin: account.admin,
       priceProvider: account.priceProvider,
       prices: account.prices,
+      maxStalenessSeconds: account.maxStalenessSeconds,
+      providers: account.providers,
+      paused: account.paused,
+      maxDeviationBps: account.maxDeviationBps,
+      confidences: account.confidences,
     };
   }
 } 
\ No newline at end of file
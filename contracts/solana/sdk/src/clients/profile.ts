@@ -1,6 +1,7 @@
 import { Program, AnchorProvider, Idl } from '@project-serum/anchor';
 import { Connection, Keypair, PublicKey, SystemProgram } from '@solana/web3.js';
 import { Profile } from '../types';
+import { deriveProfile } from '../pda';
 
 export class ProfileClient {
   private program: Program;
@@ -19,10 +20,7 @@ export class ProfileClient {
     owner: Keypair,
     username: string
   ): Promise<PublicKey> {
-    const [profilePDA] = await PublicKey.findProgramAddress(
-      [Buffer.from("profile"), owner.publicKey.toBuffer()],
-      this.program.programId
-    );
+    const [profilePDA] = deriveProfile(owner.publicKey, this.program.programId);
 
     await this.program.methods
       .createProfile(username)
@@ -52,23 +50,6 @@ export class ProfileClient {
       .rpc();
   }
 
-  async updateReputation(
-    profilePDA: PublicKey,
-    authority: Keypair,
-    owner: PublicKey,
-    scoreDelta: number
-  ): Promise<void> {
-    await this.program.methods
-      .updateReputation(scoreDelta)
-      .accounts({
-        profile: profilePDA,
-        authority: authority.publicKey,
-        owner,
-      })
-      .signers([authority])
-      .rpc();
-  }
-
   async verifyProfile(
     profilePDA: PublicKey,
     authority: Keypair,
@@ -85,36 +66,6 @@ export class ProfileClient {
       .rpc();
   }
 
-  async recordTradeCompletion(
-    profilePDA: PublicKey,
-    owner: PublicKey,
-    tradeProgram: PublicKey
-  ): Promise<void> {
-    await this.program.methods
-      .recordTradeCompletion()
-      .accounts({
-        profile: profilePDA,
-        owner,
-        tradeProgram,
-      })
-      .rpc();
-  }
-
-  async recordTradeDispute(
-    profilePDA: PublicKey,
-    owner: PublicKey,
-    tradeProgram: PublicKey
-  ): Promise<void> {
-    await this.program.methods
-      .recordTradeDispute()
-      .accounts({
-        profile: profilePDA,
-        owner,
-        tradeProgram,
-      })
-      .rpc();
-  }
-
   async getProfile(profilePDA: PublicKey): Promise<Profile> {
     const account = await this.program.account.profile.fetch(profilePDA);
     const usernameBytes = account.username.slice(0, account.usernameLen);
@@ -126,6 +77,9 @@ export class ProfileClient {
       reputationScore: account.reputationScore,
       tradesCompleted: account.tradesCompleted,
       tradesDisputed: account.tradesDisputed,
+      disputesLost: account.disputesLost,
+      tradesCancelled: account.tradesCancelled,
+      completionRateBps: account.completionRateBps,
       isVerified: account.isVerified,
       createdAt: account.createdAt.toNumber(),
       updatedAt: account.updatedAt.toNumber(),
@@ -133,9 +87,6 @@ export class ProfileClient {
   }
 
   async findProfileAddress(owner: PublicKey): Promise<[PublicKey, number]> {
-    return await PublicKey.findProgramAddress(
-      [Buffer.from("profile"), owner.toBuffer()],
-      this.program.programId
-    );
+    return deriveProfile(owner, this.program.programId);
   }
 } 
\ No newline at end of file
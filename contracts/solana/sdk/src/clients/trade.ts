@@ -1,7 +1,32 @@
 import { Program, AnchorProvider, Idl, BN } from '@project-serum/anchor';
 import { Connection, Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from '@solana/web3.js';
-import { TOKEN_PROGRAM_ID } from '@solana/spl-token';
-import { Trade, TradeStatus } from '../types';
+import { TOKEN_PROGRAM_ID, getOrCreateAssociatedTokenAccount } from '@solana/spl-token';
+import {
+  Trade,
+  TradeStatus,
+  CancelReason,
+  OfferType,
+  TradeConfig,
+  TradeRegistry,
+  ProgramHealth,
+  TradeSummary,
+  TradeQuote,
+  BatchTradeAccounts,
+  TradeCompletionResult,
+} from '../types';
+import { deriveProfile, deriveProgramData, deriveTrade, deriveTradeUnits } from '../pda';
+
+const U64_MAX = new BN('18446744073709551615');
+
+// Variant order of the program's `TradeCompletionResult` enum
+const TRADE_COMPLETION_RESULTS = [
+  TradeCompletionResult.Completed,
+  TradeCompletionResult.InvalidStatus,
+  TradeCompletionResult.MissingSignature,
+  TradeCompletionResult.InvalidAccounts,
+  TradeCompletionResult.PriceRejected,
+  TradeCompletionResult.EscrowUnderfunded,
+];
 
 export class TradeClient {
   private program: Program;
@@ -16,32 +41,292 @@ export class TradeClient {
     this.connection = provider.connection;
   }
 
+  /** Creates the config. `admin` must be the program's upgrade authority. */
+  async initializeConfig(admin: Keypair): Promise<PublicKey> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .initializeConfig()
+      .accounts({
+        config,
+        admin: admin.publicKey,
+        program: this.program.programId,
+        programData: deriveProgramData(this.program.programId)[0],
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    return config;
+  }
+
+  /** Creates the trade counter, needed once before the first trade. */
+  async initializeRegistry(payer: Keypair): Promise<PublicKey> {
+    const [registry] = await this.findRegistryAddress();
+
+    await this.program.methods
+      .initializeRegistry()
+      .accounts({
+        registry,
+        payer: payer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([payer])
+      .rpc();
+
+    return registry;
+  }
+
+  async setPaused(admin: Keypair, paused: boolean): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setPaused(paused)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async setDisputeTtl(admin: Keypair, disputeTtlSeconds: number): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setDisputeTtl(disputeTtlSeconds)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async setDisputeCooldown(
+    admin: Keypair,
+    disputeCooldownSeconds: number
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setDisputeCooldown(disputeCooldownSeconds)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * Bounds trade amounts to `minTradeUnits..=maxTradeUnits` whole tokens of
+   * the trade's mint. Zero leaves that side unbounded.
+   */
+  async setTradeUnits(
+    admin: Keypair,
+    minTradeUnits: BN,
+    maxTradeUnits: BN
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setTradeUnits(minTradeUnits, maxTradeUnits)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * Trade units for `tokenMint` alone, used instead of the config's
+   * `setTradeUnits` bounds for trades in that mint.
+   */
+  async setMintTradeUnits(
+    admin: Keypair,
+    tokenMint: PublicKey,
+    minTradeUnits: BN,
+    maxTradeUnits: BN
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    const [tradeUnits] = deriveTradeUnits(tokenMint, this.program.programId);
+
+    await this.program.methods
+      .setMintTradeUnits(minTradeUnits, maxTradeUnits)
+      .accounts({
+        tradeUnits,
+        config,
+        admin: admin.publicKey,
+        tokenMint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /** Drops the trade units of `tokenMint`, so it follows the config's again. */
+  async clearMintTradeUnits(admin: Keypair, tokenMint: PublicKey): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    const [tradeUnits] = deriveTradeUnits(tokenMint, this.program.programId);
+
+    await this.program.methods
+      .clearMintTradeUnits()
+      .accounts({
+        tradeUnits,
+        config,
+        admin: admin.publicKey,
+        tokenMint,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async setFeeBps(admin: Keypair, feeBps: number): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setFeeBps(feeBps)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async setDustThreshold(admin: Keypair, dustThreshold: BN): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setDustThreshold(dustThreshold)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async setCreationFee(admin: Keypair, creationFeeLamports: BN): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
+    await this.program.methods
+      .setCreationFee(creationFeeLamports)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * How completing a trade of `amount` of `tokenMint` would split the escrow
+   * at the current fee, less the mint's Token-2022 transfer fee.
+   */
+  async quoteTrade(amount: BN, tokenMint: PublicKey): Promise<TradeQuote> {
+    const [config] = await this.findConfigAddress();
+    return await this.program.methods
+      .quoteTrade(amount)
+      .accounts({
+        config,
+        tokenMint,
+      })
+      .view();
+  }
+
+  /**
+   * Creates the trade config's token account for `tokenMint`, which collects
+   * the protocol fee of completed trades in that mint.
+   */
+  async createFeeCollector(
+    payer: Keypair,
+    tokenMint: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<PublicKey> {
+    const [config] = await this.findConfigAddress();
+    const account = await getOrCreateAssociatedTokenAccount(
+      this.connection,
+      payer,
+      tokenMint,
+      config,
+      true,
+      undefined,
+      undefined,
+      tokenProgram
+    );
+    return account.address;
+  }
+
+  async getConfig(): Promise<TradeConfig | null> {
+    const [config] = await this.findConfigAddress();
+    return await this.program.account.tradeConfig.fetchNullable(config);
+  }
+
+  async health(): Promise<ProgramHealth> {
+    const [config] = await this.findConfigAddress();
+    return await this.program.methods
+      .health()
+      .accounts({
+        config,
+      })
+      .view();
+  }
+
+  async findConfigAddress(): Promise<[PublicKey, number]> {
+    return await PublicKey.findProgramAddress(
+      [Buffer.from("trade_config")],
+      this.program.programId
+    );
+  }
+
+  async getRegistry(): Promise<TradeRegistry | null> {
+    const [registry] = await this.findRegistryAddress();
+    return await this.program.account.tradeRegistry.fetchNullable(registry);
+  }
+
+  async findRegistryAddress(): Promise<[PublicKey, number]> {
+    return await PublicKey.findProgramAddress(
+      [Buffer.from("trade_registry")],
+      this.program.programId
+    );
+  }
+
   async createTrade(
     seller: Keypair,
     tokenMint: PublicKey,
     sellerTokenAccount: PublicKey,
     escrowAccount: Keypair,
     amount: BN,
-    price: BN
+    price: BN,
+    offer: PublicKey,
+    offerProgram: PublicKey,
+    takerLockSeconds: number = 0,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
+    offerType: OfferType = OfferType.Sell,
+    memo: string = ''
   ): Promise<PublicKey> {
-    const [tradePDA] = await PublicKey.findProgramAddress(
-      [
-        Buffer.from("trade"),
-        seller.publicKey.toBuffer(),
-        tokenMint.toBuffer(),
-      ],
-      this.program.programId
-    );
+    const [tradePDA] = deriveTrade(seller.publicKey, tokenMint, this.program.programId);
+
+    const [config] = await this.findConfigAddress();
+    const [registry] = await this.findRegistryAddress();
 
     await this.program.methods
-      .createTrade(amount, price)
+      .createTrade(amount, price, takerLockSeconds, { [offerType]: {} }, memo)
       .accounts({
         trade: tradePDA,
+        config,
+        registry,
         seller: seller.publicKey,
         tokenMint,
         sellerTokenAccount,
         escrowAccount: escrowAccount.publicKey,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        offer,
+        offerProgram,
+        tradeUnits: deriveTradeUnits(tokenMint, this.program.programId)[0],
+        tokenProgram,
         systemProgram: SystemProgram.programId,
         rent: SYSVAR_RENT_PUBKEY,
       })
@@ -51,84 +336,468 @@ export class TradeClient {
     return tradePDA;
   }
 
+  /**
+   * Subscribes to a program event such as `TradeCreated` or `TradeCancelled`.
+   * Returns the id `removeEventListener` takes.
+   */
+  addEventListener(eventName: string, callback: (event: any, slot: number) => void): number {
+    return this.program.addEventListener(eventName, callback);
+  }
+
+  async removeEventListener(listener: number): Promise<void> {
+    await this.program.removeEventListener(listener);
+  }
+
+  /**
+   * Upgrades a trade created by an older program version. `payer` covers the
+   * rent when the trade account has to grow to the current layout. Trades
+   * from before offers were recorded have none, pass `null` as the offer
+   * when accepting or settling them.
+   */
+  async migrateTrade(tradePDA: PublicKey, payer: Keypair): Promise<void> {
+    await this.program.methods
+      .migrateTrade()
+      .accounts({
+        trade: tradePDA,
+        payer: payer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([payer])
+      .rpc();
+  }
+
+  /**
+   * Takes an open trade as `buyer`. `takerProfile` is the profile PDA checked
+   * against the offer's minimum taker reputation: the buyer's on a sell offer,
+   * the seller's on a buy offer.
+   */
   async acceptTrade(
     tradePDA: PublicKey,
-    buyer: Keypair
+    buyer: Keypair,
+    offer: PublicKey | null,
+    offerProgram: PublicKey,
+    takerProfile: PublicKey,
+    makerProfile: PublicKey
   ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+
     await this.program.methods
       .acceptTrade()
       .accounts({
         trade: tradePDA,
+        config,
         buyer: buyer.publicKey,
+        offer,
+        offerProgram,
+        takerProfile,
+        makerProfile,
       })
       .signers([buyer])
       .rpc();
   }
 
+  /**
+   * Releases the escrow to the buyer. For wSOL trades `unwrap` closes
+   * `buyerTokenAccount` afterwards so the buyer receives native SOL.
+   * `feeCollector` is required while the config charges a fee. The oracle's
+   * USD price must lie within `priceBounds`, which accepts any price by default.
+   */
   async completeTrade(
     tradePDA: PublicKey,
     seller: Keypair,
     buyer: Keypair,
     escrowAccount: PublicKey,
     buyerTokenAccount: PublicKey,
+    tokenMint: PublicKey,
     priceOracle: PublicKey,
-    priceProgram: PublicKey,
     buyerProfile: PublicKey,
     sellerProfile: PublicKey,
-    profileProgram: PublicKey
+    profileProgram: PublicKey,
+    offer: PublicKey | null,
+    offerProgram: PublicKey,
+    unwrap: boolean = false,
+    feeCollector: PublicKey | null = null,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
+    priceBounds: [BN, BN] = [new BN(0), U64_MAX]
   ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    const [minAcceptablePrice, maxAcceptablePrice] = priceBounds;
     await this.program.methods
-      .completeTrade()
+      .completeTrade(unwrap, minAcceptablePrice, maxAcceptablePrice)
       .accounts({
         trade: tradePDA,
         seller: seller.publicKey,
         buyer: buyer.publicKey,
         escrowAccount,
         buyerTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMint,
+        tokenProgram,
+        config,
+        feeCollector,
         priceOracle,
-        priceProgram,
         buyerProfile,
         sellerProfile,
         profileProgram,
+        offer,
+        offerProgram,
       })
       .signers([seller, buyer])
       .rpc();
   }
 
+  /**
+   * Completes several in-progress trades in one transaction, with the checks
+   * of `completeTrade` and the same `priceBounds` for every trade. Trades that
+   * cannot be completed are skipped, the result of each is returned in order.
+   * Every seller and buyer signs, so large batches need a lookup table.
+   */
+  async completeTradesBatch(
+    trades: BatchTradeAccounts[],
+    priceOracle: PublicKey,
+    profileProgram: PublicKey,
+    offerProgram: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
+    priceBounds: [BN, BN] = [new BN(0), U64_MAX]
+  ): Promise<TradeCompletionResult[]> {
+    const [config] = await this.findConfigAddress();
+    const [minAcceptablePrice, maxAcceptablePrice] = priceBounds;
+    const writable = (pubkey: PublicKey) => ({ pubkey, isSigner: false, isWritable: true });
+    const signer = (keypair: Keypair) => ({
+      pubkey: keypair.publicKey,
+      isSigner: true,
+      isWritable: true,
+    });
+    // Same order as the program's `BATCH_TRADE_ACCOUNTS`
+    const remainingAccounts = trades.flatMap((trade) => [
+      writable(trade.trade),
+      signer(trade.seller),
+      signer(trade.buyer),
+      writable(trade.escrowAccount),
+      writable(trade.buyerTokenAccount),
+      { pubkey: trade.tokenMint, isSigner: false, isWritable: false },
+      writable(trade.buyerProfile),
+      writable(trade.sellerProfile),
+      writable(trade.offer ?? this.program.programId),
+      writable(trade.feeCollector ?? this.program.programId),
+    ]);
+    const signers = new Map<string, Keypair>();
+    trades.forEach(({ seller, buyer }) => {
+      signers.set(seller.publicKey.toBase58(), seller);
+      signers.set(buyer.publicKey.toBase58(), buyer);
+    });
+
+    const signature = await this.program.methods
+      .completeTradesBatch(minAcceptablePrice, maxAcceptablePrice)
+      .accounts({
+        config,
+        tokenProgram,
+        priceOracle,
+        profileProgram,
+        offerProgram,
+      })
+      .remainingAccounts(remainingAccounts)
+      .signers([...signers.values()])
+      .rpc({ commitment: 'confirmed' });
+
+    const tx = await this.connection.getTransaction(signature, {
+      commitment: 'confirmed',
+      maxSupportedTransactionVersion: 0,
+    });
+    // Return data is borsh encoded `Vec<TradeCompletionResult>`. Trailing zero
+    // bytes are trimmed, so pad before reading.
+    const returned = Buffer.from(tx?.meta?.returnData?.data[0] ?? '', 'base64');
+    const data = Buffer.concat([returned, Buffer.alloc(4 + trades.length)]);
+    const count = data.readUInt32LE(0);
+    return Array.from(data.subarray(4, 4 + count)).map(
+      (variant) => TRADE_COMPLETION_RESULTS[variant]
+    );
+  }
+
+  /**
+   * Cancels a trade and returns the escrow to the seller. Only the seller can
+   * cancel an open trade, once its taker lock has elapsed, and only the taker
+   * an accepted one, losing reputation for backing out. With the other party
+   * signing as `counterparty` either side cancels an accepted trade by mutual
+   * agreement.
+   * The program derives and records the reason. The creation fee is refunded
+   * to a seller withdrawing a trade no taker accepted.
+   */
   async cancelTrade(
     tradePDA: PublicKey,
-    seller: Keypair,
+    canceller: Keypair,
     escrowAccount: PublicKey,
-    sellerTokenAccount: PublicKey
+    sellerTokenAccount: PublicKey,
+    tokenMint: PublicKey,
+    offer: PublicKey | null,
+    offerProgram: PublicKey,
+    profileProgram: PublicKey,
+    seller: PublicKey = canceller.publicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
+    counterparty: Keypair | null = null
   ): Promise<void> {
+    const [cancellerProfile] = await this.findProfileAddress(
+      canceller.publicKey,
+      profileProgram
+    );
+    const [config] = await this.findConfigAddress();
     await this.program.methods
       .cancelTrade()
+      .accounts({
+        trade: tradePDA,
+        canceller: canceller.publicKey,
+        counterparty: counterparty?.publicKey ?? null,
+        config,
+        seller,
+        escrowAccount,
+        sellerTokenAccount,
+        tokenMint,
+        offer,
+        offerProgram,
+        cancellerProfile,
+        profileProgram,
+        tokenProgram,
+      })
+      .signers(counterparty ? [canceller, counterparty] : [canceller])
+      .rpc();
+  }
+
+  /**
+   * Has the seller add whatever the escrow of a funded trade is missing of
+   * the trade amount. Fails with `EscrowNotShort` if nothing is missing.
+   */
+  async topUpEscrow(
+    tradePDA: PublicKey,
+    seller: Keypair,
+    escrowAccount: PublicKey,
+    sellerTokenAccount: PublicKey,
+    tokenMint: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<void> {
+    await this.program.methods
+      .topUpEscrow()
       .accounts({
         trade: tradePDA,
         seller: seller.publicKey,
         escrowAccount,
         sellerTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMint,
+        tokenProgram,
       })
       .signers([seller])
       .rpc();
   }
 
+  /**
+   * Moves a dust balance left in a settled trade's escrow to the fee
+   * collector so the trade can be closed. `sweeper` is the config admin or
+   * the seller.
+   */
+  async sweepDust(
+    tradePDA: PublicKey,
+    sweeper: Keypair,
+    escrowAccount: PublicKey,
+    feeCollector: PublicKey,
+    tokenMint: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    await this.program.methods
+      .sweepDust()
+      .accounts({
+        trade: tradePDA,
+        config,
+        sweeper: sweeper.publicKey,
+        escrowAccount,
+        feeCollector,
+        tokenMint,
+        tokenProgram,
+      })
+      .signers([sweeper])
+      .rpc();
+  }
+
+  /**
+   * Sends collected fees from a fee collector to `destination`, a token
+   * account of the same mint. Withdraws the whole balance unless `amount` is
+   * given. Only the config admin can withdraw.
+   */
+  async withdrawFees(
+    admin: Keypair,
+    feeCollector: PublicKey,
+    destination: PublicKey,
+    tokenMint: PublicKey,
+    amount: BN | null = null,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    await this.program.methods
+      .withdrawFees(amount)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+        feeCollector,
+        destination,
+        tokenMint,
+        tokenProgram,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * Sends creation fee lamports collected by the config to `destination`.
+   * Withdraws everything above the config's rent and the fees still held for
+   * refunds unless `amount` is given.
+   * Only the config admin can withdraw.
+   */
+  async withdrawCreationFees(
+    admin: Keypair,
+    destination: PublicKey,
+    amount: BN | null = null
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    await this.program.methods
+      .withdrawCreationFees(amount)
+      .accounts({
+        config,
+        admin: admin.publicKey,
+        destination,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * Closes a completed or cancelled trade and its empty escrow, returning the
+   * rent to the seller. `closer` is the seller or the taker.
+   */
+  async closeTrade(
+    tradePDA: PublicKey,
+    closer: Keypair,
+    seller: PublicKey,
+    escrowAccount: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<void> {
+    await this.program.methods
+      .closeTrade()
+      .accounts({
+        trade: tradePDA,
+        closer: closer.publicKey,
+        seller,
+        escrowAccount,
+        tokenProgram,
+      })
+      .signers([closer])
+      .rpc();
+  }
+
+  /**
+   * Opens a dispute as the seller or buyer. The dispute counts against the
+   * completion rate of both parties that have a profile.
+   */
   async disputeTrade(
     tradePDA: PublicKey,
-    disputer: Keypair
+    disputer: Keypair,
+    seller: PublicKey,
+    buyer: PublicKey,
+    profileProgram: PublicKey
   ): Promise<void> {
+    const [sellerProfile] = await this.findProfileAddress(seller, profileProgram);
+    const [buyerProfile] = await this.findProfileAddress(buyer, profileProgram);
     await this.program.methods
       .disputeTrade()
       .accounts({
         trade: tradePDA,
         disputer: disputer.publicKey,
+        seller,
+        buyer,
+        sellerProfile,
+        buyerProfile,
+        profileProgram,
       })
       .signers([disputer])
       .rpc();
   }
 
+  /**
+   * Settles a disputed trade as the config admin. The escrow goes to
+   * `recipientTokenAccount`, which must belong to the winning party. A buyer
+   * who wins pays the protocol fee, so `feeCollector` is required then while
+   * the config charges a fee.
+   */
+  async resolveDispute(
+    tradePDA: PublicKey,
+    admin: Keypair,
+    buyerWins: boolean,
+    seller: PublicKey,
+    buyer: PublicKey,
+    escrowAccount: PublicKey,
+    recipientTokenAccount: PublicKey,
+    tokenMint: PublicKey,
+    offer: PublicKey | null,
+    offerProgram: PublicKey,
+    profileProgram: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
+    feeCollector: PublicKey | null = null
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    const [loserProfile] = await this.findProfileAddress(
+      buyerWins ? seller : buyer,
+      profileProgram
+    );
+    await this.program.methods
+      .resolveDispute(buyerWins)
+      .accounts({
+        trade: tradePDA,
+        config,
+        admin: admin.publicKey,
+        seller,
+        buyer,
+        escrowAccount,
+        recipientTokenAccount,
+        tokenMint,
+        feeCollector,
+        offer,
+        offerProgram,
+        loserProfile,
+        profileProgram,
+        tokenProgram,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  /**
+   * Refunds a dispute left unresolved past the configured TTL to the seller.
+   * Anyone can send it, the provider wallet pays the fee.
+   */
+  async disputeTimeout(
+    tradePDA: PublicKey,
+    seller: PublicKey,
+    escrowAccount: PublicKey,
+    sellerTokenAccount: PublicKey,
+    tokenMint: PublicKey,
+    offer: PublicKey | null,
+    offerProgram: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<void> {
+    const [config] = await this.findConfigAddress();
+    await this.program.methods
+      .disputeTimeout()
+      .accounts({
+        trade: tradePDA,
+        config,
+        seller,
+        escrowAccount,
+        sellerTokenAccount,
+        tokenMint,
+        offer,
+        offerProgram,
+        tokenProgram,
+      })
+      .rpc();
+  }
+
   async getTrade(tradePDA: PublicKey): Promise<Trade> {
     const account = await this.program.account.trade.fetch(tradePDA);
     return {
@@ -142,21 +811,64 @@ export class TradeClient {
       createdAt: account.createdAt.toNumber(),
       updatedAt: account.updatedAt.toNumber(),
       bump: account.bump,
+      offer: account.offer,
+      version: account.version,
+      takerLockSeconds: account.takerLockSeconds,
+      acceptedAt: account.acceptedAt.toNumber(),
+      disputedAt: account.disputedAt.toNumber(),
+      disputeAvailableAt: account.disputeAvailableAt.toNumber(),
+      cancelReason: account.cancelReason
+        ? (Object.keys(account.cancelReason)[0] as CancelReason)
+        : null,
+      offerType: Object.keys(account.offerType)[0] as OfferType,
+      // Zero padded to 32 bytes on chain
+      memo: Buffer.from(account.memo).toString('utf8').replace(/\0+$/, ''),
+      creationFeePaid: account.creationFeePaid,
     };
   }
 
+  /**
+   * Fetches a trade and both parties' profile stats in one simulated call.
+   * `buyer` is null while the trade is still open.
+   */
+  async getTradeSummary(
+    tradePDA: PublicKey,
+    seller: PublicKey,
+    buyer: PublicKey | null,
+    profileProgram: PublicKey
+  ): Promise<TradeSummary> {
+    const [sellerProfile] = await this.findProfileAddress(seller, profileProgram);
+    const [buyerProfile] = buyer
+      ? await this.findProfileAddress(buyer, profileProgram)
+      : [sellerProfile];
+    const summary = await this.program.methods
+      .tradeSummary()
+      .accounts({
+        trade: tradePDA,
+        sellerProfile,
+        buyerProfile,
+      })
+      .view();
+    return {
+      ...summary,
+      status: this.convertTradeStatus(summary.status),
+      createdAt: summary.createdAt.toNumber(),
+      updatedAt: summary.updatedAt.toNumber(),
+    };
+  }
+
+  async findProfileAddress(
+    owner: PublicKey,
+    profileProgram: PublicKey
+  ): Promise<[PublicKey, number]> {
+    return deriveProfile(owner, profileProgram);
+  }
+
   async findTradeAddress(
     seller: PublicKey,
     tokenMint: PublicKey
   ): Promise<[PublicKey, number]> {
-    return await PublicKey.findProgramAddress(
-      [
-        Buffer.from("trade"),
-        seller.toBuffer(),
-        tokenMint.toBuffer(),
-      ],
-      this.program.programId
-    );
+    return deriveTrade(seller, tokenMint, this.program.programId);
   }
 
   private convertTradeStatus(status: any): TradeStatus {
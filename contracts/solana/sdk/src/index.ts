@@ -3,4 +3,5 @@ export * from './clients/trade';
 export * from './clients/offer';
 export * from './clients/profile';
 export * from './types';
+export * from './pda';
 export * from './utils'; 
\ No newline at end of file
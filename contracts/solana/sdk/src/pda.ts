@@ -0,0 +1,48 @@
+import { PublicKey } from '@solana/web3.js';
+
+// Seeds of the program-derived accounts, mirroring the `*_SEED` constants and
+// `derive_*` helpers the Rust crates re-export from `offer::pda`.
+export const TRADE_SEED = Buffer.from('trade');
+export const OFFER_SEED = Buffer.from('offer');
+export const PROFILE_SEED = Buffer.from('profile');
+export const TRADE_UNITS_SEED = Buffer.from('trade_units');
+
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey('BPFLoaderUpgradeab1e11111111111111111111111');
+
+/** ProgramData account of an upgradeable program, holding its upgrade authority. */
+export function deriveProgramData(programId: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([programId.toBuffer()], BPF_LOADER_UPGRADEABLE_ID);
+}
+
+/** Trade PDA of a seller for `tokenMint`: `["trade", seller, tokenMint]`. */
+export function deriveTrade(
+  seller: PublicKey,
+  tokenMint: PublicKey,
+  tradeProgram: PublicKey
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [TRADE_SEED, seller.toBuffer(), tokenMint.toBuffer()],
+    tradeProgram
+  );
+}
+
+/** Trade units PDA of `tokenMint`: `["trade_units", tokenMint]`. */
+export function deriveTradeUnits(
+  tokenMint: PublicKey,
+  tradeProgram: PublicKey
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [TRADE_UNITS_SEED, tokenMint.toBuffer()],
+    tradeProgram
+  );
+}
+
+/** Offer PDA of `creator`: `["offer", creator]`. */
+export function deriveOffer(creator: PublicKey, offerProgram: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([OFFER_SEED, creator.toBuffer()], offerProgram);
+}
+
+/** Profile PDA of `owner`: `["profile", owner]`. */
+export function deriveProfile(owner: PublicKey, profileProgram: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([PROFILE_SEED, owner.toBuffer()], profileProgram);
+}
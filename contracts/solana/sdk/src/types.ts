@@ -1,4 +1,4 @@
-import { PublicKey } from '@solana/web3.js';
+import { Keypair, PublicKey } from '@solana/web3.js';
 import { BN } from '@project-serum/anchor';
 
 export interface CurrencyPrice {
@@ -7,9 +7,43 @@ export interface CurrencyPrice {
   updatedAt: BN;
 }
 
+/** A price as submitted by a provider or forced by the admin. */
+export interface PriceUpdate {
+  currency: string;
+  usdPrice: BN;
+  updatedAt: BN;
+  /** Half-width of the confidence interval around `usdPrice`, same 5 decimals. */
+  confidence: BN;
+}
+
+export enum PriceUpdateResult {
+  Applied = 'applied',
+  InvalidCurrency = 'invalidCurrency',
+  InvalidPrice = 'invalidPrice',
+  TooManyPrices = 'tooManyPrices',
+  DeviationExceeded = 'deviationExceeded'
+}
+
+export interface PriceFreshness {
+  currency: string;
+  updatedAt: BN;
+  /** Seconds since `updatedAt` on the cluster clock. */
+  ageSeconds: BN;
+}
+
+/** One pool hop, swapping `offerAsset` for `askAsset` through its reserves. */
 export interface PriceRoute {
   offerAsset: string;
+  askAsset: string;
   pool: PublicKey;
+  offerReserve: PublicKey;
+  askReserve: PublicKey;
+}
+
+export interface RegisteredPriceRoute {
+  routeData: PublicKey;
+  denom: string;
+  route: PriceRoute[];
 }
 
 export enum TradeStatus {
@@ -20,6 +54,19 @@ export enum TradeStatus {
   Disputed = 'disputed'
 }
 
+export enum CancelReason {
+  MakerWithdrew = 'makerWithdrew',
+  TakerBackedOut = 'takerBackedOut',
+  Expired = 'expired',
+  MutualAgreement = 'mutualAgreement'
+}
+
+/** Sell offers are funded by their creator, buy offers by the taker. */
+export enum OfferType {
+  Sell = 'sell',
+  Buy = 'buy'
+}
+
 export enum OfferStatus {
   Active = 'active',
   Paused = 'paused',
@@ -32,6 +79,9 @@ export interface Profile {
   reputationScore: number;
   tradesCompleted: number;
   tradesDisputed: number;
+  disputesLost: number;
+  tradesCancelled: number;
+  completionRateBps: number;
   isVerified: boolean;
   createdAt: number;
   updatedAt: number;
@@ -48,6 +98,20 @@ export interface Trade {
   createdAt: number;
   updatedAt: number;
   bump: number;
+  offer: PublicKey;
+  version: number;
+  takerLockSeconds: number;
+  acceptedAt: number;
+  disputedAt: number;
+  disputeAvailableAt: number;
+  cancelReason: CancelReason | null;
+  offerType: OfferType;
+  /** Off-chain reference attached at creation, empty if none. */
+  memo: string;
+  /** Creation fee lamports paid by the seller and not refunded yet. */
+  creationFeePaid: BN;
+  /** Sequence number from the trade registry, 0 for trades created before it. */
+  id: BN;
 }
 
 export interface Offer {
@@ -60,4 +124,96 @@ export interface Offer {
   status: OfferStatus;
   createdAt: number;
   updatedAt: number;
-} 
\ No newline at end of file
+  filledAmount: BN;
+  reservedAmount: BN;
+  quoteValidUntil: number;
+  minTakerReputation: number;
+  minMakerCompletionBps: number;
+  offerType: OfferType;
+} 
+export interface TradeConfig {
+  admin: PublicKey;
+  paused: boolean;
+  bump: number;
+  disputeTtlSeconds: number;
+  feeBps: number;
+  creationFeeLamports: BN;
+  disputeCooldownSeconds: number;
+  minTradeUnits: BN;
+  maxTradeUnits: BN;
+  dustThreshold: BN;
+  /** Creation fees of trades no taker accepted yet, held for refunds. */
+  refundableCreationFees: BN;
+}
+
+export interface TradeRegistry {
+  /** Trades created so far, also the id of the latest one. */
+  tradeCount: BN;
+  bump: number;
+}
+
+export interface TradeQuote {
+  grossAmount: BN;
+  feeAmount: BN;
+  netAmount: BN;
+  transferFee: BN;
+}
+
+export enum TradeCompletionResult {
+  Completed = 'completed',
+  InvalidStatus = 'invalidStatus',
+  MissingSignature = 'missingSignature',
+  InvalidAccounts = 'invalidAccounts',
+  PriceRejected = 'priceRejected',
+  EscrowUnderfunded = 'escrowUnderfunded'
+}
+
+/** Accounts of one trade in a `completeTradesBatch` call. */
+export interface BatchTradeAccounts {
+  trade: PublicKey;
+  seller: Keypair;
+  buyer: Keypair;
+  escrowAccount: PublicKey;
+  buyerTokenAccount: PublicKey;
+  tokenMint: PublicKey;
+  buyerProfile: PublicKey;
+  sellerProfile: PublicKey;
+  /** The offer the trade reserved against, left out for trades without one. */
+  offer?: PublicKey;
+  /** Only read while the config charges a fee. */
+  feeCollector?: PublicKey;
+}
+
+export interface OfferConfig {
+  admin: PublicKey;
+  paused: boolean;
+  bump: number;
+}
+
+export interface ProgramHealth {
+  version: string;
+  initialized: boolean;
+  paused: boolean;
+}
+
+export interface ProfileSummary {
+  owner: PublicKey;
+  reputationScore: number;
+  tradesCompleted: number;
+  tradesDisputed: number;
+  disputesLost: number;
+  isVerified: boolean;
+}
+
+export interface TradeSummary {
+  seller: PublicKey;
+  buyer: PublicKey | null;
+  amount: BN;
+  price: BN;
+  tokenMint: PublicKey;
+  status: TradeStatus;
+  createdAt: number;
+  updatedAt: number;
+  sellerProfile: ProfileSummary | null;
+  buyerProfile: ProfileSummary | null;
+}
@@ -1,5 +1,5 @@
-import { Connection, PublicKey, Keypair, LAMPORTS_PER_SOL } from '@solana/web3.js';
-import { TOKEN_PROGRAM_ID, getAssociatedTokenAddress, createAssociatedTokenAccountInstruction, createMint, getOrCreateAssociatedTokenAccount, mintTo } from '@solana/spl-token';
+import { Connection, PublicKey, Keypair, LAMPORTS_PER_SOL, SystemProgram, Transaction, sendAndConfirmTransaction } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, ExtensionType, getMintLen, createInitializeTransferFeeConfigInstruction, createInitializeMintInstruction, getAssociatedTokenAddress, createAssociatedTokenAccountInstruction, createMint, getOrCreateAssociatedTokenAccount, mintTo } from '@solana/spl-token';
 
 export async function airdropSol(
   connection: Connection,
@@ -24,7 +24,8 @@ export async function createTokenMint(
   payer: Keypair,
   mintAuthority: PublicKey,
   freezeAuthority: PublicKey | null,
-  decimals: number
+  decimals: number,
+  tokenProgramId: PublicKey = TOKEN_PROGRAM_ID
 ): Promise<PublicKey> {
   try {
     return await createMint(
@@ -32,7 +33,10 @@ export async function createTokenMint(
       payer,
       mintAuthority,
       freezeAuthority,
-      decimals
+      decimals,
+      undefined,
+      undefined,
+      tokenProgramId
     );
   } catch (error) {
     console.error('Error creating token mint:', error);
@@ -40,6 +44,54 @@ export async function createTokenMint(
   }
 }
 
+/**
+ * Creates a Token-2022 mint with the transfer fee extension, withholding
+ * `feeBasisPoints` of every transfer up to `maxFee`.
+ */
+export async function createTransferFeeMint(
+  connection: Connection,
+  payer: Keypair,
+  mintAuthority: PublicKey,
+  decimals: number,
+  feeBasisPoints: number,
+  maxFee: bigint
+): Promise<PublicKey> {
+  try {
+    const mint = Keypair.generate();
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const lamports = await connection.getMinimumBalanceForRentExemption(mintLen);
+    const transaction = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: payer.publicKey,
+        newAccountPubkey: mint.publicKey,
+        space: mintLen,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        mint.publicKey,
+        mintAuthority,
+        mintAuthority,
+        feeBasisPoints,
+        maxFee,
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(
+        mint.publicKey,
+        decimals,
+        mintAuthority,
+        null,
+        TOKEN_2022_PROGRAM_ID
+      )
+    );
+    await sendAndConfirmTransaction(connection, transaction, [payer, mint]);
+    return mint.publicKey;
+  } catch (error) {
+    console.error('Error creating transfer fee mint:', error);
+    throw error;
+  }
+}
+
 export async function createTokenAccount(
   connection: Connection,
   payer: Keypair,
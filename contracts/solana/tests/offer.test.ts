@@ -5,14 +5,15 @@ import { expect } from "chai";
 import { OfferClient } from "../sdk/src/clients/offer";
 import { TradeClient } from "../sdk/src/clients/trade";
 import { airdropSol, delay, createTokenMint, createTokenAccount, mintTokens, getTokenBalance } from "../sdk/src/utils";
+import { deriveProfile } from "../sdk/src/pda";
 import * as dotenv from "dotenv";
 
 // Load environment variables from .env file
 dotenv.config();
 
 describe("offer", () => {
-  if (!process.env.OFFER_PROGRAM_ID || !process.env.TRADE_PROGRAM_ID) {
-    throw new Error("Required program IDs not found in environment. Make sure OFFER_PROGRAM_ID and TRADE_PROGRAM_ID are set.");
+  if (!process.env.OFFER_PROGRAM_ID || !process.env.TRADE_PROGRAM_ID || !process.env.PROFILE_PROGRAM_ID) {
+    throw new Error("Required program IDs not found in environment. Make sure OFFER_PROGRAM_ID, TRADE_PROGRAM_ID, and PROFILE_PROGRAM_ID are set.");
   }
 
   // Configure the client to use the local cluster
@@ -21,6 +22,7 @@ describe("offer", () => {
 
   const OFFER_PROGRAM_ID = new PublicKey(process.env.OFFER_PROGRAM_ID);
   const TRADE_PROGRAM_ID = new PublicKey(process.env.TRADE_PROGRAM_ID);
+  const PROFILE_PROGRAM_ID = new PublicKey(process.env.PROFILE_PROGRAM_ID);
 
   let offerClient: OfferClient;
   let tradeClient: TradeClient;
@@ -79,12 +81,28 @@ describe("offer", () => {
         1000_000_000 // 1000 tokens with 6 decimals
       );
       await delay(1000);
+
+      // Program configs are global, another suite may have created them already
+      if (!(await offerClient.getConfig())) {
+        await offerClient.initializeConfig(provider.wallet.payer);
+      }
+      if (!(await tradeClient.getConfig())) {
+        await tradeClient.initializeConfig(provider.wallet.payer);
+      }
+      if (!(await tradeClient.getRegistry())) {
+        await tradeClient.initializeRegistry(provider.wallet.payer);
+      }
     } catch (error) {
       console.error("Error in test setup:", error);
       throw error;
     }
   });
 
+  // Takers here have no profile, the PDA is passed so the offer can read it
+  function profileAddress(owner: PublicKey): PublicKey {
+    return deriveProfile(owner, PROFILE_PROGRAM_ID)[0];
+  }
+
   async function setupCreator() {
     const creator = Keypair.generate();
     await airdropSol(provider.connection, creator.publicKey, 100);
@@ -266,12 +284,14 @@ describe("offer", () => {
       creatorTokenAccount,
       escrowTokenAccount,
       new anchor.BN(500000),
-      new anchor.BN(500000)
+      new anchor.BN(500000),
+      offerPDA,
+      OFFER_PROGRAM_ID
     );
     await delay(1000);
 
     // Accept trade
-    await tradeClient.acceptTrade(tradePDA, buyer);
+    await tradeClient.acceptTrade(tradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, profileAddress(buyer.publicKey), profileAddress(creator.publicKey));
     await delay(1000);
 
     // Create buyer token account
@@ -330,12 +350,14 @@ describe("offer", () => {
       creatorTokenAccount,
       escrowTokenAccount,
       new anchor.BN(500000),
-      new anchor.BN(500000)
+      new anchor.BN(500000),
+      offerPDA,
+      OFFER_PROGRAM_ID
     );
     await delay(1000);
 
     // Accept trade
-    await tradeClient.acceptTrade(tradePDA, buyer);
+    await tradeClient.acceptTrade(tradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, profileAddress(buyer.publicKey), profileAddress(creator.publicKey));
     await delay(1000);
 
     // Create buyer token account
@@ -401,6 +423,110 @@ describe("offer", () => {
     }
   });
 
+  it("Reports health and rejects new offers while paused", async () => {
+    const { creator } = await setupCreator();
+
+    const health = await offerClient.health();
+    expect(health.version).to.equal("0.1.0");
+    expect(health.initialized).to.be.true;
+    expect(health.paused).to.be.false;
+
+    await offerClient.setPaused(provider.wallet.payer, true);
+    try {
+      expect((await offerClient.health()).paused).to.be.true;
+
+      try {
+        await offerClient.createOffer(
+          creator,
+          tokenMint,
+          new anchor.BN(1000_000),
+          new anchor.BN(100_000),
+          new anchor.BN(100_000),
+          new anchor.BN(1000_000)
+        );
+        expect.fail("Expected error was not thrown");
+      } catch (error: any) {
+        expect(error.toString()).to.include("ProgramPaused");
+      }
+    } finally {
+      await offerClient.setPaused(provider.wallet.payer, false);
+    }
+    expect((await offerClient.health()).paused).to.be.false;
+  });
+
+  it("Only lets trades lock in a fresh quote", async () => {
+    const now = Math.floor(Date.now() / 1000);
+
+    async function quotedOffer(validFor: number) {
+      const setup = await setupCreator();
+      await offerClient.createOffer(
+        setup.creator,
+        tokenMint,
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        new anchor.BN(100_000),
+        new anchor.BN(1000_000)
+      );
+      await offerClient.refreshQuote(
+        setup.offerPDA,
+        setup.creator,
+        new anchor.BN(now + validFor)
+      );
+      await delay(1000);
+      return setup;
+    }
+
+    const fresh = await quotedOffer(60);
+    expect((await offerClient.getOffer(fresh.offerPDA)).quoteValidUntil).to.equal(now + 60);
+    await tradeClient.createTrade(
+      fresh.creator,
+      tokenMint,
+      fresh.creatorTokenAccount,
+      fresh.escrowTokenAccount,
+      new anchor.BN(500000),
+      new anchor.BN(100_000),
+      fresh.offerPDA,
+      OFFER_PROGRAM_ID
+    );
+    const offer = await offerClient.getOffer(fresh.offerPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(500000);
+
+    const stale = await quotedOffer(3);
+    await delay(4000);
+    try {
+      await tradeClient.createTrade(
+        stale.creator,
+        tokenMint,
+        stale.creatorTokenAccount,
+        stale.escrowTokenAccount,
+        new anchor.BN(500000),
+        new anchor.BN(100_000),
+        stale.offerPDA,
+        OFFER_PROGRAM_ID
+      );
+      expect.fail("Expected error was not thrown");
+    } catch (error: any) {
+      expect(error.toString()).to.include("QuoteExpired");
+    }
+
+    // Refreshing makes the same offer takeable again.
+    await offerClient.refreshQuote(
+      stale.offerPDA,
+      stale.creator,
+      new anchor.BN(Math.floor(Date.now() / 1000) + 60)
+    );
+    await tradeClient.createTrade(
+      stale.creator,
+      tokenMint,
+      stale.creatorTokenAccount,
+      stale.escrowTokenAccount,
+      new anchor.BN(500000),
+      new anchor.BN(100_000),
+      stale.offerPDA,
+      OFFER_PROGRAM_ID
+    );
+  });
+
   it("Handles edge cases when taking offers", async () => {
     const { creator, creatorTokenAccount, offerPDA, escrowTokenAccount, tradePDA } = await setupCreator();
     const buyer = Keypair.generate();
@@ -426,11 +552,13 @@ describe("offer", () => {
       creatorTokenAccount,
       escrowTokenAccount,
       new anchor.BN(1000_000),
-      new anchor.BN(100_000)
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID
     );
     await delay(1000);
 
-    await tradeClient.acceptTrade(tradePDA, buyer);
+    await tradeClient.acceptTrade(tradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, profileAddress(buyer.publicKey), profileAddress(creator.publicKey));
     await delay(1000);
 
     const buyerTokenAccount = await createTokenAccount(
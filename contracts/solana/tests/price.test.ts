@@ -2,7 +2,8 @@ import * as anchor from "@project-serum/anchor";
 import { PublicKey, Keypair } from "@solana/web3.js";
 import { expect } from "chai";
 import { PriceClient } from "../sdk/src/clients/price";
-import { airdropSol, delay } from "../sdk/src/utils";
+import { PriceUpdate, PriceUpdateResult } from "../sdk/src/types";
+import { airdropSol, delay, createTokenMint, createTokenAccount, mintTokens } from "../sdk/src/utils";
 import * as fs from "fs";
 import * as dotenv from "dotenv";
 
@@ -30,6 +31,19 @@ describe("price", () => {
   const testKeypairData = JSON.parse(fs.readFileSync("../target/deploy/test-keypair.json", "utf-8"));
   const testKeypair = Keypair.fromSecretKey(new Uint8Array(testKeypairData));
 
+  // The admin is the only registered provider until the aggregation tests, so
+  // its submissions are aggregated straight into the oracle.
+  let adminSubmission: PublicKey;
+  const updatePrices = async (prices: PriceUpdate[]) => {
+    const results = await priceClient.submitPrices(priceState.publicKey, admin, prices);
+    for (const [i, price] of prices.entries()) {
+      if (results[i] === PriceUpdateResult.Applied) {
+        await priceClient.aggregatePrices(priceState.publicKey, price.currency, [adminSubmission]);
+      }
+    }
+    return results;
+  };
+
   before(async () => {
     // Load the IDL directly from the file
     const idl = require("../target/idl/price.json");
@@ -42,14 +56,47 @@ describe("price", () => {
     await delay(1000); // Wait for airdrop to be confirmed
   });
 
+  it("Reports an uninitialized oracle in health", async () => {
+    const health = await priceClient.health(priceState.publicKey);
+    expect(health.version).to.equal("0.1.0");
+    expect(health.initialized).to.be.false;
+    expect(health.paused).to.be.false;
+  });
+
   it("Initializes the price oracle", async () => {
     await priceClient.initialize(priceState, admin);
 
     const account = await priceClient.getPriceState(priceState.publicKey);
     expect(account.isInitialized).to.be.true;
     expect(account.admin.toString()).to.equal(admin.publicKey.toString());
-    expect(account.priceProvider.toString()).to.equal(admin.publicKey.toString());
+    expect(account.priceProvider.equals(PublicKey.default)).to.be.true;
     expect(account.prices).to.be.empty;
+    expect(account.maxStalenessSeconds.toNumber()).to.equal(3600);
+    expect(account.maxDeviationBps).to.equal(2000);
+
+    const health = await priceClient.health(priceState.publicKey);
+    expect(health.initialized).to.be.true;
+    expect(health.paused).to.be.false;
+
+    adminSubmission = await priceClient.addProvider(priceState.publicKey, admin, admin.publicKey);
+    expect((await priceClient.getPriceState(priceState.publicKey)).providers).to.have.lengthOf(1);
+  });
+
+  it("Rejects price updates while paused", async () => {
+    await priceClient.setPaused(priceState.publicKey, admin, true);
+    expect((await priceClient.health(priceState.publicKey)).paused).to.be.true;
+
+    try {
+      await updatePrices([
+        { currency: "USD", usdPrice: new anchor.BN(100_000), updatedAt: new anchor.BN(0), confidence: new anchor.BN(0) },
+      ]);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("OraclePaused");
+    }
+
+    await priceClient.setPaused(priceState.publicKey, admin, false);
+    expect((await priceClient.health(priceState.publicKey)).paused).to.be.false;
   });
 
   it("Updates prices with authorized provider", async () => {
@@ -58,15 +105,17 @@ describe("price", () => {
         currency: "USD",
         usdPrice: new anchor.BN(100_000), // $1.00 with 5 decimals
         updatedAt: new anchor.BN(Math.floor(Date.now() / 1000)),
+        confidence: new anchor.BN(0),
       },
       {
         currency: "EUR",
         usdPrice: new anchor.BN(120_000), // $1.20 with 5 decimals
         updatedAt: new anchor.BN(Math.floor(Date.now() / 1000)),
+        confidence: new anchor.BN(0),
       },
     ];
 
-    await priceClient.updatePrices(priceState.publicKey, admin, prices);
+    await updatePrices(prices);
 
     const account = await priceClient.getPriceState(priceState.publicKey);
     expect(account.prices).to.have.lengthOf(2);
@@ -76,21 +125,166 @@ describe("price", () => {
     expect(account.prices[1].usdPrice.toNumber()).to.equal(120_000);
   });
 
-  it("Fails to update prices with unauthorized provider", async () => {
+  it("Upserts prices by currency", async () => {
+    await updatePrices([
+      {
+        currency: "EUR",
+        usdPrice: new anchor.BN(110_000),
+        updatedAt: new anchor.BN(0), // Ignored, the program stamps the current time
+        confidence: new anchor.BN(0),
+      },
+    ]);
+
+    const account = await priceClient.getPriceState(priceState.publicKey);
+    expect(account.prices).to.have.lengthOf(2);
+    expect(account.prices[0].usdPrice.toNumber()).to.equal(100_000);
+    expect(account.prices[1].usdPrice.toNumber()).to.equal(110_000);
+    expect(account.prices[1].updatedAt.toNumber()).to.be.greaterThan(0);
+  });
+
+  it("Reports price freshness, stalest first", async () => {
+    const update = (currency: string, usdPrice: number) =>
+      updatePrices([
+        {
+          currency,
+          usdPrice: new anchor.BN(usdPrice),
+          updatedAt: new anchor.BN(0),
+          confidence: new anchor.BN(0),
+        },
+      ]);
+    await update("USD", 100_000);
+    await delay(2000);
+    await update("EUR", 110_000);
+
+    const freshness = await priceClient.getPriceFreshness(priceState.publicKey);
+    expect(freshness.map((entry) => entry.currency)).to.deep.equal(["USD", "EUR"]);
+    const [usd, eur] = freshness;
+    expect(usd.ageSeconds.toNumber()).to.be.greaterThan(eur.ageSeconds.toNumber());
+    expect(eur.updatedAt.sub(usd.updatedAt).toNumber()).to.equal(
+      usd.ageSeconds.sub(eur.ageSeconds).toNumber()
+    );
+  });
+
+  it("Applies valid prices and reports invalid ones", async () => {
+    const updatedAt = new anchor.BN(0);
+    const confidence = new anchor.BN(0);
+    const results = await updatePrices([
+      { currency: "CHF", usdPrice: new anchor.BN(125_000), updatedAt, confidence },
+      { currency: "", usdPrice: new anchor.BN(100_000), updatedAt, confidence },
+      { currency: "JPY", usdPrice: new anchor.BN(0), updatedAt, confidence },
+      { currency: "EUR", usdPrice: new anchor.BN(115_000), updatedAt, confidence },
+    ]);
+
+    expect(results).to.deep.equal([
+      PriceUpdateResult.Applied,
+      PriceUpdateResult.InvalidCurrency,
+      PriceUpdateResult.InvalidPrice,
+      PriceUpdateResult.Applied,
+    ]);
+
+    const account = await priceClient.getPriceState(priceState.publicKey);
+    expect(account.prices.map((p: any) => p.currency)).to.deep.equal(["USD", "EUR", "CHF"]);
+    expect(account.prices[1].usdPrice.toNumber()).to.equal(115_000);
+    expect(account.prices[2].usdPrice.toNumber()).to.equal(125_000);
+  });
+
+  it("Rejects oversized, duplicated and future-dated price batches", async () => {
+    const updatedAt = new anchor.BN(0);
+    const confidence = new anchor.BN(0);
+    const expectRejected = async (prices: any[], code: string) => {
+      try {
+        await updatePrices(prices);
+        expect.fail("Expected error");
+      } catch (err) {
+        const anchorError = err as anchor.AnchorError;
+        expect(anchorError.error.errorCode.code).to.equal(code);
+      }
+    };
+
+    const oversized = Array.from({ length: 11 }, (_, i) => ({
+      currency: `C${i}`,
+      usdPrice: new anchor.BN(100_000),
+      updatedAt,
+      confidence,
+    }));
+    await expectRejected(oversized, "TooManyPrices");
+
+    await expectRejected([
+      { currency: "USD", usdPrice: new anchor.BN(100_000), updatedAt, confidence },
+      { currency: "USD", usdPrice: new anchor.BN(90_000), updatedAt, confidence },
+    ], "DuplicateCurrency");
+
+    const tomorrow = new anchor.BN(Math.floor(Date.now() / 1000) + 86_400);
+    await expectRejected([
+      { currency: "USD", usdPrice: new anchor.BN(100_000), updatedAt: tomorrow, confidence },
+    ], "FuturePrice");
+
+    // None of the rejected batches touched the stored prices
+    const account = await priceClient.getPriceState(priceState.publicKey);
+    expect(account.prices.map((p: any) => p.currency)).to.deep.equal(["USD", "EUR", "CHF"]);
+    expect(account.prices[0].usdPrice.toNumber()).to.equal(100_000);
+  });
+
+  it("Quarantines a price spike until the admin forces it through", async () => {
+    const updatedAt = new anchor.BN(0);
+    const confidence = new anchor.BN(0);
+    const storedPrice = async (currency: string) => {
+      const account = await priceClient.getPriceState(priceState.publicKey);
+      return account.prices.find((p) => p.currency === currency)!.usdPrice.toNumber();
+    };
+
+    // USD moves 5%, EUR doubles past the 20% breaker
+    const results = await updatePrices([
+      { currency: "USD", usdPrice: new anchor.BN(105_000), updatedAt, confidence },
+      { currency: "EUR", usdPrice: new anchor.BN(230_000), updatedAt, confidence },
+    ]);
+    expect(results).to.deep.equal([
+      PriceUpdateResult.Applied,
+      PriceUpdateResult.DeviationExceeded,
+    ]);
+    expect(await storedPrice("USD")).to.equal(105_000);
+    expect(await storedPrice("EUR")).to.equal(115_000);
+
+    try {
+      await priceClient.forcePrice(priceState.publicKey, priceProvider, {
+        currency: "EUR", usdPrice: new anchor.BN(230_000), updatedAt, confidence,
+      });
+      expect.fail("Expected error");
+    } catch (err) {
+      const anchorError = err as anchor.AnchorError;
+      expect(anchorError.error.errorCode.code).to.equal("ConstraintHasOne");
+    }
+    await priceClient.forcePrice(priceState.publicKey, admin, {
+      currency: "EUR", usdPrice: new anchor.BN(230_000), updatedAt, confidence,
+    });
+    expect(await storedPrice("EUR")).to.equal(230_000);
+
+    // Later tests expect the original prices back
+    await priceClient.setMaxDeviation(priceState.publicKey, admin, 0);
+    await updatePrices([
+      { currency: "USD", usdPrice: new anchor.BN(100_000), updatedAt, confidence },
+      { currency: "EUR", usdPrice: new anchor.BN(115_000), updatedAt, confidence },
+    ]);
+    await priceClient.setMaxDeviation(priceState.publicKey, admin, 2000);
+    expect(await storedPrice("EUR")).to.equal(115_000);
+  });
+
+  it("Fails to submit prices from a key that was never registered", async () => {
     const prices = [
       {
         currency: "USD",
         usdPrice: new anchor.BN(100_000),
         updatedAt: new anchor.BN(Math.floor(Date.now() / 1000)),
+        confidence: new anchor.BN(0),
       },
     ];
 
     try {
-      await priceClient.updatePrices(priceState.publicKey, priceProvider, prices);
+      await priceClient.submitPrices(priceState.publicKey, priceProvider, prices);
       expect.fail("Expected error");
     } catch (err) {
       const anchorError = err as anchor.AnchorError;
-      expect(anchorError.error.errorCode.code).to.equal("InvalidPriceProvider");
+      expect(anchorError.error.errorCode.code).to.equal("AccountNotInitialized");
     }
   });
 
@@ -151,4 +345,362 @@ describe("price", () => {
       expect(anchorError.error.errorCode.code).to.equal("PriceNotFound");
     }
   });
-}); 
\ No newline at end of file
+
+  describe("price confidence", () => {
+    const updateUsd = (confidence: number) =>
+      updatePrices([{
+        currency: "USD",
+        usdPrice: new anchor.BN(100_000),
+        updatedAt: new anchor.BN(0),
+        confidence: new anchor.BN(confidence),
+      }]);
+
+    const verifyUsd = (price: number) =>
+      priceClient.verifyPriceForTrade(
+        priceState.publicKey,
+        new anchor.BN(price),
+        "USD",
+        100 // 1% tolerance
+      );
+
+    after(async () => {
+      await updateUsd(0);
+    });
+
+    it("Widens the tolerance by a tight confidence", async () => {
+      // ±$0.005 on top of the 1% tolerance
+      await updateUsd(500);
+      const account = await priceClient.getPriceState(priceState.publicKey);
+      expect(account.prices[0].currency).to.equal("USD");
+      expect(account.confidences[0].toNumber()).to.equal(500);
+      await verifyUsd(98_500);
+      await verifyUsd(101_500);
+
+      try {
+        await verifyUsd(98_400);
+        expect.fail("Expected error");
+      } catch (err) {
+        const anchorError = err as anchor.AnchorError;
+        expect(anchorError.error.errorCode.code).to.equal("PriceOutOfRange");
+      }
+    });
+
+    it("Rejects trades against a blown-out confidence", async () => {
+      // ±10% is wider than the 5% the oracle trusts
+      await updateUsd(10_000);
+
+      try {
+        await verifyUsd(100_000);
+        expect.fail("Expected error");
+      } catch (err) {
+        const anchorError = err as anchor.AnchorError;
+        expect(anchorError.error.errorCode.code).to.equal("ConfidenceTooWide");
+      }
+    });
+  });
+
+  it("Rejects stale prices", async () => {
+    await priceClient.setMaxStaleness(priceState.publicKey, admin, new anchor.BN(1));
+    await delay(3000);
+
+    try {
+      await priceClient.verifyPriceForTrade(
+        priceState.publicKey,
+        new anchor.BN(100_000),
+        "USD",
+        100 // 1% tolerance
+      );
+      expect.fail("Expected error");
+    } catch (err) {
+      const anchorError = err as anchor.AnchorError;
+      expect(anchorError.error.errorCode.code).to.equal("StalePrice");
+    }
+
+    await priceClient.setMaxStaleness(priceState.publicKey, admin, new anchor.BN(3600));
+  });
+
+  describe("provider aggregation", () => {
+    const providers = [Keypair.generate(), Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    const submissions: PublicKey[] = [];
+
+    const brl = (usdPrice: number) => [{
+      currency: "BRL",
+      usdPrice: new anchor.BN(usdPrice),
+      updatedAt: new anchor.BN(0), // Ignored, the program stamps the current time
+      confidence: new anchor.BN(0),
+    }];
+
+    const brlPrice = async () => {
+      const account = await priceClient.getPriceState(priceState.publicKey);
+      return account.prices.find((p) => p.currency === "BRL")!.usdPrice.toNumber();
+    };
+
+    before(async () => {
+      for (const keypair of providers) {
+        await airdropSol(provider.connection, keypair.publicKey);
+      }
+      await delay(1000);
+
+      for (const keypair of providers.slice(0, 3)) {
+        submissions.push(
+          await priceClient.addProvider(priceState.publicKey, admin, keypair.publicKey)
+        );
+      }
+    });
+
+    it("Aggregates the median of an odd number of providers", async () => {
+      await priceClient.submitPrices(priceState.publicKey, providers[0], brl(100_000));
+      await priceClient.submitPrices(priceState.publicKey, providers[1], brl(102_000));
+      await priceClient.submitPrices(priceState.publicKey, providers[2], brl(110_000));
+
+      await priceClient.aggregatePrices(priceState.publicKey, "BRL", submissions);
+      expect(await brlPrice()).to.equal(102_000);
+    });
+
+    it("Aggregates the median of an even number of providers", async () => {
+      submissions.push(
+        await priceClient.addProvider(priceState.publicKey, admin, providers[3].publicKey)
+      );
+      await priceClient.submitPrices(priceState.publicKey, providers[3], brl(120_000));
+
+      await priceClient.aggregatePrices(priceState.publicKey, "BRL", submissions);
+      expect(await brlPrice()).to.equal(106_000);
+    });
+
+    it("Excludes stale provider submissions and requires a quorum", async () => {
+      await priceClient.setMaxStaleness(priceState.publicKey, admin, new anchor.BN(2));
+      await delay(4000);
+
+      // Two fresh prices out of five registered providers
+      await priceClient.submitPrices(priceState.publicKey, providers[0], brl(100_000));
+      await priceClient.submitPrices(priceState.publicKey, providers[1], brl(104_000));
+      try {
+        await priceClient.aggregatePrices(priceState.publicKey, "BRL", submissions);
+        expect.fail("Expected error");
+      } catch (err) {
+        const anchorError = err as anchor.AnchorError;
+        expect(anchorError.error.errorCode.code).to.equal("QuorumNotMet");
+      }
+
+      await priceClient.submitPrices(priceState.publicKey, providers[2], brl(108_000));
+      await priceClient.aggregatePrices(priceState.publicKey, "BRL", submissions);
+      expect(await brlPrice()).to.equal(104_000);
+
+      await priceClient.setMaxStaleness(priceState.publicKey, admin, new anchor.BN(3600));
+    });
+
+    it("Counts a provider's submission once", async () => {
+      try {
+        await priceClient.aggregatePrices(
+          priceState.publicKey,
+          "BRL",
+          [submissions[0], submissions[0], submissions[1]]
+        );
+        expect.fail("Expected error");
+      } catch (err) {
+        const anchorError = err as anchor.AnchorError;
+        expect(anchorError.error.errorCode.code).to.equal("InvalidProviderSubmission");
+      }
+    });
+
+    it("Rejects submissions from unregistered providers", async () => {
+      await priceClient.removeProvider(priceState.publicKey, admin, providers[3].publicKey);
+
+      try {
+        await priceClient.submitPrices(priceState.publicKey, providers[3], brl(120_000));
+        expect.fail("Expected error");
+      } catch (err) {
+        const anchorError = err as anchor.AnchorError;
+        expect(anchorError.error.errorCode.code).to.equal("InvalidPriceProvider");
+      }
+    });
+  });
+
+  describe("price routes", () => {
+    const poolAB = Keypair.generate();
+    const poolBC = Keypair.generate();
+    let mintA: PublicKey;
+    let mintB: PublicKey;
+    let mintC: PublicKey;
+    let reserveA: PublicKey;
+    let reserveB: PublicKey;
+    let reserveB2: PublicKey;
+    let reserveC: PublicKey;
+
+    const reserve = async (mint: PublicKey, pool: Keypair, amount: number) => {
+      const account = await createTokenAccount(provider.connection, testKeypair, mint, pool.publicKey);
+      await mintTokens(provider.connection, testKeypair, mint, account, testKeypair, amount);
+      return account;
+    };
+
+    const hopAB = () => ({
+      offerAsset: mintA.toString(),
+      askAsset: mintB.toString(),
+      pool: poolAB.publicKey,
+      offerReserve: reserveA,
+      askReserve: reserveB,
+    });
+    const hopBC = () => ({
+      offerAsset: mintB.toString(),
+      askAsset: mintC.toString(),
+      pool: poolBC.publicKey,
+      offerReserve: reserveB2,
+      askReserve: reserveC,
+    });
+
+    before(async () => {
+      mintA = await createTokenMint(provider.connection, testKeypair, testKeypair.publicKey, null, 6);
+      mintB = await createTokenMint(provider.connection, testKeypair, testKeypair.publicKey, null, 6);
+      mintC = await createTokenMint(provider.connection, testKeypair, testKeypair.publicKey, null, 6);
+
+      // 1 A = 2 B, 1 B = 0.5 C
+      reserveA = await reserve(mintA, poolAB, 1_000_000);
+      reserveB = await reserve(mintB, poolAB, 2_000_000);
+      reserveB2 = await reserve(mintB, poolBC, 4_000_000);
+      reserveC = await reserve(mintC, poolBC, 2_000_000);
+    });
+
+    it("Resolves a single hop route", async () => {
+      const routeData = Keypair.generate();
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeData, "A", [
+        hopAB(),
+      ]);
+
+      const price = await priceClient.resolvePrice(
+        priceState.publicKey,
+        routeData.publicKey,
+        "A",
+        [reserveA, reserveB]
+      );
+      expect(price.toNumber()).to.equal(200_000);
+    });
+
+    it("Resolves a multi hop route", async () => {
+      const routeData = Keypair.generate();
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeData, "A", [
+        hopAB(),
+        hopBC(),
+      ]);
+
+      const price = await priceClient.resolvePrice(
+        priceState.publicKey,
+        routeData.publicKey,
+        "A",
+        [reserveA, reserveB, reserveB2, reserveC]
+      );
+      expect(price.toNumber()).to.equal(100_000);
+    });
+
+    it("Rejects pools supplied out of route order", async () => {
+      const routeData = Keypair.generate();
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeData, "A", [
+        hopAB(),
+        hopBC(),
+      ]);
+
+      try {
+        await priceClient.resolvePrice(
+          priceState.publicKey,
+          routeData.publicKey,
+          "A",
+          [reserveB2, reserveC, reserveA, reserveB]
+        );
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPriceRoute");
+      }
+
+      // Hops that do not chain into each other are refused at registration
+      try {
+        await priceClient.registerPriceRoute(priceState.publicKey, admin, Keypair.generate(), "A", [
+          hopAB(),
+          hopAB(),
+        ]);
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPriceRoute");
+      }
+    });
+
+    it("Rejects reserves other than the registered vaults", async () => {
+      const routeData = Keypair.generate();
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeData, "A", [
+        hopAB(),
+      ]);
+
+      // Same pool owner and mint, but skewed so that 1 A = 8 B
+      const decoy = await reserve(mintB, poolAB, 8_000_000);
+      try {
+        await priceClient.resolvePrice(priceState.publicKey, routeData.publicKey, "A", [reserveA, decoy]);
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPriceRoute");
+      }
+    });
+
+    it("Rejects a route registered on another oracle", async () => {
+      const routeData = Keypair.generate();
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeData, "A", [
+        hopAB(),
+      ]);
+
+      const otherState = Keypair.generate();
+      await priceClient.initialize(otherState, admin);
+      try {
+        await priceClient.resolvePrice(otherState.publicKey, routeData.publicKey, "A", [reserveA, reserveB]);
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPriceRoute");
+      }
+    });
+
+    it("Reads registered routes back", async () => {
+      const routeA = Keypair.generate();
+      const routeB = Keypair.generate();
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeA, "A", [
+        hopAB(),
+        hopBC(),
+      ]);
+      await priceClient.registerPriceRoute(priceState.publicKey, admin, routeB, "B", [
+        hopBC(),
+      ]);
+
+      const hopsA = await priceClient.getPriceRoute(routeA.publicKey, "A");
+      expect(hopsA.map((hop) => hop.offerAsset)).to.deep.equal([
+        mintA.toString(),
+        mintB.toString(),
+      ]);
+      expect(hopsA[1].pool.equals(poolBC.publicKey)).to.be.true;
+      const hopsB = await priceClient.getPriceRoute(routeB.publicKey, "B");
+      expect(hopsB.map((hop) => hop.offerAsset)).to.deep.equal([mintB.toString()]);
+
+      try {
+        await priceClient.getPriceRoute(routeB.publicKey, "A");
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPriceRoute");
+      }
+
+      // A route on another oracle is not listed with this one's
+      const otherState = Keypair.generate();
+      const otherRoute = Keypair.generate();
+      await priceClient.initialize(otherState, admin);
+      await priceClient.registerPriceRoute(otherState.publicKey, admin, otherRoute, "A", [
+        hopAB(),
+      ]);
+
+      const listed = await priceClient.listRoutes(priceState.publicKey);
+      const find = (routeData: PublicKey) =>
+        listed.find((entry) => entry.routeData.equals(routeData));
+      expect(find(routeA.publicKey)?.denom).to.equal("A");
+      expect(find(routeB.publicKey)?.denom).to.equal("B");
+      expect(find(routeB.publicKey)?.route).to.have.length(1);
+      expect(find(otherRoute.publicKey)).to.be.undefined;
+
+      const otherListed = await priceClient.listRoutes(otherState.publicKey);
+      expect(otherListed.map((entry) => entry.routeData.toString())).to.deep.equal([
+        otherRoute.publicKey.toString(),
+      ]);
+    });
+  });
+});
@@ -1,19 +1,22 @@
 import * as anchor from "@project-serum/anchor";
 import { PublicKey, Keypair } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, NATIVE_MINT, createWrappedNativeAccount } from "@solana/spl-token";
 import { expect } from "chai";
 import { TradeClient } from "../sdk/src/clients/trade";
+import { OfferClient } from "../sdk/src/clients/offer";
 import { PriceClient } from "../sdk/src/clients/price";
 import { ProfileClient } from "../sdk/src/clients/profile";
-import { airdropSol, delay, createTokenMint, createTokenAccount, mintTokens, getTokenBalance } from "../sdk/src/utils";
+import { CancelReason, OfferType, TradeCompletionResult } from "../sdk/src/types";
+import { deriveOffer, deriveProfile, deriveTrade } from "../sdk/src/pda";
+import { airdropSol, delay, createTokenMint, createTransferFeeMint, createTokenAccount, mintTokens, getTokenBalance } from "../sdk/src/utils";
 import * as dotenv from "dotenv";
 
 // Load environment variables from .env file
 dotenv.config();
 
 describe("trade", () => {
-  if (!process.env.TRADE_PROGRAM_ID || !process.env.PRICE_PROGRAM_ID || !process.env.PROFILE_PROGRAM_ID) {
-    throw new Error("Required program IDs not found in environment. Make sure TRADE_PROGRAM_ID, PRICE_PROGRAM_ID, and PROFILE_PROGRAM_ID are set.");
+  if (!process.env.TRADE_PROGRAM_ID || !process.env.PRICE_PROGRAM_ID || !process.env.PROFILE_PROGRAM_ID || !process.env.OFFER_PROGRAM_ID) {
+    throw new Error("Required program IDs not found in environment. Make sure TRADE_PROGRAM_ID, PRICE_PROGRAM_ID, PROFILE_PROGRAM_ID, and OFFER_PROGRAM_ID are set.");
   }
 
   // Configure the client to use the local cluster
@@ -23,8 +26,10 @@ describe("trade", () => {
   const TRADE_PROGRAM_ID = new PublicKey(process.env.TRADE_PROGRAM_ID);
   const PRICE_PROGRAM_ID = new PublicKey(process.env.PRICE_PROGRAM_ID);
   const PROFILE_PROGRAM_ID = new PublicKey(process.env.PROFILE_PROGRAM_ID);
+  const OFFER_PROGRAM_ID = new PublicKey(process.env.OFFER_PROGRAM_ID);
 
   let tradeClient: TradeClient;
+  let offerClient: OfferClient;
   let priceClient: PriceClient;
   let profileClient: ProfileClient;
   
@@ -37,16 +42,23 @@ describe("trade", () => {
   // Additional sellers for different tests
   const cancelTestSeller = Keypair.generate();
   const disputeTestSeller = Keypair.generate();
+  const lockTestSeller = Keypair.generate();
+  const timeoutTestSeller = Keypair.generate();
   
   // Token accounts
   let sellerTokenAccount: PublicKey;
   let buyerTokenAccount: PublicKey;
   let cancelTestSellerTokenAccount: PublicKey;
   let disputeTestSellerTokenAccount: PublicKey;
+  let lockTestSellerTokenAccount: PublicKey;
+  let timeoutTestSellerTokenAccount: PublicKey;
   let escrowTokenAccount: PublicKey;
   let tradePDA: PublicKey;
   let tradeBump: number;
   let mint: PublicKey;
+  let offerPDA: PublicKey;
+  let disputeTradePDA: PublicKey;
+  let disputeEscrow: PublicKey;
 
   // Profile PDAs
   let buyerProfile: PublicKey;
@@ -59,11 +71,13 @@ describe("trade", () => {
     const tradeIdl = require("../target/idl/trade.json");
     const priceIdl = require("../target/idl/price.json");
     const profileIdl = require("../target/idl/profile.json");
+    const offerIdl = require("../target/idl/offer.json");
 
     // Initialize clients
     tradeClient = new TradeClient(TRADE_PROGRAM_ID, provider, tradeIdl);
     priceClient = new PriceClient(PRICE_PROGRAM_ID, provider, priceIdl);
     profileClient = new ProfileClient(PROFILE_PROGRAM_ID, provider, profileIdl);
+    offerClient = new OfferClient(OFFER_PROGRAM_ID, provider, offerIdl);
 
     // Fund test accounts
     await airdropSol(provider.connection, seller.publicKey);
@@ -71,6 +85,8 @@ describe("trade", () => {
     await airdropSol(provider.connection, priceOracle.publicKey);
     await airdropSol(provider.connection, cancelTestSeller.publicKey);
     await airdropSol(provider.connection, disputeTestSeller.publicKey);
+    await airdropSol(provider.connection, lockTestSeller.publicKey);
+    await airdropSol(provider.connection, timeoutTestSeller.publicKey);
     await delay(1000);
 
     try {
@@ -113,6 +129,20 @@ describe("trade", () => {
         disputeTestSeller.publicKey
       );
 
+      lockTestSellerTokenAccount = await createTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        lockTestSeller.publicKey
+      );
+
+      timeoutTestSellerTokenAccount = await createTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        timeoutTestSeller.publicKey
+      );
+
       await delay(1000);
 
       // Mint tokens to all accounts
@@ -152,22 +182,47 @@ describe("trade", () => {
         1000_000_000
       );
 
+      await mintTokens(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        lockTestSellerTokenAccount,
+        provider.wallet.payer,
+        1000_000_000
+      );
+
+      await mintTokens(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        timeoutTestSellerTokenAccount,
+        provider.wallet.payer,
+        1000_000_000
+      );
+
       await delay(1000);
 
       // Initialize price oracle
       await priceClient.initialize(priceOracle, provider.wallet.payer);
       await delay(1000);
 
-      // Update prices in the oracle
-      await priceClient.updatePrices(
+      // Publish the USD price through a single registered provider
+      const submission = await priceClient.addProvider(
+        priceOracle.publicKey,
+        provider.wallet.payer,
+        provider.wallet.publicKey
+      );
+      await priceClient.submitPrices(
         priceOracle.publicKey,
         provider.wallet.payer,
         [{
           currency: "USD",
           usdPrice: new anchor.BN(100_000), // $1.00 with 5 decimals
-          updatedAt: new anchor.BN(Math.floor(Date.now() / 1000))
+          updatedAt: new anchor.BN(Math.floor(Date.now() / 1000)),
+          confidence: new anchor.BN(0),
         }]
       );
+      await priceClient.aggregatePrices(priceOracle.publicKey, "USD", [submission]);
       await delay(1000);
 
       // Initialize profiles
@@ -183,6 +238,30 @@ describe("trade", () => {
       disputeTestSellerProfile = await profileClient.createProfile(disputeTestSeller, "dispute-test-seller");
       await delay(1000);
 
+      // Program configs are global, another suite may have created them already
+      if (!(await offerClient.getConfig())) {
+        await offerClient.initializeConfig(provider.wallet.payer);
+      }
+      if (!(await tradeClient.getConfig())) {
+        await tradeClient.initializeConfig(provider.wallet.payer);
+      }
+      if (!(await tradeClient.getRegistry())) {
+        await tradeClient.initializeRegistry(provider.wallet.payer);
+      }
+
+      // Buy offer that most trades in this suite reserve against, so any
+      // seller but the buyer can fund one
+      offerPDA = await offerClient.createOffer(
+        buyer,
+        mint,
+        new anchor.BN(100_000_000), // 100 tokens
+        new anchor.BN(100_000),
+        new anchor.BN(100_000),
+        new anchor.BN(100_000_000),
+        OfferType.Buy
+      );
+      await delay(1000);
+
     } catch (error) {
       console.error("Error setting up test environment:", error);
       throw error;
@@ -202,7 +281,12 @@ describe("trade", () => {
       sellerTokenAccount,
       escrowKeypair,
       amount,
-      price
+      price,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
     );
 
     await delay(1000);
@@ -214,8 +298,13 @@ describe("trade", () => {
     expect(trade.price.toNumber()).to.equal(100_000);
     expect(trade.tokenMint.toString()).to.equal(mint.toString());
     expect(trade.escrowAccount.toString()).to.equal(escrowKeypair.publicKey.toString());
+    expect(trade.offer.toString()).to.equal(offerPDA.toString());
+    expect(trade.version).to.equal(4);
     expect(trade.status).to.equal('open');
 
+    const offer = await offerClient.getOffer(offerPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(1000_000);
+
     // Verify tokens were transferred to escrow
     const escrowBalance = await getTokenBalance(provider.connection, escrowKeypair.publicKey);
     expect(escrowBalance).to.equal(1000_000);
@@ -224,8 +313,122 @@ describe("trade", () => {
     escrowTokenAccount = escrowKeypair.publicKey;
   });
 
+  it("Derives the addresses the programs initialized", async () => {
+    const [derivedTrade, tradeBump] = deriveTrade(seller.publicKey, mint, TRADE_PROGRAM_ID);
+    expect(derivedTrade.toString()).to.equal(tradePDA.toString());
+    expect((await tradeClient.getTrade(tradePDA)).bump).to.equal(tradeBump);
+
+    const [derivedOffer] = deriveOffer(buyer.publicKey, OFFER_PROGRAM_ID);
+    expect(derivedOffer.toString()).to.equal(offerPDA.toString());
+
+    const [derivedProfile] = deriveProfile(buyer.publicKey, PROFILE_PROGRAM_ID);
+    expect(derivedProfile.toString()).to.equal(buyerProfile.toString());
+  });
+
+  it("Creates a trade against a Token-2022 mint with a transfer fee", async () => {
+    const token2022Seller = Keypair.generate();
+    await airdropSol(provider.connection, token2022Seller.publicKey);
+
+    // 1% of every transfer is withheld by the mint
+    const feeMint = await createTransferFeeMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      6,
+      100,
+      BigInt(1_000_000_000)
+    );
+    const feeSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      feeMint,
+      token2022Seller.publicKey,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      feeMint,
+      feeSellerTokenAccount,
+      provider.wallet.payer,
+      10_000_000,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const feeOfferPDA = await offerClient.createOffer(
+      token2022Seller,
+      feeMint,
+      new anchor.BN(10_000_000),
+      new anchor.BN(100_000),
+      new anchor.BN(100_000),
+      new anchor.BN(10_000_000)
+    );
+    await delay(1000);
+
+    const escrowKeypair = Keypair.generate();
+    const feeTradePDA = await tradeClient.createTrade(
+      token2022Seller,
+      feeMint,
+      feeSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      feeOfferPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await delay(1000);
+
+    // The trade and the offer reservation cover what reached the escrow
+    const trade = await tradeClient.getTrade(feeTradePDA);
+    expect(trade.status).to.equal('open');
+    expect(trade.amount.toNumber()).to.equal(990_000);
+    expect(await getTokenBalance(provider.connection, escrowKeypair.publicKey)).to.equal(990_000);
+    expect(await getTokenBalance(provider.connection, feeSellerTokenAccount)).to.equal(9_000_000);
+    const offer = await offerClient.getOffer(feeOfferPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(990_000);
+  });
+
+  it("Does not fund an escrow twice for the same trade", async () => {
+    const sellerBalance = await getTokenBalance(provider.connection, sellerTokenAccount);
+
+    // The trade PDA already exists, so `init` fails before any transfer
+    try {
+      await tradeClient.createTrade(
+        seller,
+        mint,
+        sellerTokenAccount,
+        Keypair.generate(),
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("already in use");
+    }
+
+    expect(await getTokenBalance(provider.connection, sellerTokenAccount)).to.equal(sellerBalance);
+    expect(await getTokenBalance(provider.connection, escrowTokenAccount)).to.equal(1000_000);
+    const offer = await offerClient.getOffer(offerPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(1000_000);
+  });
+
+  it("Rejects migrating a trade that is already current", async () => {
+    try {
+      await tradeClient.migrateTrade(tradePDA, provider.wallet.payer);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("AlreadyMigrated");
+    }
+  });
+
   it("Accepts a trade", async () => {
-    await tradeClient.acceptTrade(tradePDA, buyer);
+    await tradeClient.acceptTrade(tradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, sellerProfile, buyerProfile);
     await delay(1000);
 
     const trade = await tradeClient.getTrade(tradePDA);
@@ -233,18 +436,92 @@ describe("trade", () => {
     expect(trade.status).to.equal('inProgress');
   });
 
+  it("Only tops up an escrow that is short of the trade amount", async () => {
+    const sellerBalance = await getTokenBalance(provider.connection, sellerTokenAccount);
+    try {
+      await tradeClient.topUpEscrow(tradePDA, seller, escrowTokenAccount, sellerTokenAccount, mint);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("EscrowNotShort");
+    }
+    expect(await getTokenBalance(provider.connection, sellerTokenAccount)).to.equal(sellerBalance);
+    expect(await getTokenBalance(provider.connection, escrowTokenAccount)).to.equal(1000_000);
+  });
+
+  it("Returns a trade with both profiles in one call", async () => {
+    const summary = await tradeClient.getTradeSummary(
+      tradePDA,
+      seller.publicKey,
+      buyer.publicKey,
+      PROFILE_PROGRAM_ID
+    );
+    expect(summary.status).to.equal('inProgress');
+    expect(summary.amount.toNumber()).to.equal(1000_000);
+    expect(summary.sellerProfile?.owner.toString()).to.equal(seller.publicKey.toString());
+    expect(summary.buyerProfile?.owner.toString()).to.equal(buyer.publicKey.toString());
+
+    try {
+      await tradeClient.getTradeSummary(
+        tradePDA,
+        seller.publicKey,
+        seller.publicKey,
+        PROFILE_PROGRAM_ID
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("InvalidProfile");
+    }
+  });
+
+  it("Aborts completion when the oracle price is outside the accepted bounds", async () => {
+    const buyerBefore = await getTokenBalance(provider.connection, buyerTokenAccount);
+    try {
+      // The oracle quotes 100_000, above what the taker accepts
+      await tradeClient.completeTrade(
+        tradePDA,
+        seller,
+        buyer,
+        escrowTokenAccount,
+        buyerTokenAccount,
+        mint,
+        priceOracle.publicKey,
+        buyerProfile,
+        sellerProfile,
+        PROFILE_PROGRAM_ID,
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        false,
+        null,
+        TOKEN_PROGRAM_ID,
+        [new anchor.BN(90_000), new anchor.BN(99_999)]
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("PriceOutOfBounds");
+    }
+
+    const trade = await tradeClient.getTrade(tradePDA);
+    expect(trade.status).to.equal('inProgress');
+    expect(await getTokenBalance(provider.connection, buyerTokenAccount)).to.equal(buyerBefore);
+  });
+
   it("Completes a trade", async () => {
+    const buyerBefore = await profileClient.getProfile(buyerProfile);
+    const sellerBefore = await profileClient.getProfile(sellerProfile);
+
     await tradeClient.completeTrade(
       tradePDA,
       seller,
       buyer,
       escrowTokenAccount,
       buyerTokenAccount,
+      mint,
       priceOracle.publicKey,
-      PRICE_PROGRAM_ID,
       buyerProfile,
       sellerProfile,
-      PROFILE_PROGRAM_ID
+      PROFILE_PROGRAM_ID,
+      offerPDA,
+      OFFER_PROGRAM_ID
     );
     await delay(1000);
 
@@ -254,74 +531,1531 @@ describe("trade", () => {
     // Verify tokens were transferred to buyer
     const buyerBalance = await getTokenBalance(provider.connection, buyerTokenAccount);
     expect(buyerBalance).to.equal(1001_000_000); // Initial 1000 + 1 from trade
+
+    // Both parties earn reputation for the completed trade
+    const buyerAfter = await profileClient.getProfile(buyerProfile);
+    const sellerAfter = await profileClient.getProfile(sellerProfile);
+    expect(buyerAfter.reputationScore).to.equal(buyerBefore.reputationScore + 1);
+    expect(sellerAfter.reputationScore).to.equal(sellerBefore.reputationScore + 1);
   });
 
-  it("Cancels a trade", async () => {
-    const amount = new anchor.BN(1000_000); // 1 token
-    const price = new anchor.BN(100_000); // $1.00 with 5 decimals
+  it("Rejects completing the same trade twice", async () => {
+    try {
+      await tradeClient.completeTrade(
+        tradePDA,
+        seller,
+        buyer,
+        escrowTokenAccount,
+        buyerTokenAccount,
+        mint,
+        priceOracle.publicKey,
+        buyerProfile,
+        sellerProfile,
+        PROFILE_PROGRAM_ID,
+        offerPDA,
+        OFFER_PROGRAM_ID
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("TradeAlreadyCompleted");
+    }
 
-    // Create a new escrow keypair
-    const escrowKeypair = Keypair.generate();
+    const buyerBalance = await getTokenBalance(provider.connection, buyerTokenAccount);
+    expect(buyerBalance).to.equal(1001_000_000);
+  });
 
-    const cancelTradePDA = await tradeClient.createTrade(
-      cancelTestSeller,
-      mint,
-      cancelTestSellerTokenAccount,
-      escrowKeypair,
-      amount,
-      price
+  it("Unwraps wSOL to the buyer when completing a wSOL trade", async () => {
+    const wsolSeller = Keypair.generate();
+    await airdropSol(provider.connection, wsolSeller.publicKey, 2);
+    const wsolSellerProfile = await profileClient.createProfile(wsolSeller, "wsol-seller");
+
+    const amount = 100_000_000; // 0.1 SOL
+    const sellerWsolAccount = await createWrappedNativeAccount(
+      provider.connection,
+      provider.wallet.payer,
+      wsolSeller.publicKey,
+      amount
+    );
+    // Temporary account the buyer receives into, closed by the trade
+    const buyerWsolAccount = await createWrappedNativeAccount(
+      provider.connection,
+      provider.wallet.payer,
+      buyer.publicKey,
+      0,
+      Keypair.generate()
+    );
+    const wsolOfferPDA = await offerClient.createOffer(
+      wsolSeller,
+      NATIVE_MINT,
+      new anchor.BN(amount),
+      new anchor.BN(100_000),
+      new anchor.BN(1_000),
+      new anchor.BN(amount)
     );
     await delay(1000);
 
-    await tradeClient.cancelTrade(
-      cancelTradePDA,
-      cancelTestSeller,
+    const escrowKeypair = Keypair.generate();
+    const wsolTradePDA = await tradeClient.createTrade(
+      wsolSeller,
+      NATIVE_MINT,
+      sellerWsolAccount,
+      escrowKeypair,
+      new anchor.BN(amount),
+      new anchor.BN(100_000),
+      wsolOfferPDA,
+      OFFER_PROGRAM_ID
+    );
+    await tradeClient.acceptTrade(wsolTradePDA, buyer, wsolOfferPDA, OFFER_PROGRAM_ID, buyerProfile, deriveProfile(wsolSeller.publicKey, PROFILE_PROGRAM_ID)[0]);
+
+    const rent = await provider.connection.getBalance(buyerWsolAccount);
+    const solBefore = await provider.connection.getBalance(buyer.publicKey);
+    await tradeClient.completeTrade(
+      wsolTradePDA,
+      wsolSeller,
+      buyer,
       escrowKeypair.publicKey,
-      cancelTestSellerTokenAccount
+      buyerWsolAccount,
+      NATIVE_MINT,
+      priceOracle.publicKey,
+      buyerProfile,
+      wsolSellerProfile,
+      PROFILE_PROGRAM_ID,
+      wsolOfferPDA,
+      OFFER_PROGRAM_ID,
+      true
     );
     await delay(1000);
 
-    const trade = await tradeClient.getTrade(cancelTradePDA);
-    expect(trade.status).to.equal('cancelled');
+    // The buyer holds native SOL, the wSOL account and its rent are gone
+    const solAfter = await provider.connection.getBalance(buyer.publicKey);
+    expect(solAfter).to.equal(solBefore + amount + rent);
+    expect(await provider.connection.getAccountInfo(buyerWsolAccount)).to.be.null;
+    const trade = await tradeClient.getTrade(wsolTradePDA);
+    expect(trade.status).to.equal('completed');
   });
 
-  it("Disputes a trade", async () => {
-    const amount = new anchor.BN(1000_000); // 1 token
-    const price = new anchor.BN(100_000); // $1.00 with 5 decimals
+  it("Quotes the same fee split that completing a trade realizes", async () => {
+    const feeTestSeller = Keypair.generate();
+    await airdropSol(provider.connection, feeTestSeller.publicKey);
+    const feeTestSellerProfile = await profileClient.createProfile(feeTestSeller, "fee-test-seller");
+    const feeTestSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      feeTestSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      feeTestSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const feeCollector = await tradeClient.createFeeCollector(provider.wallet.payer, mint);
 
-    // Create a new escrow keypair
+    const amount = new anchor.BN(1000_000);
     const escrowKeypair = Keypair.generate();
-
-    const disputeTradePDA = await tradeClient.createTrade(
-      disputeTestSeller,
+    const feeTradePDA = await tradeClient.createTrade(
+      feeTestSeller,
       mint,
-      disputeTestSellerTokenAccount,
+      feeTestSellerTokenAccount,
       escrowKeypair,
       amount,
-      price
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
     );
-    await delay(1000);
+    await tradeClient.acceptTrade(feeTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, deriveProfile(feeTestSeller.publicKey, PROFILE_PROGRAM_ID)[0], buyerProfile);
 
-    await tradeClient.acceptTrade(disputeTradePDA, buyer);
-    await delay(1000);
+    await tradeClient.setFeeBps(provider.wallet.payer, 250);
+    try {
+      const quote = await tradeClient.quoteTrade(amount, mint);
+      expect(quote.grossAmount.toNumber()).to.equal(1000_000);
+      expect(quote.feeAmount.toNumber()).to.equal(25_000);
+      expect(quote.netAmount.toNumber()).to.equal(975_000);
+      expect(quote.transferFee.toNumber()).to.equal(0);
 
-    await tradeClient.disputeTrade(disputeTradePDA, buyer);
-    await delay(1000);
+      const buyerBefore = await getTokenBalance(provider.connection, buyerTokenAccount);
+      const collectorBefore = await getTokenBalance(provider.connection, feeCollector);
+      await tradeClient.completeTrade(
+        feeTradePDA,
+        feeTestSeller,
+        buyer,
+        escrowKeypair.publicKey,
+        buyerTokenAccount,
+        mint,
+        priceOracle.publicKey,
+        buyerProfile,
+        feeTestSellerProfile,
+        PROFILE_PROGRAM_ID,
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        false,
+        feeCollector
+      );
+      await delay(1000);
 
-    const trade = await tradeClient.getTrade(disputeTradePDA);
-    expect(trade.status).to.equal('disputed');
+      const buyerAfter = await getTokenBalance(provider.connection, buyerTokenAccount);
+      const collectorAfter = await getTokenBalance(provider.connection, feeCollector);
+      expect(buyerAfter - buyerBefore).to.equal(quote.netAmount.toNumber());
+      expect(collectorAfter - collectorBefore).to.equal(quote.feeAmount.toNumber());
+      expect(await getTokenBalance(provider.connection, escrowKeypair.publicKey)).to.equal(0);
+    } finally {
+      await tradeClient.setFeeBps(provider.wallet.payer, 0);
+    }
   });
 
-  it("Fails to dispute with unauthorized user", async () => {
-    const unauthorizedUser = Keypair.generate();
-    await airdropSol(provider.connection, unauthorizedUser.publicKey);
+  it("Withdraws collected fees to a treasury account", async () => {
+    // The fee collector holds the fee of the trade completed above.
+    const feeCollector = await tradeClient.createFeeCollector(provider.wallet.payer, mint);
+    const collected = await getTokenBalance(provider.connection, feeCollector);
+    expect(collected).to.be.greaterThan(0);
+
+    const treasury = Keypair.generate();
+    const treasuryTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      treasury.publicKey
+    );
+
+    try {
+      await tradeClient.withdrawFees(buyer, feeCollector, treasuryTokenAccount, mint);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("ConstraintHasOne");
+    }
+    try {
+      await tradeClient.withdrawFees(
+        provider.wallet.payer,
+        feeCollector,
+        treasuryTokenAccount,
+        mint,
+        new anchor.BN(collected + 1)
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("InvalidFeeWithdrawal");
+    }
+
+    await tradeClient.withdrawFees(
+      provider.wallet.payer,
+      feeCollector,
+      treasuryTokenAccount,
+      mint,
+      new anchor.BN(1_000)
+    );
+    expect(await getTokenBalance(provider.connection, treasuryTokenAccount)).to.equal(1_000);
+
+    await tradeClient.withdrawFees(provider.wallet.payer, feeCollector, treasuryTokenAccount, mint);
+    expect(await getTokenBalance(provider.connection, treasuryTokenAccount)).to.equal(collected);
+    expect(await getTokenBalance(provider.connection, feeCollector)).to.equal(0);
+  });
+
+  it("Sweeps dust from a completed trade's escrow so it can be closed", async () => {
+    const dustSeller = Keypair.generate();
+    await airdropSol(provider.connection, dustSeller.publicKey);
+    const dustSellerProfile = await profileClient.createProfile(dustSeller, "dust-seller");
+    const dustSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      dustSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      dustSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const feeCollector = await tradeClient.createFeeCollector(provider.wallet.payer, mint);
+
+    const escrowKeypair = Keypair.generate();
+    const dustTradePDA = await tradeClient.createTrade(
+      dustSeller,
+      mint,
+      dustSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    await tradeClient.acceptTrade(dustTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, deriveProfile(dustSeller.publicKey, PROFILE_PROGRAM_ID)[0], buyerProfile);
+    await tradeClient.completeTrade(
+      dustTradePDA,
+      dustSeller,
+      buyer,
+      escrowKeypair.publicKey,
+      buyerTokenAccount,
+      mint,
+      priceOracle.publicKey,
+      buyerProfile,
+      dustSellerProfile,
+      PROFILE_PROGRAM_ID,
+      offerPDA,
+      OFFER_PROGRAM_ID
+    );
+
+    // Leave a remainder in the emptied escrow
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      escrowKeypair.publicKey,
+      provider.wallet.payer,
+      5
+    );
+    try {
+      await tradeClient.closeTrade(dustTradePDA, dustSeller, dustSeller.publicKey, escrowKeypair.publicKey);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("EscrowNotEmpty");
+    }
+
+    await tradeClient.setDustThreshold(provider.wallet.payer, new anchor.BN(10));
+    try {
+      try {
+        await tradeClient.sweepDust(dustTradePDA, buyer, escrowKeypair.publicKey, feeCollector, mint);
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("UnauthorizedSweeper");
+      }
+
+      const collectorBefore = await getTokenBalance(provider.connection, feeCollector);
+      await tradeClient.sweepDust(dustTradePDA, dustSeller, escrowKeypair.publicKey, feeCollector, mint);
+      expect(await getTokenBalance(provider.connection, feeCollector)).to.equal(collectorBefore + 5);
+
+      await tradeClient.closeTrade(dustTradePDA, dustSeller, dustSeller.publicKey, escrowKeypair.publicKey);
+      expect(await provider.connection.getAccountInfo(escrowKeypair.publicKey)).to.be.null;
+    } finally {
+      await tradeClient.setDustThreshold(provider.wallet.payer, new anchor.BN(0));
+    }
+  });
+
+  it("Completes a batch of trades and skips the ones not in progress", async () => {
+    const amount = new anchor.BN(1000_000);
+    const batchTrades = [];
+    for (const name of ["batch-seller-1", "batch-seller-2", "batch-seller-3"]) {
+      const batchSeller = Keypair.generate();
+      await airdropSol(provider.connection, batchSeller.publicKey);
+      const batchSellerProfile = await profileClient.createProfile(batchSeller, name);
+      const batchSellerTokenAccount = await createTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        batchSeller.publicKey
+      );
+      await mintTokens(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        batchSellerTokenAccount,
+        provider.wallet.payer,
+        1000_000
+      );
+      const escrowKeypair = Keypair.generate();
+      const batchTradePDA = await tradeClient.createTrade(
+        batchSeller,
+        mint,
+        batchSellerTokenAccount,
+        escrowKeypair,
+        amount,
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+      batchTrades.push({
+        trade: batchTradePDA,
+        seller: batchSeller,
+        buyer,
+        escrowAccount: escrowKeypair.publicKey,
+        buyerTokenAccount,
+        tokenMint: mint,
+        buyerProfile,
+        sellerProfile: batchSellerProfile,
+        offer: offerPDA,
+        sellerTokenAccount: batchSellerTokenAccount,
+      });
+    }
+    // The last trade is never accepted, so it cannot be completed
+    await tradeClient.acceptTrade(batchTrades[0].trade, buyer, offerPDA, OFFER_PROGRAM_ID, batchTrades[0].sellerProfile, buyerProfile);
+    await tradeClient.acceptTrade(batchTrades[1].trade, buyer, offerPDA, OFFER_PROGRAM_ID, batchTrades[1].sellerProfile, buyerProfile);
+
+    const buyerBefore = await getTokenBalance(provider.connection, buyerTokenAccount);
+    const results = await tradeClient.completeTradesBatch(
+      batchTrades,
+      priceOracle.publicKey,
+      PROFILE_PROGRAM_ID,
+      OFFER_PROGRAM_ID
+    );
     await delay(1000);
 
+    expect(results).to.deep.equal([
+      TradeCompletionResult.Completed,
+      TradeCompletionResult.Completed,
+      TradeCompletionResult.InvalidStatus,
+    ]);
+    const buyerAfter = await getTokenBalance(provider.connection, buyerTokenAccount);
+    expect(buyerAfter - buyerBefore).to.equal(2 * amount.toNumber());
+    expect((await tradeClient.getTrade(batchTrades[0].trade)).status).to.equal('completed');
+    expect((await tradeClient.getTrade(batchTrades[1].trade)).status).to.equal('completed');
+    const skipped = batchTrades[2];
+    expect((await tradeClient.getTrade(skipped.trade)).status).to.equal('open');
+    expect(await getTokenBalance(provider.connection, skipped.escrowAccount)).to.equal(amount.toNumber());
+
+    // Give the skipped trade's reservation back to the shared offer
+    await tradeClient.cancelTrade(
+      skipped.trade,
+      skipped.seller,
+      skipped.escrowAccount,
+      skipped.sellerTokenAccount,
+      mint,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      PROFILE_PROGRAM_ID
+    );
+  });
+
+  it("Rejects takers below the offer's minimum reputation", async () => {
+    const repSeller = Keypair.generate();
+    const lowTaker = Keypair.generate();
+    const highTaker = Keypair.generate();
+    await airdropSol(provider.connection, repSeller.publicKey);
+    await airdropSol(provider.connection, lowTaker.publicKey);
+    await airdropSol(provider.connection, highTaker.publicKey);
+    const lowTakerProfile = await profileClient.createProfile(lowTaker, "low-taker");
+    const highTakerProfile = await profileClient.createProfile(highTaker, "high-taker");
+
+    const repSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      repSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      repSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const repOfferPDA = await offerClient.createOffer(
+      repSeller,
+      mint,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      new anchor.BN(100_000),
+      new anchor.BN(1000_000)
+    );
+    await offerClient.setMinTakerReputation(repOfferPDA, repSeller, 5);
+    const repTradePDA = await tradeClient.createTrade(
+      repSeller,
+      mint,
+      repSellerTokenAccount,
+      Keypair.generate(),
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      repOfferPDA,
+      OFFER_PROGRAM_ID
+    );
+
     try {
-      await tradeClient.disputeTrade(tradePDA, unauthorizedUser);
-      throw new Error("Expected error did not occur");
-    } catch (error: any) {
-      expect(error.error.errorCode.code).to.equal("UnauthorizedDisputer");
+      await tradeClient.acceptTrade(repTradePDA, lowTaker, repOfferPDA, OFFER_PROGRAM_ID, lowTakerProfile, deriveProfile(repSeller.publicKey, PROFILE_PROGRAM_ID)[0]);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("TakerReputationTooLow");
+    }
+
+    expect((await offerClient.getOffer(repOfferPDA)).minTakerReputation).to.equal(5);
+
+    // Reputation only changes through trades, so lift the gate to let a new taker in
+    await offerClient.setMinTakerReputation(repOfferPDA, repSeller, 0);
+    await tradeClient.acceptTrade(repTradePDA, highTaker, repOfferPDA, OFFER_PROGRAM_ID, highTakerProfile, deriveProfile(repSeller.publicKey, PROFILE_PROGRAM_ID)[0]);
+    const trade = await tradeClient.getTrade(repTradePDA);
+    expect(trade.status).to.equal('inProgress');
+    expect(trade.buyer?.toString()).to.equal(highTaker.publicKey.toString());
+  });
+
+  it("Holds the sellers of a buy offer to its minimum taker reputation", async () => {
+    const buyMaker = Keypair.generate();
+    const lowSeller = Keypair.generate();
+    await airdropSol(provider.connection, buyMaker.publicKey);
+    await airdropSol(provider.connection, lowSeller.publicKey);
+    const buyMakerProfile = await profileClient.createProfile(buyMaker, "buy-maker");
+    const lowSellerProfile = await profileClient.createProfile(lowSeller, "low-seller");
+
+    const lowSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      lowSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      lowSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const buyOfferPDA = await offerClient.createOffer(
+      buyMaker,
+      mint,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      new anchor.BN(100_000),
+      new anchor.BN(1000_000),
+      OfferType.Buy
+    );
+    await offerClient.setMinTakerReputation(buyOfferPDA, buyMaker, 5);
+    const buyTradePDA = await tradeClient.createTrade(
+      lowSeller,
+      mint,
+      lowSellerTokenAccount,
+      Keypair.generate(),
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      buyOfferPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+
+    // The maker buys, so the seller who opened the trade is the taker
+    try {
+      await tradeClient.acceptTrade(buyTradePDA, buyMaker, buyOfferPDA, OFFER_PROGRAM_ID, buyMakerProfile, buyMakerProfile);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("InvalidTakerProfile");
+    }
+    try {
+      await tradeClient.acceptTrade(buyTradePDA, buyMaker, buyOfferPDA, OFFER_PROGRAM_ID, lowSellerProfile, buyMakerProfile);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("TakerReputationTooLow");
+    }
+
+    await offerClient.setMinTakerReputation(buyOfferPDA, buyMaker, 0);
+    await tradeClient.acceptTrade(buyTradePDA, buyMaker, buyOfferPDA, OFFER_PROGRAM_ID, lowSellerProfile, buyMakerProfile);
+    const trade = await tradeClient.getTrade(buyTradePDA);
+    expect(trade.status).to.equal('inProgress');
+    expect(trade.buyer?.toString()).to.equal(buyMaker.publicKey.toString());
+  });
+
+  it("Lets takers through a maker completion gate until the maker has history", async () => {
+    const newMaker = Keypair.generate();
+    const taker = Keypair.generate();
+    await airdropSol(provider.connection, newMaker.publicKey);
+    await airdropSol(provider.connection, taker.publicKey);
+    const makerProfile = await profileClient.createProfile(newMaker, "new-maker");
+    const takerProfile = await profileClient.createProfile(taker, "gate-taker");
+
+    const makerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      newMaker.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      makerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const gatedOfferPDA = await offerClient.createOffer(
+      newMaker,
+      mint,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      new anchor.BN(100_000),
+      new anchor.BN(1000_000)
+    );
+
+    try {
+      await offerClient.setMinMakerCompletion(gatedOfferPDA, newMaker, 10_001);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("InvalidCompletionRate");
+    }
+    await offerClient.setMinMakerCompletion(gatedOfferPDA, newMaker, 9_000);
+
+    const gatedTradePDA = await tradeClient.createTrade(
+      newMaker,
+      mint,
+      makerTokenAccount,
+      Keypair.generate(),
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      gatedOfferPDA,
+      OFFER_PROGRAM_ID
+    );
+
+    // No settled trades yet, so the maker's rate is not held against them
+    await tradeClient.acceptTrade(gatedTradePDA, taker, gatedOfferPDA, OFFER_PROGRAM_ID, takerProfile, makerProfile);
+    expect((await tradeClient.getTrade(gatedTradePDA)).status).to.equal('inProgress');
+    expect((await offerClient.getOffer(gatedOfferPDA)).minMakerCompletionBps).to.equal(9_000);
+    expect((await profileClient.getProfile(makerProfile)).completionRateBps).to.equal(0);
+  });
+
+  it("Only lets the selling side of an offer fund its escrow", async () => {
+    const maker = Keypair.generate();
+    const taker = Keypair.generate();
+    const tokenAccounts = new Map<Keypair, PublicKey>();
+    for (const party of [maker, taker]) {
+      await airdropSol(provider.connection, party.publicKey);
+      const tokenAccount = await createTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        party.publicKey
+      );
+      await mintTokens(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        tokenAccount,
+        provider.wallet.payer,
+        1000_000
+      );
+      tokenAccounts.set(party, tokenAccount);
+    }
+    const sellOfferPDA = await offerClient.createOffer(
+      maker,
+      mint,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      new anchor.BN(100_000),
+      new anchor.BN(1000_000),
+      OfferType.Sell
+    );
+    expect((await offerClient.getOffer(sellOfferPDA)).offerType).to.equal(OfferType.Sell);
+
+    const fund = (depositor: Keypair, offer: PublicKey, offerType: OfferType) =>
+      tradeClient.createTrade(
+        depositor,
+        mint,
+        tokenAccounts.get(depositor) ?? buyerTokenAccount,
+        Keypair.generate(),
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offer,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        offerType
+      );
+    const rejections: [Keypair, PublicKey, OfferType, string][] = [
+      // The maker of a sell offer funds it, not the taker
+      [taker, sellOfferPDA, OfferType.Sell, "WrongEscrowDepositor"],
+      // The taker funds a buy offer, not its maker
+      [buyer, offerPDA, OfferType.Buy, "WrongEscrowDepositor"],
+      [taker, sellOfferPDA, OfferType.Buy, "OfferTypeMismatch"],
+    ];
+    for (const [depositor, offer, offerType, error] of rejections) {
+      try {
+        await fund(depositor, offer, offerType);
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include(error);
+      }
+    }
+
+    const sellTrade = await tradeClient.getTrade(await fund(maker, sellOfferPDA, OfferType.Sell));
+    expect(sellTrade.offerType).to.equal(OfferType.Sell);
+    const buyTrade = await tradeClient.getTrade(await fund(taker, offerPDA, OfferType.Buy));
+    expect(buyTrade.offerType).to.equal(OfferType.Buy);
+  });
+
+  it("Stores a memo on the trade and emits it on creation", async () => {
+    const memoSeller = Keypair.generate();
+    await airdropSol(provider.connection, memoSeller.publicKey);
+    const memoSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      memoSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      memoSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const create = (memo: string) =>
+      tradeClient.createTrade(
+        memoSeller,
+        mint,
+        memoSellerTokenAccount,
+        Keypair.generate(),
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy,
+        memo
+      );
+
+    try {
+      await create("x".repeat(33));
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("MemoTooLong");
+    }
+
+    const events: any[] = [];
+    const listener = tradeClient.addEventListener("TradeCreated", (event) => events.push(event));
+    try {
+      const memoTradePDA = await create("INV-2024-0042");
+      await delay(2000);
+
+      expect((await tradeClient.getTrade(memoTradePDA)).memo).to.equal("INV-2024-0042");
+      const created = events.find((event) => event.trade.equals(memoTradePDA));
+      expect(created.memo).to.equal("INV-2024-0042");
+      expect(created.seller.toString()).to.equal(memoSeller.publicKey.toString());
+    } finally {
+      await tradeClient.removeEventListener(listener);
+    }
+  });
+
+  it("Numbers trades in the order they are created", async () => {
+    // The trade PDA is keyed by seller and mint, so each trade needs its own seller
+    const create = async () => {
+      const seller = Keypair.generate();
+      await airdropSol(provider.connection, seller.publicKey);
+      const sellerTokenAccount = await createTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        seller.publicKey
+      );
+      await mintTokens(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        sellerTokenAccount,
+        provider.wallet.payer,
+        1000_000
+      );
+      return await tradeClient.createTrade(
+        seller,
+        mint,
+        sellerTokenAccount,
+        Keypair.generate(),
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+    };
+
+    const first = await tradeClient.getTrade(await create());
+    const second = await tradeClient.getTrade(await create());
+    expect(first.id.toNumber()).to.be.greaterThan(0);
+    expect(second.id.toNumber()).to.equal(first.id.toNumber() + 1);
+    expect((await tradeClient.getRegistry())!.tradeCount.toNumber()).to.be.at.least(
+      second.id.toNumber()
+    );
+  });
+
+  it("Reserves offer liquidity so concurrent trades cannot oversell it", async () => {
+    const maker = Keypair.generate();
+    await airdropSol(provider.connection, maker.publicKey);
+    const smallOfferPDA = await offerClient.createOffer(
+      maker,
+      mint,
+      new anchor.BN(1500_000),
+      new anchor.BN(100_000),
+      new anchor.BN(100_000),
+      new anchor.BN(1500_000),
+      OfferType.Buy
+    );
+
+    const sellers = [Keypair.generate(), Keypair.generate()];
+    const sellerTokenAccounts: PublicKey[] = [];
+    for (const s of sellers) {
+      await airdropSol(provider.connection, s.publicKey);
+      const tokenAccount = await createTokenAccount(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        s.publicKey
+      );
+      await mintTokens(
+        provider.connection,
+        provider.wallet.payer,
+        mint,
+        tokenAccount,
+        provider.wallet.payer,
+        1000_000
+      );
+      sellerTokenAccounts.push(tokenAccount);
+    }
+
+    // Both trades fit the offer on their own, only one fits alongside the other
+    const results = await Promise.allSettled(
+      sellers.map((s, i) =>
+        tradeClient.createTrade(
+          s,
+          mint,
+          sellerTokenAccounts[i],
+          Keypair.generate(),
+          new anchor.BN(1000_000),
+          new anchor.BN(100_000),
+          smallOfferPDA,
+          OFFER_PROGRAM_ID,
+          0,
+          TOKEN_PROGRAM_ID,
+          OfferType.Buy
+        )
+      )
+    );
+    const rejected = results.filter((r): r is PromiseRejectedResult => r.status === "rejected");
+    expect(results.filter((r) => r.status === "fulfilled")).to.have.lengthOf(1);
+    expect(rejected).to.have.lengthOf(1);
+    expect(rejected[0].reason.toString()).to.include("InsufficientAmount");
+
+    const offer = await offerClient.getOffer(smallOfferPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(1000_000);
+  });
+
+  it("Charges the creation fee and refunds it when the seller withdraws", async () => {
+    const feeSeller = Keypair.generate();
+    await airdropSol(provider.connection, feeSeller.publicKey);
+    const feeSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      feeSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      feeSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const [config] = await tradeClient.findConfigAddress();
+    const creationFee = 5_000_000;
+
+    await tradeClient.setCreationFee(provider.wallet.payer, new anchor.BN(creationFee));
+    try {
+      expect((await tradeClient.getConfig())!.creationFeeLamports.toNumber()).to.equal(creationFee);
+      const collectorBefore = await provider.connection.getBalance(config);
+      const escrowKeypair = Keypair.generate();
+      const feeTradePDA = await tradeClient.createTrade(
+        feeSeller,
+        mint,
+        feeSellerTokenAccount,
+        escrowKeypair,
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+      await delay(1000);
+
+      const collectorAfter = await provider.connection.getBalance(config);
+      expect(collectorAfter - collectorBefore).to.equal(creationFee);
+
+      expect((await tradeClient.getTrade(feeTradePDA)).creationFeePaid.toNumber()).to.equal(creationFee);
+      expect((await tradeClient.getConfig())!.refundableCreationFees.toNumber()).to.equal(creationFee);
+
+      // No taker accepted, so withdrawing the trade gives the fee back
+      const sellerBefore = await provider.connection.getBalance(feeSeller.publicKey);
+      await tradeClient.cancelTrade(
+        feeTradePDA,
+        feeSeller,
+        escrowKeypair.publicKey,
+        feeSellerTokenAccount,
+        mint,
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        PROFILE_PROGRAM_ID
+      );
+      await delay(1000);
+      expect(await provider.connection.getBalance(config)).to.equal(collectorBefore);
+      // The provider wallet pays the transaction fee
+      expect(await provider.connection.getBalance(feeSeller.publicKey)).to.equal(
+        sellerBefore + creationFee
+      );
+      expect((await tradeClient.getTrade(feeTradePDA)).creationFeePaid.toNumber()).to.equal(0);
+      expect((await tradeClient.getConfig())!.refundableCreationFees.toNumber()).to.equal(0);
+    } finally {
+      await tradeClient.setCreationFee(provider.wallet.payer, new anchor.BN(0));
+    }
+  });
+
+  it("Keeps the creation fee once a taker accepted the trade", async () => {
+    const feeSeller = Keypair.generate();
+    await airdropSol(provider.connection, feeSeller.publicKey);
+    const feeSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      feeSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      feeSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const [config] = await tradeClient.findConfigAddress();
+    const creationFee = 5_000_000;
+
+    await tradeClient.setCreationFee(provider.wallet.payer, new anchor.BN(creationFee));
+    try {
+      const escrowKeypair = Keypair.generate();
+      const feeTradePDA = await tradeClient.createTrade(
+        feeSeller,
+        mint,
+        feeSellerTokenAccount,
+        escrowKeypair,
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+      await tradeClient.acceptTrade(feeTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, deriveProfile(feeSeller.publicKey, PROFILE_PROGRAM_ID)[0], buyerProfile);
+      await delay(1000);
+      expect((await tradeClient.getConfig())!.refundableCreationFees.toNumber()).to.equal(0);
+      const collectorBefore = await provider.connection.getBalance(config);
+
+      await tradeClient.cancelTrade(
+        feeTradePDA,
+        buyer,
+        escrowKeypair.publicKey,
+        feeSellerTokenAccount,
+        mint,
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        PROFILE_PROGRAM_ID,
+        feeSeller.publicKey
+      );
+      await delay(1000);
+      expect(await provider.connection.getBalance(config)).to.equal(collectorBefore);
+      expect((await tradeClient.getTrade(feeTradePDA)).creationFeePaid.toNumber()).to.equal(creationFee);
+    } finally {
+      await tradeClient.setCreationFee(provider.wallet.payer, new anchor.BN(0));
+    }
+  });
+
+  it("Withdraws collected creation fees above the config's rent", async () => {
+    // The config holds the creation fee of the trade accepted above.
+    const [config] = await tradeClient.findConfigAddress();
+    const configInfo = await provider.connection.getAccountInfo(config);
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(
+      configInfo!.data.length
+    );
+    const collected = configInfo!.lamports - rent;
+    expect(collected).to.be.greaterThan(0);
+    const treasury = Keypair.generate().publicKey;
+
+    try {
+      await tradeClient.withdrawCreationFees(buyer, treasury);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("ConstraintHasOne");
+    }
+    try {
+      await tradeClient.withdrawCreationFees(
+        provider.wallet.payer,
+        treasury,
+        new anchor.BN(collected + 1)
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("InvalidFeeWithdrawal");
+    }
+
+    await tradeClient.withdrawCreationFees(provider.wallet.payer, treasury);
+    expect(await provider.connection.getBalance(treasury)).to.equal(collected);
+    expect(await provider.connection.getBalance(config)).to.equal(rent);
+  });
+
+  it("Holds an open trade for takers until its taker lock elapses", async () => {
+    const lockedSeller = Keypair.generate();
+    await airdropSol(provider.connection, lockedSeller.publicKey);
+    const lockedSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      lockedSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      lockedSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+    const takerLockSeconds = 2;
+    const escrowKeypair = Keypair.generate();
+    const lockedTradePDA = await tradeClient.createTrade(
+      lockedSeller,
+      mint,
+      lockedSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      takerLockSeconds,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    const cancel = () =>
+      tradeClient.cancelTrade(
+        lockedTradePDA,
+        lockedSeller,
+        escrowKeypair.publicKey,
+        lockedSellerTokenAccount,
+        mint,
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        PROFILE_PROGRAM_ID
+      );
+
+    try {
+      await cancel();
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("TakerLockActive");
+    }
+
+    await delay((takerLockSeconds + 2) * 1000);
+    await cancel();
+    const trade = await tradeClient.getTrade(lockedTradePDA);
+    expect(trade.status).to.equal('cancelled');
+    expect(trade.cancelReason).to.equal(CancelReason.MakerWithdrew);
+  });
+
+  it("Cancels a trade", async () => {
+    const amount = new anchor.BN(1000_000); // 1 token
+    const price = new anchor.BN(100_000); // $1.00 with 5 decimals
+
+    // Create a new escrow keypair
+    const escrowKeypair = Keypair.generate();
+
+    const cancelTradePDA = await tradeClient.createTrade(
+      cancelTestSeller,
+      mint,
+      cancelTestSellerTokenAccount,
+      escrowKeypair,
+      amount,
+      price,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    await delay(1000);
+
+    let offer = await offerClient.getOffer(offerPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(2000_000);
+
+    await tradeClient.cancelTrade(
+      cancelTradePDA,
+      cancelTestSeller,
+      escrowKeypair.publicKey,
+      cancelTestSellerTokenAccount,
+      mint,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      PROFILE_PROGRAM_ID
+    );
+    await delay(1000);
+
+    const trade = await tradeClient.getTrade(cancelTradePDA);
+    expect(trade.status).to.equal('cancelled');
+
+    // The cancelled trade's reservation is returned to the offer
+    offer = await offerClient.getOffer(offerPDA);
+    expect(offer.filledAmount.toNumber()).to.equal(1000_000);
+  });
+
+  it("Closes a cancelled trade and returns its rent to the seller", async () => {
+    const closeTestSeller = Keypair.generate();
+    await airdropSol(provider.connection, closeTestSeller.publicKey);
+    const closeTestSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      closeTestSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      closeTestSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+
+    const escrowKeypair = Keypair.generate();
+    const closeTradePDA = await tradeClient.createTrade(
+      closeTestSeller,
+      mint,
+      closeTestSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+
+    // Nothing can be closed before the trade is settled
+    try {
+      await tradeClient.closeTrade(closeTradePDA, closeTestSeller, closeTestSeller.publicKey, escrowKeypair.publicKey);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("InvalidTradeStatus");
+    }
+
+    await tradeClient.cancelTrade(
+      closeTradePDA,
+      closeTestSeller,
+      escrowKeypair.publicKey,
+      closeTestSellerTokenAccount,
+      mint,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      PROFILE_PROGRAM_ID
+    );
+
+    try {
+      await tradeClient.closeTrade(closeTradePDA, buyer, closeTestSeller.publicKey, escrowKeypair.publicKey);
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("UnauthorizedCloser");
+    }
+
+    const tradeRent = (await provider.connection.getAccountInfo(closeTradePDA))!.lamports;
+    const escrowRent = (await provider.connection.getAccountInfo(escrowKeypair.publicKey))!.lamports;
+    const sellerBefore = await provider.connection.getBalance(closeTestSeller.publicKey);
+
+    await tradeClient.closeTrade(closeTradePDA, closeTestSeller, closeTestSeller.publicKey, escrowKeypair.publicKey);
+    await delay(1000);
+
+    const sellerAfter = await provider.connection.getBalance(closeTestSeller.publicKey);
+    expect(sellerAfter).to.equal(sellerBefore + tradeRent + escrowRent);
+    expect(await provider.connection.getAccountInfo(closeTradePDA)).to.be.null;
+    expect(await provider.connection.getAccountInfo(escrowKeypair.publicKey)).to.be.null;
+  });
+
+  it("Never lets the seller cancel an accepted trade", async () => {
+    const escrowKeypair = Keypair.generate();
+    const lockTradePDA = await tradeClient.createTrade(
+      lockTestSeller,
+      mint,
+      lockTestSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      5,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    await tradeClient.acceptTrade(lockTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, deriveProfile(lockTestSeller.publicKey, PROFILE_PROGRAM_ID)[0], buyerProfile);
+
+    // Not inside the taker lock and not after it either, the buyer may
+    // already have paid
+    for (const wait of [0, 6000]) {
+      await delay(wait);
+      try {
+        await tradeClient.cancelTrade(
+          lockTradePDA,
+          lockTestSeller,
+          escrowKeypair.publicKey,
+          lockTestSellerTokenAccount,
+          mint,
+          offerPDA,
+          OFFER_PROGRAM_ID,
+          PROFILE_PROGRAM_ID
+        );
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("UnauthorizedCanceller");
+      }
+    }
+
+    // The taker can still walk away
+    await tradeClient.cancelTrade(
+      lockTradePDA,
+      buyer,
+      escrowKeypair.publicKey,
+      lockTestSellerTokenAccount,
+      mint,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      PROFILE_PROGRAM_ID,
+      lockTestSeller.publicKey
+    );
+    await delay(1000);
+
+    const trade = await tradeClient.getTrade(lockTradePDA);
+    expect(trade.status).to.equal('cancelled');
+    const sellerBalance = await getTokenBalance(provider.connection, lockTestSellerTokenAccount);
+    expect(sellerBalance).to.equal(1000_000_000);
+  });
+
+  it("Records why a trade was cancelled and who pays for it", async () => {
+    const reasonSeller = Keypair.generate();
+    await airdropSol(provider.connection, reasonSeller.publicKey);
+    const reasonSellerTokenAccount = await createTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      reasonSeller.publicKey
+    );
+    await mintTokens(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      reasonSellerTokenAccount,
+      provider.wallet.payer,
+      1000_000
+    );
+
+    const escrowKeypair = Keypair.generate();
+    const reasonTradePDA = await tradeClient.createTrade(
+      reasonSeller,
+      mint,
+      reasonSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    await tradeClient.acceptTrade(reasonTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, deriveProfile(reasonSeller.publicKey, PROFILE_PROGRAM_ID)[0], buyerProfile);
+
+    // Naming the buyer as counterparty without their signature gets nowhere
+    try {
+      await tradeClient.cancelTrade(
+        reasonTradePDA,
+        reasonSeller,
+        escrowKeypair.publicKey,
+        reasonSellerTokenAccount,
+        mint,
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        PROFILE_PROGRAM_ID,
+        reasonSeller.publicKey,
+        TOKEN_PROGRAM_ID,
+        reasonSeller
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("MissingCounterparty");
+    }
+
+    const before = await profileClient.getProfile(buyerProfile);
+    await tradeClient.cancelTrade(
+      reasonTradePDA,
+      buyer,
+      escrowKeypair.publicKey,
+      reasonSellerTokenAccount,
+      mint,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      PROFILE_PROGRAM_ID,
+      reasonSeller.publicKey
+    );
+    await delay(1000);
+
+    const trade = await tradeClient.getTrade(reasonTradePDA);
+    expect(trade.status).to.equal('cancelled');
+    expect(trade.cancelReason).to.equal(CancelReason.TakerBackedOut);
+    const after = await profileClient.getProfile(buyerProfile);
+    expect(after.tradesCancelled).to.equal(before.tradesCancelled + 1);
+    expect(after.reputationScore).to.equal(Math.max(before.reputationScore - 2, 0));
+  });
+
+  it("Disputes a trade", async () => {
+    const amount = new anchor.BN(1000_000); // 1 token
+    const price = new anchor.BN(100_000); // $1.00 with 5 decimals
+
+    // Create a new escrow keypair
+    const escrowKeypair = Keypair.generate();
+
+    disputeTradePDA = await tradeClient.createTrade(
+      disputeTestSeller,
+      mint,
+      disputeTestSellerTokenAccount,
+      escrowKeypair,
+      amount,
+      price,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    disputeEscrow = escrowKeypair.publicKey;
+    await delay(1000);
+
+    await tradeClient.acceptTrade(disputeTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, disputeTestSellerProfile, buyerProfile);
+    await delay(1000);
+
+    const sellerBefore = await profileClient.getProfile(disputeTestSellerProfile);
+    const buyerBefore = await profileClient.getProfile(buyerProfile);
+
+    await tradeClient.disputeTrade(
+      disputeTradePDA,
+      buyer,
+      disputeTestSeller.publicKey,
+      buyer.publicKey,
+      PROFILE_PROGRAM_ID
+    );
+    await delay(1000);
+
+    const trade = await tradeClient.getTrade(disputeTradePDA);
+    expect(trade.status).to.equal('disputed');
+
+    // The dispute counts against both parties' completion rate
+    const sellerAfter = await profileClient.getProfile(disputeTestSellerProfile);
+    const buyerAfter = await profileClient.getProfile(buyerProfile);
+    expect(sellerAfter.tradesDisputed).to.equal(sellerBefore.tradesDisputed + 1);
+    expect(buyerAfter.tradesDisputed).to.equal(buyerBefore.tradesDisputed + 1);
+  });
+
+  it("Fails to dispute with unauthorized user", async () => {
+    const unauthorizedUser = Keypair.generate();
+    await airdropSol(provider.connection, unauthorizedUser.publicKey);
+    await delay(1000);
+
+    try {
+      await tradeClient.disputeTrade(
+        tradePDA,
+        unauthorizedUser,
+        seller.publicKey,
+        buyer.publicKey,
+        PROFILE_PROGRAM_ID
+      );
+      throw new Error("Expected error did not occur");
+    } catch (error: any) {
+      expect(error.error.errorCode.code).to.equal("UnauthorizedDisputer");
+    }
+  });
+
+  it("Resolves a dispute and records the loss on the losing party", async () => {
+    const before = await profileClient.getProfile(buyerProfile);
+
+    // The seller wins, so the escrow goes back to them and the buyer loses
+    await tradeClient.resolveDispute(
+      disputeTradePDA,
+      provider.wallet.payer,
+      false,
+      disputeTestSeller.publicKey,
+      buyer.publicKey,
+      disputeEscrow,
+      disputeTestSellerTokenAccount,
+      mint,
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      PROFILE_PROGRAM_ID
+    );
+    await delay(1000);
+
+    const trade = await tradeClient.getTrade(disputeTradePDA);
+    expect(trade.status).to.equal('cancelled');
+
+    const sellerBalance = await getTokenBalance(provider.connection, disputeTestSellerTokenAccount);
+    expect(sellerBalance).to.equal(1000_000_000);
+
+    const after = await profileClient.getProfile(buyerProfile);
+    expect(after.disputesLost).to.equal(before.disputesLost + 1);
+    expect(after.reputationScore).to.equal(Math.max(before.reputationScore - 5, 0));
+  });
+
+  it("Refunds a dispute to the seller once the dispute TTL passes", async () => {
+    const escrowKeypair = Keypair.generate();
+    const timeoutTradePDA = await tradeClient.createTrade(
+      timeoutTestSeller,
+      mint,
+      timeoutTestSellerTokenAccount,
+      escrowKeypair,
+      new anchor.BN(1000_000),
+      new anchor.BN(100_000),
+      offerPDA,
+      OFFER_PROGRAM_ID,
+      0,
+      TOKEN_PROGRAM_ID,
+      OfferType.Buy
+    );
+    await tradeClient.acceptTrade(timeoutTradePDA, buyer, offerPDA, OFFER_PROGRAM_ID, deriveProfile(timeoutTestSeller.publicKey, PROFILE_PROGRAM_ID)[0], buyerProfile);
+    await tradeClient.disputeTrade(
+      timeoutTradePDA,
+      buyer,
+      timeoutTestSeller.publicKey,
+      buyer.publicKey,
+      PROFILE_PROGRAM_ID
+    );
+
+    const disputed = await tradeClient.getTrade(timeoutTradePDA);
+    expect(disputed.disputedAt).to.be.greaterThan(0);
+
+    await tradeClient.setDisputeTtl(provider.wallet.payer, 3);
+    try {
+      const timeout = () => tradeClient.disputeTimeout(
+        timeoutTradePDA,
+        timeoutTestSeller.publicKey,
+        escrowKeypair.publicKey,
+        timeoutTestSellerTokenAccount,
+        mint,
+        offerPDA,
+        OFFER_PROGRAM_ID
+      );
+
+      try {
+        await timeout();
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("DisputeNotExpired");
+      }
+
+      // Nobody arbitrates, so anyone can return the escrow after the TTL
+      await delay(5000);
+      await timeout();
+    } finally {
+      await tradeClient.setDisputeTtl(provider.wallet.payer, 0);
+    }
+    await delay(1000);
+
+    const trade = await tradeClient.getTrade(timeoutTradePDA);
+    expect(trade.status).to.equal('cancelled');
+    expect(trade.cancelReason).to.equal(CancelReason.Expired);
+    const sellerBalance = await getTokenBalance(provider.connection, timeoutTestSellerTokenAccount);
+    expect(sellerBalance).to.equal(1000_000_000);
+  });
+
+  it("Rejects trades below the configured minimum units", async () => {
+    // The mint has 6 decimals, so 1 token is below a 2 token minimum
+    await tradeClient.setTradeUnits(provider.wallet.payer, new anchor.BN(2), new anchor.BN(0));
+    try {
+      await tradeClient.createTrade(
+        buyer,
+        mint,
+        buyerTokenAccount,
+        Keypair.generate(),
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("TradeAmountOutOfRange");
+    } finally {
+      await tradeClient.setTradeUnits(provider.wallet.payer, new anchor.BN(0), new anchor.BN(0));
+    }
+  });
+
+  it("Applies a mint's own trade units over the config's", async () => {
+    // The config allows 1 token, but this mint needs at least 2
+    await tradeClient.setTradeUnits(provider.wallet.payer, new anchor.BN(1), new anchor.BN(0));
+    await tradeClient.setMintTradeUnits(provider.wallet.payer, mint, new anchor.BN(2), new anchor.BN(0));
+    try {
+      await tradeClient.createTrade(
+        buyer,
+        mint,
+        buyerTokenAccount,
+        Keypair.generate(),
+        new anchor.BN(1000_000),
+        new anchor.BN(100_000),
+        offerPDA,
+        OFFER_PROGRAM_ID,
+        0,
+        TOKEN_PROGRAM_ID,
+        OfferType.Buy
+      );
+      expect.fail("Expected error");
+    } catch (err: any) {
+      expect(err.toString()).to.include("TradeAmountOutOfRange");
+    } finally {
+      await tradeClient.clearMintTradeUnits(provider.wallet.payer, mint);
+      await tradeClient.setTradeUnits(provider.wallet.payer, new anchor.BN(0), new anchor.BN(0));
+    }
+  });
+
+  it("Reports health and rejects new trades while paused", async () => {
+    const health = await tradeClient.health();
+    expect(health.version).to.equal("0.1.0");
+    expect(health.initialized).to.be.true;
+    expect(health.paused).to.be.false;
+
+    await tradeClient.setPaused(provider.wallet.payer, true);
+    try {
+      expect((await tradeClient.health()).paused).to.be.true;
+
+      try {
+        await tradeClient.createTrade(
+          buyer,
+          mint,
+          buyerTokenAccount,
+          Keypair.generate(),
+          new anchor.BN(1000_000),
+          new anchor.BN(100_000),
+          offerPDA,
+          OFFER_PROGRAM_ID,
+          0,
+          TOKEN_PROGRAM_ID,
+          OfferType.Buy
+        );
+        expect.fail("Expected error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("ProgramPaused");
+      }
+    } finally {
+      await tradeClient.setPaused(provider.wallet.payer, false);
     }
+    expect((await tradeClient.health()).paused).to.be.false;
   });
-});
\ No newline at end of file
+});